@@ -0,0 +1,171 @@
+// Highlights the annotations of an article and lets readers add new ones.
+// Annotations are anchored by their text, the text around it and its offset
+// in the article's text content (see src/annotations.rs).
+(() => {
+    const CONTEXT_LENGTH = 32;
+
+    function textNodes(root) {
+        const walker = document.createTreeWalker(root, NodeFilter.SHOW_TEXT);
+        const nodes = [];
+        while (walker.nextNode()) {
+            nodes.push(walker.currentNode);
+        }
+        return nodes;
+    }
+
+    // The offset of a position in the DOM within the text content of `root`
+    function textOffset(root, node, offset) {
+        let position = 0;
+        for (const text of textNodes(root)) {
+            if (text === node) {
+                return position + offset;
+            }
+            position += text.length;
+        }
+        return position;
+    }
+
+    // Finds the occurrence of the quote that matches its context best
+    function locate(text, annotation) {
+        let best = -1;
+        let bestScore = -Infinity;
+        let index = text.indexOf(annotation.exact);
+        while (index !== -1) {
+            let score = -Math.abs(index - annotation.start) / text.length;
+            if (text.slice(0, index).endsWith(annotation.prefix)) {
+                score += 1;
+            }
+            if (text.slice(index + annotation.exact.length).startsWith(annotation.suffix)) {
+                score += 1;
+            }
+            if (score > bestScore) {
+                best = index;
+                bestScore = score;
+            }
+            index = text.indexOf(annotation.exact, index + 1);
+        }
+        return best;
+    }
+
+    function showPopover(mark, annotation, url, reload) {
+        document.querySelectorAll('.annotation-popover').forEach((popover) => popover.remove());
+        const popover = document.createElement('div');
+        popover.className = 'annotation-popover box';
+        const comment = document.createElement('p');
+        comment.textContent = annotation.comment;
+        const details = document.createElement('p');
+        details.className = 'is-size-7';
+        details.textContent = `${annotation.author || 'Anonymous'}, ${new Date(annotation.created).toLocaleString()}`;
+        const remove = document.createElement('button');
+        remove.className = 'button is-small';
+        remove.textContent = 'Delete';
+        remove.addEventListener('click', () => {
+            fetch(`${url}/${annotation.id}`, { method: 'DELETE' }).then(reload);
+        });
+        popover.append(comment, details, remove);
+        const rect = mark.getBoundingClientRect();
+        popover.style.top = `${window.scrollY + rect.bottom + 4}px`;
+        popover.style.left = `${window.scrollX + rect.left}px`;
+        document.body.append(popover);
+    }
+
+    // Wraps the text between `start` and `end` in marks, one per text node
+    function highlight(root, start, end, annotation, url, reload) {
+        let position = 0;
+        for (const node of textNodes(root)) {
+            const nodeStart = position;
+            position += node.length;
+            if (position <= start || nodeStart >= end) {
+                continue;
+            }
+            const range = document.createRange();
+            range.setStart(node, Math.max(start - nodeStart, 0));
+            range.setEnd(node, Math.min(end - nodeStart, node.length));
+            const mark = document.createElement('mark');
+            mark.className = 'annotation';
+            mark.tabIndex = 0;
+            mark.title = annotation.comment;
+            mark.addEventListener('click', (event) => {
+                event.stopPropagation();
+                showPopover(mark, annotation, url, reload);
+            });
+            range.surroundContents(mark);
+        }
+    }
+
+    document.addEventListener('DOMContentLoaded', () => {
+        const root = document.getElementById('article-content');
+        if (!root || !root.dataset.annotations) {
+            return;
+        }
+        const url = root.dataset.annotations;
+        const original = root.innerHTML;
+
+        function reload() {
+            fetch(url)
+                .then((response) => response.ok ? response.json() : [])
+                .then((annotations) => {
+                    root.innerHTML = original;
+                    for (const annotation of annotations) {
+                        const text = root.textContent;
+                        const start = locate(text, annotation);
+                        if (start !== -1) {
+                            highlight(root, start, start + annotation.exact.length, annotation, url, reload);
+                        }
+                    }
+                });
+        }
+
+        const button = document.createElement('button');
+        button.className = 'button is-small is-warning annotation-button';
+        button.textContent = 'Annotate';
+        button.hidden = true;
+        document.body.append(button);
+
+        document.addEventListener('mouseup', (event) => {
+            if (event.target === button) {
+                return;
+            }
+            const selection = window.getSelection();
+            if (selection.isCollapsed || !root.contains(selection.anchorNode) || !root.contains(selection.focusNode)) {
+                button.hidden = true;
+                return;
+            }
+            const rect = selection.getRangeAt(0).getBoundingClientRect();
+            button.style.top = `${window.scrollY + rect.top - 36}px`;
+            button.style.left = `${window.scrollX + rect.left}px`;
+            button.hidden = false;
+        });
+
+        document.addEventListener('click', (event) => {
+            if (!event.target.closest('.annotation-popover')) {
+                document.querySelectorAll('.annotation-popover').forEach((popover) => popover.remove());
+            }
+        });
+
+        button.addEventListener('click', () => {
+            const range = window.getSelection().getRangeAt(0);
+            const start = textOffset(root, range.startContainer, range.startOffset);
+            const end = textOffset(root, range.endContainer, range.endOffset);
+            const text = root.textContent;
+            const comment = window.prompt('Comment');
+            button.hidden = true;
+            if (!comment) {
+                return;
+            }
+            fetch(url, {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({
+                    exact: text.slice(start, end),
+                    prefix: text.slice(Math.max(start - CONTEXT_LENGTH, 0), start),
+                    suffix: text.slice(end, end + CONTEXT_LENGTH),
+                    start,
+                    comment,
+                }),
+            }).then(reload);
+        });
+
+        reload();
+    });
+})();
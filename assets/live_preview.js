@@ -0,0 +1,41 @@
+// Shows the article being edited next to the editor, rendered by the wiki
+// itself at `/preview` (see src/preview.rs), shortly after typing stops.
+(() => {
+    const DELAY = 300;
+
+    document.addEventListener('DOMContentLoaded', () => {
+        const editor = document.querySelector('#article-editor textarea[name="content"]');
+        const preview = document.getElementById('live-preview');
+        if (!editor || !preview) {
+            return;
+        }
+        const title = encodeURIComponent(preview.dataset.title || '');
+        const base = document.documentElement.dataset.basePath || '';
+        let timer = null;
+        let latest = 0;
+
+        async function render() {
+            const request = ++latest;
+            try {
+                const response = await fetch(`${base}/preview?title=${title}`, {
+                    method: 'POST',
+                    headers: { 'Content-Type': 'text/plain; charset=utf-8' },
+                    body: editor.value,
+                });
+                // Answers to older requests may arrive after newer ones
+                if (response.ok && request === latest) {
+                    // The HTML is rendered and cleaned by the wiki like the article itself
+                    preview.innerHTML = await response.text();
+                }
+            } catch (e) {
+                // The next change tries again
+            }
+        }
+
+        editor.addEventListener('input', () => {
+            clearTimeout(timer);
+            timer = setTimeout(render, DELAY);
+        });
+        render();
+    });
+})();
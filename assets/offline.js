@@ -0,0 +1,29 @@
+// Registers the service worker and shows whether the current page can be read offline.
+document.addEventListener('DOMContentLoaded', () => {
+    if (!('serviceWorker' in navigator) || !('caches' in window)) {
+        return;
+    }
+
+    navigator.serviceWorker.register(`${document.documentElement.dataset.basePath || ''}/sw.js`);
+
+    const indicator = document.getElementById('offline-indicator');
+
+    const update = () => {
+        caches.match(location.href).then((cached) => {
+            if (!navigator.onLine) {
+                indicator.textContent = 'Offline';
+                indicator.hidden = false;
+            } else if (cached) {
+                indicator.textContent = 'Available offline';
+                indicator.hidden = false;
+            } else {
+                indicator.hidden = true;
+            }
+        });
+    };
+
+    update();
+    window.addEventListener('online', update);
+    window.addEventListener('offline', update);
+    navigator.serviceWorker.addEventListener('controllerchange', update);
+});
@@ -0,0 +1,94 @@
+// Draws the PDFs embedded in articles and the thumbnails of PDFs on the
+// media page with PDF.js, which is only loaded if there are any (see
+// src/pdf_preview.rs). If PDF.js can't be loaded, the links stay.
+const BASE = document.documentElement.dataset.basePath || '';
+const PDFJS = `${BASE}/static/pdfjs/pdf.min.mjs`;
+const WORKER = `${BASE}/static/pdfjs/pdf.worker.min.mjs`;
+
+let pdfjs = null;
+
+function load() {
+    if (!pdfjs) {
+        pdfjs = import(PDFJS).then((module) => {
+            module.GlobalWorkerOptions.workerSrc = WORKER;
+            return module;
+        });
+    }
+    return pdfjs;
+}
+
+async function open(url) {
+    const { getDocument } = await load();
+    return getDocument(url).promise;
+}
+
+// Draws page `number` of `pdf` onto `canvas`, `width` pixels wide
+async function draw(pdf, number, canvas, width) {
+    const page = await pdf.getPage(number);
+    const viewport = page.getViewport({ scale: width / page.getViewport({ scale: 1 }).width });
+    // Sharp on high-density screens
+    const ratio = window.devicePixelRatio || 1;
+    canvas.width = Math.floor(viewport.width * ratio);
+    canvas.height = Math.floor(viewport.height * ratio);
+    canvas.style.width = `${Math.floor(viewport.width)}px`;
+    canvas.style.height = `${Math.floor(viewport.height)}px`;
+    await page.render({
+        canvasContext: canvas.getContext('2d'),
+        viewport,
+        transform: ratio === 1 ? null : [ratio, 0, 0, ratio, 0, 0],
+    }).promise;
+}
+
+function button(label, text) {
+    const button = document.createElement('button');
+    button.type = 'button';
+    button.className = 'button is-small';
+    button.textContent = text;
+    button.setAttribute('aria-label', label);
+    return button;
+}
+
+async function showPreview(link) {
+    const pdf = await open(link.dataset.pdf);
+    const viewer = document.createElement('figure');
+    viewer.className = 'pdf-viewer box';
+    const canvas = document.createElement('canvas');
+    canvas.setAttribute('role', 'img');
+    const previous = button('Previous page', '‹');
+    const next = button('Next page', '›');
+    const status = document.createElement('span');
+    status.setAttribute('aria-live', 'polite');
+    const controls = document.createElement('figcaption');
+    controls.className = 'buttons';
+    controls.append(previous, status, next);
+    viewer.append(canvas, controls);
+    link.after(viewer);
+
+    let current = 1;
+    async function show(number) {
+        current = Math.min(Math.max(number, 1), pdf.numPages);
+        status.textContent = `Page ${current} of ${pdf.numPages}`;
+        canvas.setAttribute('aria-label', `${link.textContent}, page ${current}`);
+        previous.disabled = current === 1;
+        next.disabled = current === pdf.numPages;
+        await draw(pdf, current, canvas, Math.min(viewer.clientWidth || 600, 800));
+    }
+    previous.addEventListener('click', () => show(current - 1));
+    next.addEventListener('click', () => show(current + 1));
+    await show(1);
+}
+
+async function showThumbnail(canvas) {
+    const width = canvas.width;
+    const pdf = await open(canvas.dataset.pdfThumbnail);
+    await draw(pdf, 1, canvas, width);
+}
+
+document.addEventListener('DOMContentLoaded', () => {
+    for (const link of document.querySelectorAll('a[data-pdf]')) {
+        showPreview(link).catch((e) => console.warn('Could not preview', link.href, e));
+    }
+    for (const canvas of document.querySelectorAll('canvas[data-pdf-thumbnail]')) {
+        showThumbnail(canvas).catch((e) => console.warn('Could not draw a thumbnail', e));
+    }
+});
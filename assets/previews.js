@@ -0,0 +1,65 @@
+// Shows a preview card while hovering a link to another article, or to one
+// of its sections or blocks (like `[[Page#Section]]`). The renderer gives
+// these links the API URL of the preview in `data-preview`, see
+// src/preview.rs and src/fragment.rs.
+(() => {
+    const cache = new Map();
+
+    // The previews' HTML is shown as it is, so it may only come from the wiki's own API
+    function isPreview(src) {
+        const url = new URL(src, window.location.href);
+        const base = document.documentElement.dataset.basePath || '';
+        return (
+            url.origin === window.location.origin &&
+            (url.pathname.startsWith(`${base}/api/preview/`) || url.pathname === `${base}/api/fragment`)
+        );
+    }
+
+    function load(src) {
+        if (!cache.has(src)) {
+            cache.set(src, fetch(src).then((response) => (response.ok ? response.json() : null)));
+        }
+        return cache.get(src);
+    }
+
+    function hide() {
+        document.querySelectorAll('.link-preview').forEach((preview) => preview.remove());
+    }
+
+    function show(link, preview) {
+        hide();
+        const card = document.createElement('div');
+        card.className = 'link-preview box content';
+        const title = document.createElement('p');
+        title.className = 'has-text-weight-bold';
+        title.textContent = preview.title;
+        const body = document.createElement('div');
+        // The HTML is rendered by the wiki like the article itself
+        body.innerHTML = preview.html;
+        card.append(title, body);
+        const rect = link.getBoundingClientRect();
+        card.style.top = `${window.scrollY + rect.bottom + 4}px`;
+        card.style.left = `${window.scrollX + rect.left}px`;
+        document.body.append(card);
+    }
+
+    document.addEventListener('DOMContentLoaded', () => {
+        document.querySelectorAll('a[data-preview]').forEach((link) => {
+            const src = link.dataset.preview;
+            if (!isPreview(src)) {
+                return;
+            }
+            ['mouseenter', 'focus'].forEach((event) => {
+                link.addEventListener(event, () => {
+                    load(src).then((preview) => {
+                        if (preview && (link.matches(':hover') || link === document.activeElement)) {
+                            show(link, preview);
+                        }
+                    });
+                });
+            });
+            link.addEventListener('mouseleave', hide);
+            link.addEventListener('blur', hide);
+        });
+    });
+})();
@@ -0,0 +1,40 @@
+// Keeps a copy of every page visited so it can be read without a connection.
+// Pages are always fetched from the network first so edits show up immediately.
+const CACHE = 'tome-pages-v1';
+
+self.addEventListener('install', (event) => {
+    self.skipWaiting();
+});
+
+self.addEventListener('activate', (event) => {
+    event.waitUntil(
+        caches.keys()
+            .then((keys) => Promise.all(keys.filter((key) => key !== CACHE).map((key) => caches.delete(key))))
+            .then(() => self.clients.claim())
+    );
+});
+
+self.addEventListener('fetch', (event) => {
+    const request = event.request;
+    const url = new URL(request.url);
+
+    const editor = new URL('edit/', self.registration.scope).pathname;
+    if (request.method !== 'GET' || url.origin !== self.location.origin || url.pathname.startsWith(editor)) {
+        return;
+    }
+
+    event.respondWith(
+        fetch(request)
+            .then((response) => {
+                if (response.ok) {
+                    const copy = response.clone();
+                    caches.open(CACHE).then((cache) => cache.put(request, copy));
+                }
+                return response;
+            })
+            .catch(() => caches.match(request).then((cached) => cached || new Response(
+                '<h1>You are offline</h1><p>This page has not been saved for offline reading yet.</p>',
+                { status: 503, headers: { 'Content-Type': 'text/html; charset=utf-8' } }
+            )))
+    );
+});
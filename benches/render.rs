@@ -0,0 +1,99 @@
+//! Times rendering, the overview and history listings on generated content,
+//! run with `cargo bench`. `tome bench` times the same with a wiki's own content.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use figment::providers::Serialized;
+use figment::Figment;
+use tome::{bench, Article, TomeConfig, Wiki};
+
+/// A long article with headings, lists, tables and code
+fn large_document(sections: usize) -> String {
+    (0..sections)
+        .map(|section| {
+            format!(
+                "## Section {section}\n\n\
+                 Some *emphasized* and **strong** text with `code` in it.\n\n\
+                 - An item\n- Another item\n\n\
+                 | Name | Value |\n| --- | --- |\n| a | {section} |\n\n\
+                 ```rust\nfn main() {{}}\n```\n\n"
+            )
+        })
+        .collect()
+}
+
+/// An article that is mostly links to other articles
+fn many_links(links: usize) -> String {
+    (0..links)
+        .map(|link| format!("See [[Article {link}]] and [the page](/article/page-{link}). "))
+        .collect()
+}
+
+fn render(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (_content, wiki) = runtime.block_on(content(0, 0));
+    wiki.run_sync(|| render_documents(c));
+}
+
+fn render_documents(c: &mut Criterion) {
+    let mut group = c.benchmark_group("custom_md");
+    for sections in [10, 100, 1000] {
+        let markdown = large_document(sections);
+        group.bench_with_input(
+            BenchmarkId::new("sections", sections),
+            &markdown,
+            |b, markdown| b.iter(|| bench::custom_md(markdown)),
+        );
+    }
+    for links in [10, 100, 1000] {
+        let markdown = many_links(links);
+        group.bench_with_input(
+            BenchmarkId::new("links", links),
+            &markdown,
+            |b, markdown| b.iter(|| bench::custom_md(markdown)),
+        );
+    }
+    group.finish();
+}
+
+/// A wiki in a temporary directory with `articles` articles of `versions` versions each
+async fn content(articles: usize, versions: usize) -> (tempfile::TempDir, Wiki) {
+    let dir = tempfile::tempdir().unwrap();
+    let config: TomeConfig = Figment::from(Serialized::defaults(TomeConfig::default()))
+        .merge(Serialized::default(
+            "content_dir",
+            dir.path().join("content"),
+        ))
+        .extract()
+        .unwrap();
+    let wiki = Wiki::open(&config).await.unwrap();
+    wiki.run(async {
+        for article in 0..articles {
+            for version in 0..versions {
+                Article::new(
+                    format!("article-{article}"),
+                    format!("Version {version} of article {article}"),
+                )
+                .write_to_disk()
+                .await
+                .unwrap();
+            }
+        }
+    })
+    .await;
+    (dir, wiki)
+}
+
+fn listings(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (_content, wiki) = runtime.block_on(content(200, 20));
+
+    c.bench_function("overview/200 articles", |b| {
+        b.to_async(&runtime).iter(|| wiki.run(bench::overview()))
+    });
+    c.bench_function("history/20 versions", |b| {
+        b.to_async(&runtime)
+            .iter(|| wiki.run(bench::history("article-0")))
+    });
+}
+
+criterion_group!(benches, render, listings);
+criterion_main!(benches);
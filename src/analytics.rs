@@ -0,0 +1,310 @@
+//! # First-Party Analytics
+//!
+//! When `analytics` is enabled, every successful page view is counted
+//! per path and day. No cookies are set; unique visitors are counted by
+//! hashing the client address and user agent together with a random salt
+//! that is replaced every day and never written to disk, so visitors
+//! can't be recognized across days.
+//!
+//! Views that come from another site are also counted per page and
+//! referring site, by the host in the `Referer` header, so authors can see
+//! where readers come from. `/admin/analytics/referrers` lists them as
+//! JSON, for a single page with `?path=`.
+//!
+//! The editor opens for any title, so there is no end to the pages that
+//! can be viewed. A day only counts the first thousand pages and pairs of
+//! page and referring site separately, views of other pages and from other
+//! sites are added up as "(other pages)" and "(other sites)".
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use askama::Template;
+use askama_axum::IntoResponse;
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::{header, Method, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::sync::Mutex;
+
+use crate::config::content_path;
+use crate::hotlink;
+use crate::layout::Layout;
+use crate::{wiki, TomeConfig};
+
+const ANALYTICS_PATH: &str = "analytics.json";
+/// How many pages, and pairs of page and referring site, a day counts separately
+const MAX_COUNTED: usize = 1000;
+const OTHER_PAGES: &str = "(other pages)";
+const OTHER_SITES: &str = "(other sites)";
+
+/// Page views and unique visitors of a single day
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct Day {
+    visitors: u64,
+    paths: BTreeMap<String, u64>,
+    /// Views from other sites, by page and the host of the referring site
+    #[serde(default)]
+    referrers: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    days: BTreeMap<String, Day>,
+    today: String,
+    salt: String,
+    visitors: HashSet<u64>,
+    dirty: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct Analytics {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Analytics {
+    /// Loads previously recorded statistics and starts a task
+    /// that periodically writes them back to disk.
+    pub async fn start() -> Self {
+        let days = match tokio::fs::read_to_string(content_path(ANALYTICS_PATH)).await {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_default(),
+            Err(_) => BTreeMap::new(),
+        };
+        let analytics = Analytics {
+            inner: Arc::new(Mutex::new(Inner {
+                days,
+                ..Default::default()
+            })),
+        };
+
+        let background = analytics.clone();
+        wiki::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                if let Err(e) = background.flush().await {
+                    tracing::warn!("Could not save analytics: {e}");
+                }
+            }
+        });
+
+        analytics
+    }
+
+    async fn flush(&self) -> tokio::io::Result<()> {
+        let mut inner = self.inner.lock().await;
+        if !inner.dirty {
+            return Ok(());
+        }
+        let json = serde_json::to_string(&inner.days)?;
+        tokio::fs::write(content_path(ANALYTICS_PATH), json).await?;
+        inner.dirty = false;
+        Ok(())
+    }
+
+    async fn record(
+        &self,
+        path: &str,
+        referrer: Option<String>,
+        client: Option<SocketAddr>,
+        user_agent: &str,
+    ) {
+        let today = OffsetDateTime::now_utc().date().to_string();
+        let mut inner = self.inner.lock().await;
+
+        if inner.today != today {
+            inner.today = today.clone();
+            inner.salt = uuid::Uuid::new_v4().to_string();
+            inner.visitors.clear();
+        }
+
+        let mut hasher = DefaultHasher::new();
+        inner.salt.hash(&mut hasher);
+        client.map(|addr| addr.ip()).hash(&mut hasher);
+        user_agent.hash(&mut hasher);
+        let visitor = hasher.finish();
+        let new_visitor = inner.visitors.insert(visitor);
+
+        let day = inner.days.entry(today).or_default();
+        if new_visitor {
+            day.visitors += 1;
+        }
+        let path = match day.paths.contains_key(path) || day.paths.len() < MAX_COUNTED {
+            true => path,
+            false => OTHER_PAGES,
+        };
+        *day.paths.entry(path.to_string()).or_default() += 1;
+        if let Some(mut referrer) = referrer {
+            let counted: usize = day.referrers.values().map(BTreeMap::len).sum();
+            let known = day
+                .referrers
+                .get(path)
+                .is_some_and(|referrers| referrers.contains_key(&referrer));
+            if !known && counted >= MAX_COUNTED {
+                referrer = OTHER_SITES.to_string();
+            }
+            *day.referrers
+                .entry(path.to_string())
+                .or_default()
+                .entry(referrer)
+                .or_default() += 1;
+        }
+        inner.dirty = true;
+    }
+}
+
+fn is_page(path: &str) -> bool {
+    !(path.starts_with("/media/") || path.starts_with("/admin/") || path == "/favicon.ico")
+}
+
+/// Middleware counting successful `GET` requests for pages
+pub async fn track<B>(
+    State(config): State<TomeConfig>,
+    State(analytics): State<Analytics>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let counted = request.method() == Method::GET && is_page(request.uri().path());
+    let path = request.uri().path().to_string();
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|agent| agent.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let header = |name| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    // Only other sites are interesting, not links within the wiki
+    let referrer = header(header::REFERER)
+        .and_then(hotlink::host_of)
+        .filter(|referrer| !hotlink::is_own_host(&config, header(header::HOST), referrer));
+
+    let response = next.run(request).await;
+
+    if counted && response.status().is_success() {
+        let path = urlencoding::decode(&path)
+            .map(|path| path.into_owned())
+            .unwrap_or(path);
+        analytics.record(&path, referrer, client, &user_agent).await;
+    }
+
+    response
+}
+
+#[derive(Template)]
+#[template(path = "analytics.html")]
+pub struct Report {
+    layout: Layout,
+    enabled: bool,
+    days: Vec<(String, u64, u64)>,
+    paths: Vec<(String, u64)>,
+    referrers: Vec<Referrer>,
+}
+
+/// How often a page was visited from another site
+#[derive(Serialize)]
+pub struct Referrer {
+    path: String,
+    referrer: String,
+    views: u64,
+}
+
+/// The referrers of the last 30 days of `days`, most views first
+fn referrers<'a>(days: impl Iterator<Item = &'a Day>, path: Option<&str>) -> Vec<Referrer> {
+    let mut totals: BTreeMap<(&str, &str), u64> = BTreeMap::new();
+    for day in days {
+        for (page, referrers) in &day.referrers {
+            if path.is_some_and(|path| path != page) {
+                continue;
+            }
+            for (referrer, views) in referrers {
+                *totals.entry((page, referrer)).or_default() += views;
+            }
+        }
+    }
+    let mut referrers: Vec<Referrer> = totals
+        .into_iter()
+        .map(|((path, referrer), views)| Referrer {
+            path: path.to_string(),
+            referrer: referrer.to_string(),
+            views,
+        })
+        .collect();
+    referrers.sort_by_key(|referrer| std::cmp::Reverse(referrer.views));
+    referrers
+}
+
+/// Shows the statistics of the last 30 days
+pub async fn get_analytics(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    State(analytics): State<Analytics>,
+) -> impl IntoResponse {
+    let inner = analytics.inner.lock().await;
+
+    let recent: Vec<(&String, &Day)> = inner.days.iter().rev().take(30).collect();
+
+    let days = recent
+        .iter()
+        .map(|(date, day)| {
+            (
+                date.to_string(),
+                day.paths.values().sum::<u64>(),
+                day.visitors,
+            )
+        })
+        .collect();
+
+    let mut totals: BTreeMap<&str, u64> = BTreeMap::new();
+    for (_, day) in &recent {
+        for (path, views) in &day.paths {
+            *totals.entry(path).or_default() += views;
+        }
+    }
+    let mut paths: Vec<(String, u64)> = totals
+        .into_iter()
+        .map(|(path, views)| (path.to_string(), views))
+        .collect();
+    paths.sort_by_key(|(_, views)| std::cmp::Reverse(*views));
+    paths.truncate(50);
+
+    let mut referrers = referrers(recent.iter().map(|(_, day)| *day), None);
+    referrers.truncate(50);
+
+    Report {
+        layout,
+        enabled: config.analytics,
+        days,
+        paths,
+        referrers,
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ReferrerQuery {
+    path: Option<String>,
+}
+
+/// Lists the referrers of the last 30 days as JSON
+pub async fn get_referrers(
+    State(analytics): State<Analytics>,
+    Query(query): Query<ReferrerQuery>,
+) -> Json<Vec<Referrer>> {
+    let inner = analytics.inner.lock().await;
+    let recent = inner.days.values().rev().take(30);
+    Json(referrers(recent, query.path.as_deref()))
+}
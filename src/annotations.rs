@@ -0,0 +1,128 @@
+//! # Annotations
+//!
+//! Readers can highlight a passage of an article and leave a comment next
+//! to it. Annotations are kept in an `annotations.json` file next to the
+//! article's versions. Each one is anchored by the quoted text with a bit
+//! of context before and after it, as well as its offset in the rendered
+//! text, so it can still be found after small edits elsewhere in the article.
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::error::TomeError;
+use crate::storage::article_dir;
+use crate::Article;
+
+/// How much context is kept around the quoted text
+const CONTEXT_LENGTH: usize = 32;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Annotation {
+    id: String,
+    /// The highlighted text
+    exact: String,
+    /// Text directly in front of the highlight
+    prefix: String,
+    /// Text directly after the highlight
+    suffix: String,
+    /// Character offset of the highlight in the article's rendered text
+    start: usize,
+    comment: String,
+    author: Option<String>,
+    created: String,
+}
+
+/// A new annotation as sent by the article page
+#[derive(Deserialize)]
+pub struct NewAnnotation {
+    exact: String,
+    #[serde(default)]
+    prefix: String,
+    #[serde(default)]
+    suffix: String,
+    #[serde(default)]
+    start: usize,
+    comment: String,
+}
+
+fn annotations_path(article: &Article) -> String {
+    format!("{}/annotations.json", article_dir(&article.title))
+}
+
+async fn load(article: &Article) -> Vec<Annotation> {
+    match tokio::fs::read_to_string(annotations_path(article)).await {
+        Ok(json) => serde_json::from_str(&json).unwrap_or_default(),
+        Err(_) => vec![],
+    }
+}
+
+async fn save(article: &Article, annotations: &[Annotation]) -> tokio::io::Result<()> {
+    let json = serde_json::to_string_pretty(annotations)?;
+    tokio::fs::write(annotations_path(article), json).await
+}
+
+/// Keeps at most `CONTEXT_LENGTH` characters of `context`, on the side next to the highlight
+fn truncate(context: &str, from_end: bool) -> String {
+    let count = context.chars().count();
+    if count <= CONTEXT_LENGTH {
+        context.to_string()
+    } else if from_end {
+        context.chars().skip(count - CONTEXT_LENGTH).collect()
+    } else {
+        context.chars().take(CONTEXT_LENGTH).collect()
+    }
+}
+
+pub async fn get_annotations(Path(title): Path<String>) -> Result<impl IntoResponse, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let article = Article::load(&title).await.ok_or(TomeError::NotFound)?;
+    Ok(Json(load(&article).await))
+}
+
+pub async fn post_annotation(
+    Path(title): Path<String>,
+    Json(new): Json<NewAnnotation>,
+) -> Result<impl IntoResponse, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let article = Article::load(&title).await.ok_or(TomeError::NotFound)?;
+    if new.exact.trim().is_empty() || new.comment.trim().is_empty() {
+        return Err(TomeError::BadRequest(
+            "Both a quote and a comment are required".to_string(),
+        ));
+    }
+
+    let annotation = Annotation {
+        id: uuid::Uuid::new_v4().hyphenated().to_string(),
+        exact: new.exact,
+        prefix: truncate(&new.prefix, true),
+        suffix: truncate(&new.suffix, false),
+        start: new.start,
+        comment: new.comment.trim().to_string(),
+        author: None,
+        created: OffsetDateTime::now_utc().format(&Rfc3339).unwrap(),
+    };
+    let mut annotations = load(&article).await;
+    annotations.push(annotation.clone());
+    save(&article, &annotations).await?;
+
+    Ok((StatusCode::CREATED, Json(annotation)))
+}
+
+pub async fn delete_annotation(
+    Path((title, id)): Path<(String, String)>,
+) -> Result<StatusCode, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let article = Article::load(&title).await.ok_or(TomeError::NotFound)?;
+    let mut annotations = load(&article).await;
+    let count = annotations.len();
+    annotations.retain(|annotation| annotation.id != id);
+    if annotations.len() == count {
+        return Err(TomeError::NotFound);
+    }
+    save(&article, &annotations).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
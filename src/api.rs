@@ -0,0 +1,203 @@
+//! # Content API
+//!
+//! `/api/articles` lists the articles and `/api/changes` their saved
+//! versions as JSON, so other tools can sync with the wiki. Both take the
+//! same parameters: `?page` and `?per_page` (`history_page_size` by
+//! default, at most 500) page the list, `?tag` only lists articles with a
+//! tag, `?modified_since` only what was saved since an RFC 3339 time, and
+//! `?sort` orders by `title` or `modified`, descending with a leading `-`.
+//! Responses have the number of entries in `X-Total-Count` and links to
+//! the other pages in a `Link` header.
+use std::time::SystemTime;
+
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderValue, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::error::TomeError;
+use crate::storage::article_slugs;
+use crate::{changes, Article, TomeConfig};
+
+const MAX_PER_PAGE: usize = 500;
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    page: Option<usize>,
+    per_page: Option<usize>,
+    tag: Option<String>,
+    modified_since: Option<String>,
+    sort: Option<String>,
+}
+
+/// An entry of a list, with what it is filtered and sorted by
+struct Listed<T> {
+    title: String,
+    tags: Vec<String>,
+    modified: SystemTime,
+    entry: T,
+}
+
+#[derive(Serialize)]
+struct ArticleEntry {
+    slug: String,
+    title: String,
+    tags: Vec<String>,
+    version: Option<String>,
+    modified: String,
+    archived: bool,
+}
+
+#[derive(Serialize)]
+struct ChangeEntry {
+    slug: String,
+    title: String,
+    version: String,
+    saved: String,
+}
+
+fn format_time(time: SystemTime) -> String {
+    OffsetDateTime::from(time).format(&Rfc3339).unwrap()
+}
+
+/// The link to `page` of the list at `uri`, keeping its other parameters
+fn page_link(uri: &Uri, page: usize, rel: &str) -> String {
+    let mut parameters: Vec<(String, String)> =
+        serde_urlencoded::from_str(uri.query().unwrap_or_default()).unwrap_or_default();
+    parameters.retain(|(key, _)| key != "page");
+    parameters.push(("page".to_string(), page.to_string()));
+    let query = serde_urlencoded::to_string(parameters).unwrap_or_default();
+    format!("<{}?{query}>; rel=\"{rel}\"", uri.path())
+}
+
+/// Filters, sorts and pages `listed` as `query` asks, sorted by `default_sort` otherwise
+fn respond<T: Serialize>(
+    config: &TomeConfig,
+    uri: &Uri,
+    query: ListQuery,
+    default_sort: &str,
+    mut listed: Vec<Listed<T>>,
+) -> Result<Response, TomeError> {
+    if let Some(tag) = &query.tag {
+        listed.retain(|listed| listed.tags.contains(tag));
+    }
+    if let Some(since) = &query.modified_since {
+        let since: SystemTime = OffsetDateTime::parse(since, &Rfc3339)
+            .map_err(|_| {
+                TomeError::BadRequest(format!("modified_since \"{since}\" isn't an RFC 3339 time"))
+            })?
+            .into();
+        listed.retain(|listed| listed.modified >= since);
+    }
+    match query.sort.as_deref().unwrap_or(default_sort) {
+        "title" => listed.sort_by_key(|listed| listed.title.to_lowercase()),
+        "-title" => listed.sort_by_key(|listed| std::cmp::Reverse(listed.title.to_lowercase())),
+        "modified" => listed.sort_by_key(|listed| listed.modified),
+        "-modified" => listed.sort_by_key(|listed| std::cmp::Reverse(listed.modified)),
+        sort => {
+            return Err(TomeError::BadRequest(format!(
+                "Lists can't be sorted by \"{sort}\", only by title or modified"
+            )))
+        }
+    }
+
+    let per_page = query
+        .per_page
+        .unwrap_or(config.history_page_size.unwrap_or(50))
+        .clamp(1, MAX_PER_PAGE);
+    let page = query.page.unwrap_or(1);
+    if page == 0 {
+        return Err(TomeError::BadRequest("Pages start at 1".to_string()));
+    }
+    let total = listed.len();
+    let pages = total.div_ceil(per_page).max(1);
+    let entries: Vec<T> = listed
+        .into_iter()
+        .skip((page - 1) * per_page)
+        .take(per_page)
+        .map(|listed| listed.entry)
+        .collect();
+
+    let mut links = vec![page_link(uri, 1, "first")];
+    if page > 1 {
+        links.push(page_link(uri, (page - 1).min(pages), "prev"));
+    }
+    if page < pages {
+        links.push(page_link(uri, page + 1, "next"));
+    }
+    links.push(page_link(uri, pages, "last"));
+
+    let mut response = Json(entries).into_response();
+    let headers = response.headers_mut();
+    headers.insert("X-Total-Count", HeaderValue::from(total));
+    if let Ok(links) = HeaderValue::from_str(&links.join(", ")) {
+        headers.insert(header::LINK, links);
+    }
+    Ok(response)
+}
+
+pub async fn get_articles(
+    State(config): State<TomeConfig>,
+    uri: Uri,
+    Query(query): Query<ListQuery>,
+) -> Result<Response, TomeError> {
+    let mut listed = vec![];
+    for slug in article_slugs().await {
+        let Some(article) = Article::load(&slug).await else {
+            continue;
+        };
+        let latest = Article::get_versions(&article.title)
+            .await
+            .into_iter()
+            .max_by_key(|(_, saved)| *saved);
+        let modified = latest
+            .as_ref()
+            .map_or(SystemTime::UNIX_EPOCH, |(_, saved)| *saved);
+        let tags = article.metadata().tags;
+        listed.push(Listed {
+            title: article.title.clone(),
+            tags: tags.clone(),
+            modified,
+            entry: ArticleEntry {
+                slug,
+                archived: article.is_archived(),
+                title: article.title,
+                tags,
+                version: latest.map(|(version, _)| version),
+                modified: format_time(modified),
+            },
+        });
+    }
+    respond(&config, &uri, query, "title", listed)
+}
+
+pub async fn get_changes(
+    State(config): State<TomeConfig>,
+    uri: Uri,
+    Query(query): Query<ListQuery>,
+) -> Result<Response, TomeError> {
+    let mut listed = vec![];
+    for slug in article_slugs().await {
+        let Some(article) = Article::load(&slug).await else {
+            continue;
+        };
+        let tags = article.metadata().tags;
+        for change in changes::of_article(&article).await {
+            listed.push(Listed {
+                title: change.title.clone(),
+                tags: tags.clone(),
+                modified: change.saved,
+                entry: ChangeEntry {
+                    slug: change.slug,
+                    title: change.title,
+                    version: change.version,
+                    saved: format_time(change.saved),
+                },
+            });
+        }
+    }
+    respond(&config, &uri, query, "-modified", listed)
+}
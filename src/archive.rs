@@ -0,0 +1,146 @@
+//! # Archive
+//!
+//! Archiving keeps articles that are no longer current without deleting
+//! them. An archived article has `archived: true` in its frontmatter and is
+//! read-only: it can't be edited, renamed, reviewed or deleted until it is
+//! unarchived again. Every change of an article goes through
+//! [`ensure_changeable`], archiving and unarchiving are the only exception.
+//! It is labeled as archived on its page and left out of the overview and
+//! search, unless they are asked to show archived articles with
+//! `?archived=true`. The overview of a namespace archives or unarchives
+//! every article in it at once.
+use askama_axum::IntoResponse;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::{Redirect, Response};
+use axum::Form;
+use serde::Deserialize;
+use serde_yaml::Value;
+
+use crate::error::TomeError;
+use crate::layout::{Layout, NAMESPACE_SEPARATOR};
+use crate::{frontmatter, Article, Invalid, Overview};
+
+/// Whether an article with this content is archived
+pub fn is_archived(content: &str) -> bool {
+    frontmatter::parse(content)
+        .unwrap_or_default()
+        .get(&Value::from("archived"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// `content` with `archived: true` in its frontmatter, or without it
+fn set_archived(content: &str, archived: bool) -> Result<String, String> {
+    let mut meta = frontmatter::parse(content)?;
+    if archived {
+        meta.insert(Value::from("archived"), Value::from(true));
+    } else {
+        meta.remove(&Value::from("archived"));
+    }
+    Ok(frontmatter::join(&meta, frontmatter::split(content).1))
+}
+
+fn refusal(title: &str) -> String {
+    format!("\"{title}\" is archived, unarchive it before changing it.")
+}
+
+/// The page turning away a change to the archived article `title`
+pub fn refuse_change(layout: Layout, title: &str) -> Response {
+    let message = refusal(title);
+    (StatusCode::FORBIDDEN, Invalid { layout, message }).into_response()
+}
+
+/// Refuses changing or deleting the article `title` while its current version is archived
+pub async fn ensure_changeable(title: &str) -> Result<(), TomeError> {
+    match Article::load(title).await {
+        Some(current) if is_archived(&current.content) => {
+            Err(TomeError::Forbidden(refusal(&current.title)))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Archives or unarchives `article` as a new version by `author`, unless it already is
+async fn archive(article: Article, archived: bool, author: Option<&str>) -> Result<(), TomeError> {
+    if is_archived(&article.content) == archived {
+        return Ok(());
+    }
+    let summary = if archived { "Archived" } else { "Unarchived" };
+    let content = set_archived(&article.content, archived).map_err(|e| {
+        TomeError::BadRequest(format!(
+            "The frontmatter of \"{}\" is invalid, fix it first: {e}",
+            article.title
+        ))
+    })?;
+    Ok(Article { content, ..article }
+        .write_to_disk_by(author, Some(summary))
+        .await?)
+}
+
+/// Whether to archive or unarchive, from an `action` of a form
+fn parse_action(action: &str) -> Result<bool, TomeError> {
+    match action {
+        "archive" => Ok(true),
+        "unarchive" => Ok(false),
+        action => Err(TomeError::BadRequest(format!(
+            "\"{action}\" is neither archive nor unarchive"
+        ))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ArchiveForm {
+    action: String,
+}
+
+pub async fn post_archive(
+    layout: Layout,
+    Path(title): Path<String>,
+    Form(form): Form<ArchiveForm>,
+) -> Result<impl IntoResponse, TomeError> {
+    let archived = parse_action(&form.action)?;
+    let title = urlencoding::decode(&title)?.into_owned();
+    let article = Article::load(&title).await.ok_or(TomeError::NotFound)?;
+    let path = article.path();
+    archive(article, archived, layout.user.as_deref()).await?;
+    Ok(Redirect::to(&format!("/article/{path}")))
+}
+
+#[derive(Deserialize)]
+pub struct NamespaceArchiveForm {
+    namespace: String,
+    action: String,
+}
+
+pub async fn post_archive_namespace(
+    layout: Layout,
+    Form(form): Form<NamespaceArchiveForm>,
+) -> Result<impl IntoResponse, TomeError> {
+    let archived = parse_action(&form.action)?;
+    let namespace = form.namespace.trim();
+    if namespace.is_empty() {
+        return Err(TomeError::BadRequest("No namespace was given".to_string()));
+    }
+    let prefix = format!("{namespace}{NAMESPACE_SEPARATOR}");
+    let mut changed = 0;
+    for (_, title) in Overview::load().await.articles {
+        if !title.starts_with(&prefix) {
+            continue;
+        }
+        let Some(article) = Article::load(&title).await else {
+            continue;
+        };
+        archive(article, archived, layout.user.as_deref()).await?;
+        changed += 1;
+    }
+    tracing::info!(
+        "{} {changed} articles in {namespace}",
+        if archived { "Archived" } else { "Unarchived" }
+    );
+    let mut location = format!("/overview?namespace={}", urlencoding::encode(namespace));
+    if archived {
+        location.push_str("&archived=true");
+    }
+    Ok(Redirect::to(&location))
+}
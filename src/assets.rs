@@ -0,0 +1,114 @@
+//! Static files embedded into the binary, so tome works without
+//! shipping anything besides the executable and the content directory.
+//!
+//! `/custom.css` is linked after the default styles. It applies the
+//! `accent_color` and `font_family` from the config, followed by
+//! `content/custom.css` if there is one, so the look of the wiki can be
+//! changed without touching tome itself. Pages of namespaces with their
+//! own `accent_color` override it in their `<head>`.
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+
+use crate::config::content_path;
+use crate::layout::Layout;
+use crate::{base_path, NotFound, TomeConfig};
+
+/// Name, content type and content of every file served under `/static/`
+const ASSETS: &[(&str, &str, &str)] = &[
+    (
+        "offline.js",
+        "text/javascript",
+        include_str!("../assets/offline.js"),
+    ),
+    (
+        "annotations.js",
+        "text/javascript",
+        include_str!("../assets/annotations.js"),
+    ),
+    (
+        "previews.js",
+        "text/javascript",
+        include_str!("../assets/previews.js"),
+    ),
+    (
+        "live_preview.js",
+        "text/javascript",
+        include_str!("../assets/live_preview.js"),
+    ),
+    (
+        "pdf_preview.js",
+        "text/javascript",
+        include_str!("../assets/pdf_preview.js"),
+    ),
+    ("tome.css", "text/css", include_str!("../assets/tome.css")),
+    ("rtl.css", "text/css", include_str!("../assets/rtl.css")),
+];
+
+pub async fn get_asset(layout: Layout, Path(name): Path<String>) -> impl IntoResponse {
+    match ASSETS.iter().find(|(asset, _, _)| *asset == name) {
+        Some((_, content_type, content)) => {
+            ([(header::CONTENT_TYPE, *content_type)], *content).into_response()
+        }
+        None => (StatusCode::NOT_FOUND, NotFound { layout }).into_response(),
+    }
+}
+
+/// Styles using the accent color, which Bulma doesn't read from a variable
+const ACCENT_STYLES: &str = "
+.content a:not(.button),
+.navbar-item.is-active,
+.breadcrumb a {
+    color: var(--tome-accent);
+}
+
+.button.is-primary,
+.button.is-link {
+    background-color: var(--tome-accent);
+}
+";
+
+/// Styles giving links and buttons the `accent` color
+pub fn accent_css(accent: &str) -> String {
+    format!(":root {{\n    --tome-accent: {accent};\n}}\n{ACCENT_STYLES}")
+}
+
+pub async fn custom_css(State(config): State<TomeConfig>) -> impl IntoResponse {
+    let mut css = String::new();
+    if let Some(accent) = &config.accent_color {
+        css.push_str(&accent_css(accent));
+    }
+    if let Some(font) = &config.font_family {
+        css.push_str(&format!(
+            "\nbody,\nbutton,\ninput,\ntextarea {{\n    font-family: {font};\n}}\n"
+        ));
+    }
+    if let Ok(custom) = tokio::fs::read_to_string(content_path("custom.css")).await {
+        css.push('\n');
+        css.push_str(&custom);
+    }
+    (
+        [
+            (header::CONTENT_TYPE, "text/css"),
+            // Changes to custom.css should show up right away
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        css,
+    )
+}
+
+/// The service worker has to be served from the root to control every page
+pub async fn service_worker() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/javascript")],
+        include_str!("../assets/sw.js"),
+    )
+}
+
+pub async fn manifest(State(config): State<TomeConfig>) -> impl IntoResponse {
+    let base = format!("\"{}/", base_path::of(&config));
+    (
+        [(header::CONTENT_TYPE, "application/manifest+json")],
+        include_str!("../assets/manifest.webmanifest").replace("\"/", &base),
+    )
+}
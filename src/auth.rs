@@ -0,0 +1,380 @@
+//! # Authentication
+//!
+//! Once there are users, only they can change the wiki while reading stays
+//! public. Users are the administrator from `tome.toml` (`admin_user` and
+//! `admin_password_hash`) and everyone in the `users_file` (`users.toml`
+//! by default), which maps user names to Argon2 password hashes and is
+//! written by `tome user add`. Every wiki has its own, so tenants don't
+//! share users.
+//! Logging in at `/login` starts a session kept in memory, so restarting
+//! tome logs everyone out.
+//!
+//! Every request that changes something (anything but `GET` and `HEAD`)
+//! and every editor and admin page needs a session. The session cookie is
+//! `SameSite=Lax`, so other sites can't send such requests in a user's
+//! name. `POST /api/inbox` has its own token instead. In `demo_mode`,
+//! anyone can edit.
+//!
+//! Behind a proxy that logs users in, like Authelia or oauth2-proxy,
+//! `proxy_user_header` names the header with the user name, e.g.
+//! `Remote-User` or `X-Auth-Request-User`. It is only trusted in requests
+//! from the `trusted_proxies`. With `proxy_editor_groups`, only users in
+//! one of those groups (listed in `proxy_groups_header`, separated by
+//! commas) can edit, everyone else can only read.
+use std::collections::{BTreeMap, HashMap};
+use std::io::BufRead;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use askama::Template;
+use askama_axum::IntoResponse;
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::{header, HeaderMap, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{Redirect, Response};
+use axum::Form;
+use clap::{Args, Subcommand};
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+use crate::layout::Layout;
+use crate::TomeConfig;
+
+/// Where users are read from, unless `users_file` is given
+const USERS_PATH: &str = "users.toml";
+const SESSION_COOKIE: &str = "tome_session";
+const SESSION_LIFETIME: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// The logged in user of a request, added to its extensions
+#[derive(Clone)]
+pub struct Session {
+    pub user: Option<String>,
+    /// Whether changing the wiki requires logging in
+    pub required: bool,
+}
+
+/// Logging in with headers set by a proxy
+struct ProxyAuth {
+    user_header: String,
+    groups_header: Option<String>,
+    /// The groups that can edit, if not everyone can
+    editor_groups: Vec<String>,
+    trusted: Vec<IpAddr>,
+}
+
+#[derive(Clone, Default)]
+pub struct Auth {
+    /// Password hashes by user name
+    users: Arc<HashMap<String, String>>,
+    /// User names and expiry by session token
+    sessions: Arc<RwLock<HashMap<String, (String, Instant)>>>,
+    proxy: Option<Arc<ProxyAuth>>,
+}
+
+/// The file the users of the wiki with `config` are stored in
+pub fn users_path(config: &TomeConfig) -> &str {
+    config.users_file.as_deref().unwrap_or(USERS_PATH)
+}
+
+pub async fn read_users(path: &str) -> color_eyre::Result<BTreeMap<String, String>> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(users) => {
+            toml::from_str(&users).map_err(|e| color_eyre::eyre::eyre!("{path} is invalid: {e}"))
+        }
+        Err(_) => Ok(BTreeMap::new()),
+    }
+}
+
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .unwrap()
+        .to_string()
+}
+
+impl Auth {
+    pub async fn load(config: &TomeConfig) -> color_eyre::Result<Self> {
+        if config.demo_mode {
+            tracing::info!("Anyone can edit the demo");
+            return Ok(Auth::default());
+        }
+        let mut users: HashMap<String, String> =
+            read_users(users_path(config)).await?.into_iter().collect();
+        if let (Some(user), Some(hash)) = (&config.admin_user, &config.admin_password_hash) {
+            users.insert(user.clone(), hash.clone());
+        }
+        if !users.is_empty() {
+            tracing::info!("Only {} users can edit the wiki", users.len());
+        }
+        let proxy = config.proxy_user_header.clone().map(|user_header| {
+            tracing::info!("Users logged in by a proxy are read from {user_header}");
+            Arc::new(ProxyAuth {
+                user_header,
+                groups_header: config.proxy_groups_header.clone(),
+                editor_groups: config.proxy_editor_groups.clone(),
+                trusted: config.trusted_proxies.clone(),
+            })
+        });
+        Ok(Auth {
+            users: Arc::new(users),
+            proxy,
+            ..Default::default()
+        })
+    }
+
+    /// Whether there is a user called `name`
+    pub fn exists(&self, name: &str) -> bool {
+        self.users.contains_key(name)
+    }
+
+    fn is_required(&self) -> bool {
+        !self.users.is_empty() || self.proxy.is_some()
+    }
+
+    /// The user a trusted proxy logged in, if they can edit
+    fn proxy_user<B>(&self, request: &Request<B>) -> Option<String> {
+        let proxy = self.proxy.as_ref()?;
+        let ConnectInfo(peer) = request.extensions().get::<ConnectInfo<SocketAddr>>()?;
+        if !proxy.trusted.contains(&peer.ip()) {
+            return None;
+        }
+        let header = |name: &str| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        let user = header(&proxy.user_header)?;
+        if !proxy.editor_groups.is_empty() {
+            let groups = proxy.groups_header.as_deref().and_then(header)?;
+            if !groups.split(',').any(|group| {
+                proxy
+                    .editor_groups
+                    .iter()
+                    .any(|editor| editor == group.trim())
+            }) {
+                return None;
+            }
+        }
+        Some(user.to_string())
+    }
+
+    fn verify(&self, user: &str, password: &str) -> bool {
+        let Some(hash) = self.users.get(user) else {
+            return false;
+        };
+        let Ok(hash) = PasswordHash::new(hash) else {
+            tracing::error!("The password hash of {user} is invalid");
+            return false;
+        };
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    }
+
+    async fn start_session(&self, user: &str) -> String {
+        let mut bytes = [0; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let token: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+        let mut sessions = self.sessions.write().await;
+        let now = Instant::now();
+        sessions.retain(|_, (_, expires)| *expires > now);
+        sessions.insert(token.clone(), (user.to_string(), now + SESSION_LIFETIME));
+        token
+    }
+
+    async fn user(&self, token: &str) -> Option<String> {
+        match self.sessions.read().await.get(token) {
+            Some((user, expires)) if *expires > Instant::now() => Some(user.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// The session token sent with a request
+fn session_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|cookies| cookies.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .find_map(|cookie| {
+            let (name, value) = cookie.trim().split_once('=')?;
+            (name == SESSION_COOKIE).then(|| value.to_string())
+        })
+}
+
+/// Whether a request changes the wiki or shows a page that does
+fn needs_login(method: &Method, path: &str) -> bool {
+    if path == "/login" || path == "/api/inbox" {
+        return false;
+    }
+    !(method == Method::GET || method == Method::HEAD)
+        || path == "/new"
+        || path.starts_with("/edit/")
+        || path.starts_with("/m/edit/")
+        || path.starts_with("/admin/")
+}
+
+/// Middleware adding the [`Session`] to requests and turning away anonymous edits
+pub async fn authenticate<B>(
+    State(auth): State<Auth>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let user = match session_token(request.headers()) {
+        Some(token) => auth.user(&token).await,
+        None => None,
+    };
+    let user = user.or_else(|| auth.proxy_user(&request));
+    let required = auth.is_required();
+
+    if required && user.is_none() && needs_login(request.method(), request.uri().path()) {
+        if request.method() == Method::GET || request.method() == Method::HEAD {
+            let next = request
+                .uri()
+                .path_and_query()
+                .map(|path| path.as_str())
+                .unwrap_or("/");
+            return Redirect::to(&format!("/login?next={}", urlencoding::encode(next)))
+                .into_response();
+        }
+        return (StatusCode::UNAUTHORIZED, "Log in to change the wiki").into_response();
+    }
+
+    request.extensions_mut().insert(Session { user, required });
+    next.run(request).await
+}
+
+#[derive(Template)]
+#[template(path = "login.html")]
+pub struct Login {
+    layout: Layout,
+    next: String,
+    user: String,
+    failed: bool,
+}
+
+#[derive(Deserialize)]
+pub struct LoginQuery {
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct LoginForm {
+    user: String,
+    password: String,
+    #[serde(default)]
+    next: String,
+}
+
+/// Only redirect to pages of the wiki after logging in
+fn local_path(next: &str) -> &str {
+    if next.starts_with('/') && !next.starts_with("//") && !next.contains('\\') {
+        next
+    } else {
+        "/"
+    }
+}
+
+pub async fn get_login(layout: Layout, Query(query): Query<LoginQuery>) -> impl IntoResponse {
+    Login {
+        layout,
+        next: query.next.unwrap_or_else(|| "/".to_string()),
+        user: String::new(),
+        failed: false,
+    }
+}
+
+pub async fn post_login(
+    layout: Layout,
+    State(auth): State<Auth>,
+    Form(form): Form<LoginForm>,
+) -> impl IntoResponse {
+    if !auth.verify(&form.user, &form.password) {
+        tracing::warn!("Failed login for {}", form.user);
+        return (
+            StatusCode::UNAUTHORIZED,
+            Login {
+                layout,
+                next: form.next,
+                user: form.user,
+                failed: true,
+            },
+        )
+            .into_response();
+    }
+
+    let token = auth.start_session(&form.user).await;
+    let cookie = format!(
+        "{SESSION_COOKIE}={token}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}",
+        SESSION_LIFETIME.as_secs()
+    );
+    (
+        [(header::SET_COOKIE, cookie)],
+        Redirect::to(local_path(&form.next)),
+    )
+        .into_response()
+}
+
+pub async fn post_logout(State(auth): State<Auth>, headers: HeaderMap) -> impl IntoResponse {
+    if let Some(token) = session_token(&headers) {
+        auth.sessions.write().await.remove(&token);
+    }
+    let cookie = format!("{SESSION_COOKIE}=; Path=/; HttpOnly; SameSite=Lax; Max-Age=0");
+    ([(header::SET_COOKIE, cookie)], Redirect::to("/"))
+}
+
+/// Arguments for `tome user`
+#[derive(Args)]
+pub struct UserArgs {
+    #[command(subcommand)]
+    command: UserCommand,
+}
+
+#[derive(Subcommand)]
+enum UserCommand {
+    /// Add a user or change their password, which is read from stdin
+    Add { name: String },
+    /// Remove a user
+    Remove { name: String },
+}
+
+pub async fn write_users(path: &str, users: &BTreeMap<String, String>) -> color_eyre::Result<()> {
+    tokio::fs::write(path, toml::to_string(users)?).await?;
+    Ok(())
+}
+
+pub async fn run(args: UserArgs, config: &TomeConfig) -> color_eyre::Result<()> {
+    let path = users_path(config);
+    let mut users = read_users(path).await?;
+    match args.command {
+        UserCommand::Add { name } => {
+            println!("Password for {name}:");
+            let mut password = String::new();
+            std::io::stdin().lock().read_line(&mut password)?;
+            let password = password.trim_end_matches(['\r', '\n']);
+            if password.is_empty() {
+                return Err(color_eyre::eyre::eyre!("The password can't be empty"));
+            }
+            users.insert(name.clone(), hash_password(password));
+            write_users(path, &users).await?;
+            println!("Saved {name} in {path}, restart tome to let them log in.");
+        }
+        UserCommand::Remove { name } => {
+            if users.remove(&name).is_none() {
+                return Err(color_eyre::eyre::eyre!("There is no user {name} in {path}"));
+            }
+            write_users(path, &users).await?;
+            println!("Removed {name} from {path}.");
+        }
+    }
+    Ok(())
+}
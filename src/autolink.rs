@@ -0,0 +1,156 @@
+//! # Automatic Links
+//!
+//! With `autolink_titles`, the titles of existing articles are linked
+//! wherever an article's text mentions them, so authors don't have to
+//! bracket every cross-reference. `autolink: true` or `autolink: false` in
+//! an article's frontmatter turns this on or off for that article. Titles
+//! match whole words regardless of case, and where titles overlap, the
+//! longest one wins. Only the first mention of each article is linked.
+//! Code, headings and existing links are left alone, and so are the
+//! article itself and articles it already links to. The
+//! [glossary](crate::glossary) finds its terms the same way.
+use std::borrow::Cow;
+use std::ops::Range;
+
+use pulldown_cmark::{Event, Options, Tag};
+
+use crate::filters::{handle_broken_link, linked_articles, wikilinks};
+use crate::frontmatter::{self, Metadata};
+use crate::{Article, TomeConfig};
+
+/// Whether `article` is shown with automatic links
+pub fn enabled(config: &TomeConfig, article: &Article) -> bool {
+    Metadata::from(&frontmatter::parse(&article.content).unwrap_or_default())
+        .autolink
+        .unwrap_or(config.autolink_titles)
+}
+
+/// The text in `markdown` that isn't in code, a heading or a link
+fn plain_text(markdown: &str) -> Vec<Range<usize>> {
+    let mut binding = handle_broken_link;
+    let events = pulldown_cmark::Parser::new_with_broken_link_callback(
+        markdown,
+        Options::all(),
+        Some(&mut binding),
+    );
+    let mut skipped = 0;
+    let mut ranges = vec![];
+    for (event, range) in events.into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading(..) | Tag::Link(..) | Tag::Image(..) | Tag::CodeBlock(_)) => {
+                skipped += 1
+            }
+            Event::End(Tag::Heading(..) | Tag::Link(..) | Tag::Image(..) | Tag::CodeBlock(_)) => {
+                skipped -= 1
+            }
+            // Text with escapes or entities differs from its Markdown
+            Event::Text(text) if skipped == 0 && markdown.get(range.clone()) == Some(&*text) => {
+                ranges.push(range)
+            }
+            _ => {}
+        }
+    }
+    ranges
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Where `terms` are mentioned in the plain text of `markdown` as whole
+/// words, with the index of the term. Where terms overlap, the longest wins.
+pub fn find(markdown: &str, terms: &[String], ignore_case: bool) -> Vec<(Range<usize>, usize)> {
+    let mut candidates: Vec<(String, usize)> = terms
+        .iter()
+        .enumerate()
+        .filter(|(_, term)| !term.trim().is_empty())
+        .map(|(index, term)| match ignore_case {
+            true => (term.to_lowercase(), index),
+            false => (term.clone(), index),
+        })
+        .collect();
+    candidates.sort_by_key(|(term, _)| std::cmp::Reverse(term.chars().count()));
+
+    let mut found = vec![];
+    for text in plain_text(markdown) {
+        let mut position = text.start;
+        while position < text.end {
+            let rest = &markdown[position..text.end];
+            let Some(first) = rest.chars().next() else {
+                break;
+            };
+            let at_word_start = is_word_char(first)
+                && !markdown[..position]
+                    .chars()
+                    .next_back()
+                    .is_some_and(|c| is_word_char(c) || c == '@');
+            let matched = at_word_start
+                .then(|| {
+                    candidates.iter().find(|(term, _)| {
+                        rest.get(..term.len()).is_some_and(|mention| {
+                            let same = match ignore_case {
+                                true => mention.to_lowercase() == *term,
+                                false => mention == term,
+                            };
+                            same && !rest[term.len()..].starts_with(is_word_char)
+                        })
+                    })
+                })
+                .flatten();
+            match matched {
+                Some((term, index)) => {
+                    found.push((position..position + term.len(), *index));
+                    position += term.len();
+                }
+                None => position += first.len_utf8(),
+            }
+        }
+    }
+    found
+}
+
+/// Replaces the `found` ranges of `markdown` with what `replace` makes of their text
+pub fn replace<'a>(
+    markdown: Cow<'a, str>,
+    found: Vec<(Range<usize>, usize)>,
+    replace: impl Fn(&str, usize) -> String,
+) -> Cow<'a, str> {
+    if found.is_empty() {
+        return markdown;
+    }
+    let mut out = String::with_capacity(markdown.len());
+    let mut copied = 0;
+    for (range, index) in found {
+        out.push_str(&markdown[copied..range.start]);
+        out.push_str(&replace(&markdown[range.clone()], index));
+        copied = range.end;
+    }
+    out.push_str(&markdown[copied..]);
+    Cow::Owned(out)
+}
+
+/// Links the first mention of each of the articles in `titles`, given as
+/// slugs and titles, in `markdown`, except for the article at `own`
+pub fn link<'a>(markdown: &'a str, own: &str, titles: &[(String, String)]) -> Cow<'a, str> {
+    let markdown = wikilinks(markdown);
+    let linked = linked_articles(&markdown);
+    let (slugs, titles): (Vec<&str>, Vec<String>) = titles
+        .iter()
+        .filter(|(slug, title)| {
+            slug != own && !linked.contains(slug) && !title.contains(['[', ']'])
+        })
+        .map(|(slug, title)| (slug.as_str(), title.clone()))
+        .unzip();
+
+    let mut linked = vec![false; titles.len()];
+    let found = find(&markdown, &titles, true)
+        .into_iter()
+        .filter(|(_, index)| !std::mem::replace(&mut linked[*index], true))
+        .collect();
+    replace(markdown, found, |mention, index| {
+        format!(
+            "[{mention}](/article/{})",
+            urlencoding::encode(slugs[index])
+        )
+    })
+}
@@ -0,0 +1,84 @@
+//! # Backlinks
+//!
+//! Every article lists the articles linking to it under "What links here".
+//! When the server starts, the links of every article are collected into a
+//! link graph kept in memory, which saving, renaming and deleting articles
+//! update. Links count whether they are Markdown links to `/article/...`
+//! or `[[wikilinks]]`, and links to sections count as links to the article.
+use std::collections::{BTreeSet, HashMap};
+
+use tokio::sync::RwLock;
+
+use crate::filters::linked_articles;
+use crate::slug::slug;
+use crate::{wiki, Article, Overview};
+
+/// The title and linked slugs of every article, by slug
+pub(crate) type Graph = HashMap<String, (String, BTreeSet<String>)>;
+
+/// Collects the links of every article, replacing the graph if it was built before
+pub async fn build() {
+    let mut graph = Graph::new();
+    for (path, _) in Overview::load().await.articles {
+        if let Some(article) = Article::load(&path).await {
+            graph.insert(
+                article.path(),
+                (article.title.clone(), linked_articles(article.body())),
+            );
+        }
+    }
+    let wiki = wiki::current();
+    if let Err(graph) = wiki.links.set(RwLock::new(graph)) {
+        *wiki.links.get().unwrap().write().await = graph.into_inner();
+    }
+}
+
+/// Updates the links of a newly saved version of an article
+pub async fn update(article: &Article) {
+    if let Some(graph) = wiki::current().links.get() {
+        graph.write().await.insert(
+            article.path(),
+            (article.title.clone(), linked_articles(article.body())),
+        );
+    }
+}
+
+/// Forgets the links of an article that no longer exists under `title`
+pub async fn remove(title: &str) {
+    if let Some(graph) = wiki::current().links.get() {
+        graph.write().await.remove(&slug(title));
+    }
+}
+
+/// Slugs and titles of every article
+pub async fn titles() -> Vec<(String, String)> {
+    let wiki = wiki::current();
+    let Some(graph) = wiki.links.get() else {
+        return vec![];
+    };
+    let titles = graph
+        .read()
+        .await
+        .iter()
+        .map(|(slug, (title, _))| (slug.clone(), title.clone()))
+        .collect();
+    titles
+}
+
+/// Slugs and titles of the articles linking to `title`, sorted by title
+pub async fn of(title: &str) -> Vec<(String, String)> {
+    let wiki = wiki::current();
+    let Some(graph) = wiki.links.get() else {
+        return vec![];
+    };
+    let target = slug(title);
+    let mut articles: Vec<(String, String)> = graph
+        .read()
+        .await
+        .iter()
+        .filter(|(source, (_, links))| **source != target && links.contains(&target))
+        .map(|(source, (title, _))| (source.clone(), title.clone()))
+        .collect();
+    articles.sort_by_key(|(_, title)| title.to_lowercase());
+    articles
+}
@@ -0,0 +1,93 @@
+//! # Base Path
+//!
+//! Programs embedding tome can nest it under a path like `/wiki`, which
+//! `Router::nest` takes off requests before tome sees them. With
+//! `base_path = "/wiki"`, tome puts it back in front of the paths it sends:
+//! redirects, and the links, forms, images and script data of HTML pages,
+//! including the links in articles. Scripts read it from the
+//! `data-base-path` of the page, and the service worker is registered
+//! under it. `public_url` has to include it.
+use std::sync::OnceLock;
+
+use axum::body::{Bytes, Full};
+use axum::extract::State;
+use axum::http::{header, HeaderValue, Request};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use regex::{Captures, Regex};
+
+use crate::TomeConfig;
+
+/// The `base_path` of `config`, empty if tome is served at the root
+pub fn of(config: &TomeConfig) -> &str {
+    config
+        .base_path
+        .as_deref()
+        .unwrap_or_default()
+        .trim_end_matches('/')
+}
+
+/// `path` of the wiki, as it is reached from outside
+pub fn prefixed(config: &TomeConfig, path: &str) -> String {
+    match path.starts_with('/') && !path.starts_with("//") {
+        true => format!("{}{path}", of(config)),
+        false => path.to_string(),
+    }
+}
+
+/// Attributes with paths of the wiki, which start with a single slash
+fn path_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(
+            r#"(\s(?:href|src|action|data-preview|data-pdf|data-pdf-thumbnail|data-annotations)=")(/[^/]|/")"#,
+        )
+        .unwrap()
+    })
+}
+
+/// `html` with `base` in front of the paths of the wiki in its attributes
+fn prefix_html(html: &str, base: &str) -> String {
+    path_pattern()
+        .replace_all(html, |captures: &Captures| {
+            format!("{}{base}{}", &captures[1], &captures[2])
+        })
+        .into_owned()
+}
+
+/// Middleware putting the `base_path` in front of the paths in responses
+pub async fn prefix<B>(
+    State(config): State<TomeConfig>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let mut response = next.run(request).await;
+    if let Some(location) = response
+        .headers()
+        .get(header::LOCATION)
+        .and_then(|location| location.to_str().ok())
+    {
+        if let Ok(location) = HeaderValue::try_from(prefixed(&config, location)) {
+            response.headers_mut().insert(header::LOCATION, location);
+        }
+    }
+    let is_html = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("text/html"));
+    if !is_html {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("Could not read the page to prefix its paths: {e}");
+            return parts.into_response();
+        }
+    };
+    let html = prefix_html(&String::from_utf8_lossy(&body), of(&config));
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Full::new(Bytes::from(html))).into_response()
+}
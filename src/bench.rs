@@ -0,0 +1,142 @@
+//! # Benchmarks
+//!
+//! `tome bench` times what article pages spend most of their time on with
+//! the wiki's own content: rendering every article, loading the overview
+//! and listing the history of every article, each `--runs` times. It
+//! reports the median and the slowest run, and fails if rendering any
+//! article takes longer than the `--budget` in milliseconds, so slow
+//! articles can be found before and after changes like caching. The
+//! criterion benchmarks in `benches/` time the same operations on
+//! generated content with the functions of this module.
+use std::time::{Duration, Instant};
+
+use clap::Args;
+
+use crate::storage::article_slugs;
+use crate::{filters, version_info, Article, Overview, TomeConfig};
+
+/// Arguments for `tome bench`
+#[derive(Args)]
+pub struct BenchArgs {
+    /// How often every operation is timed
+    #[arg(long, default_value_t = 10)]
+    runs: usize,
+    /// The longest an article may take to render, in milliseconds
+    #[arg(long, default_value_t = 50)]
+    budget: u64,
+}
+
+/// Renders `markdown` with the filter article pages use
+pub fn custom_md(markdown: &str) -> String {
+    filters::custom_md(markdown).unwrap().to_string()
+}
+
+/// Renders `article` as its page shows it, without the render cache
+pub async fn render_article(config: &TomeConfig, article: &Article) -> String {
+    article.shown_html(config).await
+}
+
+/// Loads the overview like `/overview` does, returning the number of articles
+pub async fn overview() -> usize {
+    Overview::load().await.articles.len()
+}
+
+/// Lists the versions of `title` like its history page does, returning how many there are
+pub async fn history(title: &str) -> usize {
+    let versions = Article::get_versions(title).await;
+    for (version, _) in &versions {
+        version_info::read(title, version).await;
+    }
+    versions.len()
+}
+
+/// The median and the slowest of some timings
+struct Timings {
+    median: Duration,
+    slowest: Duration,
+}
+
+impl Timings {
+    fn of(mut durations: Vec<Duration>) -> Self {
+        durations.sort();
+        Timings {
+            median: durations
+                .get(durations.len() / 2)
+                .copied()
+                .unwrap_or_default(),
+            slowest: durations.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+fn report(operation: &str, timings: &Timings, slowest: Option<&str>) {
+    let of = slowest.map_or(String::new(), |title| format!(" ({title})"));
+    println!(
+        "{operation:<10} median {:>10.3?}  slowest {:>10.3?}{of}",
+        timings.median, timings.slowest
+    );
+}
+
+pub async fn run(args: BenchArgs, config: &TomeConfig) -> color_eyre::Result<()> {
+    let runs = args.runs.max(1);
+    let budget = Duration::from_millis(args.budget);
+    let mut articles = vec![];
+    for slug in article_slugs().await {
+        if let Some(article) = Article::load(&slug).await {
+            articles.push(article);
+        }
+    }
+    println!("Timing {} articles, {runs} runs each", articles.len());
+
+    let mut render = vec![];
+    let mut slowest_render: Option<(Duration, &str)> = None;
+    let mut over_budget = vec![];
+    for article in &articles {
+        let mut durations = vec![];
+        for _ in 0..runs {
+            let start = Instant::now();
+            render_article(config, article).await;
+            durations.push(start.elapsed());
+        }
+        let median = Timings::of(durations.clone()).median;
+        if median > budget {
+            over_budget.push(format!("{} ({median:.3?})", article.title));
+        }
+        if slowest_render.is_none_or(|(slowest, _)| median > slowest) {
+            slowest_render = Some((median, &article.title));
+        }
+        render.extend(durations);
+    }
+    report(
+        "render",
+        &Timings::of(render),
+        slowest_render.map(|(_, title)| title),
+    );
+
+    let mut durations = vec![];
+    for _ in 0..runs {
+        let start = Instant::now();
+        overview().await;
+        durations.push(start.elapsed());
+    }
+    report("overview", &Timings::of(durations), None);
+
+    let mut durations = vec![];
+    for article in &articles {
+        for _ in 0..runs {
+            let start = Instant::now();
+            history(&article.title).await;
+            durations.push(start.elapsed());
+        }
+    }
+    report("history", &Timings::of(durations), None);
+
+    if !over_budget.is_empty() {
+        return Err(color_eyre::eyre::eyre!(
+            "{} articles take longer than {budget:?} to render: {}",
+            over_budget.len(),
+            over_budget.join(", ")
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+//! # Conditional Requests
+//!
+//! Article pages, the index page and media files get an `ETag`, so
+//! browsers that already have them get a `304 Not Modified` instead of the
+//! whole page or file again. Pages are tagged with a hash of their HTML,
+//! which also changes with the backlinks or the login shown on them, and
+//! are revalidated every time (`Cache-Control: private, no-cache`). Media
+//! files are tagged by their size and modification time and can be kept
+//! for `media_cache_seconds`, 5 minutes unless it is set, before browsers
+//! ask again. `If-Modified-Since` works for media files as well.
+use axum::body::{boxed, Body, Full};
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use sha2::{Digest, Sha256};
+
+use crate::TomeConfig;
+
+const DEFAULT_MEDIA_CACHE_SECONDS: u64 = 300;
+
+fn short_hash(data: &[u8]) -> String {
+    Sha256::digest(data)[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// The ETag of a file served with its modification time and size, which
+/// is weak because the time is only precise to the second
+fn file_etag(headers: &HeaderMap) -> Option<String> {
+    let modified = headers.get(header::LAST_MODIFIED)?.as_bytes();
+    let length = headers.get(header::CONTENT_LENGTH)?.as_bytes();
+    // Variants of an image in other formats have the same time
+    let kind = headers
+        .get(header::CONTENT_TYPE)
+        .map_or(&b""[..], HeaderValue::as_bytes);
+    Some(format!(
+        "W/\"{}\"",
+        short_hash(&[modified, length, kind].join(&b'|'))
+    ))
+}
+
+/// Whether `If-None-Match` lists `etag`, comparing weakly like a `GET` should
+fn matches(if_none_match: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    if_none_match.split(',').any(|tag| {
+        let tag = tag.trim();
+        tag == "*" || tag.trim_start_matches("W/") == etag
+    })
+}
+
+fn not_modified(etag: HeaderValue, cache_control: HeaderValue) -> Response {
+    (
+        StatusCode::NOT_MODIFIED,
+        [(header::ETAG, etag), (header::CACHE_CONTROL, cache_control)],
+    )
+        .into_response()
+}
+
+/// Tags media files and answers requests for files the browser already has
+pub async fn media(
+    State(config): State<TomeConfig>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let if_none_match = request.headers().get(header::IF_NONE_MATCH).cloned();
+    let mut response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    let Some(etag) = file_etag(response.headers()) else {
+        return response;
+    };
+    let seconds = config
+        .media_cache_seconds
+        .unwrap_or(DEFAULT_MEDIA_CACHE_SECONDS);
+    let cache_control = HeaderValue::from_str(&format!("max-age={seconds}")).unwrap();
+    let etag = HeaderValue::from_str(&etag).unwrap();
+    if let Some(if_none_match) = if_none_match.as_ref().and_then(|tags| tags.to_str().ok()) {
+        if matches(if_none_match, etag.to_str().unwrap()) {
+            let mut not_modified = not_modified(etag, cache_control);
+            if let Some(vary) = response.headers().get(header::VARY) {
+                not_modified
+                    .headers_mut()
+                    .insert(header::VARY, vary.clone());
+            }
+            return not_modified;
+        }
+    }
+    let headers = response.headers_mut();
+    headers.insert(header::ETAG, etag);
+    headers.insert(header::CACHE_CONTROL, cache_control);
+    response
+}
+
+/// Tags pages with a hash of their HTML and answers requests for pages
+/// the browser already has
+pub async fn page(request: Request<Body>, next: Next<Body>) -> Response {
+    if request.method() != Method::GET {
+        return next.run(request).await;
+    }
+    let if_none_match = request.headers().get(header::IF_NONE_MATCH).cloned();
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let Ok(html) = hyper::body::to_bytes(body).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let etag = HeaderValue::from_str(&format!("\"{}\"", short_hash(&html))).unwrap();
+    let cache_control = HeaderValue::from_static("private, no-cache");
+    if let Some(if_none_match) = if_none_match.as_ref().and_then(|tags| tags.to_str().ok()) {
+        if matches(if_none_match, etag.to_str().unwrap()) {
+            return not_modified(etag, cache_control);
+        }
+    }
+    parts.headers.insert(header::ETAG, etag);
+    parts.headers.insert(header::CACHE_CONTROL, cache_control);
+    Response::from_parts(parts, boxed(Full::from(html)))
+}
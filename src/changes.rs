@@ -0,0 +1,98 @@
+//! # Recent Changes
+//!
+//! `/changes` lists the latest saved versions of all articles, newest
+//! first, read from the history of every article. It is paged like the
+//! history of a single article, `history_page_size` versions per page.
+//! The same changes are available as Atom feeds, see [`crate::feed`].
+use std::time::SystemTime;
+
+use askama::Template;
+use askama_axum::IntoResponse;
+use axum::extract::{Query, State};
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+use crate::layout::Layout;
+use crate::storage::{article_slugs, storage};
+use crate::{Article, TomeConfig};
+
+/// A saved version of an article
+pub struct Change {
+    pub slug: String,
+    /// The current title of the article
+    pub title: String,
+    pub version: String,
+    pub saved: SystemTime,
+}
+
+/// Every saved version of `article`, newest first
+pub async fn of_article(article: &Article) -> Vec<Change> {
+    let mut changes: Vec<Change> = storage()
+        .versions(&article.title)
+        .await
+        .into_iter()
+        .map(|(version, saved)| Change {
+            slug: article.path(),
+            title: article.title.clone(),
+            version,
+            saved,
+        })
+        .collect();
+    changes.sort_by_key(|change| std::cmp::Reverse(change.saved));
+    changes
+}
+
+/// Every saved version of every article, newest first
+pub async fn recent() -> Vec<Change> {
+    let mut changes = vec![];
+    for slug in article_slugs().await {
+        if let Some(article) = Article::load(&slug).await {
+            changes.extend(of_article(&article).await);
+        }
+    }
+    changes.sort_by_key(|change| std::cmp::Reverse(change.saved));
+    changes
+}
+
+#[derive(Deserialize)]
+pub struct ChangesQuery {
+    page: Option<usize>,
+}
+
+#[derive(Template)]
+#[template(path = "changes.html")]
+pub struct Changes {
+    layout: Layout,
+    /// Slug, title, version and time of each change
+    changes: Vec<(String, String, String, String)>,
+    page: usize,
+    pages: usize,
+}
+
+pub async fn get_changes(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    Query(query): Query<ChangesQuery>,
+) -> impl IntoResponse {
+    let changes = recent().await;
+    let page_size = config.history_page_size.unwrap_or(50).max(1);
+    let pages = changes.len().div_ceil(page_size).max(1);
+    let page = query.page.unwrap_or(1).clamp(1, pages);
+
+    Changes {
+        layout,
+        changes: changes
+            .into_iter()
+            .skip((page - 1) * page_size)
+            .take(page_size)
+            .map(|change| {
+                let saved = OffsetDateTime::from(change.saved)
+                    .format(&time::format_description::well_known::Rfc2822)
+                    .unwrap();
+                (change.slug, change.title, change.version, saved)
+            })
+            .collect(),
+        page,
+        pages,
+    }
+}
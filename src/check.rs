@@ -0,0 +1,73 @@
+//! # Content Check
+//!
+//! `tome check` looks through the content directory for damage that the
+//! wiki itself wouldn't report: articles whose frontmatter can't be read,
+//! versions that are listed but can't be loaded (e.g. after copying only
+//! part of a backup) and media files that articles use but that don't
+//! exist. It lists every problem and fails if there are any, so it can run
+//! after restoring a backup or in a cron job.
+use std::path::Path;
+
+use crate::config::content_path;
+use crate::storage::article_slugs;
+use crate::{frontmatter, media_usage, Article, TomeConfig};
+
+/// Whether `content` has frontmatter that isn't a valid mapping
+fn has_broken_frontmatter(content: &str) -> bool {
+    frontmatter::split(content)
+        .0
+        .is_some_and(|_| frontmatter::parse(content).is_err())
+}
+
+/// Every problem with the content, as a sentence
+pub async fn problems(config: &TomeConfig) -> Vec<String> {
+    let mut problems = vec![];
+    for slug in article_slugs().await {
+        let Some(article) = Article::load(&slug).await else {
+            problems.push(format!("{slug} has no current version"));
+            continue;
+        };
+        if has_broken_frontmatter(&article.content) {
+            problems.push(format!(
+                "{} has frontmatter that can't be read",
+                article.title
+            ));
+        }
+        for (version, _) in Article::get_versions(&article.title).await {
+            if Article::load_version(&article.title, &version)
+                .await
+                .is_none()
+            {
+                problems.push(format!(
+                    "Version {version} of {} can't be loaded",
+                    article.title
+                ));
+            }
+        }
+    }
+
+    for (name, articles) in media_usage::index(config).await {
+        if Path::new(&content_path(&format!("media/{name}"))).exists() || articles.is_empty() {
+            continue;
+        }
+        let articles: Vec<String> = articles.into_iter().collect();
+        problems.push(format!(
+            "The media file {name} doesn't exist, but {} uses it",
+            articles.join(", ")
+        ));
+    }
+    problems
+}
+
+pub async fn run(config: &TomeConfig) -> color_eyre::Result<()> {
+    let problems = problems(config).await;
+    if problems.is_empty() {
+        println!("No problems found.");
+        return Ok(());
+    }
+    Err(color_eyre::eyre::eyre!(
+        "Found {} problems in the content:\n{}",
+        problems.len(),
+        problems.join("\n")
+    ))
+}
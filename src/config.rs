@@ -0,0 +1,648 @@
+//! # Configuration
+//!
+//! The configuration is read from `tome.toml`, options given on the
+//! command line take precedence over it. After reading it, tome checks it
+//! for mistakes: keys it doesn't know (usually typos, which would
+//! otherwise be ignored), values that can't work and options that
+//! contradict each other. Errors keep tome from starting, warnings are
+//! only logged. `tome config check` lists all of them, and
+//! `tome config init` writes a `tome.toml` listing every option with its
+//! documentation.
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
+
+use clap::{Args, CommandFactory, Subcommand};
+use figment::providers::{Format, Serialized, Toml};
+use figment::{Figment, Provider};
+
+use crate::media::{Role, UploadPolicy};
+use crate::namespace::NamespaceDefaults;
+use crate::sampling::RouteSampling;
+use crate::{wiki, TomeConfig};
+
+/// Where the configuration is read from, unless `--config-file` is given
+pub const CONFIG_PATH: &str = "tome.toml";
+const DEFAULT_CONTENT_DIR: &str = "content";
+/// axum rejects larger request bodies, so articles can't get bigger than this
+const REQUEST_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Arguments for `tome config`
+#[derive(Args)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    command: ConfigCommand,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Check the configuration for mistakes
+    Check,
+    /// Write a tome.toml documenting every option
+    Init {
+        /// Replace an existing tome.toml
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// Mistakes found in the configuration
+#[derive(Default)]
+pub struct Problems {
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+/// The number of single character edits turning `a` into `b`
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous + usize::from(a != *b);
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Keys in the configuration file at `path` that aren't configuration options
+fn unknown_keys(path: &Path, problems: &mut Problems) {
+    let known: Vec<String> = match serde_json::to_value(TomeConfig::default()) {
+        Ok(serde_json::Value::Object(options)) => options.keys().cloned().collect(),
+        _ => return,
+    };
+    let Ok(data) = Toml::file(path).data() else {
+        return;
+    };
+    for key in data.values().flat_map(|dict| dict.keys()) {
+        if known.contains(key) {
+            continue;
+        }
+        let suggestion = known
+            .iter()
+            .min_by_key(|option| distance(key, option))
+            .filter(|option| distance(key, option) <= 3);
+        problems.errors.push(match suggestion {
+            Some(option) => {
+                format!(
+                    "{}: unknown option `{key}`, did you mean `{option}`?",
+                    path.display()
+                )
+            }
+            None => format!("{}: unknown option `{key}`", path.display()),
+        });
+    }
+}
+
+/// Checks the file endings `option` allows uploading
+fn check_endings(option: &str, endings: &[String], problems: &mut Problems) {
+    for ending in endings {
+        if ending.is_empty() {
+            problems.errors.push(format!(
+                "{option} contains an empty ending, which would allow uploading any file"
+            ));
+        } else if ending.contains(['/', '\\']) {
+            problems.errors.push(format!(
+                "{option}: `{ending}` should be a file ending like `.png`, not a path"
+            ));
+        }
+    }
+}
+
+/// Checks the values of `config`
+fn check_values(config: &TomeConfig, problems: &mut Problems) {
+    match config.port {
+        Some(0) => problems
+            .errors
+            .push("port must be between 1 and 65535".to_string()),
+        Some(port) if port < 1024 => problems.warnings.push(format!(
+            "port {port} is below 1024, which usually requires running as root"
+        )),
+        _ => {}
+    }
+    if config.content_dir.as_deref() == Some("") {
+        problems
+            .errors
+            .push("content_dir can't be empty, leave it out to use content".to_string());
+    }
+    if config.history_page_size == Some(0) {
+        problems
+            .errors
+            .push("history_page_size must be at least 1".to_string());
+    }
+    match config.max_article_size {
+        Some(0) => problems
+            .errors
+            .push("max_article_size must be at least 1 byte".to_string()),
+        Some(size) if size > REQUEST_LIMIT => problems.warnings.push(format!(
+            "max_article_size is {size} bytes, but requests larger than {REQUEST_LIMIT} bytes are rejected anyway"
+        )),
+        _ => {}
+    }
+    if config.max_upload_size == Some(0) {
+        problems
+            .errors
+            .push("max_upload_size must be at least 1 byte".to_string());
+    }
+    if config.thumbnail_sizes.contains(&0) {
+        problems
+            .errors
+            .push("thumbnail_sizes must be at least 1 pixel".to_string());
+    }
+    let supported = crate::image_variants::supported();
+    for variant in &config.image_variants {
+        if !supported
+            .iter()
+            .any(|(name, _)| variant.eq_ignore_ascii_case(name))
+        {
+            let names: Vec<&str> = supported.iter().map(|(name, _)| *name).collect();
+            problems.errors.push(format!(
+                "image_variants: {variant} isn't supported, only {}{}",
+                names.join(", "),
+                if cfg!(feature = "avif") {
+                    ""
+                } else {
+                    " (and avif with the avif feature)"
+                }
+            ));
+        }
+    }
+    let rates = config
+        .trace_sample_rate
+        .map(|rate| ("trace_sample_rate".to_string(), rate));
+    let route_rates = config
+        .trace_sampling
+        .iter()
+        .map(|sampling| (format!("trace_sampling.{}", sampling.route), sampling.rate));
+    for (option, rate) in rates.into_iter().chain(route_rates) {
+        if !(0.0..=1.0).contains(&rate) {
+            problems
+                .errors
+                .push(format!("{option}: {rate} isn't a share between 0 and 1"));
+        }
+    }
+    if let Some(dir) = &config.pdfjs_dir {
+        for file in crate::pdf_preview::PDFJS_FILES {
+            if !std::path::Path::new(dir).join(file).exists() {
+                problems
+                    .warnings
+                    .push(format!("pdfjs_dir: {dir} has no {file}"));
+            }
+        }
+    }
+    let max_upload_size = crate::media::max_upload_size(config);
+    check_endings("allowed_uploads", &config.allowed_uploads, problems);
+    for policy in &config.upload_policies {
+        let option = format!("upload_policies.{}", policy.name);
+        if policy.endings.is_empty() {
+            problems
+                .warnings
+                .push(format!("{option} has no endings, so it allows nothing"));
+        }
+        check_endings(&option, &policy.endings, problems);
+        match policy.max_size {
+            Some(0) => problems
+                .errors
+                .push(format!("{option}: max_size must be at least 1 byte")),
+            Some(size) if size > max_upload_size => problems.warnings.push(format!(
+                "{option}: max_size is {size} bytes, but uploads larger than max_upload_size ({max_upload_size} bytes) are rejected anyway"
+            )),
+            _ => {}
+        }
+    }
+    if config
+        .upload_policies
+        .iter()
+        .any(|policy| policy.role == Role::Admin)
+        && config.admin_user.is_none()
+    {
+        problems.warnings.push(
+            "upload_policies only lets the admin upload some files, but there is no admin_user"
+                .to_string(),
+        );
+    }
+    for namespace in &config.namespaces {
+        if crate::slug::slug(&namespace.name).is_empty() {
+            problems
+                .errors
+                .push("namespaces: every namespace needs a name".to_string());
+        }
+    }
+    if config.inbox_article.is_some() && config.inbox_token.is_none() {
+        problems.warnings.push(
+            "inbox_article is set, but the inbox is disabled without an inbox_token".to_string(),
+        );
+    }
+    if config.inbox_token.as_deref() == Some("") {
+        problems
+            .errors
+            .push("inbox_token is empty, anyone could add to the inbox".to_string());
+    }
+    let namespace_accents = config.namespaces.iter().map(|namespace| {
+        (
+            format!("namespaces.{}.accent_color", namespace.name),
+            &namespace.accent_color,
+        )
+    });
+    for (option, value) in [
+        ("accent_color".to_string(), &config.accent_color),
+        ("font_family".to_string(), &config.font_family),
+    ]
+    .into_iter()
+    .chain(namespace_accents)
+    {
+        if value
+            .as_deref()
+            .is_some_and(|value| value.contains(['{', '}', ';', '<', '>']))
+        {
+            problems.errors.push(format!(
+                "{option} should be a single CSS value, without any of {{ }} ; < >"
+            ));
+        }
+    }
+    for window in &config.freeze_windows {
+        if crate::freeze::parse_window(window).is_none() {
+            problems.errors.push(format!(
+                "freeze_windows: `{window}` should be a start and a later end in RFC 3339, separated by /"
+            ));
+        }
+    }
+    if config.proxy_user_header.is_some() && config.trusted_proxies.is_empty() {
+        problems.errors.push(
+            "proxy_user_header is set, but no trusted_proxies are allowed to send it".to_string(),
+        );
+    }
+    if !config.proxy_editor_groups.is_empty() && config.proxy_groups_header.is_none() {
+        problems.warnings.push(
+            "proxy_editor_groups is set, but nobody can edit without proxy_groups_header"
+                .to_string(),
+        );
+    }
+    if config.trusted_html
+        && !(config.allowed_html_tags.is_empty() && config.allowed_html_attributes.is_empty())
+    {
+        problems.warnings.push(
+            "allowed_html_tags and allowed_html_attributes have no effect with trusted_html"
+                .to_string(),
+        );
+    }
+    for tag in &config.allowed_html_tags {
+        if crate::lint::DISALLOWED_ELEMENTS.contains(&tag.to_lowercase().as_str()) {
+            problems.warnings.push(format!(
+                "allowed_html_tags: <{tag}> lets editors change pages in ways that aren't safe, use trusted_html if that is intended"
+            ));
+        }
+    }
+    for attribute in &config.allowed_html_attributes {
+        if attribute.to_lowercase().starts_with("on") {
+            problems.warnings.push(format!(
+                "allowed_html_attributes: {attribute} lets editors run scripts, use trusted_html if that is intended"
+            ));
+        }
+    }
+    if config.git_remote.is_some() && !config.git_storage {
+        problems
+            .warnings
+            .push("git_remote is set, but nothing is pushed without git_storage".to_string());
+    }
+    match (&config.admin_user, &config.admin_password_hash) {
+        (Some(_), None) => problems
+            .warnings
+            .push("admin_user is set, but admin_password_hash is missing".to_string()),
+        (None, Some(_)) => problems
+            .warnings
+            .push("admin_password_hash is set, but admin_user is missing".to_string()),
+        (_, Some(hash)) if argon2::PasswordHash::new(hash).is_err() => problems.errors.push(
+            "admin_password_hash isn't a password hash, run the setup again or hash it with Argon2"
+                .to_string(),
+        ),
+        _ => {}
+    }
+    if let Some(base_path) = &config.base_path {
+        if !base_path.starts_with('/') || base_path.starts_with("//") {
+            problems.errors.push(format!(
+                "base_path should be a path like /wiki, not `{base_path}`"
+            ));
+        }
+    }
+    if config.storage_quota == Some(0) {
+        problems
+            .errors
+            .push("storage_quota must be at least 1 byte".to_string());
+    }
+    match (&config.tenants_dir, &config.tenant_domain) {
+        (Some(_), None) => problems.errors.push(
+            "tenants_dir is set, but tenants can't be reached without a tenant_domain".to_string(),
+        ),
+        (None, Some(_)) => problems
+            .warnings
+            .push("tenant_domain is set, but there are no tenants without tenants_dir".to_string()),
+        _ => {}
+    }
+    #[cfg(feature = "pandoc")]
+    if let Some(pandoc) = &config.pandoc_path {
+        if !std::path::Path::new(pandoc).exists() {
+            problems
+                .warnings
+                .push(format!("pandoc_path: {pandoc} doesn't exist"));
+        }
+    }
+}
+
+/// Checks `config`, read from the file at `path`, for mistakes
+pub fn check(path: &Path, config: &TomeConfig) -> Problems {
+    let mut problems = Problems::default();
+    unknown_keys(path, &mut problems);
+    check_values(config, &mut problems);
+    problems
+}
+
+/// The options in `arguments` that were given, rather than left at their default
+///
+/// Flags can only be turned on and options only set on the command line,
+/// so whatever isn't the default was given.
+fn given(arguments: &TomeConfig) -> serde_json::Map<String, serde_json::Value> {
+    let (Ok(serde_json::Value::Object(mut given)), Ok(serde_json::Value::Object(defaults))) = (
+        serde_json::to_value(arguments),
+        serde_json::to_value(TomeConfig::default()),
+    ) else {
+        unreachable!("the configuration is a struct")
+    };
+    given.retain(|key, value| defaults.get(key) != Some(value));
+    given
+}
+
+/// The configuration in the file at `path`, with the options given in `arguments` instead
+fn extract(path: &Path, arguments: TomeConfig) -> color_eyre::Result<TomeConfig> {
+    Figment::new()
+        .merge(Toml::file(path))
+        .merge(Serialized::defaults(given(&arguments)))
+        .join(Serialized::defaults(arguments))
+        .extract()
+        .map_err(|e| color_eyre::eyre::eyre!("{} is invalid: {e}", path.display()))
+}
+
+/// Reads the configuration from the file at `path` and the command line `arguments`
+pub fn load(path: &Path, arguments: TomeConfig) -> color_eyre::Result<TomeConfig> {
+    let config = extract(path, arguments)?;
+
+    let problems = check(path, &config);
+    for warning in &problems.warnings {
+        tracing::warn!("{warning}");
+    }
+    if !problems.errors.is_empty() {
+        return Err(color_eyre::eyre::eyre!(
+            "The configuration has mistakes, see `tome config check`:\n{}",
+            problems.errors.join("\n")
+        ));
+    }
+    Ok(config)
+}
+
+/// Every option with its default, or an example value if it has none
+///
+/// This doesn't use `..Default::default()` so that new options can't be
+/// forgotten here.
+fn example() -> TomeConfig {
+    TomeConfig {
+        site_name: Some("Tome".to_string()),
+        host: Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        port: Some(5422),
+        allowed_uploads: vec![".png".to_string(), ".jpg".to_string()],
+        namespaces: vec![NamespaceDefaults {
+            name: "Team".to_string(),
+            template: Some("Meeting notes".to_string()),
+            tags: vec!["team".to_string()],
+            requires_review: Some(false),
+            accent_color: Some("#2a7ab0".to_string()),
+        }],
+        upload_policies: vec![
+            UploadPolicy {
+                name: "images".to_string(),
+                endings: vec![".png".to_string(), ".jpg".to_string(), ".gif".to_string()],
+                max_size: Some(1024 * 1024),
+                role: Role::Editor,
+            },
+            UploadPolicy {
+                name: "documents".to_string(),
+                endings: vec![".pdf".to_string()],
+                max_size: None,
+                role: Role::Admin,
+            },
+        ],
+        thumbnail_sizes: vec![200, 400],
+        image_variants: vec!["webp".to_string()],
+        hotlink_protection: false,
+        hotlink_allowed_hosts: vec!["*.example.com".to_string()],
+        hotlink_token: Some("a long random secret".to_string()),
+        pdfjs_dir: Some("/usr/share/pdfjs-dist/build".to_string()),
+        media_trash_days: Some(30),
+        media_cache_seconds: Some(300),
+        max_upload_size: Some(10 * 1024 * 1024),
+        fix_upload_endings: false,
+        content_dir: Some(DEFAULT_CONTENT_DIR.to_string()),
+        accent_color: Some("#8c4799".to_string()),
+        font_family: Some("Georgia, serif".to_string()),
+        license: Some("CC BY-SA 4.0".to_string()),
+        public_url: Some("https://wiki.example.com".to_string()),
+        base_path: Some("/wiki".to_string()),
+        export_header: Some("{site_name}: {title}".to_string()),
+        export_footer: Some("{url}, exported on {date}. Licensed under CC BY-SA 4.0.".to_string()),
+        custom_head_html: Some(r#"<link rel="stylesheet" href="/media/custom.css">"#.to_string()),
+        custom_footer_html: Some("<p>Hosted by us</p>".to_string()),
+        analytics: false,
+        metrics: false,
+        trace_sample_rate: Some(0.01),
+        trace_sampling: vec![RouteSampling {
+            route: "/article/:id".to_string(),
+            rate: 0.1,
+        }],
+        #[cfg(feature = "pandoc")]
+        pandoc_path: Some("pandoc".to_string()),
+        inbox_token: Some("a long random secret".to_string()),
+        inbox_article: Some("Inbox".to_string()),
+        stale_command: Some("notify-send Stale".to_string()),
+        sign_versions: false,
+        append_only_history: false,
+        history_page_size: Some(50),
+        autolink_titles: false,
+        glossary_article: Some("Glossary".to_string()),
+        trusted_html: false,
+        allowed_html_tags: vec!["video".to_string()],
+        allowed_html_attributes: vec!["style".to_string()],
+        require_alt_text: false,
+        lint_blocking: false,
+        max_article_size: Some(1024 * 1024),
+        git_storage: false,
+        git_remote: Some("origin".to_string()),
+        demo_mode: false,
+        demo_reset_minutes: Some(60),
+        freeze_windows: vec!["2024-06-01T00:00:00Z/2024-06-03T00:00:00Z".to_string()],
+        proxy_user_header: Some("Remote-User".to_string()),
+        proxy_groups_header: Some("Remote-Groups".to_string()),
+        proxy_editor_groups: vec!["editors".to_string()],
+        trusted_proxies: vec![IpAddr::V4(Ipv4Addr::LOCALHOST)],
+        admin_user: Some("admin".to_string()),
+        admin_password_hash: Some(
+            "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHRzYWx0$ZmMcww0DbYDkEX6lvd8Ue5bh5lzGH1PTelsVZCxLg4M"
+                .to_string(),
+        ),
+        users_file: Some("users.toml".to_string()),
+        storage_quota: Some(1024 * 1024 * 1024),
+        tenants_dir: Some("tenants".to_string()),
+        tenant_domain: Some("wiki.example.com".to_string()),
+    }
+}
+
+/// Parses the content of an inline TOML table like `name = "images"`, for
+/// options given on the command line
+pub fn parse_table<T: serde::de::DeserializeOwned>(table: &str) -> Result<T, String> {
+    let table: toml::Value = format!("table = {{ {table} }}")
+        .parse()
+        .map_err(|e: toml::de::Error| e.to_string())?;
+    table["table"]
+        .clone()
+        .try_into()
+        .map_err(|e: toml::de::Error| e.to_string())
+}
+
+/// `value` written as TOML on a single line, with tables inline
+fn inline_toml(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Array(values) => {
+            let values: Vec<String> = values.iter().map(inline_toml).collect();
+            format!("[{}]", values.join(", "))
+        }
+        serde_json::Value::Object(table) => {
+            let entries: Vec<String> = table
+                .iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| format!("{key} = {}", inline_toml(value)))
+                .collect();
+            format!("{{ {} }}", entries.join(", "))
+        }
+        // JSON strings, numbers and booleans are valid TOML
+        value => value.to_string(),
+    }
+}
+
+/// A `tome.toml` with every option commented out, documented by its help text
+fn template() -> String {
+    let values = match serde_json::to_value(example()) {
+        Ok(serde_json::Value::Object(values)) => values,
+        _ => unreachable!("the configuration is a struct"),
+    };
+
+    let mut template = String::from(
+        "# The configuration of tome, with every option and its default value.\n\
+         # Options without a default show an example instead.\n\
+         # Options on the command line take precedence over this file.\n",
+    );
+    for arg in TomeConfig::command().get_arguments() {
+        let key = arg.get_id().as_str();
+        let Some(value) = values.get(key) else {
+            continue;
+        };
+        template.push('\n');
+        if let Some(help) = arg.get_long_help().or(arg.get_help()) {
+            for line in help.to_string().lines() {
+                template.push_str(&format!("# {line}\n").replace("# \n", "#\n"));
+            }
+        }
+        template.push_str(&format!("# {key} = {}\n", inline_toml(value)));
+    }
+    template
+}
+
+/// Sets options in the configuration file at `path`, keeping the others
+///
+/// Comments in an existing file are lost.
+pub async fn update(path: &Path, options: toml::value::Table) -> color_eyre::Result<()> {
+    let mut table = match tokio::fs::read_to_string(path).await {
+        Ok(existing) => toml::from_str(&existing)
+            .map_err(|e| color_eyre::eyre::eyre!("{} is invalid: {e}", path.display()))?,
+        Err(_) => toml::value::Table::new(),
+    };
+    table.extend(options);
+    tokio::fs::write(path, toml::to_string(&table)?).await?;
+    Ok(())
+}
+
+/// The `content_dir` of `config`, or the default one
+pub fn configured_content_dir(config: &TomeConfig) -> &str {
+    config.content_dir.as_deref().unwrap_or(DEFAULT_CONTENT_DIR)
+}
+
+/// The directory the content of the current wiki is stored in
+pub fn content_dir() -> String {
+    wiki::current().content_dir.clone()
+}
+
+/// The path of `path` in the content directory
+pub fn content_path(path: &str) -> String {
+    format!("{}/{path}", content_dir())
+}
+
+/// Creates the directories tome needs in `content_dir` and a default index page
+pub async fn create_directories(content_dir: &str) -> tokio::io::Result<()> {
+    for dir in [
+        format!("{content_dir}/articles"),
+        format!("{content_dir}/media"),
+    ] {
+        if tokio::fs::metadata(&dir).await.is_err() {
+            tokio::fs::create_dir_all(&dir).await?;
+            tracing::info!("Created {dir}");
+        }
+    }
+    let index = format!("{content_dir}/index.md");
+    if tokio::fs::metadata(&index).await.is_err() {
+        tokio::fs::write(&index, "# Welcome to Tome\n").await?;
+        tracing::info!("Created {index}");
+    }
+    Ok(())
+}
+
+pub async fn run(args: ConfigArgs, path: &Path, arguments: TomeConfig) -> color_eyre::Result<()> {
+    match args.command {
+        ConfigCommand::Check => {
+            let config = extract(path, arguments)?;
+            let problems = check(path, &config);
+            for error in &problems.errors {
+                println!("error: {error}");
+            }
+            for warning in &problems.warnings {
+                println!("warning: {warning}");
+            }
+            let content_dir = configured_content_dir(&config);
+            for dir in ["articles", "media"] {
+                let dir = format!("{content_dir}/{dir}");
+                if tokio::fs::metadata(&dir).await.is_err() {
+                    println!("note: {dir} doesn't exist yet and will be created on start");
+                }
+            }
+            if problems.errors.is_empty() {
+                println!("The configuration is valid.");
+                Ok(())
+            } else {
+                Err(color_eyre::eyre::eyre!(
+                    "Found {} mistakes in the configuration",
+                    problems.errors.len()
+                ))
+            }
+        }
+        ConfigCommand::Init { force } => {
+            if !force && tokio::fs::metadata(path).await.is_ok() {
+                return Err(color_eyre::eyre::eyre!(
+                    "{} already exists, use --force to replace it",
+                    path.display()
+                ));
+            }
+            tokio::fs::write(path, template()).await?;
+            println!("Wrote {}", path.display());
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! # Edit Conflicts
+//!
+//! The editor sends the version the article was at when it was opened as
+//! `base_version`. If someone else saved the article in the meantime, the
+//! save is rejected with a conflict page showing how the text differs from
+//! the current version, so the changes can be merged by hand and saved
+//! again. Forms without `base_version` are saved as they are, and appended
+//! notes never conflict.
+use askama::Template;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+use crate::diff::{diff, DiffLine};
+use crate::layout::Layout;
+use crate::slug::slug;
+use crate::Article;
+
+#[derive(Template)]
+#[template(path = "conflict.html")]
+struct Conflict {
+    layout: Layout,
+    /// The title the article is saved under
+    original_title: String,
+    path: String,
+    title: String,
+    /// The current version, which the merged text replaces
+    base_version: String,
+    /// The text that couldn't be saved
+    content: String,
+    summary: String,
+    /// Changes from the current version to the text that couldn't be saved
+    lines: Vec<DiffLine>,
+}
+
+/// Whether `base` is out of date, because `title` was saved since
+pub async fn is_outdated(title: &str, base: &str) -> bool {
+    Article::current_version(title).await.unwrap_or_default() != base
+}
+
+/// The page for merging `article` with the current version of `original_title`
+pub async fn page(
+    layout: Layout,
+    original_title: &str,
+    article: Article,
+    summary: String,
+) -> Response {
+    let current = Article::load(original_title)
+        .await
+        .map(|current| current.content)
+        .unwrap_or_default();
+    (
+        StatusCode::CONFLICT,
+        Conflict {
+            layout,
+            original_title: original_title.to_string(),
+            path: slug(original_title),
+            base_version: Article::current_version(original_title)
+                .await
+                .unwrap_or_default(),
+            lines: diff(&current, &article.content),
+            title: article.title,
+            content: article.content,
+            summary,
+        },
+    )
+        .into_response()
+}
@@ -0,0 +1,137 @@
+//! # Demo Mode
+//!
+//! With `demo_mode`, anyone can edit the wiki, and every
+//! `demo_reset_minutes` (an hour by default) the content directory is
+//! replaced with a copy of `demo/`, which is next to it. If there is no
+//! `demo/` yet, a few sample articles are written and the content directory
+//! is copied there first, so the demo can be customized by editing `demo/`.
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config::{content_dir, content_path};
+use crate::{backlinks, render_cache, search, wiki, Article, TomeConfig};
+
+const SNAPSHOT_DIR: &str = "demo";
+const DEFAULT_RESET_MINUTES: u64 = 60;
+
+const INDEX: &str = "# Welcome to the Tome demo
+
+Tome is a wiki that stores its articles as Markdown files. Feel free to
+change anything, every change is undone regularly.
+
+- [Writing articles](/article/writing-articles)
+- [Namespaces](/article/demo:namespaces)
+- [All articles](/overview)
+";
+
+/// Titles and content of the sample articles
+const SAMPLES: &[(&str, &str)] = &[
+    (
+        "Writing articles",
+        "Articles are written in **Markdown**. Open *Edit this page* to see how
+this one is written.
+
+## Lists and code
+
+1. Numbered lists
+2. `inline code`
+
+```rust
+fn main() {
+    println!(\"Code blocks are highlighted\");
+}
+```
+
+> Quotes work, too.
+",
+    ),
+    (
+        "Demo:Namespaces",
+        "Titles with colons put articles into namespaces. This article is
+called *Namespaces* in the namespace *Demo*, see the breadcrumbs above.
+",
+    ),
+];
+
+/// How long changes to the demo last
+pub fn reset_interval(config: &TomeConfig) -> Duration {
+    Duration::from_secs(
+        60 * config
+            .demo_reset_minutes
+            .unwrap_or(DEFAULT_RESET_MINUTES)
+            .max(1),
+    )
+}
+
+/// The directory with the pristine snapshot, next to the content directory
+fn snapshot_dir() -> PathBuf {
+    // Made absolute so that a content directory like `.` has a parent
+    let content = content_dir();
+    std::path::absolute(&content)
+        .unwrap_or_else(|_| content.into())
+        .with_file_name(SNAPSHOT_DIR)
+}
+
+/// Copies the directory `from` to `to`
+pub fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the sample articles and saves the content directory as the pristine snapshot
+async fn seed() -> color_eyre::Result<()> {
+    tokio::fs::write(content_path("index.md"), INDEX).await?;
+    for (title, content) in SAMPLES {
+        Article::new(*title, *content).write_to_disk().await?;
+    }
+    let (content, snapshot) = (content_dir(), snapshot_dir());
+    tokio::task::spawn_blocking(move || copy_dir(Path::new(&content), &snapshot)).await??;
+    tracing::info!("Saved the demo content in {}", snapshot_dir().display());
+    Ok(())
+}
+
+/// Replaces the content directory with the snapshot
+async fn reset() -> color_eyre::Result<()> {
+    let (content, snapshot) = (content_dir(), snapshot_dir());
+    tokio::task::spawn_blocking(move || {
+        std::fs::remove_dir_all(&content)?;
+        copy_dir(&snapshot, Path::new(&content))
+    })
+    .await??;
+    search::build().await;
+    backlinks::build().await;
+    render_cache::invalidate();
+    Ok(())
+}
+
+/// Seeds the demo if necessary and resets it regularly
+pub async fn start(config: &TomeConfig) -> color_eyre::Result<()> {
+    if tokio::fs::metadata(snapshot_dir()).await.is_err() {
+        seed().await?;
+    }
+    reset().await?;
+
+    let interval = reset_interval(config);
+    wiki::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        // The first tick completes immediately, but the demo was just reset
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            match reset().await {
+                Ok(()) => tracing::info!("Reset the demo"),
+                Err(e) => tracing::error!("Could not reset the demo: {e}"),
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,115 @@
+//! # Diffs
+//!
+//! Line diffs between two versions of an article, with the words that
+//! changed within a line highlighted. `/article/:id/history/:version/diff`
+//! compares a version with the current one and
+//! `/article/:id/compare?from=&to=` any two versions, where `current`
+//! stands for the current version.
+use askama::Template;
+use axum::extract::{Path, Query};
+use axum::response::IntoResponse;
+use serde::Deserialize;
+use similar::{ChangeTag, TextDiff};
+
+use crate::error::TomeError;
+use crate::layout::Layout;
+use crate::Article;
+
+/// A line of a diff
+pub struct DiffLine {
+    /// `diff-insert`, `diff-delete` or `diff-equal`
+    pub class: &'static str,
+    /// The text of the line, which is highlighted where it changed
+    pub pieces: Vec<(&'static str, String)>,
+}
+
+/// Compares `old` and `new` line by line
+pub fn diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let diff = TextDiff::from_lines(old, new);
+    let mut lines = vec![];
+    for op in diff.ops() {
+        for change in diff.iter_inline_changes(op) {
+            let (class, marker) = match change.tag() {
+                ChangeTag::Delete => ("diff-delete", "- "),
+                ChangeTag::Insert => ("diff-insert", "+ "),
+                ChangeTag::Equal => ("diff-equal", "  "),
+            };
+            let mut pieces = vec![("", marker.to_string())];
+            for (emphasized, text) in change.iter_strings_lossy() {
+                let class = if emphasized { "diff-word" } else { "" };
+                pieces.push((class, text.trim_end_matches('\n').to_string()));
+            }
+            lines.push(DiffLine { class, pieces });
+        }
+    }
+    lines
+}
+
+#[derive(Template)]
+#[template(path = "diff.html")]
+struct Diff {
+    layout: Layout,
+    title: String,
+    path: String,
+    from: String,
+    to: String,
+    lines: Vec<DiffLine>,
+    insertions: usize,
+    deletions: usize,
+}
+
+#[derive(Deserialize)]
+pub struct CompareQuery {
+    from: String,
+    #[serde(default = "current")]
+    to: String,
+}
+
+fn current() -> String {
+    "current".to_string()
+}
+
+async fn load(title: &str, version: &str) -> Option<Article> {
+    if version == "current" {
+        Article::load(title).await
+    } else {
+        Article::load_version(title, version).await
+    }
+}
+
+/// Compares a version with the current version of the article
+pub async fn get_diff(
+    layout: Layout,
+    Path((title, version)): Path<(String, String)>,
+) -> Result<impl IntoResponse, TomeError> {
+    compare(layout, &title, &version, "current").await
+}
+
+/// Compares two versions of the article
+pub async fn get_compare(
+    layout: Layout,
+    Path(title): Path<String>,
+    Query(query): Query<CompareQuery>,
+) -> Result<impl IntoResponse, TomeError> {
+    compare(layout, &title, &query.from, &query.to).await
+}
+
+async fn compare(layout: Layout, title: &str, from: &str, to: &str) -> Result<Diff, TomeError> {
+    let title = urlencoding::decode(title)?.into_owned();
+    let (Some(old), Some(new)) = (load(&title, from).await, load(&title, to).await) else {
+        return Err(TomeError::NotFound);
+    };
+
+    let lines = diff(&old.content, &new.content);
+    let count = |class| lines.iter().filter(|line| line.class == class).count();
+    Ok(Diff {
+        layout,
+        insertions: count("diff-insert"),
+        deletions: count("diff-delete"),
+        path: new.path(),
+        title: new.title,
+        from: from.to_string(),
+        to: to.to_string(),
+        lines,
+    })
+}
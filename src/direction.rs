@@ -0,0 +1,62 @@
+//! # Text Direction
+//!
+//! Articles written in right-to-left scripts like Arabic or Hebrew are
+//! rendered with `dir="rtl"`. The direction is taken from the `dir:` field
+//! of the frontmatter if there is one, and otherwise guessed from whichever
+//! kind of letters the article's text (not counting code) mostly
+//! consists of.
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use serde_yaml::Value;
+
+use crate::frontmatter;
+
+fn is_rtl(c: char) -> bool {
+    matches!(c,
+        '\u{0590}'..='\u{08FF}'
+        | '\u{FB1D}'..='\u{FDFF}'
+        | '\u{FE70}'..='\u{FEFF}'
+        | '\u{10800}'..='\u{10FFF}'
+        | '\u{1E800}'..='\u{1EFFF}')
+}
+
+/// The dominant direction of Markdown, `ltr` unless most letters outside of code are right-to-left
+fn detect(markdown: &str) -> &'static str {
+    let (mut rtl, mut ltr) = (0usize, 0usize);
+    let mut in_code_block = false;
+    for event in Parser::new_ext(markdown, Options::all()) {
+        let text = match event {
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code_block = true;
+                continue;
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                in_code_block = false;
+                continue;
+            }
+            Event::Text(text) if !in_code_block => text,
+            _ => continue,
+        };
+        for c in text.chars().filter(|c| c.is_alphabetic()) {
+            if is_rtl(c) {
+                rtl += 1;
+            } else {
+                ltr += 1;
+            }
+        }
+    }
+    if rtl > ltr {
+        "rtl"
+    } else {
+        "ltr"
+    }
+}
+
+/// The direction (`ltr` or `rtl`) an article should be rendered in
+pub fn direction(content: &str) -> &'static str {
+    let meta = frontmatter::parse(content).unwrap_or_default();
+    match meta.get(&Value::from("dir")).and_then(Value::as_str) {
+        Some("rtl") => "rtl",
+        Some("ltr") => "ltr",
+        _ => detect(frontmatter::split(content).1),
+    }
+}
@@ -0,0 +1,279 @@
+//! # Dump and Load
+//!
+//! `tome dump <file>` writes all of the wiki as a stream of JSON objects,
+//! one per line, for backups, moving a wiki to another storage and other
+//! tools. Every line has a `kind`: a `header` first, then the `index`
+//! page, every `article` with its title, tags and whether it is archived,
+//! followed by each of its `version`s oldest first with when and by whom
+//! it was saved, and a `media` line for every media file with its size,
+//! modification time and SHA-256 hash. With `--media` the media lines also
+//! have the files themselves in base64, otherwise they are a manifest.
+//! Articles in the trash aren't dumped.
+//!
+//! `tome load <file>` saves every version of a dump again, in order and
+//! with its author and summary, through the configured storage. The
+//! versions get new ids and the time they are loaded. Media files are
+//! written if the dump has them, and listed if it only has a manifest and
+//! they are missing.
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tokio::io::AsyncBufReadExt;
+
+use crate::config::content_path;
+use crate::migrations::LAYOUT;
+use crate::storage::article_slugs;
+use crate::{frontmatter, paths, version_info, Article};
+
+const FORMAT: &str = "tome-dump";
+const FORMAT_VERSION: u32 = 1;
+
+/// A line of a dump
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Record {
+    Header {
+        format: String,
+        version: u32,
+        /// The layout of the content it was dumped from
+        layout: usize,
+        created: String,
+    },
+    Index {
+        content: String,
+    },
+    Article {
+        slug: String,
+        title: String,
+        tags: Vec<String>,
+        archived: bool,
+    },
+    Version {
+        title: String,
+        id: String,
+        saved: String,
+        #[serde(default)]
+        author: Option<String>,
+        #[serde(default)]
+        summary: Option<String>,
+        content: String,
+    },
+    Media {
+        name: String,
+        size: u64,
+        modified: String,
+        sha256: String,
+        /// The file in base64, if the dump has the media
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        data: Option<String>,
+    },
+}
+
+/// Arguments for `tome dump`
+#[derive(Args)]
+pub struct DumpArgs {
+    /// Include the media files themselves, not just a list of them
+    #[arg(long)]
+    media: bool,
+    /// The file to write
+    output: PathBuf,
+}
+
+/// Arguments for `tome load`
+#[derive(Args)]
+pub struct LoadArgs {
+    /// A file written by `tome dump`
+    input: PathBuf,
+}
+
+fn format_time(time: SystemTime) -> String {
+    OffsetDateTime::from(time).format(&Rfc3339).unwrap()
+}
+
+fn sha256(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn write_record(output: &mut impl Write, record: &Record) -> color_eyre::Result<()> {
+    serde_json::to_writer(&mut *output, record)?;
+    output.write_all(b"\n")?;
+    Ok(())
+}
+
+pub async fn dump(args: DumpArgs) -> color_eyre::Result<()> {
+    let mut output = std::io::BufWriter::new(std::fs::File::create(&args.output)?);
+    write_record(
+        &mut output,
+        &Record::Header {
+            format: FORMAT.to_string(),
+            version: FORMAT_VERSION,
+            layout: LAYOUT,
+            created: format_time(SystemTime::now()),
+        },
+    )?;
+    let index = tokio::fs::read_to_string(content_path("index.md"))
+        .await
+        .unwrap_or_default();
+    write_record(&mut output, &Record::Index { content: index })?;
+
+    let (mut articles, mut versions) = (0, 0);
+    for slug in article_slugs().await {
+        let Some(article) = Article::load(&slug).await else {
+            continue;
+        };
+        write_record(
+            &mut output,
+            &Record::Article {
+                slug,
+                title: article.title.clone(),
+                tags: frontmatter::tags(&frontmatter::parse(&article.content).unwrap_or_default()),
+                archived: article.is_archived(),
+            },
+        )?;
+        let mut saved = Article::get_versions(&article.title).await;
+        saved.sort_by_key(|(_, saved)| *saved);
+        for (id, saved) in saved {
+            let Some(version) = Article::load_version(&article.title, &id).await else {
+                tracing::warn!(
+                    "Skipping version {id} of {}, it can't be read",
+                    article.title
+                );
+                continue;
+            };
+            let info = version_info::read(&article.title, &id).await;
+            let (author, summary) = match info {
+                Some(info) => (info.author, info.summary),
+                None => (None, None),
+            };
+            write_record(
+                &mut output,
+                &Record::Version {
+                    title: article.title.clone(),
+                    id,
+                    saved: format_time(saved),
+                    author,
+                    summary,
+                    content: version.content,
+                },
+            )?;
+            versions += 1;
+        }
+        articles += 1;
+    }
+
+    let mut media = 0;
+    let mut entries = tokio::fs::read_dir(content_path("media")).await?;
+    let mut names = vec![];
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_file() {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    for name in names {
+        let path = content_path(&format!("media/{name}"));
+        // Files deleted while dumping are left out
+        let (Ok(metadata), Ok(data)) = (
+            tokio::fs::metadata(&path).await,
+            tokio::fs::read(&path).await,
+        ) else {
+            continue;
+        };
+        write_record(
+            &mut output,
+            &Record::Media {
+                size: metadata.len(),
+                modified: format_time(metadata.modified()?),
+                sha256: sha256(&data),
+                data: args.media.then(|| STANDARD.encode(&data)),
+                name,
+            },
+        )?;
+        media += 1;
+    }
+    output.flush()?;
+    println!(
+        "Dumped {articles} articles with {versions} versions and {media} media files to {}.",
+        args.output.display()
+    );
+    Ok(())
+}
+
+pub async fn load(args: LoadArgs) -> color_eyre::Result<()> {
+    let file = tokio::fs::File::open(&args.input).await?;
+    let mut lines = tokio::io::BufReader::new(file).lines();
+    let (mut versions, mut media) = (0, 0);
+    let mut missing = vec![];
+    let mut number = 0;
+    while let Some(line) = lines.next_line().await? {
+        number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record = serde_json::from_str(&line)
+            .map_err(|e| color_eyre::eyre::eyre!("Line {number} isn't part of a dump: {e}"))?;
+        match record {
+            Record::Header {
+                format, version, ..
+            } => {
+                if format != FORMAT || version > FORMAT_VERSION {
+                    return Err(color_eyre::eyre::eyre!(
+                        "{} is a {format} {version}, not a {FORMAT} of up to version {FORMAT_VERSION}",
+                        args.input.display()
+                    ));
+                }
+            }
+            Record::Index { content } => {
+                tokio::fs::write(content_path("index.md"), content).await?;
+            }
+            // The versions that follow have everything an article is
+            Record::Article { .. } => {}
+            Record::Version {
+                title,
+                author,
+                summary,
+                content,
+                ..
+            } => {
+                Article { title, content }
+                    .write_to_disk_by(author.as_deref(), summary.as_deref())
+                    .await?;
+                versions += 1;
+            }
+            Record::Media { name, data, .. } => {
+                if let Err(message) = paths::check_file_name(&name) {
+                    tracing::warn!("Skipping a media file: {message}");
+                    continue;
+                }
+                let path = content_path(&format!("media/{name}"));
+                match data {
+                    Some(data) => {
+                        tokio::fs::write(&path, STANDARD.decode(data)?).await?;
+                        media += 1;
+                    }
+                    None if tokio::fs::metadata(&path).await.is_err() => missing.push(name),
+                    None => {}
+                }
+            }
+        }
+    }
+    println!("Loaded {versions} versions and {media} media files.");
+    if !missing.is_empty() {
+        println!(
+            "The dump only lists these media files, copy them into the media: {}",
+            missing.join(", ")
+        );
+    }
+    Ok(())
+}
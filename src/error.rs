@@ -0,0 +1,174 @@
+//! # Errors
+//!
+//! Handlers return `Result<_, TomeError>` instead of panicking when
+//! something fails. Files that don't exist become a 404, other IO errors a
+//! 500 whose cause is only logged, not shown. Rendering the error page
+//! needs the [`Layout`] of the request, which a `TomeError` doesn't have,
+//! so it only marks its response and the [`render`] middleware replaces it
+//! with the page for browsers. Other clients, like the scripts of the
+//! annotations, get the error as plain text. Handlers that panic anyway
+//! get the same 500 page.
+use std::any::Any;
+use std::string::FromUtf8Error;
+
+use askama::Template;
+use axum::extract::multipart::MultipartError;
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::layout::Layout;
+use crate::NotFound;
+
+#[derive(Debug)]
+pub enum TomeError {
+    /// The page or file doesn't exist
+    NotFound,
+    /// The request can't work, the message says why
+    BadRequest(String),
+    /// The request isn't allowed, the message says why
+    Forbidden(String),
+    /// The request is larger than allowed, the message says how large it can be
+    TooLarge(String),
+    /// The wiki has used up its `storage_quota`, the message says how much room is left
+    OutOfRoom(String),
+    /// Something went wrong on the server, the message is only logged
+    Internal(String),
+}
+
+impl std::fmt::Display for TomeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TomeError::NotFound => write!(f, "Not found"),
+            TomeError::BadRequest(message)
+            | TomeError::Forbidden(message)
+            | TomeError::TooLarge(message)
+            | TomeError::OutOfRoom(message)
+            | TomeError::Internal(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for TomeError {}
+
+impl From<std::io::Error> for TomeError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::NotFound => TomeError::NotFound,
+            _ => TomeError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for TomeError {
+    fn from(e: serde_json::Error) -> Self {
+        TomeError::Internal(e.to_string())
+    }
+}
+
+/// Percent-encoded titles that aren't UTF-8 can't name an article
+impl From<FromUtf8Error> for TomeError {
+    fn from(_: FromUtf8Error) -> Self {
+        TomeError::NotFound
+    }
+}
+
+impl From<time::error::Format> for TomeError {
+    fn from(e: time::error::Format) -> Self {
+        TomeError::Internal(e.to_string())
+    }
+}
+
+impl From<MultipartError> for TomeError {
+    fn from(e: MultipartError) -> Self {
+        // Requests over the body limit fail while their fields are read
+        match e.status() {
+            StatusCode::PAYLOAD_TOO_LARGE => TomeError::TooLarge(e.body_text()),
+            _ => TomeError::BadRequest(e.to_string()),
+        }
+    }
+}
+
+impl From<color_eyre::Report> for TomeError {
+    fn from(e: color_eyre::Report) -> Self {
+        TomeError::Internal(e.to_string())
+    }
+}
+
+/// Marks a response [`render`] should replace with the error page
+#[derive(Clone)]
+struct ErrorPage(Option<String>);
+
+impl IntoResponse for TomeError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            TomeError::NotFound => (StatusCode::NOT_FOUND, None),
+            TomeError::BadRequest(message) => (StatusCode::BAD_REQUEST, Some(message)),
+            TomeError::Forbidden(message) => (StatusCode::FORBIDDEN, Some(message)),
+            TomeError::TooLarge(message) => (StatusCode::PAYLOAD_TOO_LARGE, Some(message)),
+            TomeError::OutOfRoom(message) => (StatusCode::INSUFFICIENT_STORAGE, Some(message)),
+            TomeError::Internal(message) => {
+                tracing::error!("{message}");
+                (StatusCode::INTERNAL_SERVER_ERROR, None)
+            }
+        };
+        let text = message
+            .clone()
+            .unwrap_or_else(|| status.canonical_reason().unwrap_or_default().to_string());
+        let mut response = (status, text).into_response();
+        response.extensions_mut().insert(ErrorPage(message));
+        response
+    }
+}
+
+/// The 500 page of [`tower_http::catch_panic`]
+pub fn panicked(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let message = panic
+        .downcast_ref::<String>()
+        .cloned()
+        .or_else(|| {
+            panic
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+        })
+        .unwrap_or_default();
+    TomeError::Internal(format!("A handler panicked: {message}")).into_response()
+}
+
+#[derive(Template)]
+#[template(path = "error.html")]
+struct ErrorTemplate {
+    layout: Layout,
+    status: StatusCode,
+    message: Option<String>,
+}
+
+/// Middleware rendering the error page of failed requests from browsers
+pub async fn render<B>(layout: Layout, request: Request<B>, next: Next<B>) -> Response {
+    let wants_html = request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    let response = next.run(request).await;
+    if !wants_html {
+        return response;
+    }
+    let Some(ErrorPage(message)) = response.extensions().get::<ErrorPage>().cloned() else {
+        return response;
+    };
+    let status = response.status();
+    if status == StatusCode::NOT_FOUND {
+        (status, NotFound { layout }).into_response()
+    } else {
+        (
+            status,
+            ErrorTemplate {
+                layout,
+                status,
+                message,
+            },
+        )
+            .into_response()
+    }
+}
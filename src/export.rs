@@ -0,0 +1,179 @@
+//! # Exports
+//!
+//! `/article/:id/export.html` renders an article into a single HTML file
+//! that doesn't depend on the wiki: styles are inlined and images from the
+//! media directory are embedded as data URIs. `tome export` writes the
+//! whole wiki into other formats.
+//!
+//! `export_header` and `export_footer` are added to exported articles and
+//! shown when articles are printed, e.g. for confidentiality notices. So is
+//! the article's license.
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use askama::Template;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap};
+use axum::response::IntoResponse;
+use base64::Engine;
+use clap::{Args, ValueEnum};
+use pulldown_cmark::{Event, Tag};
+use time::OffsetDateTime;
+
+use crate::config::content_path;
+use crate::error::TomeError;
+use crate::license::License;
+use crate::media::mime_type;
+use crate::{filters, paths, zim, Article, TomeConfig};
+
+/// Arguments for `tome export`
+#[derive(Args)]
+pub struct ExportArgs {
+    /// The format to export the wiki in
+    #[arg(long, value_enum)]
+    format: ExportFormat,
+    /// The file to write
+    output: PathBuf,
+}
+
+#[derive(Clone, ValueEnum)]
+enum ExportFormat {
+    /// A ZIM archive for offline readers like Kiwix
+    Zim,
+}
+
+pub async fn run(args: ExportArgs, config: &TomeConfig) -> color_eyre::Result<()> {
+    match args.format {
+        ExportFormat::Zim => zim::export(&args.output, config).await,
+    }
+}
+
+/// The header, footer and license of a printed or exported article
+#[derive(Default)]
+pub struct Notices {
+    pub header: Option<String>,
+    pub footer: Option<String>,
+    pub license: Option<License>,
+}
+
+/// The address of the wiki, `public_url` or else the host the request was sent to
+pub fn base_url(config: &TomeConfig, headers: &HeaderMap) -> String {
+    match &config.public_url {
+        Some(url) => url.trim_end_matches('/').to_string(),
+        None => {
+            let host = headers
+                .get(header::HOST)
+                .and_then(|host| host.to_str().ok())
+                .unwrap_or("localhost");
+            format!("http://{host}{}", crate::base_path::of(config))
+        }
+    }
+}
+
+impl Notices {
+    pub fn new(config: &TomeConfig, headers: &HeaderMap, article: &Article) -> Self {
+        let url = format!("{}/article/{}", base_url(config, headers), article.path());
+        let date = OffsetDateTime::now_utc().date().to_string();
+        let site_name = config.site_name.as_deref().unwrap_or("Tome");
+        let fill = |text: &String| {
+            text.replace("{site_name}", site_name)
+                .replace("{title}", &article.title)
+                .replace("{url}", &url)
+                .replace("{date}", &date)
+        };
+        Notices {
+            header: config.export_header.as_ref().map(fill),
+            footer: config.export_footer.as_ref().map(fill),
+            license: License::of(config, article),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "export.html")]
+struct Export {
+    title: String,
+    html: String,
+    exported: String,
+    notices: Notices,
+}
+
+/// Returns the media file an image destination refers to, if any
+pub fn media_name(dest: &str) -> Option<String> {
+    let name = urlencoding::decode(dest.strip_prefix("/media/")?).ok()?;
+    if !paths::is_file_name(&name) {
+        return None;
+    }
+    Some(name.into_owned())
+}
+
+/// The images of `markdown` from the media as data URLs, by their destination
+pub async fn embed_images(markdown: &str) -> HashMap<String, String> {
+    let destinations: Vec<String> = pulldown_cmark::Parser::new(markdown)
+        .filter_map(|event| match event {
+            Event::Start(Tag::Image(_, dest, _)) => Some(dest.to_string()),
+            _ => None,
+        })
+        .collect();
+
+    let mut images = HashMap::new();
+    for dest in destinations {
+        let Some(name) = media_name(&dest) else {
+            continue;
+        };
+        if images.contains_key(&dest) {
+            continue;
+        }
+        if let Ok(data) = tokio::fs::read(content_path(&format!("media/{name}"))).await {
+            let data = base64::engine::general_purpose::STANDARD.encode(data);
+            images.insert(dest, format!("data:{};base64,{data}", mime_type(&name)));
+        }
+    }
+
+    images
+}
+
+/// Points the media images of rendered `html` to their data URLs from `images`
+///
+/// This happens after the article's HTML was cleaned, which doesn't allow data URLs.
+pub fn embed(html: &str, images: &HashMap<String, String>) -> String {
+    let mut html = html.to_string();
+    for (dest, data) in images {
+        let src = format!(
+            "src=\"{}\"",
+            askama_escape::escape(dest, askama_escape::Html)
+        );
+        html = html.replace(&src, &format!("src=\"{data}\""));
+    }
+    html
+}
+
+pub async fn export_html(
+    State(config): State<TomeConfig>,
+    headers: HeaderMap,
+    Path(title): Path<String>,
+) -> Result<impl IntoResponse, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let article = Article::load(&title).await.ok_or(TomeError::NotFound)?;
+
+    let images = embed_images(article.body()).await;
+    let html = embed(&filters::render(article.body(), |event| event), &images);
+
+    let exported = OffsetDateTime::now_utc()
+        .format(&time::format_description::well_known::Rfc2822)
+        .unwrap();
+
+    let file_name = format!("{}.html", article.path());
+    Ok((
+        [(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{file_name}\""),
+        )],
+        Export {
+            title: article.title.clone(),
+            html,
+            exported,
+            notices: Notices::new(&config, &headers, &article),
+        },
+    ))
+}
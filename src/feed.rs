@@ -0,0 +1,111 @@
+//! # Atom Feeds
+//!
+//! `/changes.atom` has an entry for each of the latest saved versions in
+//! the wiki, like [`crate::changes`], and `/article/:id/history.atom` for
+//! those of one article. Entries link to the version and are dated by when
+//! it was saved. Links are absolute, using `public_url` if it is set.
+use askama::Template;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap};
+use axum::response::IntoResponse;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::changes::{self, Change};
+use crate::error::TomeError;
+use crate::export::base_url;
+use crate::license::License;
+use crate::{Article, TomeConfig};
+
+/// The number of versions in a feed
+const FEED_LENGTH: usize = 50;
+
+#[derive(Template)]
+#[template(path = "feed.xml")]
+struct Feed {
+    title: String,
+    /// The absolute URL of the feed
+    id: String,
+    /// The page the feed is about
+    link: String,
+    updated: String,
+    rights: Option<String>,
+    /// Title, link and time of each entry
+    entries: Vec<(String, String, String)>,
+}
+
+fn timestamp(change: &Change) -> String {
+    OffsetDateTime::from(change.saved).format(&Rfc3339).unwrap()
+}
+
+fn feed(
+    config: &TomeConfig,
+    headers: &HeaderMap,
+    title: String,
+    path: &str,
+    page: &str,
+    changes: Vec<Change>,
+) -> impl IntoResponse {
+    let base = base_url(config, headers);
+    let changes: Vec<Change> = changes.into_iter().take(FEED_LENGTH).collect();
+    let updated = changes
+        .first()
+        .map(timestamp)
+        .unwrap_or_else(|| OffsetDateTime::UNIX_EPOCH.format(&Rfc3339).unwrap());
+    let entries = changes
+        .iter()
+        .map(|change| {
+            let link = format!("{base}/article/{}/history/{}", change.slug, change.version);
+            (change.title.clone(), link, timestamp(change))
+        })
+        .collect();
+    (
+        [(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+        Feed {
+            title,
+            id: format!("{base}{path}"),
+            link: format!("{base}{page}"),
+            updated,
+            rights: License::of_site(config).map(|license| license.name),
+            entries,
+        },
+    )
+}
+
+pub async fn changes_feed(
+    State(config): State<TomeConfig>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let title = format!(
+        "Recent changes in {}",
+        config.site_name.as_deref().unwrap_or("Tome")
+    );
+    let changes = changes::recent().await;
+    feed(
+        &config,
+        &headers,
+        title,
+        "/changes.atom",
+        "/changes",
+        changes,
+    )
+}
+
+pub async fn history_feed(
+    State(config): State<TomeConfig>,
+    headers: HeaderMap,
+    Path(title): Path<String>,
+) -> Result<impl IntoResponse, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let article = Article::load(&title).await.ok_or(TomeError::NotFound)?;
+    let path = article.path();
+    let changes = changes::of_article(&article).await;
+    Ok(feed(
+        &config,
+        &headers,
+        format!("History of {}", article.title),
+        &format!("/article/{path}/history.atom"),
+        &format!("/article/{path}/history"),
+        changes,
+    ))
+}
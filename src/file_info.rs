@@ -0,0 +1,96 @@
+//! # File Info
+//!
+//! `{{file-info name.pdf}}` in an article shows the media file `name.pdf`
+//! with its size, when it was uploaded and a button to download it, for
+//! pages that share documents. Files that aren't in the media are marked
+//! as missing instead. Shortcodes in code are left as they are.
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+use regex::Regex;
+use time::macros::format_description;
+use time::OffsetDateTime;
+
+use crate::config::content_path;
+use crate::{filters, paths};
+
+fn shortcode_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"\{\{\s*file-info\s+([^}\n]+?)\s*\}\}").unwrap())
+}
+
+/// The names of the media files `markdown` shows with `{{file-info name}}`
+pub fn references(markdown: &str) -> Vec<String> {
+    shortcode_pattern()
+        .captures_iter(markdown)
+        .map(|captures| captures[1].to_string())
+        .collect()
+}
+
+/// `size` in bytes, in the largest unit it has at least one of
+pub fn format_size(size: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+    if size < 1024 {
+        return format!("{size} B");
+    }
+    let mut size = size as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// The HTML showing the media file `name`
+async fn render(name: &str) -> String {
+    let escape = |text: &str| askama_escape::escape(text, askama_escape::Html).to_string();
+    let metadata = match paths::is_file_name(name) {
+        true => tokio::fs::metadata(content_path(&format!("media/{name}")))
+            .await
+            .ok()
+            .filter(|metadata| metadata.is_file()),
+        false => None,
+    };
+    let Some(metadata) = metadata else {
+        return format!(
+            "<span class=\"file-info has-text-danger\">{} (missing)</span>",
+            escape(name)
+        );
+    };
+    let href = format!("/media/{}", urlencoding::encode(name));
+    let mut details = format_size(metadata.len());
+    if let Ok(modified) = metadata.modified() {
+        let date =
+            OffsetDateTime::from(modified).format(format_description!("[year]-[month]-[day]"));
+        if let Ok(date) = date {
+            details.push_str(&format!(", uploaded {date}"));
+        }
+    }
+    format!(
+        "<span class=\"file-info\"><a href=\"{href}\">{}</a> ({details}) \
+         <a class=\"button is-small\" href=\"{href}\" download=\"\">Download</a></span>",
+        escape(name)
+    )
+}
+
+/// Replaces the `{{file-info name}}` shortcodes of `markdown` outside of code
+pub async fn expand(markdown: &str) -> Cow<'_, str> {
+    if !markdown.contains("file-info") {
+        return Cow::Borrowed(markdown);
+    }
+    let code = filters::code_ranges(markdown);
+    let mut out = String::with_capacity(markdown.len());
+    let mut copied = 0;
+    for captures in shortcode_pattern().captures_iter(markdown) {
+        let shortcode = captures.get(0).unwrap();
+        if code.iter().any(|range| range.contains(&shortcode.start())) {
+            continue;
+        }
+        out.push_str(&markdown[copied..shortcode.start()]);
+        out.push_str(&render(&captures[1]).await);
+        copied = shortcode.end();
+    }
+    out.push_str(&markdown[copied..]);
+    Cow::Owned(out)
+}
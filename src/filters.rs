@@ -5,10 +5,36 @@
 /// Markdown, it lacks some configuration options tome needs (specifically,
 /// rewriting broken links). This means we use a custom filter to
 /// render Markdown using the pulldown_cmark crate.
+///
+/// Besides Markdown's links, articles can link to each other with
+/// `[[Page Name]]` or `[[Page Name|label]]`, and to users with `@name`.
+/// `[[Page Name#Section]]` links to a heading of the article and
+/// `[[Page Name#^id]]` to the paragraph ending with the block id `^id`.
+///
+/// Every heading gets an id to link to, and a line with just `[TOC]` (or
+/// `toc: true` in the frontmatter) becomes a table of contents linking to
+/// the headings below it.
+///
+/// On the wiki's pages, links to articles get a `data-preview` attribute
+/// with the API URL of a preview, which `previews.js` shows on hover.
+///
+/// PDFs from the media that are embedded like images become links that
+/// `pdf_preview.js` previews, see [`crate::pdf_preview`].
+///
+/// The HTML is cleaned by [`crate::sanitize`] before it is shown. The
+/// attributes for the previews are only added to the cleaned HTML, from
+/// the targets of its links, so articles can't set them.
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+
 use askama::MarkupDisplay;
-use pulldown_cmark::{html, BrokenLink, CowStr, Event, LinkType, Options, Tag};
+use pulldown_cmark::{html, BrokenLink, CowStr, Event, HeadingLevel, LinkType, Options, Tag};
 
-fn handle_broken_link(broken_link: BrokenLink<'_>) -> Option<(CowStr<'_>, CowStr<'_>)> {
+/// The line that is replaced by a table of contents
+pub const TOC_MARKER: &str = "[TOC]";
+
+pub fn handle_broken_link(broken_link: BrokenLink<'_>) -> Option<(CowStr<'_>, CowStr<'_>)> {
     Some((broken_link.reference.clone(), broken_link.reference))
 }
 
@@ -16,40 +42,569 @@ pub fn custom_md<S>(s: S) -> askama::Result<MarkupDisplay<askama_escape::Html, S
 where
     S: AsRef<str>,
 {
+    let html_out = render_with(s.as_ref(), None, true, |event| event);
+    Ok(MarkupDisplay::new_safe(html_out, askama_escape::Html))
+}
+
+/// Renders an article's Markdown like [`custom_md`], adding a link
+/// to edit the section below every heading of the article at `path`.
+pub fn article_md<S, P>(s: S, path: P) -> askama::Result<MarkupDisplay<askama_escape::Html, String>>
+where
+    S: AsRef<str>,
+    P: AsRef<str>,
+{
+    let html_out = render_with(s.as_ref(), Some(path.as_ref()), true, |event| event);
+    Ok(MarkupDisplay::new_safe(html_out, askama_escape::Html))
+}
+
+/// Renders Markdown to HTML like [`custom_md`], passing every event
+/// through `rewrite` first, e.g. to change link targets for exports.
+pub fn render<F>(s: &str, rewrite: F) -> String
+where
+    F: FnMut(Event<'_>) -> Event<'_>,
+{
+    render_with(s, None, false, rewrite)
+}
+
+/// Where `markdown` has inline code or code blocks
+pub fn code_ranges(markdown: &str) -> Vec<Range<usize>> {
+    pulldown_cmark::Parser::new_ext(markdown, Options::all())
+        .into_offset_iter()
+        .filter_map(|(event, range)| {
+            matches!(event, Event::Code(_) | Event::Start(Tag::CodeBlock(_))).then_some(range)
+        })
+        .collect()
+}
+
+/// The link to a wikilink's target, like `Page#Section` or `#^block`
+fn wikilink_target(target: &str) -> String {
+    let (title, fragment) = target.split_once('#').unwrap_or((target, ""));
+    let fragment = match fragment.trim() {
+        "" => String::new(),
+        block if block.starts_with('^') => format!("#{block}"),
+        heading => format!("#{}", slug(heading)),
+    };
+    match title.trim() {
+        "" => fragment,
+        title => format!("/article/{}{fragment}", urlencoding::encode(title)),
+    }
+}
+
+/// Turns `[[Page Name]]` and `[[Page Name|label]]` outside of code into
+/// Markdown links to the article
+pub fn wikilinks(markdown: &str) -> Cow<'_, str> {
+    if !markdown.contains("[[") {
+        return Cow::Borrowed(markdown);
+    }
+    let code = code_ranges(markdown);
+
+    let mut out = String::with_capacity(markdown.len());
+    let mut copied = 0;
+    let mut next = 0;
+    while let Some(found) = markdown[next..].find("[[") {
+        let start = next + found;
+        let Some(length) = markdown[start + 2..].find("]]") else {
+            break;
+        };
+        let end = start + 2 + length + 2;
+        let inner = &markdown[start + 2..end - 2];
+        // `![[...]]` embeds a file in other wikis, and `\[[` is escaped
+        if markdown[..start].ends_with(['\\', '!'])
+            || inner.contains(['\n', '[', ']'])
+            || inner.trim().is_empty()
+            || code.iter().any(|range| range.contains(&start))
+        {
+            next = start + 1;
+            continue;
+        }
+
+        let (title, label) = inner.split_once('|').unwrap_or((inner, inner));
+        let title = title.trim();
+        let label = match label.trim() {
+            "" => title,
+            label => label,
+        };
+        out.push_str(&markdown[copied..start]);
+        out.push_str(&format!("[{label}]({})", wikilink_target(title)));
+        copied = end;
+        next = end;
+    }
+    out.push_str(&markdown[copied..]);
+    Cow::Owned(out)
+}
+
+/// The slugs of the articles `markdown` links to, like it is rendered
+pub fn linked_articles(markdown: &str) -> BTreeSet<String> {
+    let markdown = wikilinks(markdown);
     let mut binding = handle_broken_link;
-    let parser = pulldown_cmark::Parser::new_with_broken_link_callback(
-        s.as_ref(),
+    let events = pulldown_cmark::Parser::new_with_broken_link_callback(
+        &markdown,
         Options::all(),
         Some(&mut binding),
-    )
-    .map(|event| match event {
-        Event::Start(tag) => {
-            let tag = match tag {
-                Tag::Link(link_type, dest, title) => {
-                    let dest = if link_type == LinkType::ShortcutUnknown {
-                        format!("/article/{dest}")
-                    } else {
-                        dest.to_string()
-                    };
-                    Tag::Link(link_type, dest.into(), title)
-                }
-                _ => tag,
-            };
-            Event::Start(tag)
-        }
-        Event::Html(node) => {
-            let replacement = if node.starts_with("<script") {
-                "<pre><code>"
-            } else if node.starts_with("</script") {
-                "</code></pre>"
-            } else {
-                &node
-            };
-            Event::Html(replacement.to_string().into())
+    );
+    let mut articles = BTreeSet::new();
+    for event in events {
+        let Event::Start(Tag::Link(link_type, dest, _)) = event else {
+            continue;
+        };
+        let article = if link_type == LinkType::ShortcutUnknown {
+            dest.to_string()
+        } else if let Some(article) = dest.strip_prefix("/article/") {
+            article.to_string()
+        } else {
+            continue;
+        };
+        let article = article.split('#').next().unwrap_or_default();
+        let article = urlencoding::decode(article).map_or(article.into(), |article| article);
+        if !article.is_empty() && !article.contains('/') {
+            articles.insert(crate::slug::slug(&article));
+        }
+    }
+    articles
+}
+
+/// The API URL previewing the target of a link to `dest`, if it is an article
+fn preview_url(dest: &str) -> Option<String> {
+    let (article, fragment) = match dest.strip_prefix("/article/")?.split_once('#') {
+        Some((article, fragment)) => (article, Some(fragment)),
+        None => (dest.strip_prefix("/article/")?, None),
+    };
+    let article = urlencoding::decode(article).ok()?;
+    if article.is_empty() || article.contains('/') {
+        return None;
+    }
+    let article = urlencoding::encode(&article);
+    Some(match fragment {
+        Some(fragment) => {
+            let id = urlencoding::decode(fragment).ok()?;
+            format!(
+                "/api/fragment?article={article}&id={}",
+                urlencoding::encode(&id)
+            )
+        }
+        None => format!("/api/preview/{article}"),
+    })
+}
+
+/// The attributes of the opening tag `tag` of cleaned HTML, like `<a href="x"`
+fn attributes(tag: &str) -> Vec<(&str, String)> {
+    let mut attributes = vec![];
+    let mut rest = tag.split_once(' ').map_or("", |(_, rest)| rest);
+    while let Some((name, value)) = rest.split_once("=\"") {
+        let Some((value, after)) = value.split_once('"') else {
+            break;
+        };
+        // Cleaned HTML escapes quotes within values, so the next quote ends the value
+        let value = value
+            .replace("&quot;", "\"")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&nbsp;", "\u{a0}")
+            .replace("&amp;", "&");
+        // Attributes without a value, like `download`, come before the name
+        attributes.push((name.split_whitespace().last().unwrap_or_default(), value));
+        rest = after;
+    }
+    attributes
+}
+
+/// Where the opening tag at the start of cleaned HTML ends, at its `>`
+fn tag_end(html: &str) -> Option<usize> {
+    let mut quoted = false;
+    html.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        '>' if !quoted => Some(i),
+        _ => None,
+    })
+}
+
+/// Gives the links of cleaned `html` the attributes `previews.js` (if `previews`
+/// is set) and `pdf_preview.js` need, computed from where they link to
+fn with_preview_attributes(html: &str, previews: bool) -> String {
+    let escape = |text: &str| askama_escape::escape(text, askama_escape::Html).to_string();
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("<a ") {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = tag_end(rest) else {
+            break;
+        };
+        let tag = &rest[..end];
+        out.push_str(tag);
+        let attributes = attributes(tag);
+        let value = |name: &str| {
+            attributes
+                .iter()
+                .find(|(attribute, _)| *attribute == name)
+                .map(|(_, value)| value.as_str())
+        };
+        if let Some(href) = value("href") {
+            if let Some(preview) = preview_url(href).filter(|_| previews) {
+                out.push_str(&format!(" data-preview=\"{}\"", escape(&preview)));
+            }
+            if value("class") == Some("pdf-preview") && crate::pdf_preview::is_media_pdf(href) {
+                out.push_str(&format!(" data-pdf=\"{}\"", escape(href)));
+            }
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Renders images of PDFs in the media as links to preview them
+fn with_pdf_previews<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+) -> impl Iterator<Item = Event<'a>> {
+    let mut in_pdf = false;
+    events.map(move |event| match event {
+        Event::Start(Tag::Image(_, dest, _)) if crate::pdf_preview::is_media_pdf(&dest) => {
+            in_pdf = true;
+            let dest = askama_escape::escape(&dest, askama_escape::Html).to_string();
+            Event::Html(format!("<a class=\"pdf-preview\" href=\"{dest}\">").into())
+        }
+        Event::End(Tag::Image(..)) if in_pdf => {
+            in_pdf = false;
+            Event::Html("</a>".into())
         }
         _ => event,
-    });
+    })
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(length = s.len()))]
+fn render_with<F>(s: &str, section_edit: Option<&str>, previews: bool, rewrite: F) -> String
+where
+    F: FnMut(Event<'_>) -> Event<'_>,
+{
+    let s = wikilinks(s);
+    let s = crate::mentions::link(&s);
+    let s = with_toc(&s);
+    let mut binding = handle_broken_link;
+    let events = pulldown_cmark::Parser::new_with_broken_link_callback(
+        &s,
+        Options::all(),
+        Some(&mut binding),
+    );
+    let parser = with_block_ids(events)
+        .into_iter()
+        .map(|event| match event {
+            // Lets browsers pick the direction of every paragraph from its text
+            Event::Start(Tag::Paragraph) => Event::Html("<p dir=\"auto\">".into()),
+            Event::End(Tag::Paragraph) => Event::Html("</p>\n".into()),
+            Event::Start(tag) => {
+                let tag = match tag {
+                    Tag::Link(link_type, dest, title) => {
+                        let dest = if link_type == LinkType::ShortcutUnknown {
+                            format!("/article/{dest}")
+                        } else {
+                            dest.to_string()
+                        };
+                        Tag::Link(link_type, dest.into(), title)
+                    }
+                    _ => tag,
+                };
+                Event::Start(tag)
+            }
+            // Screen readers otherwise announce an unlabeled checkbox
+            Event::TaskListMarker(done) => {
+                let (checked, label) = if done {
+                    (" checked=\"\"", "Done")
+                } else {
+                    ("", "Not done")
+                };
+                Event::Html(
+                    format!(
+                    "<input disabled=\"\" type=\"checkbox\"{checked} aria-label=\"{label}\"/>\n"
+                )
+                    .into(),
+                )
+            }
+            Event::Html(node) => {
+                let replacement = if node.starts_with("<script") {
+                    "<pre><code>"
+                } else if node.starts_with("</script") {
+                    "</code></pre>"
+                } else {
+                    &node
+                };
+                Event::Html(replacement.to_string().into())
+            }
+            _ => event,
+        })
+        .map(rewrite);
+    let events = with_heading_anchors(with_pdf_previews(parser), section_edit);
     let mut html_out = String::new();
-    html::push_html(&mut html_out, parser);
-    Ok(MarkupDisplay::new_safe(html_out, askama_escape::Html))
+    html::push_html(&mut html_out, events.into_iter());
+    with_preview_attributes(&crate::sanitize::clean(html_out), previews)
+}
+
+/// The block id at the end of a paragraph's text, like `^id`, and the text before it
+pub fn block_id(text: &str) -> Option<(&str, &str)> {
+    let (before, id) = text.trim_end().rsplit_once(' ')?;
+    let name = id.strip_prefix('^')?;
+    (!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+        .then_some((before, id))
+}
+
+/// Gives paragraphs ending with a block id that id and removes it from their text
+fn with_block_ids<'a>(events: impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+    let mut events: Vec<Event<'a>> = events.collect();
+    let mut start = None;
+    for i in 0..events.len() {
+        match &events[i] {
+            Event::Start(Tag::Paragraph) => start = Some(i),
+            Event::End(Tag::Paragraph) => {
+                let Some(start) = start.take() else {
+                    continue;
+                };
+                let Some(Event::Text(text)) = events.get(i - 1) else {
+                    continue;
+                };
+                let Some((before, id)) = block_id(text) else {
+                    continue;
+                };
+                let (before, id) = (before.to_string(), id.to_string());
+                events[i - 1] = Event::Text(before.into());
+                events[start] = Event::Html(format!("<p id=\"{id}\" dir=\"auto\">").into());
+            }
+            _ => {}
+        }
+    }
+    events
+}
+
+/// The id of every heading in `markdown`, in the order of [`crate::section::sections`]
+pub fn heading_ids(markdown: &str) -> Vec<String> {
+    let markdown = wikilinks(markdown);
+    let markdown = crate::mentions::link(&markdown);
+    headings(&markdown)
+        .into_iter()
+        .map(|(_, id, _)| id)
+        .collect()
+}
+
+/// The level, id and text of every heading in `markdown`, whose wikilinks
+/// and mentions are links already
+fn headings(markdown: &str) -> Vec<(HeadingLevel, String, String)> {
+    let mut used = HashMap::new();
+    let mut headings = vec![];
+    let mut heading: Option<(HeadingLevel, Option<String>, String)> = None;
+    for event in pulldown_cmark::Parser::new_ext(markdown, Options::all()) {
+        match event {
+            Event::Start(Tag::Heading(level, id, _)) => {
+                heading = Some((level, id.map(str::to_string), String::new()))
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, _, heading_text)) = &mut heading {
+                    heading_text.push_str(&text);
+                }
+            }
+            Event::End(Tag::Heading(..)) => {
+                if let Some((level, id, text)) = heading.take() {
+                    let id = heading_id(id, &text, &mut used);
+                    headings.push((level, id, text));
+                }
+            }
+            _ => {}
+        }
+    }
+    headings
+}
+
+/// Where `markdown` has a line with just [`TOC_MARKER`] outside of code
+fn toc_markers(markdown: &str) -> Vec<Range<usize>> {
+    let code = code_ranges(markdown);
+    let mut markers = vec![];
+    let mut start = 0;
+    for line in markdown.split_inclusive('\n') {
+        if line.trim() == TOC_MARKER && !code.iter().any(|range| range.contains(&start)) {
+            markers.push(start..start + line.len());
+        }
+        start += line.len();
+    }
+    markers
+}
+
+/// Whether `markdown` asks for a table of contents itself
+pub fn has_toc_marker(markdown: &str) -> bool {
+    !toc_markers(markdown).is_empty()
+}
+
+/// The table of contents of `headings` as lists nested like the headings
+fn toc(headings: &[(HeadingLevel, String, String)]) -> String {
+    let escape = |text: &str| askama_escape::escape(text, askama_escape::Html).to_string();
+    let mut html = String::from("<nav class=\"toc\" aria-label=\"Contents\">");
+    // The levels of the lists that are open
+    let mut open: Vec<HeadingLevel> = vec![];
+    for (level, id, text) in headings {
+        while open.last().is_some_and(|open| open > level) {
+            html.push_str("</li></ul>");
+            open.pop();
+        }
+        if open.last() == Some(level) {
+            html.push_str("</li>");
+        } else {
+            html.push_str("<ul>");
+            open.push(*level);
+        }
+        html.push_str(&format!(
+            "<li><a href=\"#{}\">{}</a>",
+            escape(id),
+            escape(text.trim())
+        ));
+    }
+    for _ in open {
+        html.push_str("</li></ul>");
+    }
+    html.push_str("</nav>");
+    html
+}
+
+/// Replaces the [`TOC_MARKER`] lines of `markdown` with its table of contents
+fn with_toc(markdown: &str) -> Cow<'_, str> {
+    let markers = toc_markers(markdown);
+    if markers.is_empty() {
+        return Cow::Borrowed(markdown);
+    }
+    let headings = headings(markdown);
+    // An HTML block, which ends at a blank line
+    let toc = match headings.is_empty() {
+        true => "\n".to_string(),
+        false => format!("\n{}\n\n", toc(&headings)),
+    };
+    let mut out = String::with_capacity(markdown.len() + toc.len());
+    let mut copied = 0;
+    for marker in markers {
+        out.push_str(&markdown[copied..marker.start]);
+        out.push_str(&toc);
+        copied = marker.end;
+    }
+    out.push_str(&markdown[copied..]);
+    Cow::Owned(out)
+}
+
+/// The targets of all images in `markdown` that have no alt text
+pub fn images_without_alt(markdown: &str) -> Vec<String> {
+    let mut images = vec![];
+    let mut current: Option<(String, String)> = None;
+    for event in pulldown_cmark::Parser::new_ext(markdown, Options::all()) {
+        match event {
+            Event::Start(Tag::Image(_, dest, _)) => {
+                current = Some((dest.to_string(), String::new()))
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, alt)) = &mut current {
+                    alt.push_str(&text);
+                }
+            }
+            Event::End(Tag::Image(..)) => {
+                if let Some((dest, alt)) = current.take() {
+                    if alt.trim().is_empty() {
+                        images.push(dest);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    images
+}
+
+/// Turns heading text into an id for the heading
+fn slug(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-' || c == '_') && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() {
+        "section".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// The explicit `id` of a heading, or one made from its `text` that isn't `used` yet
+fn heading_id(id: Option<String>, text: &str, used: &mut HashMap<String, usize>) -> String {
+    if let Some(id) = id {
+        return id;
+    }
+    let slug = slug(text);
+    let count = used.entry(slug.clone()).or_default();
+    *count += 1;
+    if *count == 1 {
+        slug
+    } else {
+        format!("{slug}-{count}")
+    }
+}
+
+/// Gives every heading an id (unless it has an explicit `{#id}`) and appends
+/// a permalink to it. With `section_edit`, headings also link to the editor
+/// for their section of the article at that path.
+fn with_heading_anchors<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+    section_edit: Option<&str>,
+) -> Vec<Event<'a>> {
+    let events: Vec<Event<'a>> = events.collect();
+    let mut out = Vec::with_capacity(events.len());
+    let mut used: HashMap<String, usize> = HashMap::new();
+    let mut section = 0;
+
+    let mut i = 0;
+    while i < events.len() {
+        let Event::Start(Tag::Heading(level, id, classes)) = &events[i] else {
+            out.push(events[i].clone());
+            i += 1;
+            continue;
+        };
+        let end = events[i..]
+            .iter()
+            .position(|event| matches!(event, Event::End(Tag::Heading(..))))
+            .map_or(events.len(), |end| i + end);
+        let inner = &events[i + 1..end];
+
+        let text: String = inner
+            .iter()
+            .filter_map(|event| match event {
+                Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                _ => None,
+            })
+            .collect();
+        let id = heading_id(id.map(|id| id.to_string()), &text, &mut used);
+        let id = askama_escape::escape(&id, askama_escape::Html).to_string();
+
+        let mut open = format!("<{level} id=\"{id}\" dir=\"auto\"");
+        if !classes.is_empty() {
+            let classes =
+                askama_escape::escape(&classes.join(" "), askama_escape::Html).to_string();
+            open.push_str(&format!(" class=\"{classes}\""));
+        }
+        open.push('>');
+        out.push(Event::Html(open.into()));
+        out.extend(inner.iter().cloned());
+
+        let mut close = format!(
+            "<a class=\"heading-anchor\" href=\"#{id}\" aria-label=\"Link to this section\">#</a>"
+        );
+        if let Some(path) = section_edit {
+            close.push_str(&format!(
+                "<a class=\"section-edit\" href=\"/edit/article/{path}?section={section}\">edit</a>"
+            ));
+        }
+        close.push_str(&format!("</{level}>\n"));
+        out.push(Event::Html(close.into()));
+
+        section += 1;
+        i = end + 1;
+    }
+    out
 }
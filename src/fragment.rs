@@ -0,0 +1,61 @@
+//! # Fragments
+//!
+//! `/api/fragment?article=...&id=...` returns one section or block of an
+//! article with its rendered HTML, which `previews.js` shows as a preview
+//! when hovering links like `[[Page#Section]]`. `id` is the id of the
+//! section's heading or a block id like `^id`, the same as the fragment of
+//! the link.
+use askama_axum::IntoResponse;
+use axum::extract::Query;
+use axum::http::StatusCode;
+use axum::Json;
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use serde::{Deserialize, Serialize};
+
+use crate::{filters, section, Article};
+
+#[derive(Deserialize)]
+pub struct FragmentQuery {
+    article: String,
+    id: String,
+}
+
+#[derive(Serialize)]
+struct Fragment {
+    title: String,
+    html: String,
+}
+
+/// The Markdown of the section or block `id` in `markdown`
+fn find(markdown: &str, id: &str) -> Option<String> {
+    if id.starts_with('^') {
+        Parser::new_ext(markdown, Options::all())
+            .into_offset_iter()
+            .find_map(|(event, range)| match event {
+                Event::Start(Tag::Paragraph) => {
+                    let (before, block) = filters::block_id(&markdown[range])?;
+                    (block == id).then(|| before.to_string())
+                }
+                _ => None,
+            })
+    } else {
+        let index = filters::heading_ids(markdown)
+            .iter()
+            .position(|heading| heading == id)?;
+        section::get(markdown, index).map(str::to_string)
+    }
+}
+
+pub async fn get_fragment(Query(query): Query<FragmentQuery>) -> impl IntoResponse {
+    let Some(article) = Article::load(&query.article).await else {
+        return (StatusCode::NOT_FOUND, "No such article").into_response();
+    };
+    match find(article.body(), &query.id) {
+        Some(markdown) => Json(Fragment {
+            title: article.title,
+            html: filters::render(&markdown, |event| event),
+        })
+        .into_response(),
+        None => (StatusCode::NOT_FOUND, "No such section or block").into_response(),
+    }
+}
@@ -0,0 +1,172 @@
+//! # Content Freeze
+//!
+//! While the wiki is frozen, every change is turned away with a page
+//! saying so, e.g. during a migration, an audit or before a release. It is
+//! frozen by hand on `/admin/freeze`, which is remembered in `freeze.json`
+//! in the content directory, and during the `freeze_windows` of the
+//! config. Windows are a start and an end time in RFC 3339 separated by a
+//! slash, like `2024-06-01T00:00:00Z/2024-06-03T00:00:00Z`. The freeze only
+//! applies to the web interface, commands like `tome import` still work.
+use std::time::SystemTime;
+
+use askama::Template;
+use axum::extract::State;
+use axum::http::{Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::Form;
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::{Rfc2822, Rfc3339};
+use time::OffsetDateTime;
+
+use crate::config::content_path;
+use crate::error::TomeError;
+use crate::layout::Layout;
+use crate::TomeConfig;
+
+const FREEZE_PATH: &str = "freeze.json";
+
+/// Why the wiki was frozen by hand
+#[derive(Serialize, Deserialize)]
+struct Frozen {
+    by: Option<String>,
+    since: SystemTime,
+}
+
+/// The start and end of a freeze window like `2024-06-01T00:00:00Z/2024-06-03T00:00:00Z`
+pub fn parse_window(window: &str) -> Option<(OffsetDateTime, OffsetDateTime)> {
+    let (start, end) = window.split_once('/')?;
+    let start = OffsetDateTime::parse(start.trim(), &Rfc3339).ok()?;
+    let end = OffsetDateTime::parse(end.trim(), &Rfc3339).ok()?;
+    (start < end).then_some((start, end))
+}
+
+/// The end of the freeze window the wiki is in now, if it is in one
+fn current_window(config: &TomeConfig) -> Option<OffsetDateTime> {
+    let now = OffsetDateTime::now_utc();
+    config
+        .freeze_windows
+        .iter()
+        .filter_map(|window| parse_window(window))
+        .filter(|(start, end)| *start <= now && now < *end)
+        .map(|(_, end)| end)
+        .max()
+}
+
+async fn read_frozen() -> Option<Frozen> {
+    let json = tokio::fs::read_to_string(content_path(FREEZE_PATH))
+        .await
+        .ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// Whether a request changes the wiki or opens an editor
+fn is_change(method: &Method, path: &str) -> bool {
+    // Logging in has to work to unfreeze the wiki
+    if ["/login", "/logout", "/admin/freeze", "/preview"].contains(&path) {
+        return false;
+    }
+    !(method == Method::GET || method == Method::HEAD)
+        || path.starts_with("/edit/")
+        || path.starts_with("/m/edit/")
+}
+
+#[derive(Template)]
+#[template(path = "frozen.html")]
+struct FrozenPage {
+    layout: Layout,
+    /// When the freeze window ends, if the wiki wasn't frozen by hand
+    until: Option<String>,
+}
+
+/// Middleware turning away changes while the wiki is frozen
+pub async fn guard<B>(
+    State(config): State<TomeConfig>,
+    layout: Layout,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if !is_change(request.method(), request.uri().path()) {
+        return next.run(request).await;
+    }
+    let until = if read_frozen().await.is_some() {
+        None
+    } else if let Some(end) = current_window(&config) {
+        Some(end.format(&Rfc2822).unwrap())
+    } else {
+        return next.run(request).await;
+    };
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        FrozenPage { layout, until },
+    )
+        .into_response()
+}
+
+#[derive(Template)]
+#[template(path = "freeze.html")]
+struct FreezePage {
+    layout: Layout,
+    /// Who froze the wiki by hand and when
+    frozen: Option<(Option<String>, String)>,
+    /// The configured windows, and whether the wiki is in them now
+    windows: Vec<(String, bool)>,
+}
+
+pub async fn get_freeze(layout: Layout, State(config): State<TomeConfig>) -> impl IntoResponse {
+    let now = OffsetDateTime::now_utc();
+    let windows = config
+        .freeze_windows
+        .iter()
+        .map(|window| {
+            let active = parse_window(window).is_some_and(|(start, end)| start <= now && now < end);
+            (window.clone(), active)
+        })
+        .collect();
+    let frozen = read_frozen().await.map(|frozen| {
+        let since = OffsetDateTime::from(frozen.since).format(&Rfc2822).unwrap();
+        (frozen.by, since)
+    });
+    FreezePage {
+        layout,
+        frozen,
+        windows,
+    }
+}
+
+#[derive(Deserialize)]
+pub struct FreezeForm {
+    action: String,
+}
+
+pub async fn post_freeze(
+    layout: Layout,
+    Form(form): Form<FreezeForm>,
+) -> Result<impl IntoResponse, TomeError> {
+    match form.action.as_str() {
+        "freeze" => {
+            let frozen = Frozen {
+                by: layout.user.clone(),
+                since: SystemTime::now(),
+            };
+            tokio::fs::write(
+                content_path(FREEZE_PATH),
+                serde_json::to_string_pretty(&frozen)?,
+            )
+            .await?;
+            tracing::info!("The wiki was frozen");
+        }
+        "unfreeze" => {
+            if tokio::fs::metadata(content_path(FREEZE_PATH)).await.is_ok() {
+                tokio::fs::remove_file(content_path(FREEZE_PATH)).await?;
+            }
+            tracing::info!("The wiki was unfrozen");
+        }
+        action => {
+            return Err(TomeError::BadRequest(format!(
+                "\"{action}\" is neither freeze nor unfreeze"
+            )))
+        }
+    }
+    Ok(Redirect::to("/admin/freeze"))
+}
@@ -0,0 +1,220 @@
+//! # Article Frontmatter
+//!
+//! Articles may start with a YAML block delimited by `---` lines, or a
+//! TOML block delimited by `+++` lines, that holds metadata such as tags.
+//! These helpers split that block from the Markdown body and write
+//! modified metadata back, always as YAML.
+//!
+//! [`Metadata`] collects the fields articles have in common, `title`,
+//! `tags`, `created` and `draft`, along with every field tome doesn't
+//! know, so templates can show them.
+use serde_yaml::{Mapping, Value};
+use time::macros::format_description;
+use time::Date;
+
+/// Fields with a meaning of their own, which aren't listed as custom fields
+const KNOWN_FIELDS: &[&str] = &[
+    "title",
+    "tags",
+    "created",
+    "draft",
+    "autolink",
+    "license",
+    "dir",
+    "review_by",
+    "watchers",
+    "requires_review",
+    "archived",
+    "toc",
+];
+
+/// Splits `content` into its raw frontmatter (without delimiters) and the body.
+pub fn split(content: &str) -> (Option<&str>, &str) {
+    for delimiter in ["---", "+++"] {
+        let Some(rest) = content.strip_prefix(delimiter).and_then(|rest| {
+            rest.strip_prefix('\n')
+                .or_else(|| rest.strip_prefix("\r\n"))
+        }) else {
+            continue;
+        };
+
+        let mut offset = 0;
+        for line in rest.split_inclusive('\n') {
+            if line.trim_end() == delimiter {
+                return (Some(&rest[..offset]), &rest[offset + line.len()..]);
+            }
+            offset += line.len();
+        }
+    }
+
+    (None, content)
+}
+
+/// Converts TOML to YAML, with dates as strings like `2024-01-31`
+fn from_toml(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(string) => Value::from(string),
+        toml::Value::Integer(integer) => Value::from(integer),
+        toml::Value::Float(float) => Value::from(float),
+        toml::Value::Boolean(boolean) => Value::from(boolean),
+        toml::Value::Datetime(datetime) => Value::from(datetime.to_string()),
+        toml::Value::Array(values) => Value::Sequence(values.into_iter().map(from_toml).collect()),
+        toml::Value::Table(table) => Value::Mapping(
+            table
+                .into_iter()
+                .map(|(key, value)| (Value::from(key), from_toml(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Parses the frontmatter of `content`, which is an empty mapping if there is none.
+/// Fails with the reason if it isn't a valid YAML or TOML mapping, code writing
+/// the frontmatter back must then leave it as it is, or its fields would be lost.
+/// Code only reading it can use the empty mapping instead.
+pub fn parse(content: &str) -> Result<Mapping, String> {
+    let Some(raw) = split(content).0.filter(|raw| !raw.trim().is_empty()) else {
+        return Ok(Mapping::new());
+    };
+    if content.starts_with("+++") {
+        match raw.parse::<toml::Value>().map(from_toml) {
+            Ok(Value::Mapping(meta)) => Ok(meta),
+            Ok(_) => Err("the frontmatter isn't a table".to_string()),
+            Err(e) => Err(e.to_string()),
+        }
+    } else {
+        serde_yaml::from_str(raw).map_err(|e| e.to_string())
+    }
+}
+
+/// Combines `meta` and `body` into article content.
+/// An empty mapping produces no frontmatter block at all.
+pub fn join(meta: &Mapping, body: &str) -> String {
+    if meta.is_empty() {
+        return body.to_string();
+    }
+    let yaml = serde_yaml::to_string(meta).unwrap_or_default();
+    let yaml = yaml.strip_prefix("---\n").unwrap_or(&yaml);
+    format!("---\n{yaml}---\n{body}")
+}
+
+/// Returns the tags listed under `tags:` in `meta`.
+pub fn tags(meta: &Mapping) -> Vec<String> {
+    match meta.get(&Value::from("tags")) {
+        Some(Value::Sequence(tags)) => tags
+            .iter()
+            .filter_map(|tag| tag.as_str().map(str::to_string))
+            .collect(),
+        Some(Value::String(tag)) => vec![tag.clone()],
+        _ => vec![],
+    }
+}
+
+/// Replaces the `tags:` list in `meta`, removing it if `tags` is empty.
+pub fn set_tags(meta: &mut Mapping, tags: Vec<String>) {
+    if tags.is_empty() {
+        meta.remove(&Value::from("tags"));
+    } else {
+        meta.insert(
+            Value::from("tags"),
+            Value::Sequence(tags.into_iter().map(Value::from).collect()),
+        );
+    }
+}
+
+/// Returns the display title under `title:` in `meta`, if there is one.
+pub fn title(meta: &Mapping) -> Option<String> {
+    meta.get(&Value::from("title"))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+/// Returns the license under `license:` in `meta`, if there is one.
+pub fn license(meta: &Mapping) -> Option<String> {
+    meta.get(&Value::from("license"))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+/// Replaces the `title:` in `meta`, removing it if `title` is `None`.
+pub fn set_title(meta: &mut Mapping, title: Option<&str>) {
+    match title {
+        Some(title) => {
+            meta.insert(Value::from("title"), Value::from(title));
+        }
+        None => {
+            meta.remove(&Value::from("title"));
+        }
+    }
+}
+
+/// `value` as text, with lists separated by commas
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(string) => Some(string.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(boolean) => Some(boolean.to_string()),
+        Value::Sequence(values) => Some(
+            values
+                .iter()
+                .filter_map(text)
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        Value::Null | Value::Mapping(_) => None,
+    }
+}
+
+/// The metadata of an article
+#[derive(Default)]
+pub struct Metadata {
+    /// The display title, if it differs from the slug
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+    /// When the article was written, from `created: YYYY-MM-DD`
+    pub created: Option<Date>,
+    /// Whether the article isn't finished yet
+    pub draft: bool,
+    /// Whether titles of other articles are linked automatically, if the article decides
+    pub autolink: Option<bool>,
+    /// Whether a table of contents is shown above the article, from `toc: true`
+    pub toc: bool,
+    /// Every other field with its value as text, in the order they are written
+    pub fields: Vec<(String, String)>,
+}
+
+impl Metadata {
+    pub fn from(meta: &Mapping) -> Self {
+        let created = meta
+            .get(&Value::from("created"))
+            .and_then(Value::as_str)
+            // Times after the date are ignored
+            .and_then(|created| created.get(..10))
+            .and_then(|date| Date::parse(date, format_description!("[year]-[month]-[day]")).ok());
+        let fields = meta
+            .iter()
+            .filter_map(|(key, value)| {
+                let key = key.as_str()?;
+                if KNOWN_FIELDS.contains(&key) {
+                    return None;
+                }
+                Some((key.to_string(), text(value)?))
+            })
+            .collect();
+        Metadata {
+            title: title(meta),
+            tags: tags(meta),
+            created,
+            draft: meta
+                .get(&Value::from("draft"))
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            autolink: meta.get(&Value::from("autolink")).and_then(Value::as_bool),
+            toc: meta
+                .get(&Value::from("toc"))
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            fields,
+        }
+    }
+}
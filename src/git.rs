@@ -0,0 +1,206 @@
+//! # Git Storage
+//!
+//! With `git_storage`, the `articles/` directory of the content is a git repository and every
+//! saved version is a commit of the article's `current.md`, so the history
+//! can be read, diffed and backed up with git itself. Version ids are
+//! commit hashes, and the history follows articles across renames. With
+//! `git_remote`, every commit is pushed there in the background.
+//!
+//! Versions stored before switching to git are still read from the
+//! content-addressed storage. Purging a deleted article removes its files,
+//! but its commits stay in the repository.
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+use crate::slug::slug;
+use crate::storage::{articles_dir, dir_name, ContentAddressed, Storage};
+use crate::wiki;
+
+/// Guards the repository's index against concurrent commits
+static LOCK: Mutex<()> = Mutex::const_new(());
+
+pub struct Git {
+    remote: Option<String>,
+}
+
+/// Runs git in the repository and returns its output
+async fn git(args: &[&str]) -> tokio::io::Result<String> {
+    let output = Command::new("git")
+        .args(["-c", "user.name=Tome", "-c", "user.email=tome@localhost"])
+        .args(args)
+        .current_dir(articles_dir())
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(tokio::io::Error::other(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The path of an article's `current.md` in the repository
+fn current_path(title: &str) -> String {
+    format!("{}/current.md", dir_name(&slug(title)))
+}
+
+fn is_commit(version: &str) -> bool {
+    version.len() == 40 && version.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// The commits that changed `path`, newest first, with the path at the time
+async fn log(path: &str) -> Vec<(String, SystemTime, String)> {
+    let Ok(log) = git(&[
+        "log",
+        "--follow",
+        "--name-only",
+        "--format=%x00%H %ct",
+        "--",
+        path,
+    ])
+    .await
+    else {
+        return vec![];
+    };
+    log.split('\0')
+        .filter_map(|entry| {
+            let mut lines = entry.trim().lines();
+            let (hash, time) = lines.next()?.split_once(' ')?;
+            let time = SystemTime::UNIX_EPOCH + Duration::from_secs(time.parse().ok()?);
+            Some((hash.to_string(), time, lines.last()?.to_string()))
+        })
+        .collect()
+}
+
+impl Git {
+    /// Opens the repository, creating it with the existing articles if there is none
+    pub async fn open(remote: Option<String>) -> tokio::io::Result<Self> {
+        if tokio::fs::metadata(format!("{}/.git", articles_dir()))
+            .await
+            .is_err()
+        {
+            git(&["init", "--quiet"]).await?;
+            // Fails if there are no articles yet
+            let imported = git(&["add", "--", ":(glob)*/current.md"]).await.is_ok();
+            if imported && git(&["diff", "--cached", "--quiet"]).await.is_err() {
+                git(&["commit", "--quiet", "-m", "Import existing articles"]).await?;
+            }
+            tracing::info!("Created a git repository in {}", articles_dir());
+        }
+        Ok(Git { remote })
+    }
+
+    /// Commits the changes to `paths`, returns whether there were any
+    async fn commit(&self, paths: &[&str], message: &str) -> tokio::io::Result<bool> {
+        // git refuses paths that neither exist nor are tracked
+        let mut changed = vec![];
+        for path in paths {
+            let exists = tokio::fs::metadata(format!("{}/{path}", articles_dir()))
+                .await
+                .is_ok();
+            if exists
+                || git(&["ls-files", "--error-unmatch", "--", path])
+                    .await
+                    .is_ok()
+            {
+                changed.push(*path);
+            }
+        }
+        if changed.is_empty() {
+            return Ok(false);
+        }
+
+        let mut add = vec!["add", "--all", "--"];
+        add.extend(&changed);
+        git(&add).await?;
+        let mut diff = vec!["diff", "--cached", "--quiet", "--"];
+        diff.extend(&changed);
+        if git(&diff).await.is_ok() {
+            return Ok(false);
+        }
+        let mut commit = vec!["commit", "--quiet", "-m", message, "--"];
+        commit.extend(&changed);
+        git(&commit).await?;
+
+        if let Some(remote) = self.remote.clone() {
+            wiki::spawn(async move {
+                if let Err(e) = git(&["push", "--quiet", &remote, "HEAD"]).await {
+                    tracing::error!("Could not push to {remote}: {e}");
+                }
+            });
+        }
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl Storage for Git {
+    async fn save(&self, title: &str, content: &str) -> tokio::io::Result<String> {
+        let _lock = LOCK.lock().await;
+        let path = current_path(title);
+        tokio::fs::write(format!("{}/{path}", articles_dir()), content).await?;
+        self.commit(&[&path], &format!("Update {title}")).await?;
+        // Saving the same content again doesn't make a commit, it stays the latest version
+        Ok(git(&["log", "-1", "--format=%H", "--", &path])
+            .await?
+            .trim()
+            .to_string())
+    }
+
+    async fn load(&self, title: &str, version: &str) -> Option<String> {
+        if !is_commit(version) {
+            return ContentAddressed.load(title, version).await;
+        }
+        let (_, _, path) = log(&current_path(title))
+            .await
+            .into_iter()
+            .find(|(hash, _, _)| hash == version)?;
+        git(&["show", &format!("{version}:{path}")]).await.ok()
+    }
+
+    async fn versions(&self, title: &str) -> Vec<(String, SystemTime)> {
+        let mut versions = ContentAddressed.versions(title).await;
+        versions.extend(
+            log(&current_path(title))
+                .await
+                .into_iter()
+                .rev()
+                .map(|(hash, time, _)| (hash, time)),
+        );
+        versions
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> tokio::io::Result<()> {
+        let _lock = LOCK.lock().await;
+        ContentAddressed.rename(from, to).await?;
+        let (from_path, to_path) = (current_path(from), current_path(to));
+        self.commit(&[&from_path, &to_path], &format!("Rename {from} to {to}"))
+            .await?;
+        Ok(())
+    }
+
+    async fn trash(&self, title: &str, id: &str) -> tokio::io::Result<()> {
+        let _lock = LOCK.lock().await;
+        ContentAddressed.trash(title, id).await?;
+        self.commit(&[&current_path(title)], &format!("Delete {title}"))
+            .await?;
+        Ok(())
+    }
+
+    async fn restore(&self, id: &str, title: &str) -> tokio::io::Result<()> {
+        let _lock = LOCK.lock().await;
+        ContentAddressed.restore(id, title).await?;
+        self.commit(&[&current_path(title)], &format!("Restore {title}"))
+            .await?;
+        Ok(())
+    }
+
+    async fn purge(&self, id: &str) -> tokio::io::Result<()> {
+        ContentAddressed.purge(id).await
+    }
+}
@@ -0,0 +1,46 @@
+//! # Glossary
+//!
+//! `glossary_article` names an article that explains the wiki's jargon in
+//! list items like `- API: Application Programming Interface`. Wherever
+//! other articles mention one of its terms, the term is shown as an
+//! `<abbr>` with the explanation as its title, so readers can hover over it.
+//! Terms are matched like [automatic links](crate::autolink), but only with
+//! the case they are written in, and every mention is marked.
+use crate::{autolink, Article, TomeConfig};
+
+/// The terms and explanations in the list items of the glossary's `markdown`
+pub fn parse(markdown: &str) -> Vec<(String, String)> {
+    markdown
+        .lines()
+        .filter_map(|line| {
+            let item = line.trim_start().strip_prefix(['-', '*'])?;
+            let (term, explanation) = item.split_once(':')?;
+            // Terms may be bold, like `- **API**: ...`
+            let term = term.trim().trim_matches('*').trim();
+            let explanation = explanation.trim();
+            (!term.is_empty() && !explanation.is_empty())
+                .then(|| (term.to_string(), explanation.to_string()))
+        })
+        .collect()
+}
+
+/// Marks the glossary's terms in `markdown`, the body of the article `title`
+pub async fn expand(config: &TomeConfig, title: &str, markdown: &str) -> String {
+    let Some(glossary) = &config.glossary_article else {
+        return markdown.to_string();
+    };
+    let Some(glossary) = Article::load(glossary).await else {
+        return markdown.to_string();
+    };
+    if glossary.title == title {
+        return markdown.to_string();
+    }
+    let (terms, explanations): (Vec<String>, Vec<String>) =
+        parse(glossary.body()).into_iter().unzip();
+    let found = autolink::find(markdown, &terms, false);
+    autolink::replace(markdown.into(), found, |mention, index| {
+        let explanation = askama_escape::escape(&explanations[index], askama_escape::Html);
+        format!("<abbr title=\"{explanation}\">{mention}</abbr>")
+    })
+    .into_owned()
+}
@@ -0,0 +1,241 @@
+//! # Append-Only History
+//!
+//! With `append_only_history` enabled, every saved version is recorded in
+//! `content/history.log`, one JSON entry per line. Renaming an article or
+//! restoring it from the trash records its current version again, noting
+//! what happened. Each entry contains the hash of the previous one, so
+//! removing or changing an entry (or the version it describes) breaks the
+//! chain, which `tome history verify` detects. Versions must never be
+//! deleted in this mode, so articles can't be deleted or purged either.
+use clap::{Args, Subcommand};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::config::content_path;
+use crate::{rename, wiki, Article, TomeConfig};
+
+const LOG_PATH: &str = "history.log";
+/// The `prev` of the first entry
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Sequence number and hash of the last entry
+pub(crate) type LastEntry = (u64, String);
+
+pub fn init(config: &TomeConfig) {
+    if config.append_only_history {
+        // The log is read when the first version is recorded
+        let _ = wiki::current().last_entry.set(Mutex::new(None));
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    seq: u64,
+    time: String,
+    article: String,
+    version: String,
+    sha256: String,
+    prev: String,
+    hash: String,
+    /// What happened to the article, for entries that don't record a new version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    event: Option<String>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+impl Entry {
+    fn compute_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for field in [
+            &self.seq.to_string(),
+            &self.time,
+            &self.article,
+            &self.version,
+            &self.sha256,
+            &self.prev,
+        ] {
+            hasher.update(field.as_bytes());
+            hasher.update([0]);
+        }
+        // Entries without an event hash like those of older releases
+        if let Some(event) = &self.event {
+            hasher.update(event.as_bytes());
+            hasher.update([0]);
+        }
+        hex(&hasher.finalize())
+    }
+}
+
+async fn read_log() -> tokio::io::Result<Vec<Result<Entry, usize>>> {
+    let log = match tokio::fs::read_to_string(content_path(LOG_PATH)).await {
+        Ok(log) => log,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    Ok(log
+        .lines()
+        .enumerate()
+        .map(|(line, entry)| serde_json::from_str(entry).map_err(|_| line + 1))
+        .collect())
+}
+
+/// Appends a newly written version to the log, if the log is enabled
+pub async fn record(title: &str, version: &str, content: &str) -> tokio::io::Result<()> {
+    append(title, version, content, None).await
+}
+
+/// Records the current version of the article `title` again because of `event`,
+/// like "Renamed from Old title", if the log is enabled
+pub async fn record_event(title: &str, event: &str) -> tokio::io::Result<()> {
+    if wiki::current().last_entry.get().is_none() {
+        return Ok(());
+    }
+    let (Some(version), Some(article)) = (
+        Article::current_version(title).await,
+        Article::load(title).await,
+    ) else {
+        return Ok(());
+    };
+    append(title, &version, &article.content, Some(event)).await
+}
+
+async fn append(
+    title: &str,
+    version: &str,
+    content: &str,
+    event: Option<&str>,
+) -> tokio::io::Result<()> {
+    let wiki = wiki::current();
+    let Some(last) = wiki.last_entry.get() else {
+        return Ok(());
+    };
+    let mut last = last.lock().await;
+    if last.is_none() {
+        *last = read_log()
+            .await?
+            .into_iter()
+            .filter_map(Result::ok)
+            .next_back()
+            .map(|entry| (entry.seq, entry.hash));
+    }
+
+    let (seq, prev) = match &*last {
+        Some((seq, hash)) => (seq + 1, hash.clone()),
+        None => (0, GENESIS.to_string()),
+    };
+    let mut entry = Entry {
+        seq,
+        time: OffsetDateTime::now_utc().format(&Rfc3339).unwrap(),
+        article: title.to_string(),
+        version: version.to_string(),
+        sha256: hex(&Sha256::digest(content.as_bytes())),
+        prev,
+        hash: String::new(),
+        event: event.map(str::to_string),
+    };
+    entry.hash = entry.compute_hash();
+
+    let mut log = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(content_path(LOG_PATH))
+        .await?;
+    log.write_all(format!("{}\n", serde_json::to_string(&entry)?).as_bytes())
+        .await?;
+    *last = Some((entry.seq, entry.hash));
+    Ok(())
+}
+
+/// Arguments for `tome history`
+#[derive(Args)]
+pub struct HistoryArgs {
+    #[command(subcommand)]
+    command: HistoryCommand,
+}
+
+#[derive(Subcommand)]
+enum HistoryCommand {
+    /// Check the hash chain of the history log and the versions it records
+    Verify,
+}
+
+async fn verify() -> color_eyre::Result<()> {
+    let mut problems = vec![];
+    let mut prev = GENESIS.to_string();
+    let mut count = 0;
+
+    for (index, entry) in read_log().await?.into_iter().enumerate() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(line) => {
+                problems.push(format!("line {line}: not a valid entry"));
+                continue;
+            }
+        };
+        if entry.seq != index as u64 {
+            problems.push(format!(
+                "entry {}: expected sequence number {index}",
+                entry.seq
+            ));
+        }
+        if entry.prev != prev {
+            problems.push(format!(
+                "entry {}: doesn't follow the previous entry",
+                entry.seq
+            ));
+        }
+        if entry.hash != entry.compute_hash() {
+            problems.push(format!(
+                "entry {}: hash doesn't match its content",
+                entry.seq
+            ));
+        }
+        // Versions move with their article when it is renamed
+        let article = match Article::load_version(&entry.article, &entry.version).await {
+            Some(article) => Some(article),
+            None => match rename::resolve(&entry.article).await {
+                Some(title) => Article::load_version(&title, &entry.version).await,
+                None => None,
+            },
+        };
+        match article {
+            Some(article) if hex(&Sha256::digest(article.content.as_bytes())) == entry.sha256 => {}
+            Some(_) => problems.push(format!(
+                "entry {}: version {} of {} was modified",
+                entry.seq, entry.version, entry.article
+            )),
+            None => problems.push(format!(
+                "entry {}: version {} of {} is missing",
+                entry.seq, entry.version, entry.article
+            )),
+        }
+        prev = entry.hash;
+        count += 1;
+    }
+
+    if problems.is_empty() {
+        println!("History is intact ({count} entries).");
+        Ok(())
+    } else {
+        for problem in &problems {
+            println!("{problem}");
+        }
+        Err(color_eyre::eyre::eyre!(
+            "Found {} problems in {LOG_PATH}",
+            problems.len()
+        ))
+    }
+}
+
+pub async fn run(args: HistoryArgs) -> color_eyre::Result<()> {
+    match args.command {
+        HistoryCommand::Verify => verify().await,
+    }
+}
@@ -0,0 +1,97 @@
+//! # Hotlink Protection
+//!
+//! With `hotlink_protection`, other sites can't embed the media of the
+//! wiki: requests under `/media/` whose `Referer` is another site are
+//! turned away, unless that site is in `hotlink_allowed_hosts` (where
+//! `*.example.com` allows every subdomain) or the request has the
+//! `hotlink_token` as `?token=`. Requests without a `Referer`, like
+//! someone opening a link, are always served, since browsers and privacy
+//! tools often leave it out.
+use axum::extract::State;
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::TomeConfig;
+
+/// The host of a URL like `https://user@example.com:8080/page`, in lowercase
+pub fn host_of(url: &str) -> Option<String> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    // IPv6 addresses are in brackets and contain colons themselves
+    let host = match host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next()?,
+        None => host.split(':').next()?,
+    };
+    (!host.is_empty()).then(|| host.to_lowercase())
+}
+
+/// Whether `host` is `allowed` or a subdomain of an allowed `*.example.com`
+fn matches(host: &str, allowed: &str) -> bool {
+    let allowed = allowed.trim().to_lowercase();
+    match allowed.strip_prefix("*.") {
+        Some(domain) => host == domain || host.ends_with(&format!(".{domain}")),
+        None => host == allowed,
+    }
+}
+
+/// Whether `host` is the wiki itself, which was asked for as `own_host`
+pub fn is_own_host(config: &TomeConfig, own_host: Option<&str>, host: &str) -> bool {
+    let public_host = config.public_url.as_deref().and_then(host_of);
+    own_host.and_then(host_of).as_deref() == Some(host) || public_host.as_deref() == Some(host)
+}
+
+/// Whether a page on `referer` may embed media of the wiki at `own_host`
+fn may_embed(config: &TomeConfig, own_host: Option<&str>, referer: &str) -> bool {
+    let Some(referer) = host_of(referer) else {
+        return true;
+    };
+    is_own_host(config, own_host, &referer)
+        || config
+            .hotlink_allowed_hosts
+            .iter()
+            .any(|allowed| matches(&referer, allowed))
+}
+
+fn has_token<B>(config: &TomeConfig, request: &Request<B>) -> bool {
+    let Some(token) = &config.hotlink_token else {
+        return false;
+    };
+    let query: Vec<(String, String)> =
+        serde_urlencoded::from_str(request.uri().query().unwrap_or_default()).unwrap_or_default();
+    query
+        .iter()
+        .any(|(key, value)| key == "token" && value == token)
+}
+
+pub async fn protect<B>(
+    State(config): State<TomeConfig>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if !config.hotlink_protection {
+        return next.run(request).await;
+    }
+    let headers = request.headers();
+    let Some(referer) = headers
+        .get(header::REFERER)
+        .and_then(|referer| referer.to_str().ok())
+    else {
+        return next.run(request).await;
+    };
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok());
+    if may_embed(&config, host, referer) || has_token(&config, &request) {
+        return next.run(request).await;
+    }
+    tracing::debug!("Refused to serve {} to {referer}", request.uri().path());
+    (
+        StatusCode::FORBIDDEN,
+        "Other sites can't embed the media of this wiki.",
+    )
+        .into_response()
+}
@@ -0,0 +1,202 @@
+//! # Image Variants
+//!
+//! With `image_variants = ["webp"]`, or `"avif"` with the `avif` feature,
+//! uploaded PNGs, JPEGs and BMPs are also encoded in those formats, and
+//! `/media/<name>` serves browsers the first of them their `Accept` header
+//! allows instead of the original, as long as it is smaller. Links keep
+//! the original name, so nothing in the articles changes. Variants are
+//! cached in `.variants/` of the content directory, and images from before
+//! they were configured get theirs in the background when first asked for.
+use std::collections::HashSet;
+use std::fs::Metadata;
+use std::sync::{Mutex, OnceLock};
+
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderValue, Request};
+use axum::response::{IntoResponse, Response};
+use image::{DynamicImage, ImageFormat};
+use tower_http::services::ServeFile;
+
+use crate::config::content_path;
+use crate::error::TomeError;
+use crate::{paths, shutdown, wiki, TomeConfig};
+
+const VARIANTS_PATH: &str = ".variants";
+
+/// The formats images can be converted to, with their name in `image_variants`
+pub fn supported() -> Vec<(&'static str, ImageFormat)> {
+    #[allow(unused_mut)]
+    let mut supported = vec![("webp", ImageFormat::WebP)];
+    #[cfg(feature = "avif")]
+    supported.push(("avif", ImageFormat::Avif));
+    supported
+}
+
+/// The formats of `image_variants`, in the order browsers get them
+fn formats(config: &TomeConfig) -> Vec<ImageFormat> {
+    let supported = supported();
+    config
+        .image_variants
+        .iter()
+        .filter_map(|variant| {
+            supported
+                .iter()
+                .find(|(name, _)| variant.eq_ignore_ascii_case(name))
+                .map(|(_, format)| *format)
+        })
+        .collect()
+}
+
+/// Whether the media file `name` is an image that variants are made of.
+/// GIFs would lose their animation.
+fn is_convertible(name: &str) -> bool {
+    matches!(
+        ImageFormat::from_path(name),
+        Ok(ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Bmp)
+    )
+}
+
+fn variant_path(format: ImageFormat, name: &str) -> String {
+    let extension = format.extensions_str()[0];
+    content_path(&format!("{VARIANTS_PATH}/{extension}/{name}.{extension}"))
+}
+
+/// The variants that are being made right now, so they are only made once
+fn in_progress() -> &'static Mutex<HashSet<String>> {
+    static IN_PROGRESS: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+    IN_PROGRESS.get_or_init(Default::default)
+}
+
+/// Makes the variant of the media file `name` in `format`
+async fn make(format: ImageFormat, name: &str) -> std::io::Result<()> {
+    let original = content_path(&format!("media/{name}"));
+    let target = variant_path(format, name);
+    if !in_progress().lock().unwrap().insert(target.clone()) {
+        return Ok(());
+    }
+    let made = tokio::task::spawn_blocking({
+        let target = target.clone();
+        move || {
+            let Ok(image) = image::open(&original) else {
+                return Ok(());
+            };
+            // The encoders only take 8 bits per channel
+            let image = match image.color().has_alpha() {
+                true => DynamicImage::from(image.to_rgba8()),
+                false => DynamicImage::from(image.to_rgb8()),
+            };
+            let target = std::path::Path::new(&target);
+            let dir = target.parent().unwrap();
+            std::fs::create_dir_all(dir)?;
+            // Written next to it first, so nobody is served half a variant
+            let partial = dir.join(uuid::Uuid::new_v4().hyphenated().to_string());
+            if let Err(e) = image.save_with_format(&partial, format) {
+                let _ = std::fs::remove_file(&partial);
+                return Err(std::io::Error::other(e));
+            }
+            std::fs::rename(&partial, target)
+        }
+    })
+    .await;
+    in_progress().lock().unwrap().remove(&target);
+    made?
+}
+
+/// Removes the variants of the media file `name`, after it was deleted or renamed
+pub async fn remove(name: &str) {
+    for (_, format) in supported() {
+        let variant = variant_path(format, name);
+        if tokio::fs::metadata(&variant).await.is_ok() {
+            let _ = tokio::fs::remove_file(variant).await;
+        }
+    }
+}
+
+/// Makes the variants of the media file `name` again, after it was uploaded
+pub async fn refresh(config: &TomeConfig, name: &str) {
+    remove(name).await;
+    if !is_convertible(name) {
+        return;
+    }
+    for format in formats(config) {
+        if let Err(e) = make(format, name).await {
+            tracing::warn!("Couldn't convert {name} to {format:?}: {e}");
+        }
+    }
+}
+
+/// The variant of `name` in `format`, if it is up to date and smaller than
+/// the `original`. Missing and outdated ones are made in the background.
+async fn usable_variant(format: ImageFormat, name: &str, original: &Metadata) -> Option<String> {
+    let path = variant_path(format, name);
+    let variant = tokio::fs::metadata(&path).await.ok();
+    let modified = original.modified().ok()?;
+    match variant {
+        Some(variant) if variant.modified().ok()? >= modified => {
+            (variant.len() < original.len()).then_some(path)
+        }
+        _ => {
+            let name = name.to_string();
+            wiki::spawn(async move {
+                let _writing = shutdown::writing().await;
+                if let Err(e) = make(format, &name).await {
+                    tracing::warn!("Couldn't convert {name} to {format:?}: {e}");
+                }
+            });
+            None
+        }
+    }
+}
+
+async fn serve(path: &str, request: Request<Body>) -> Result<Response, TomeError> {
+    let response = ServeFile::new(path).try_call(request).await?;
+    Ok(response.map(axum::body::boxed).into_response())
+}
+
+pub async fn get_media(
+    State(config): State<TomeConfig>,
+    Path(name): Path<String>,
+    request: Request<Body>,
+) -> Result<Response, TomeError> {
+    let formats = formats(&config);
+    let original = content_path(&format!("media/{name}"));
+    if formats.is_empty() || !is_convertible(&name) || !paths::is_file_name(&name) {
+        return serve(&original, request).await;
+    }
+
+    let accept = request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let mut chosen = None;
+    if let Ok(metadata) = tokio::fs::metadata(&original).await {
+        for format in formats {
+            if !accept.contains(format.to_mime_type()) {
+                continue;
+            }
+            if let Some(path) = usable_variant(format, &name, &metadata).await {
+                chosen = Some((path, format));
+                break;
+            }
+        }
+    }
+    let mut response = match chosen {
+        Some((path, format)) => {
+            let mut response = serve(&path, request).await?;
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(format.to_mime_type()),
+            );
+            response
+        }
+        None => serve(&original, request).await?,
+    };
+    // Caches have to keep the variants apart
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("Accept"));
+    Ok(response)
+}
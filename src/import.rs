@@ -0,0 +1,128 @@
+//! # Importers
+//!
+//! `tome import` converts content exported from other wikis and note-taking
+//! tools into tome articles and media files. Every importer only produces
+//! an [`Import`], which is then written to the content directory in one place.
+mod dokuwiki;
+mod gollum;
+mod notion;
+mod obsidian;
+
+use std::path::{Path, PathBuf};
+
+use clap::{Args, ValueEnum};
+
+use crate::config::content_path;
+use crate::{paths, Article};
+
+/// Arguments for `tome import`
+#[derive(Args)]
+pub struct ImportArgs {
+    /// The format of the data to import
+    #[arg(long, value_enum, alias = "from")]
+    format: ImportFormat,
+    /// The exported archive or directory
+    path: PathBuf,
+}
+
+#[derive(Clone, ValueEnum)]
+enum ImportFormat {
+    /// A zip archive from Notion's "Markdown & CSV" export
+    Notion,
+    /// The data directory of a DokuWiki installation
+    Dokuwiki,
+    /// The git repository of a Gollum wiki
+    Gollum,
+    /// An Obsidian vault or any other folder of Markdown files
+    Obsidian,
+}
+
+/// A single imported article with all its versions, oldest first
+struct Page {
+    title: String,
+    versions: Vec<String>,
+}
+
+/// Everything an importer found
+#[derive(Default)]
+struct Import {
+    pages: Vec<Page>,
+    media: Vec<(String, Vec<u8>)>,
+}
+
+impl Import {
+    fn add_page(&mut self, title: String, content: String) {
+        self.pages.push(Page {
+            title,
+            versions: vec![content],
+        });
+    }
+
+    async fn write_to_disk(self) -> tokio::io::Result<()> {
+        for (name, data) in &self.media {
+            if let Err(message) = paths::check_file_name(name) {
+                tracing::warn!("Skipping a media file: {message}");
+                continue;
+            }
+            tokio::fs::write(content_path(&format!("media/{name}")), data).await?;
+        }
+
+        for page in &self.pages {
+            for content in &page.versions {
+                Article {
+                    title: page.title.clone(),
+                    content: content.clone(),
+                }
+                .write_to_disk()
+                .await?;
+            }
+        }
+
+        println!(
+            "Imported {} articles and {} media files.",
+            self.pages.len(),
+            self.media.len()
+        );
+        Ok(())
+    }
+}
+
+/// Replaces characters media file names shouldn't contain
+fn media_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '?' | '#' | '%' => '-',
+            c if c.is_whitespace() => '-',
+            c => c,
+        })
+        .collect()
+}
+
+/// Lists all files below `dir`, which doesn't have to exist
+fn files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(files);
+    };
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            files.extend(self::files(&entry.path())?);
+        } else {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+pub async fn run(args: ImportArgs) -> color_eyre::Result<()> {
+    let import = match args.format {
+        ImportFormat::Notion => notion::import(&args.path)?,
+        ImportFormat::Dokuwiki => dokuwiki::import(&args.path)?,
+        ImportFormat::Gollum => gollum::import(&args.path)?,
+        ImportFormat::Obsidian => obsidian::import(&args.path)?,
+    };
+    import.write_to_disk().await?;
+    Ok(())
+}
@@ -0,0 +1,379 @@
+//! DokuWiki keeps its pages as `pages/<namespace>/<page>.txt` in its data
+//! directory, older revisions as gzipped `attic/<namespace>/<page>.<timestamp>.txt.gz`
+//! files and uploads in `media/`. Page ids like `wiki:syntax` become article
+//! titles with `:` separating namespaces, and the markup is converted to Markdown.
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use regex::{Captures, Regex};
+
+use super::{files, media_file_name, Import, Page};
+
+/// Turns a page id into the title of the imported article
+fn title(id: &str) -> String {
+    id.trim_start_matches(':').replace('_', " ")
+}
+
+/// Resolves a link target relative to the namespace of the page it appears on
+fn resolve(namespace: &str, target: &str) -> String {
+    let target = target.trim();
+    if let Some(target) = target.strip_prefix(':') {
+        target.to_string()
+    } else if let Some(target) = target.strip_prefix(".:").or(target.strip_prefix('.')) {
+        if namespace.is_empty() {
+            target.to_string()
+        } else {
+            format!("{namespace}:{target}")
+        }
+    } else if target.contains(':') || namespace.is_empty() {
+        target.to_string()
+    } else {
+        format!("{namespace}:{target}")
+    }
+    .to_lowercase()
+    .replace(' ', "_")
+}
+
+fn media_name(id: &str) -> String {
+    media_file_name(&id.replace(':', "-"))
+}
+
+struct Converter {
+    link: Regex,
+    media: Regex,
+    italic: Regex,
+    monospace: Regex,
+    underline: Regex,
+    heading: Regex,
+    list: Regex,
+}
+
+impl Converter {
+    fn new() -> Self {
+        Converter {
+            link: Regex::new(r"\[\[([^|\]]+)(?:\|([^\]]*))?\]\]").unwrap(),
+            media: Regex::new(r"\{\{\s*([^|}?]+?)\s*(?:\?[^|}]*)?(?:\|([^}]*))?\}\}").unwrap(),
+            italic: Regex::new(r"(^|[^:])//(.+?)//").unwrap(),
+            monospace: Regex::new(r"''(.+?)''").unwrap(),
+            underline: Regex::new(r"__(.+?)__").unwrap(),
+            heading: Regex::new(r"^(={2,6})\s*(.*?)\s*={2,6}\s*$").unwrap(),
+            list: Regex::new(r"^((?:  )+)([*-])\s*(.*)$").unwrap(),
+        }
+    }
+
+    fn inline(&self, line: &str, namespace: &str) -> String {
+        let line = self.link.replace_all(line, |caps: &Captures| {
+            let target = &caps[1];
+            let label = caps.get(2).map(|label| label.as_str().trim());
+            if target.contains("://") {
+                return format!("[{}]({target})", label.unwrap_or(target));
+            }
+            let (page, anchor) = target.split_once('#').unwrap_or((target, ""));
+            let page = title(&resolve(namespace, page));
+            let anchor = if anchor.is_empty() {
+                String::new()
+            } else {
+                format!("#{anchor}")
+            };
+            format!(
+                "[{}](/article/{}{anchor})",
+                label.filter(|label| !label.is_empty()).unwrap_or(&page),
+                urlencoding::encode(&page)
+            )
+        });
+        let line = self.media.replace_all(&line, |caps: &Captures| {
+            let target = &caps[1];
+            let caption = caps.get(2).map_or("", |caption| caption.as_str().trim());
+            if target.contains("://") {
+                format!("![{caption}]({target})")
+            } else {
+                let name = media_name(&resolve(namespace, target));
+                format!("![{caption}](/media/{})", urlencoding::encode(&name))
+            }
+        });
+        let line = self.italic.replace_all(&line, "$1*$2*");
+        let line = self.monospace.replace_all(&line, "`$1`");
+        let line = self.underline.replace_all(&line, "<u>$1</u>");
+        line.replace("\\\\ ", "<br>").replace("\\\\", "<br>")
+    }
+
+    fn table_row(&self, line: &str, namespace: &str) -> String {
+        let cells: Vec<String> = line
+            .trim()
+            .trim_matches(|c| c == '|' || c == '^')
+            .split(['|', '^'])
+            .map(|cell| self.inline(cell.trim(), namespace))
+            .collect();
+        format!("| {} |", cells.join(" | "))
+    }
+
+    /// Converts DokuWiki markup of a page in `namespace` to Markdown
+    fn convert(&self, text: &str, namespace: &str) -> String {
+        // Markdown needs blank lines around block elements that DokuWiki doesn't
+        fn separate(out: &mut Vec<String>) {
+            if out.last().is_some_and(|line| !line.is_empty()) {
+                out.push(String::new());
+            }
+        }
+
+        let mut out = vec![];
+        let mut code_end: Option<&str> = None;
+        let mut in_table = false;
+        let mut in_list = false;
+
+        for line in text.lines() {
+            if let Some(end) = code_end {
+                if line.trim_start().starts_with(end) {
+                    out.push("```".to_string());
+                    out.push(String::new());
+                    code_end = None;
+                } else {
+                    out.push(line.to_string());
+                }
+                continue;
+            }
+
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("<code") || trimmed.starts_with("<file") {
+                let (tag, end) = if trimmed.starts_with("<code") {
+                    ("<code", "</code>")
+                } else {
+                    ("<file", "</file>")
+                };
+                separate(&mut out);
+                in_list = false;
+                in_table = false;
+                let language = trimmed[tag.len()..]
+                    .trim_start()
+                    .split(|c: char| c.is_whitespace() || c == '>')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+                out.push(format!("```{language}"));
+                match trimmed.split_once('>') {
+                    Some((_, rest)) if rest.contains(end) => {
+                        out.push(rest.split(end).next().unwrap_or_default().to_string());
+                        out.push("```".to_string());
+                    }
+                    Some((_, rest)) if !rest.is_empty() => {
+                        out.push(rest.to_string());
+                        code_end = Some(end);
+                    }
+                    _ => code_end = Some(end),
+                }
+                continue;
+            }
+
+            if trimmed.starts_with('^') || trimmed.starts_with('|') {
+                let row = self.table_row(line, namespace);
+                if !in_table {
+                    separate(&mut out);
+                    in_list = false;
+                    let columns = row.matches(" | ").count() + 1;
+                    out.push(row);
+                    out.push(format!("|{}", " --- |".repeat(columns)));
+                    in_table = true;
+                } else {
+                    out.push(row);
+                }
+                continue;
+            }
+            if in_table {
+                out.push(String::new());
+                in_table = false;
+            }
+
+            let is_list = self.list.is_match(line);
+            if is_list != in_list {
+                separate(&mut out);
+                in_list = is_list;
+            }
+
+            if let Some(caps) = self.heading.captures(line) {
+                let level = 7 - caps[1].len();
+                separate(&mut out);
+                out.push(format!(
+                    "{} {}",
+                    "#".repeat(level),
+                    self.inline(&caps[2], namespace)
+                ));
+                out.push(String::new());
+            } else if let Some(caps) = self.list.captures(line) {
+                let depth = caps[1].len() / 2 - 1;
+                let marker = if &caps[2] == "*" { "-" } else { "1." };
+                out.push(format!(
+                    "{}{marker} {}",
+                    "    ".repeat(depth),
+                    self.inline(&caps[3], namespace)
+                ));
+            } else if line.starts_with("  ") && !trimmed.is_empty() {
+                out.push(format!("    {trimmed}"));
+            } else {
+                out.push(self.inline(line, namespace));
+            }
+        }
+
+        if code_end.is_some() {
+            out.push("```".to_string());
+        }
+
+        out.join("\n")
+    }
+}
+
+/// Returns the id of a file below `root`, e.g. `wiki:syntax` for `wiki/syntax.txt`
+fn id(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let segments: Vec<String> = relative
+        .iter()
+        .map(|segment| segment.to_string_lossy().into_owned())
+        .collect();
+    Some(segments.join(":"))
+}
+
+pub fn import(path: &Path) -> color_eyre::Result<Import> {
+    let converter = Converter::new();
+    let mut import = Import::default();
+
+    // Old revisions of every page, keyed by page id and timestamp
+    let mut attic: BTreeMap<String, BTreeMap<u64, String>> = BTreeMap::new();
+    let attic_dir = path.join("attic");
+    for file in files(&attic_dir)? {
+        let Some(id) = id(&attic_dir, &file) else {
+            continue;
+        };
+        let Some(rest) = id.strip_suffix(".txt.gz") else {
+            continue;
+        };
+        let Some((id, timestamp)) = rest.rsplit_once('.') else {
+            continue;
+        };
+        let Ok(timestamp) = timestamp.parse() else {
+            continue;
+        };
+        let mut text = String::new();
+        GzDecoder::new(std::fs::File::open(&file)?).read_to_string(&mut text)?;
+        attic
+            .entry(id.to_string())
+            .or_default()
+            .insert(timestamp, text);
+    }
+
+    let pages_dir = path.join("pages");
+    for file in files(&pages_dir)? {
+        let Some(id) = id(&pages_dir, &file) else {
+            continue;
+        };
+        let Some(id) = id.strip_suffix(".txt") else {
+            continue;
+        };
+        let namespace = id.rsplit_once(':').map_or("", |(namespace, _)| namespace);
+        let current = std::fs::read_to_string(&file)?;
+
+        let mut revisions: Vec<String> =
+            attic.remove(id).unwrap_or_default().into_values().collect();
+        if revisions.last() != Some(&current) {
+            revisions.push(current);
+        }
+
+        import.pages.push(Page {
+            title: title(id),
+            versions: revisions
+                .iter()
+                .map(|text| converter.convert(text, namespace))
+                .collect(),
+        });
+    }
+
+    let media_dir = path.join("media");
+    for file in files(&media_dir)? {
+        if let Some(id) = id(&media_dir, &file) {
+            import.media.push((media_name(&id), std::fs::read(&file)?));
+        }
+    }
+
+    Ok(import)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    #[test]
+    fn converts_code_tables_and_lists() {
+        let text = "\
+==== Setup ====
+Run ''make'' in **the** [[build|build directory]].
+<code rust>
+fn main() {}
+</code>
+^ Name ^ Value ^
+| a | [[:start]] |
+  * one
+    * nested
+  - first
+";
+        assert_eq!(
+            Converter::new().convert(text, "wiki"),
+            "\
+### Setup
+
+Run `make` in **the** [build directory](/article/wiki%3Abuild).
+
+```rust
+fn main() {}
+```
+
+| Name | Value |
+| --- | --- |
+| a | [start](/article/start) |
+
+- one
+    - nested
+1. first"
+        );
+    }
+
+    #[test]
+    fn imports_attic_revisions_as_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in ["pages/wiki", "attic/wiki", "media/wiki"] {
+            std::fs::create_dir_all(dir.path().join(path)).unwrap();
+        }
+        std::fs::write(
+            dir.path().join("pages/wiki/some_page.txt"),
+            "Third //draft//",
+        )
+        .unwrap();
+        for (timestamp, text) in [(1_600_000_000, "First"), (1_500_000_000, "Zeroth")] {
+            let mut gz = GzEncoder::new(vec![], Compression::default());
+            gz.write_all(text.as_bytes()).unwrap();
+            std::fs::write(
+                dir.path()
+                    .join(format!("attic/wiki/some_page.{timestamp}.txt.gz")),
+                gz.finish().unwrap(),
+            )
+            .unwrap();
+        }
+        std::fs::write(dir.path().join("media/wiki/logo.png"), b"logo").unwrap();
+
+        let import = import(dir.path()).unwrap();
+        assert_eq!(import.pages.len(), 1);
+        assert_eq!(import.pages[0].title, "wiki:some page");
+        assert_eq!(
+            import.pages[0].versions,
+            vec!["Zeroth", "First", "Third *draft*"]
+        );
+        assert_eq!(
+            import.media,
+            vec![("wiki-logo.png".to_string(), b"logo".to_vec())]
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Gollum wikis are git repositories of Markdown files, so the history of
+//! every page is read with `git log` and `git show`. Gollum's own link syntax
+//! `[[Page]]` and `[[Label|Page]]` is converted into regular Markdown links,
+//! and files linked from pages are copied into the media directory.
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+
+use color_eyre::eyre::eyre;
+use regex::{Captures, Regex};
+
+use super::{files, media_file_name, Import, Page};
+
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "gif", "webp", "svg"];
+
+fn git(repo: &Path, args: &[&str]) -> color_eyre::Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Gollum stores spaces in page names as dashes
+fn title(file_name: &str) -> String {
+    let stem = file_name
+        .rsplit_once('.')
+        .map_or(file_name, |(stem, _)| stem);
+    stem.replace('-', " ")
+}
+
+fn is_image(target: &str) -> bool {
+    target
+        .rsplit_once('.')
+        .is_some_and(|(_, extension)| IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+pub fn import(path: &Path) -> color_eyre::Result<Import> {
+    let mut import = Import::default();
+    let wikilink = Regex::new(r"\[\[([^|\]]+)(?:\|([^\]]+))?\]\]").unwrap();
+    let image = Regex::new(r"!\[([^\]]*)\]\(([^)\s]+)\)").unwrap();
+
+    // Every file in the repository that isn't a page, by its path relative to the repository
+    let mut media: HashMap<String, Option<String>> = HashMap::new();
+    let mut pages = vec![];
+    for file in files(path)? {
+        let relative = file
+            .strip_prefix(path)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        if relative.starts_with(".git/") {
+            continue;
+        }
+        if relative.ends_with(".md") || relative.ends_with(".markdown") {
+            pages.push(relative);
+        } else {
+            media.insert(relative, None);
+        }
+    }
+
+    for relative in pages {
+        let file_name = relative.rsplit('/').next().unwrap_or(&relative);
+        let directory = relative
+            .rsplit_once('/')
+            .map_or("", |(directory, _)| directory);
+
+        let log = git(path, &["log", "--reverse", "--format=%H", "--", &relative])?;
+        let mut versions = vec![];
+        for commit in log.lines() {
+            versions.push(git(path, &["show", &format!("{commit}:{relative}")])?);
+        }
+        if versions.is_empty() {
+            versions.push(std::fs::read_to_string(path.join(&relative))?);
+        }
+
+        let mut link_media = |target: &str| -> Option<String> {
+            let target = target.trim_start_matches('/');
+            let key = if media.contains_key(target) {
+                target.to_string()
+            } else {
+                let joined = format!("{directory}/{target}");
+                let joined = joined.trim_start_matches('/').to_string();
+                if !media.contains_key(&joined) {
+                    return None;
+                }
+                joined
+            };
+            let name = media
+                .get_mut(&key)?
+                .get_or_insert_with(|| media_file_name(&key))
+                .clone();
+            Some(format!("/media/{}", urlencoding::encode(&name)))
+        };
+
+        let versions = versions
+            .iter()
+            .map(|content| {
+                let content = image.replace_all(content, |caps: &Captures| {
+                    if caps[2].contains("://") {
+                        return caps[0].to_string();
+                    }
+                    match link_media(&caps[2]) {
+                        Some(dest) => format!("![{}]({dest})", &caps[1]),
+                        None => caps[0].to_string(),
+                    }
+                });
+                wikilink
+                    .replace_all(&content, |caps: &Captures| {
+                        // Gollum puts the label first: [[Label|Page]]
+                        let (label, target) = match caps.get(2) {
+                            Some(target) => (caps[1].trim(), target.as_str().trim()),
+                            None => (caps[1].trim(), caps[1].trim()),
+                        };
+                        if target.contains("://") {
+                            return format!("[{label}]({target})");
+                        }
+                        if is_image(target) {
+                            if let Some(dest) = link_media(target) {
+                                return format!("![{label}]({dest})");
+                            }
+                        }
+                        format!("[{label}](/article/{})", urlencoding::encode(target))
+                    })
+                    .into_owned()
+            })
+            .collect();
+
+        import.pages.push(Page {
+            title: title(file_name),
+            versions,
+        });
+    }
+
+    for (relative, name) in media {
+        if let Some(name) = name {
+            import
+                .media
+                .push((name, std::fs::read(path.join(&relative))?));
+        }
+    }
+
+    Ok(import)
+}
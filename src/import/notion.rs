@@ -0,0 +1,302 @@
+//! Notion's "Markdown & CSV" export is a zip archive (sometimes containing
+//! further zip archives) in which pages are `Title <id>.md` files, sub pages
+//! and attachments live in a `Title <id>/` directory next to their parent and
+//! databases are exported as `Title <id>.csv`. Links between these files are
+//! percent-encoded relative paths, which are rewritten into article and media
+//! links here.
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use regex::{Captures, Regex};
+
+use super::{media_file_name, Import};
+
+fn read_archive(data: Vec<u8>, files: &mut HashMap<String, Vec<u8>>) -> color_eyre::Result<()> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_string();
+        let mut data = vec![];
+        file.read_to_end(&mut data)?;
+        if name.ends_with(".zip") {
+            read_archive(data, files)?;
+        } else {
+            files.insert(name, data);
+        }
+    }
+    Ok(())
+}
+
+/// Strips the extension and the id Notion appends to every file name
+fn title(path: &str) -> String {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    let stem = file_name
+        .rsplit_once('.')
+        .map_or(file_name, |(stem, _)| stem);
+    let stem = stem.strip_suffix("_all").unwrap_or(stem);
+    match stem.rsplit_once(' ') {
+        Some((title, id)) if id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()) => {
+            title.to_string()
+        }
+        _ => stem.to_string(),
+    }
+}
+
+/// Resolves `target` relative to the directory of `path`
+fn resolve(path: &str, target: &str) -> String {
+    let mut segments: Vec<&str> = path.split('/').collect();
+    segments.pop();
+    for segment in target.split('/') {
+        match segment {
+            "." | "" => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+fn text(data: &[u8]) -> String {
+    let text = String::from_utf8_lossy(data);
+    text.strip_prefix('\u{feff}').unwrap_or(&text).to_string()
+}
+
+/// Splits CSV into records of fields, allowing line breaks and escaped quotes (`""`) inside
+/// quoted fields
+fn csv_records(csv: &str) -> Vec<Vec<String>> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = csv.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+fn table_cell(value: &str) -> String {
+    value.replace('|', "\\|").replace('\n', "<br>")
+}
+
+/// Renders a Notion database as a Markdown table, linking rows that have their own page
+fn database(csv: &str, titles: &[String]) -> String {
+    let records = csv_records(csv);
+    let Some((header, rows)) = records.split_first() else {
+        return String::new();
+    };
+
+    let mut table = format!(
+        "| {} |\n|{}\n",
+        header
+            .iter()
+            .map(|cell| table_cell(cell))
+            .collect::<Vec<_>>()
+            .join(" | "),
+        " --- |".repeat(header.len())
+    );
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                if i == 0 && titles.contains(cell) {
+                    format!("[{}]", table_cell(cell))
+                } else {
+                    table_cell(cell)
+                }
+            })
+            .collect();
+        table.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+    table
+}
+
+pub fn import(path: &Path) -> color_eyre::Result<Import> {
+    let mut files = HashMap::new();
+    read_archive(std::fs::read(path)?, &mut files)?;
+
+    let mut pages: BTreeMap<String, String> = BTreeMap::new();
+    let mut databases: BTreeMap<String, String> = BTreeMap::new();
+    for name in files.keys() {
+        if name.ends_with(".md") {
+            pages.insert(name.clone(), title(name));
+        } else if name.ends_with(".csv") {
+            let title = title(name);
+            if name.ends_with("_all.csv") || !databases.contains_key(&title) {
+                databases.insert(title, name.clone());
+            }
+        }
+    }
+    let titles: Vec<String> = pages.values().cloned().collect();
+
+    let link = Regex::new(r"(!?)\[([^\]]*)\]\(([^)]+)\)").unwrap();
+    let mut import = Import::default();
+    let mut media: HashMap<String, String> = HashMap::new();
+
+    for (path, title) in &pages {
+        let content = text(&files[path]);
+        let content = content
+            .strip_prefix(&format!("# {title}"))
+            .map_or(content.as_str(), |rest| rest.trim_start_matches('\n'));
+
+        let content = link.replace_all(content, |caps: &Captures| {
+            let (image, label, target) = (&caps[1], &caps[2], &caps[3]);
+            if target.contains("://") || target.starts_with('#') || target.starts_with("mailto:") {
+                return caps[0].to_string();
+            }
+
+            let decoded = urlencoding::decode(target)
+                .map(|target| target.into_owned())
+                .unwrap_or_else(|_| target.to_string());
+            let resolved = resolve(path, &decoded);
+
+            if resolved.ends_with(".md") || resolved.ends_with(".csv") {
+                let target = self::title(&resolved);
+                let label = if label.is_empty() { &target } else { label };
+                return format!("[{label}](/article/{})", urlencoding::encode(&target));
+            }
+
+            if let Some(data) = files.get(&resolved) {
+                let name = media.entry(resolved.clone()).or_insert_with(|| {
+                    let file_name = resolved.rsplit('/').next().unwrap_or(&resolved);
+                    let name = media_file_name(&format!("{title}-{file_name}"));
+                    import.media.push((name.clone(), data.clone()));
+                    name
+                });
+                return format!("{image}[{label}](/media/{})", urlencoding::encode(name));
+            }
+
+            caps[0].to_string()
+        });
+
+        import.add_page(title.clone(), content.into_owned());
+    }
+
+    for (title, path) in databases {
+        let table = database(&text(&files[&path]), &titles);
+        match import.pages.iter_mut().find(|page| page.title == title) {
+            Some(page) => {
+                let content = &mut page.versions[0];
+                content.push_str("\n\n");
+                content.push_str(&table);
+            }
+            None => import.add_page(title, table),
+        }
+    }
+
+    Ok(import)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use super::*;
+
+    const ID: &str = "0123456789abcdef0123456789abcdef";
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+        for (name, data) in files {
+            zip.start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn splits_csv_with_quotes_and_line_breaks() {
+        let csv = "Name,Notes\r\n\"Quote\",\"He said \"\"\nhi\"\"\"\nPlain,\"a,b\"\n";
+        assert_eq!(
+            csv_records(csv),
+            vec![
+                vec!["Name", "Notes"],
+                vec!["Quote", "He said \"\nhi\""],
+                vec!["Plain", "a,b"],
+            ]
+        );
+        // An escaped quote ending a line doesn't end the field
+        assert_eq!(
+            csv_records("\"ends with \"\"\nnext\",x"),
+            vec![vec!["ends with \"\nnext", "x"]]
+        );
+    }
+
+    #[test]
+    fn resolves_relative_paths() {
+        assert_eq!(resolve("Home/Page.md", "Sub/Child.md"), "Home/Sub/Child.md");
+        assert_eq!(resolve("Home/Sub/Child.md", "../Page.md"), "Home/Page.md");
+        assert_eq!(resolve("Page.md", "./image.png"), "image.png");
+    }
+
+    #[test]
+    fn imports_nested_archives_with_databases() {
+        let page = format!(
+            "# Home\n\nSee [the tasks](Tasks%20{ID}.csv), [a child](Home%20{ID}/Child%20{ID}.md) \
+             and ![a cat](Home%20{ID}/cat.png).\n"
+        );
+        let inner = zip(&[
+            (&format!("Home {ID}.md"), page.as_bytes()),
+            (
+                &format!("Home {ID}/Child {ID}.md"),
+                b"# Child\n\nA sub page",
+            ),
+            (&format!("Home {ID}/cat.png"), b"not really a cat"),
+            (
+                &format!("Tasks {ID}.csv"),
+                b"Name,Status\nChild,\"Done, \"\"finally\"\"\"\nOther,Open\n",
+            ),
+        ]);
+        let outer = zip(&[("Export-part-1.zip", &inner)]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.zip");
+        std::fs::write(&path, outer).unwrap();
+
+        let import = import(&path).unwrap();
+        let page = |title: &str| {
+            import
+                .pages
+                .iter()
+                .find(|page| page.title == title)
+                .map(|page| page.versions[0].as_str())
+                .unwrap()
+        };
+        assert_eq!(
+            page("Home"),
+            "See [the tasks](/article/Tasks), [a child](/article/Child) \
+             and ![a cat](/media/Home-cat.png).\n"
+        );
+        assert_eq!(page("Child"), "A sub page");
+        assert_eq!(
+            page("Tasks"),
+            "| Name | Status |\n| --- | --- |\n| [Child] | Done, \"finally\" |\n| Other | Open |\n"
+        );
+        assert_eq!(import.media.len(), 1);
+        assert_eq!(import.media[0].1, b"not really a cat");
+    }
+}
@@ -0,0 +1,222 @@
+//! An Obsidian vault, or any folder of Markdown files, is imported note by
+//! note: every `.md` file becomes an article titled like the file, and every
+//! other file an attachment in the media. Notes in different folders that
+//! share a name keep the folders as namespaces, like `Projects:Plan`.
+//! Obsidian links notes by their name or their path, so `[[Note]]`,
+//! `[[Folder/Note#Heading|label]]` and relative links like `[x](Note.md)`
+//! are pointed at the article's title, and embeds like `![[photo.png]]` or
+//! `![](attachments/photo.png)` at the media file. Hidden folders like
+//! `.obsidian` and `.trash` are left out.
+use std::collections::HashMap;
+use std::path::Path;
+
+use regex::{Captures, Regex};
+
+use super::{files, media_file_name, Import};
+
+/// Where to find notes and attachments by the ways Obsidian refers to them
+#[derive(Default)]
+struct Vault {
+    /// The title of every note, by its lowercase path without `.md`
+    notes: HashMap<String, String>,
+    /// The title of the first note with a name, by the lowercase name
+    notes_by_name: HashMap<String, String>,
+    /// The media file name of every attachment, by its lowercase path
+    attachments: HashMap<String, String>,
+    attachments_by_name: HashMap<String, String>,
+}
+
+/// `path` with `.` and `..` segments resolved
+fn normalize(path: &str) -> String {
+    let mut segments: Vec<&str> = vec![];
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+impl Vault {
+    /// Looks `target` up in `by_path` and `by_name`, as a path relative to
+    /// the vault, to the `directory` of the note linking to it, or a name
+    fn find<'a>(
+        by_path: &'a HashMap<String, String>,
+        by_name: &'a HashMap<String, String>,
+        directory: &str,
+        target: &str,
+    ) -> Option<&'a String> {
+        let target = target.trim().to_lowercase();
+        let relative = format!("{}/{target}", directory.to_lowercase());
+        by_path
+            .get(&normalize(&target))
+            .or_else(|| by_path.get(&normalize(&relative)))
+            .or_else(|| by_name.get(file_name(&target)))
+    }
+
+    fn note(&self, directory: &str, target: &str) -> Option<&String> {
+        let target = target.strip_suffix(".md").unwrap_or(target);
+        Self::find(&self.notes, &self.notes_by_name, directory, target)
+    }
+
+    fn attachment(&self, directory: &str, target: &str) -> Option<&String> {
+        Self::find(
+            &self.attachments,
+            &self.attachments_by_name,
+            directory,
+            target,
+        )
+    }
+}
+
+/// Whether the label of an embed like `![[photo.png|300]]` is its size
+fn is_size(label: &str) -> bool {
+    let label = label.trim();
+    !label.is_empty() && label.chars().all(|c| c.is_ascii_digit() || c == 'x')
+}
+
+struct Converter {
+    wikilink: Regex,
+    link: Regex,
+}
+
+impl Converter {
+    fn new() -> Self {
+        Converter {
+            wikilink: Regex::new(r"(!?)\[\[([^\]|#]*)(#[^\]|]*)?(?:\|([^\]]*))?\]\]").unwrap(),
+            link: Regex::new(r"(!?)\[([^\]]*)\]\((?:<([^>]+)>|([^)\s]+))\)").unwrap(),
+        }
+    }
+
+    /// Converts the links of a note in `directory` to the articles and media of `vault`
+    fn convert(&self, vault: &Vault, directory: &str, text: &str) -> String {
+        let text = self.wikilink.replace_all(text, |caps: &Captures| {
+            let embed = !caps[1].is_empty();
+            let target = caps[2].trim();
+            let fragment = caps.get(3).map_or("", |fragment| fragment.as_str());
+            let label = caps.get(4).map(|label| label.as_str().trim());
+            if embed {
+                if let Some(name) = vault.attachment(directory, target) {
+                    let alt = label.filter(|label| !is_size(label)).unwrap_or("");
+                    return format!("![{alt}](/media/{})", urlencoding::encode(name));
+                }
+            }
+            // Links to the note itself, like `[[#Heading]]`, work as they are
+            if target.is_empty() {
+                return caps[0].trim_start_matches('!').to_string();
+            }
+            let Some(title) = vault.note(directory, target) else {
+                return caps[0].to_string();
+            };
+            let label = label.unwrap_or(target);
+            if label == title {
+                format!("[[{title}{fragment}]]")
+            } else {
+                format!("[[{title}{fragment}|{label}]]")
+            }
+        });
+
+        self.link
+            .replace_all(&text, |caps: &Captures| {
+                let embed = &caps[1];
+                let label = &caps[2];
+                let dest = caps.get(3).or(caps.get(4)).unwrap().as_str();
+                if dest.contains("://") || dest.starts_with(['#', '/']) || dest.contains(':') {
+                    return caps[0].to_string();
+                }
+                let decoded = urlencoding::decode(dest).map_or(dest.into(), |dest| dest);
+                let (path, fragment) = decoded.split_once('#').unwrap_or((&decoded, ""));
+                if let Some(name) = vault.attachment(directory, path) {
+                    return format!("{embed}[{label}](/media/{})", urlencoding::encode(name));
+                }
+                match vault.note(directory, path) {
+                    Some(title) if fragment.is_empty() => format!("[[{title}|{label}]]"),
+                    Some(title) => format!("[[{title}#{fragment}|{label}]]"),
+                    None => caps[0].to_string(),
+                }
+            })
+            .into_owned()
+    }
+}
+
+pub fn import(path: &Path) -> color_eyre::Result<Import> {
+    let mut notes = vec![];
+    let mut attachments = vec![];
+    for file in files(path)? {
+        let relative = file
+            .strip_prefix(path)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        if relative.split('/').any(|segment| segment.starts_with('.')) {
+            continue;
+        }
+        match relative.strip_suffix(".md") {
+            Some(note) => notes.push(note.to_string()),
+            None => attachments.push(relative),
+        }
+    }
+
+    let mut vault = Vault::default();
+    let mut name_counts: HashMap<String, usize> = HashMap::new();
+    for note in &notes {
+        *name_counts
+            .entry(file_name(note).to_lowercase())
+            .or_default() += 1;
+    }
+    // The notes closest to the top of the vault win links by name, like in Obsidian
+    notes.sort_by_key(|note| (note.matches('/').count(), note.clone()));
+    for note in &notes {
+        let name = file_name(note);
+        let title = match name_counts[&name.to_lowercase()] {
+            1 => name.to_string(),
+            _ => note.replace('/', ":"),
+        };
+        vault.notes.insert(note.to_lowercase(), title.clone());
+        vault
+            .notes_by_name
+            .entry(name.to_lowercase())
+            .or_insert(title);
+    }
+
+    let mut import = Import::default();
+    let mut media_names: HashMap<String, usize> = HashMap::new();
+    for attachment in &attachments {
+        *media_names
+            .entry(file_name(attachment).to_lowercase())
+            .or_default() += 1;
+    }
+    for attachment in attachments {
+        let name = file_name(&attachment);
+        let media_name = match media_names[&name.to_lowercase()] {
+            1 => media_file_name(name),
+            _ => media_file_name(&attachment),
+        };
+        vault
+            .attachments
+            .insert(attachment.to_lowercase(), media_name.clone());
+        vault
+            .attachments_by_name
+            .entry(name.to_lowercase())
+            .or_insert(media_name.clone());
+        import
+            .media
+            .push((media_name, std::fs::read(path.join(&attachment))?));
+    }
+
+    let converter = Converter::new();
+    for note in &notes {
+        let content = std::fs::read_to_string(path.join(format!("{note}.md")))?;
+        let directory = note.rsplit_once('/').map_or("", |(directory, _)| directory);
+        let title = vault.notes[&note.to_lowercase()].clone();
+        import.add_page(title, converter.convert(&vault, directory, &content));
+    }
+    Ok(import)
+}
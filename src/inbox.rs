@@ -0,0 +1,101 @@
+//! # Quick-Capture Inbox
+//!
+//! `POST /api/inbox` appends a timestamped snippet to the inbox article
+//! (`inbox_article`, "Inbox" by default). It is meant for shortcut apps and
+//! bookmarklets, so the `inbox_token` may be passed either as a bearer token
+//! or as a `token` query parameter. Without a configured token the endpoint
+//! is disabled. If the inbox article requires a review, every snippet is
+//! submitted as its own revision.
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use time::macros::format_description;
+use time::OffsetDateTime;
+
+use crate::error::TomeError;
+use crate::{review, Article, TomeConfig};
+
+#[derive(Deserialize)]
+pub struct InboxQuery {
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Snippet {
+    text: String,
+}
+
+/// Compares tokens without returning early, so timing doesn't reveal a prefix
+fn tokens_match(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Reads the snippet from a form, JSON object or plain text body
+fn snippet(headers: &HeaderMap, body: &str) -> Option<String> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .unwrap_or_default();
+    let text = if content_type.starts_with("application/x-www-form-urlencoded") {
+        serde_urlencoded::from_str::<Snippet>(body).ok()?.text
+    } else if content_type.starts_with("application/json") {
+        serde_json::from_str::<Snippet>(body).ok()?.text
+    } else {
+        body.to_string()
+    };
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+pub async fn post_inbox(
+    State(config): State<TomeConfig>,
+    Query(query): Query<InboxQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Response, TomeError> {
+    let Some(token) = &config.inbox_token else {
+        return Ok((StatusCode::NOT_FOUND, "The inbox is disabled").into_response());
+    };
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|authorization| authorization.to_str().ok())
+        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+        .map(str::to_string)
+        .or(query.token);
+    if !given.is_some_and(|given| tokens_match(token, &given)) {
+        return Ok((StatusCode::UNAUTHORIZED, "Invalid token").into_response());
+    }
+
+    let Some(text) = snippet(&headers, &body) else {
+        return Ok((StatusCode::BAD_REQUEST, "Nothing to add").into_response());
+    };
+
+    let title = config.inbox_article.as_deref().unwrap_or("Inbox");
+    let timestamp = OffsetDateTime::now_utc()
+        .format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+        .unwrap();
+    let entry = format!("- **{timestamp}** {}", text.replace('\n', "\n  "));
+
+    let content = match Article::load(title).await {
+        Some(article) => format!("{}\n{entry}\n", article.content.trim_end()),
+        None => format!("{entry}\n"),
+    };
+    let article = Article {
+        title: title.to_string(),
+        content,
+    };
+    if review::save_or_submit(&article, None, None)
+        .await?
+        .is_some()
+    {
+        return Ok((StatusCode::ACCEPTED, "Submitted to the inbox for review").into_response());
+    }
+
+    Ok((StatusCode::CREATED, "Added to the inbox").into_response())
+}
@@ -0,0 +1,153 @@
+//! # Page Layout
+//!
+//! Every page extends `meta.html`, which renders the navigation, search box
+//! and footer. The context it needs is collected in a [`Layout`], which
+//! handlers extract and put into the `layout` field of their template, so
+//! new pages get the same chrome without any extra work.
+//!
+//! On article pages, the layout also holds breadcrumbs for the namespaces
+//! in the article's title (`Team:Ops:Runbook` is the page `Runbook` in the
+//! namespace `Ops` inside `Team`), each linking to the namespace's overview.
+//!
+//! `custom_head_html` and `custom_footer_html` from the config are inserted
+//! verbatim, so only the wiki operator should be able to set them.
+use std::convert::Infallible;
+
+use axum::async_trait;
+use axum::extract::{FromRef, FromRequestParts};
+use axum::http::request::Parts;
+
+use crate::auth::Session;
+use crate::license::License;
+use crate::{namespace, Article, TomeConfig};
+
+#[derive(Clone, Default)]
+pub struct Layout {
+    /// The name of the wiki
+    pub site_name: String,
+    /// The `base_path` scripts put in front of the paths they request
+    pub base_path: String,
+    pub custom_head: String,
+    pub custom_footer: String,
+    /// The path of the requested page
+    path: String,
+    /// Label and link of every breadcrumb after "Home"
+    pub breadcrumbs: Vec<(String, String)>,
+    /// The text direction of the page, `ltr` or `rtl`
+    pub dir: &'static str,
+    /// The logged in user
+    pub user: Option<String>,
+    /// Whether the visitor can change the wiki
+    pub can_edit: bool,
+    /// Whether the wiki has users who can log in
+    pub has_login: bool,
+    /// Minutes between resets if this is a demo
+    pub demo_reset_minutes: Option<u64>,
+    /// The license of the page's content
+    pub license: Option<License>,
+    /// The accent color of the article's namespace
+    accent: Option<String>,
+}
+
+/// Separates namespaces in article titles
+pub const NAMESPACE_SEPARATOR: char = ':';
+
+/// The percent-encoded title of the article an `/article/...` or `/edit/article/...` path belongs to
+fn encoded_title(path: &str) -> Option<&str> {
+    let rest = path
+        .strip_prefix("/article/")
+        .or_else(|| path.strip_prefix("/edit/article/"))?;
+    rest.split('/').next()
+}
+
+/// Breadcrumbs for the article a path belongs to
+fn breadcrumbs(path: &str) -> Vec<(String, String)> {
+    let Some(encoded) = encoded_title(path) else {
+        return vec![];
+    };
+    let Ok(title) = urlencoding::decode(encoded) else {
+        return vec![];
+    };
+
+    let segments: Vec<&str> = title.split(NAMESPACE_SEPARATOR).collect();
+    let mut breadcrumbs = vec![];
+    for (i, segment) in segments.iter().enumerate() {
+        let href = if i + 1 == segments.len() {
+            format!("/article/{encoded}")
+        } else {
+            let namespace = segments[..=i].join(&NAMESPACE_SEPARATOR.to_string());
+            format!("/overview?namespace={}", urlencoding::encode(&namespace))
+        };
+        breadcrumbs.push((segment.to_string(), href));
+    }
+    breadcrumbs
+}
+
+impl Layout {
+    /// Whether the navigation link to `path` leads to the current page
+    pub fn is_current(&self, path: &str) -> bool {
+        self.path == path
+    }
+
+    /// Styles giving the page the accent color of its namespace
+    pub fn accent_css(&self) -> Option<String> {
+        self.accent.as_deref().map(crate::assets::accent_css)
+    }
+
+    /// Renders the page in the direction of the given content
+    pub fn with_direction_of(self, content: &str) -> Self {
+        Layout {
+            dir: crate::direction::direction(content),
+            ..self
+        }
+    }
+
+    /// Shows the license of `article` if it has its own
+    pub fn with_license_of(self, article: &Article) -> Self {
+        Layout {
+            license: License::of_article(article).or(self.license.clone()),
+            ..self
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Layout
+where
+    TomeConfig: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let config = TomeConfig::from_ref(state);
+        let session = parts
+            .extensions
+            .get::<Session>()
+            .cloned()
+            .unwrap_or(Session {
+                user: None,
+                required: false,
+            });
+        let accent = encoded_title(parts.uri.path())
+            .and_then(|encoded| urlencoding::decode(encoded).ok())
+            .and_then(|title| namespace::of(&config, &title).accent_color);
+        Ok(Layout {
+            accent,
+            demo_reset_minutes: config
+                .demo_mode
+                .then(|| crate::demo::reset_interval(&config).as_secs() / 60),
+            can_edit: !session.required || session.user.is_some(),
+            has_login: session.required,
+            user: session.user,
+            license: License::of_site(&config),
+            base_path: crate::base_path::of(&config).to_string(),
+            site_name: config.site_name.unwrap_or_else(|| "Tome".to_string()),
+            custom_head: config.custom_head_html.unwrap_or_default(),
+            custom_footer: config.custom_footer_html.unwrap_or_default(),
+            path: parts.uri.path().to_string(),
+            breadcrumbs: breadcrumbs(parts.uri.path()),
+            dir: "ltr",
+        })
+    }
+}
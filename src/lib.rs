@@ -0,0 +1,1561 @@
+//! # Tome
+//!
+//! A small wiki that keeps its articles as Markdown files. [`app`] builds
+//! the web application for a configuration, which is how the `tome`
+//! binary serves it and how integration tests and embedders can run it
+//! in-process. [`run`] is the command line interface.
+//!
+//! Other axum applications can embed a wiki by merging its router into
+//! their own, or by nesting it under a path. A nested wiki needs that
+//! path as the `base_path` of its configuration, so its links and redirects
+//! lead back to it. It reads and writes the `content_dir` of its
+//! configuration, `content` in the current directory by default, and apps
+//! with different content directories can run side by side.
+//!
+//! ```no_run
+//! use axum::routing::get;
+//! use axum::Router;
+//! use figment::providers::{Format, Serialized, Toml};
+//! use figment::Figment;
+//!
+//! # async fn serve() -> color_eyre::Result<()> {
+//! let config: tome::TomeConfig = Figment::from(Serialized::defaults(tome::TomeConfig::default()))
+//!     .merge(Toml::string(r#"base_path = "/wiki""#))
+//!     .extract()?;
+//! let wiki = tome::app(config).await?;
+//! let app = Router::new()
+//!     .route("/health", get(|| async { "ok" }))
+//!     .nest("/wiki", wiki);
+//! axum::Server::bind(&"127.0.0.1:3000".parse()?)
+//!     .serve(app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Articles can also be read and written directly with [`Article`], their
+//! versions are kept by [`storage()`] and [`render`] turns Markdown into the
+//! HTML shown on article pages. They work with the content of a [`Wiki`],
+//! inside of [`Wiki::run`]:
+//!
+//! ```no_run
+//! # async fn write() -> color_eyre::Result<()> {
+//! let wiki = tome::Wiki::open(&tome::TomeConfig::default()).await?;
+//! wiki.run(tome::Article::new("Notes", "Written *directly*").write_to_disk())
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+mod analytics;
+mod annotations;
+mod api;
+mod archive;
+mod assets;
+mod auth;
+mod autolink;
+mod backlinks;
+mod base_path;
+pub mod bench;
+mod caching;
+mod changes;
+mod check;
+mod config;
+mod conflict;
+mod demo;
+mod diff;
+mod direction;
+mod dump;
+mod error;
+mod export;
+mod feed;
+mod file_info;
+mod filters;
+mod fragment;
+mod freeze;
+mod frontmatter;
+mod git;
+mod glossary;
+mod history;
+mod hotlink;
+mod image_variants;
+mod import;
+mod inbox;
+mod layout;
+mod license;
+mod lint;
+mod media;
+mod media_usage;
+mod mentions;
+mod migrations;
+mod namespace;
+mod page_template;
+#[cfg(feature = "pandoc")]
+mod pandoc;
+mod paste;
+mod paths;
+mod pdf_preview;
+mod permalink;
+mod preview;
+mod prometheus;
+mod quota;
+mod rename;
+mod render_cache;
+mod replace;
+mod retag;
+mod review;
+mod sampling;
+mod sanitize;
+mod search;
+mod section;
+mod setup;
+mod shutdown;
+mod signature;
+mod slug;
+mod snapshot;
+mod stale;
+mod storage;
+mod tags;
+mod tenant;
+mod thumbnail;
+mod trash;
+mod version_info;
+mod version_tags;
+mod wiki;
+mod zim;
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use askama::Template;
+use axum::extract::{DefaultBodyLimit, Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::routing::{delete, get, get_service, post};
+use axum::{Form, Router};
+use axum_macros::{debug_handler, FromRef};
+
+use analytics::Analytics;
+use clap::{Parser, Subcommand};
+use config::content_path;
+use error::TomeError;
+use layout::Layout;
+use media::{get_media_overview, post_media, UploadPolicy};
+use namespace::NamespaceDefaults;
+use render_cache::RenderCache;
+use sampling::RouteSampling;
+pub use sampling::DEFAULT_LOG_FILTER;
+use serde::{Deserialize, Serialize};
+pub use slug::slug;
+use stale::Stale;
+pub use storage::{storage, Storage};
+use time::OffsetDateTime;
+use tower_http::catch_panic::CatchPanicLayer;
+use tower_http::services::{ServeDir, ServeFile};
+pub use wiki::Wiki;
+
+/// The configuration, read from `tome.toml` and the command line
+#[derive(Serialize, Deserialize, Parser, Clone, Default)]
+pub struct TomeConfig {
+    /// The name of the wiki shown in page titles, defaults to "Tome"
+    #[arg(long)]
+    site_name: Option<String>,
+    /// The address to listen on, defaults to all interfaces
+    host: Option<IpAddr>,
+    /// The port to listen on, defaults to 5422
+    port: Option<u16>,
+    /// Defaults of the articles in namespaces: the page `template` and `tags` new articles start
+    /// with, whether they `requires_review` and the `accent_color` of their pages
+    #[arg(long, value_parser = NamespaceDefaults::parse)]
+    namespaces: Vec<NamespaceDefaults>,
+    /// File endings of media that anyone who can edit can upload, without a size limit
+    allowed_uploads: Vec<String>,
+    /// Kinds of media that can be uploaded, each with its `endings`, a `max_size` in bytes and
+    /// the `role` that can upload them, `editor` or `admin`
+    #[arg(long, value_parser = UploadPolicy::parse)]
+    upload_policies: Vec<UploadPolicy>,
+    /// The longest sides in pixels of the thumbnails made of images, defaults to 200
+    #[arg(long)]
+    thumbnail_sizes: Vec<u32>,
+    /// Turns away requests for media from pages on other sites, so they can't embed it
+    #[arg(long)]
+    hotlink_protection: bool,
+    /// Other sites that can embed media despite `hotlink_protection`, e.g. `*.example.com`
+    #[arg(long)]
+    hotlink_allowed_hosts: Vec<String>,
+    /// A token that lets any site embed media as `/media/<name>?token=<token>`
+    #[arg(long)]
+    hotlink_token: Option<String>,
+    /// Formats uploaded images are also converted to and served in to browsers that accept them:
+    /// `webp`, and `avif` with the `avif` feature
+    #[arg(long)]
+    image_variants: Vec<String>,
+    /// A copy of the `build` directory of `pdfjs-dist` to preview PDFs with, instead of a CDN
+    #[arg(long)]
+    pdfjs_dir: Option<String>,
+    /// How many days files stay in the media trash of `tome media gc --delete`, defaults to 30
+    #[arg(long)]
+    media_trash_days: Option<u64>,
+    /// How many seconds browsers can keep media files before asking again, defaults to 300
+    #[arg(long)]
+    media_cache_seconds: Option<u64>,
+    /// The largest file in bytes that can be uploaded at all, defaults to 10 MiB
+    #[arg(long)]
+    max_upload_size: Option<usize>,
+    /// Store uploads whose ending doesn't match their contents with the right ending, instead
+    /// of rejecting them
+    #[arg(long)]
+    fix_upload_endings: bool,
+    /// The directory articles, media and the index page are stored in, defaults to `content`
+    #[arg(long, env = "TOME_CONTENT_DIR")]
+    content_dir: Option<String>,
+    /// A CSS color for links, buttons and the current page in the navigation
+    #[arg(long)]
+    accent_color: Option<String>,
+    /// The CSS font family of all text, e.g. `Georgia, serif`
+    #[arg(long)]
+    font_family: Option<String>,
+    /// The license of the content, e.g. `CC BY-SA 4.0`, which articles can override with
+    /// `license:` in their frontmatter
+    #[arg(long)]
+    license: Option<String>,
+    /// The address the wiki is reachable at, e.g. `https://wiki.example.com`
+    #[arg(long)]
+    public_url: Option<String>,
+    /// The path a program embedding tome nests it under, e.g. `/wiki`
+    #[arg(long)]
+    base_path: Option<String>,
+    /// Text above printed and exported articles, see `export_footer`
+    #[arg(long)]
+    export_header: Option<String>,
+    /// Text below printed and exported articles, e.g. a license notice. `{site_name}`,
+    /// `{title}`, `{url}` and `{date}` are replaced with the article's details
+    #[arg(long)]
+    export_footer: Option<String>,
+    /// Raw HTML inserted into the `<head>` of every page, e.g. fonts or analytics
+    #[arg(long)]
+    custom_head_html: Option<String>,
+    /// Raw HTML inserted at the end of the footer of every page
+    #[arg(long)]
+    custom_footer_html: Option<String>,
+    /// Count page views and daily visitors without cookies, see `/admin/analytics`
+    #[arg(long)]
+    analytics: bool,
+    /// Serve request, save and upload metrics for Prometheus at `/metrics`
+    #[arg(long)]
+    metrics: bool,
+    /// The share of requests traced at DEBUG with timings, like 0.01, defaults to none
+    #[arg(long)]
+    trace_sample_rate: Option<f64>,
+    /// Other shares for some routes, each a `route` pattern like `/article/:id` and its `rate`
+    #[arg(long, value_parser = RouteSampling::parse)]
+    trace_sampling: Vec<RouteSampling>,
+    /// The pandoc binary used for `/article/:id/export`, defaults to `pandoc`
+    #[cfg(feature = "pandoc")]
+    #[arg(long)]
+    pandoc_path: Option<String>,
+    /// Token required to add snippets via `POST /api/inbox`, which is disabled without one
+    #[arg(long)]
+    inbox_token: Option<String>,
+    /// The article inbox snippets are appended to, defaults to "Inbox"
+    #[arg(long)]
+    inbox_article: Option<String>,
+    /// Command run for every article past its `review_by` date, see `/stale`
+    #[arg(long)]
+    stale_command: Option<String>,
+    /// Sign every saved version with the server's key, see `signing.key` in the content directory
+    #[arg(long)]
+    sign_versions: bool,
+    /// Record every saved version in a hash-chained log and never delete versions
+    #[arg(long)]
+    append_only_history: bool,
+    /// Number of versions listed per page of an article's history, defaults to 50
+    #[arg(long)]
+    history_page_size: Option<usize>,
+    /// Warn before saving articles with images that have no alt text
+    #[arg(long)]
+    require_alt_text: bool,
+    /// Link the titles of other articles wherever an article mentions them, which articles can
+    /// override with `autolink:` in their frontmatter
+    #[arg(long)]
+    autolink_titles: bool,
+    /// Show raw HTML in articles as it is written, only for wikis where everyone who edits is
+    /// trusted with running scripts on the pages
+    #[arg(long)]
+    trusted_html: bool,
+    /// HTML elements articles can contain besides the harmless ones, e.g. `abbr` or `video`
+    #[arg(long)]
+    allowed_html_tags: Vec<String>,
+    /// HTML attributes articles can use on any element, e.g. `style`
+    #[arg(long)]
+    allowed_html_attributes: Vec<String>,
+    /// The article explaining terms, in list items like `- Term: explanation`, which other
+    /// articles show with their explanation
+    #[arg(long)]
+    glossary_article: Option<String>,
+    /// Refuse to save articles with problems instead of only warning about them
+    #[arg(long)]
+    lint_blocking: bool,
+    /// Largest article that can be saved in bytes, defaults to 1 MiB
+    #[arg(long)]
+    max_article_size: Option<usize>,
+    /// The user name of the wiki's administrator, set up on the first start
+    #[arg(long)]
+    admin_user: Option<String>,
+    /// The administrator's password hashed with Argon2, in PHC string format
+    #[arg(long)]
+    admin_password_hash: Option<String>,
+    /// The file with the users who can log in, defaults to `users.toml`
+    #[arg(long)]
+    users_file: Option<String>,
+    /// The most bytes the content directory can take up before saves and uploads are refused
+    #[arg(long)]
+    storage_quota: Option<u64>,
+    /// A directory with a wiki for every tenant, served at `<name>.<tenant_domain>`
+    #[arg(long)]
+    tenants_dir: Option<String>,
+    /// The domain tenants are served under, e.g. `wiki.example.com`
+    #[arg(long)]
+    tenant_domain: Option<String>,
+    /// The header a proxy in front of tome sends the logged in user's name in, e.g. `Remote-User`
+    #[arg(long)]
+    proxy_user_header: Option<String>,
+    /// The header with the comma-separated groups of the user, e.g. `Remote-Groups`
+    #[arg(long)]
+    proxy_groups_header: Option<String>,
+    /// Only users in these groups can edit, if any are given
+    #[arg(long)]
+    proxy_editor_groups: Vec<String>,
+    /// Addresses of the proxies whose user headers are trusted
+    #[arg(long)]
+    trusted_proxies: Vec<IpAddr>,
+    /// Keep the history of articles in a git repository in the content's `articles` directory
+    #[arg(long)]
+    git_storage: bool,
+    /// A git remote every commit of the git storage is pushed to, e.g. for backups
+    #[arg(long)]
+    git_remote: Option<String>,
+    /// Let anyone edit and regularly reset the content to the snapshot in `demo/` next to it
+    #[arg(long)]
+    demo_mode: bool,
+    /// Minutes between resets of the demo, defaults to 60
+    #[arg(long)]
+    demo_reset_minutes: Option<u64>,
+    /// Times nobody can change the wiki, like `2024-06-01T00:00:00Z/2024-06-03T00:00:00Z`
+    #[arg(long)]
+    freeze_windows: Vec<String>,
+}
+
+#[derive(Clone, FromRef)]
+struct AppState {
+    config: TomeConfig,
+    auth: auth::Auth,
+    analytics: Analytics,
+    stale: Stale,
+    render_cache: RenderCache,
+    tenants: tenant::Tenants,
+}
+
+/// The command line arguments of `tome`
+#[derive(Parser)]
+pub struct Cli {
+    /// The configuration file to read
+    #[arg(long, env = "TOME_CONFIG", default_value = config::CONFIG_PATH)]
+    config_file: PathBuf,
+    #[command(flatten)]
+    config: TomeConfig,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serve the wiki, which is also what tome does without a command
+    Serve,
+    /// Check the content for damage, like versions that can't be loaded
+    Check,
+    /// Preview or apply a regex replacement across articles
+    Replace(replace::ReplaceArgs),
+    /// Import articles and media exported from another tool
+    Import(import::ImportArgs),
+    /// Inspect the append-only history log
+    History(history::HistoryArgs),
+    /// Maintain the version storage
+    Storage(storage::StorageArgs),
+    /// Export the whole wiki into another format
+    Export(export::ExportArgs),
+    /// Check the configuration
+    Config(config::ConfigArgs),
+    /// Manage the users who can edit the wiki
+    User(auth::UserArgs),
+    /// Record or list snapshots of the whole wiki
+    Snapshot(snapshot::SnapshotArgs),
+    /// Manage the media files
+    Media(media::MediaArgs),
+    /// Time rendering, the overview and history listings with the wiki's content
+    Bench(bench::BenchArgs),
+    /// Show or revert the migrations of the content's layout
+    Migrate(migrations::MigrateArgs),
+    /// Write all articles, versions and media to a file of JSON lines
+    Dump(dump::DumpArgs),
+    /// Save the articles, versions and media of a dump
+    Load(dump::LoadArgs),
+}
+
+#[derive(Template, Clone)]
+#[template(path = "not_found.html", escape = "none")]
+struct NotFound {
+    layout: Layout,
+}
+
+#[derive(Template)]
+#[template(path = "invalid.html")]
+struct Invalid {
+    layout: Layout,
+    message: String,
+}
+
+/// An article, identified by its title
+#[derive(Clone, Deserialize)]
+pub struct Article {
+    pub(crate) title: String,
+    pub(crate) content: String,
+}
+
+#[derive(Template)]
+#[template(path = "article.html", escape = "none")]
+struct ArticlePage {
+    layout: Layout,
+    article: Article,
+    /// The HTML shown, which can have more links than the article's body
+    html: Arc<str>,
+    /// Problems found in the article when it was saved
+    warnings: Vec<String>,
+    /// The old version shown instead of the current one
+    version: Option<String>,
+    /// The permanent link of the version shown
+    permalink: Option<String>,
+    /// Whether this is the page of a permanent link
+    pinned: bool,
+    /// Slugs and titles of the articles linking to this one
+    backlinks: Vec<(String, String)>,
+    /// Shown when the article is printed
+    notices: export::Notices,
+}
+
+#[derive(Deserialize)]
+struct ArticleQuery {
+    /// Set after saving, to show the problems found in the article
+    #[serde(default)]
+    check: bool,
+}
+
+impl Article {
+    /// An article that isn't saved yet
+    pub fn new(title: impl Into<String>, content: impl Into<String>) -> Self {
+        Article {
+            title: title.into(),
+            content: content.into(),
+        }
+    }
+
+    /// The title shown to readers
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// The Markdown content, including its frontmatter
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// The slug the article is stored and linked under
+    pub fn path(&self) -> String {
+        slug(&self.title)
+    }
+
+    /// The content with the display title in its frontmatter, if it differs from the slug
+    /// and the frontmatter can be written back
+    fn content_with_title(&self) -> String {
+        let Ok(mut meta) = frontmatter::parse(&self.content) else {
+            return self.content.clone();
+        };
+        let before = meta.clone();
+        frontmatter::set_title(
+            &mut meta,
+            Some(self.title.as_str()).filter(|t| *t != self.path()),
+        );
+        if meta == before {
+            return self.content.clone();
+        }
+        frontmatter::join(&meta, frontmatter::split(&self.content).1)
+    }
+
+    /// The content without the display title, which the editor has its own field for
+    fn content_without_title(&self) -> String {
+        let Ok(mut meta) = frontmatter::parse(&self.content) else {
+            return self.content.clone();
+        };
+        if frontmatter::title(&meta).is_none() {
+            return self.content.clone();
+        }
+        frontmatter::set_title(&mut meta, None);
+        frontmatter::join(&meta, frontmatter::split(&self.content).1)
+    }
+
+    /// Checks that `title` can be used for an article, returning why not otherwise
+    fn validate_title(title: &str) -> Result<(), String> {
+        let title = title.trim();
+        if title.is_empty() {
+            return Err("Articles need a title.".to_string());
+        }
+        if let Some(problem) = paths::problem(title) {
+            return Err(format!("\"{title}\" can't be used as a title, {problem}."));
+        }
+        if slug(title).is_empty() {
+            return Err("Titles need at least one letter or digit.".to_string());
+        }
+        Ok(())
+    }
+
+    /// The Markdown content without its frontmatter
+    pub fn body(&self) -> &str {
+        frontmatter::split(&self.content).1
+    }
+
+    /// The HTML of the article's page, with links to edit its sections
+    async fn shown_html(&self, config: &TomeConfig) -> String {
+        filters::article_md(self.shown_body(config).await, self.path())
+            .unwrap()
+            .to_string()
+    }
+
+    /// The Markdown body as it is shown, with automatic links and glossary terms
+    #[tracing::instrument(level = "debug", skip_all, fields(title = %self.title))]
+    async fn shown_body(&self, config: &TomeConfig) -> String {
+        let body = file_info::expand(self.body()).await;
+        let body = match autolink::enabled(config, self) {
+            true => autolink::link(&body, &self.path(), &backlinks::titles().await),
+            false => Cow::Borrowed(body.as_ref()),
+        };
+        let body = glossary::expand(config, &self.title, &body).await;
+        if self.metadata().toc && !filters::has_toc_marker(&body) {
+            format!("{}\n\n{body}", filters::TOC_MARKER)
+        } else {
+            body
+        }
+    }
+
+    /// The tags in the frontmatter
+    fn tags(&self) -> Vec<String> {
+        frontmatter::tags(&frontmatter::parse(&self.content).unwrap_or_default())
+    }
+
+    /// The metadata in the frontmatter
+    fn metadata(&self) -> frontmatter::Metadata {
+        frontmatter::Metadata::from(&frontmatter::parse(&self.content).unwrap_or_default())
+    }
+
+    fn is_stale(&self) -> bool {
+        stale::is_overdue(&self.content)
+    }
+
+    fn requires_review(&self) -> bool {
+        review::requires_review(&self.content)
+    }
+
+    fn is_archived(&self) -> bool {
+        archive::is_archived(&self.content)
+    }
+
+    /// Saves the article as its new current version
+    pub async fn write_to_disk(&self) -> tokio::io::Result<()> {
+        self.write_to_disk_by(None, None).await
+    }
+
+    /// Saves the article as its new current version, edited by `author` as `summary` says
+    #[tracing::instrument(level = "debug", skip_all, fields(title = %self.title))]
+    pub async fn write_to_disk_by(
+        &self,
+        author: Option<&str>,
+        summary: Option<&str>,
+    ) -> tokio::io::Result<()> {
+        let _writing = shutdown::writing().await;
+        let _ = tokio::fs::create_dir(storage::article_dir(&self.title)).await;
+
+        let content = self.content_with_title();
+        let version = storage().save(&self.title, &content).await?;
+        version_info::write(&self.title, &version, author, summary).await?;
+        signature::sign(&self.title, &version, &content).await?;
+        history::record(&self.title, &version, &content).await?;
+        prometheus::article_saved();
+        render_cache::invalidate();
+        search::update(self).await;
+        backlinks::update(self).await;
+
+        shutdown::write_atomically(
+            format!("{}/current.md", storage::article_dir(&self.title)),
+            content.as_bytes(),
+        )
+        .await
+    }
+
+    /// Saves the article like [`Article::write_to_disk_by`], unless its current version is archived
+    pub async fn change_by(
+        &self,
+        author: Option<&str>,
+        summary: Option<&str>,
+    ) -> Result<(), TomeError> {
+        archive::ensure_changeable(&self.title).await?;
+        quota::ensure_room(self.content.len()).await?;
+        Ok(self.write_to_disk_by(author, summary).await?)
+    }
+
+    /// The current version of the article `title`, which may be its slug
+    #[tracing::instrument(level = "debug")]
+    pub async fn load(title: &str) -> Option<Self> {
+        let path = format!("{}/current.md", storage::article_dir(title));
+        match tokio::fs::read_to_string(&path).await {
+            Ok(content) => Some(Article {
+                title: frontmatter::Metadata::from(
+                    &frontmatter::parse(&content).unwrap_or_default(),
+                )
+                .title
+                .unwrap_or_else(|| title.to_string()),
+                content,
+            }),
+            Err(_) => None,
+        }
+    }
+
+    /// An older version of the article `title`
+    pub async fn load_version(title: &str, version: &str) -> Option<Self> {
+        storage().load(title, version).await.map(|content| Article {
+            title: title.to_string(),
+            content,
+        })
+    }
+
+    /// The ids of all versions of the article `title` and when they were saved
+    pub async fn get_versions(title: &str) -> Vec<(String, SystemTime)> {
+        storage().versions(title).await
+    }
+
+    /// The id of the latest version of the article `title`
+    pub async fn current_version(title: &str) -> Option<String> {
+        // The last of versions saved at the same time is the latest
+        Article::get_versions(title)
+            .await
+            .into_iter()
+            .max_by_key(|(_, saved)| *saved)
+            .map(|(version, _)| version)
+    }
+}
+
+#[derive(Template, Clone)]
+#[template(path = "editor.html")]
+struct Editor {
+    layout: Layout,
+    is_index: bool,
+    title: String,
+    /// The title the article is saved under, it is renamed if `title` is changed
+    original_title: String,
+    content: String,
+    templates: Vec<String>,
+    /// The section being edited, if only part of the article is edited
+    section: Option<usize>,
+    /// Images without alt text that kept the article from being saved
+    missing_alt_text: Vec<String>,
+    /// Problems that kept the article from being saved
+    errors: Vec<String>,
+    /// The edit summary entered before the article couldn't be saved
+    summary: String,
+    /// The version being edited, empty for new articles
+    base_version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EditQuery {
+    template: Option<String>,
+    section: Option<usize>,
+}
+
+#[derive(Template, Clone)]
+#[template(path = "mobile_editor.html")]
+struct MobileEditor {
+    title: String,
+    content: String,
+    append: bool,
+    /// The version being edited, empty for new articles
+    base_version: String,
+}
+
+/// The fields of the article editor forms
+#[derive(Deserialize)]
+struct ArticleForm {
+    title: String,
+    /// The title the article had when the editor was opened
+    #[serde(default)]
+    original_title: String,
+    content: String,
+    /// Add `content` to the end of the article instead of replacing it
+    #[serde(default)]
+    append: bool,
+    /// Replace only this section of the article with `content`
+    section: Option<usize>,
+    /// Save even if images are missing alt text
+    #[serde(default)]
+    ignore_missing_alt_text: bool,
+    /// What was changed and why
+    #[serde(default)]
+    summary: String,
+    /// The version the editor was opened with, see [`conflict`]
+    base_version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MobileEditQuery {
+    #[serde(default)]
+    append: bool,
+}
+
+#[derive(Deserialize, Clone, Default)]
+struct Index {
+    content: String,
+}
+
+#[derive(Template)]
+#[template(path = "index.html", escape = "none")]
+struct IndexPage {
+    layout: Layout,
+    index: Index,
+}
+
+#[derive(Deserialize, Clone, Default)]
+struct Overview {
+    articles: Vec<(String, String)>,
+    /// The slugs of the archived articles
+    #[serde(default)]
+    archived: HashSet<String>,
+}
+
+#[derive(Template)]
+#[template(path = "overview.html")]
+struct OverviewPage {
+    layout: Layout,
+    /// The slug and title of each article, and whether it is archived
+    articles: Vec<(String, String, bool)>,
+    query: String,
+    namespace: String,
+    archived: bool,
+}
+
+#[derive(Deserialize)]
+struct OverviewQuery {
+    #[serde(default)]
+    q: String,
+    /// Only list articles in this namespace
+    #[serde(default)]
+    namespace: String,
+    /// Also list archived articles
+    #[serde(default)]
+    archived: bool,
+}
+
+#[derive(Template)]
+#[template(path = "history.html")]
+struct History {
+    layout: Layout,
+    article: String,
+    /// The slug of the article, for links
+    path: String,
+    /// Id, time, author and edit summary of each version, newest first
+    versions: Vec<(String, String, Option<String>, Option<String>)>,
+    /// The tags of each version
+    tags: BTreeMap<String, Vec<String>>,
+    page: usize,
+    pages: usize,
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    page: Option<usize>,
+}
+
+impl Overview {
+    async fn load() -> Self {
+        let mut articles = vec![];
+        let mut archived = HashSet::new();
+        for slug in storage::article_slugs().await {
+            let title = match Article::load(&slug).await {
+                Some(article) => {
+                    if article.is_archived() {
+                        archived.insert(slug.clone());
+                    }
+                    article.title
+                }
+                None => slug.clone(),
+            };
+            articles.push((slug, title))
+        }
+        Overview { articles, archived }
+    }
+}
+
+impl Index {
+    async fn write_to_disk(&self) -> tokio::io::Result<()> {
+        tokio::fs::write(content_path("index.md"), self.content.as_bytes()).await
+    }
+
+    async fn load() -> tokio::io::Result<Self> {
+        let content = tokio::fs::read_to_string(content_path("index.md")).await?;
+        Ok(Index { content })
+    }
+}
+
+async fn get_article(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    State(render_cache): State<RenderCache>,
+    headers: HeaderMap,
+    Path(title): Path<String>,
+    Query(query): Query<ArticleQuery>,
+) -> Result<Response, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let response = if let Some(article) = Article::load(&title).await {
+        let layout = layout
+            .with_direction_of(&article.content)
+            .with_license_of(&article);
+        let warnings = if query.check {
+            lint::check(article.body()).await
+        } else {
+            vec![]
+        };
+        let permalink = Article::current_version(&article.title)
+            .await
+            .map(|version| permalink::url(&article.title, &version));
+        ArticlePage {
+            layout,
+            notices: export::Notices::new(&config, &headers, &article),
+            backlinks: backlinks::of(&article.title).await,
+            html: render_cache.article_html(&config, &article).await,
+            article,
+            warnings,
+            version: None,
+            permalink,
+            pinned: false,
+        }
+        .into_response()
+    } else if let Some(renamed) = rename::resolve(&title).await {
+        Redirect::permanent(&format!("/article/{renamed}")).into_response()
+    } else {
+        Redirect::temporary(&format!("/edit/article/{title}")).into_response()
+    };
+    Ok(response)
+}
+
+/// The Markdown of the article as it is stored, for backups and other tools
+async fn raw_article(Path(title): Path<String>) -> Result<Response, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    if let Some(article) = Article::load(&title).await {
+        Ok((
+            [(header::CONTENT_TYPE, "text/markdown; charset=utf-8")],
+            article.content,
+        )
+            .into_response())
+    } else if let Some(renamed) = rename::resolve(&title).await {
+        Ok(Redirect::permanent(&format!("/article/{renamed}/raw")).into_response())
+    } else {
+        Err(TomeError::NotFound)
+    }
+}
+
+async fn article_history(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    Path(title): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<impl IntoResponse, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let mut versions: Vec<(String, SystemTime)> = Article::get_versions(&title).await;
+    versions.sort_by_key(|(_, edited)| *edited);
+    versions.reverse();
+
+    let page_size = config.history_page_size.unwrap_or(50).max(1);
+    let pages = versions.len().div_ceil(page_size).max(1);
+    let page = query.page.unwrap_or(1).clamp(1, pages);
+
+    let mut shown = vec![];
+    for (version, edited) in versions
+        .into_iter()
+        .skip((page - 1) * page_size)
+        .take(page_size)
+    {
+        let info = version_info::read(&title, &version).await;
+        let edited =
+            OffsetDateTime::from(edited).format(&time::format_description::well_known::Rfc2822)?;
+        let (author, summary) = match info {
+            Some(info) => (info.author, info.summary),
+            None => (None, None),
+        };
+        shown.push((version, edited, author, summary));
+    }
+    Ok(History {
+        layout,
+        path: slug(&title),
+        versions: shown,
+        tags: version_tags::by_version(&title).await,
+        article: title,
+        page,
+        pages,
+    })
+}
+
+async fn article_version(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    headers: HeaderMap,
+    Path((title, version)): Path<(String, String)>,
+) -> Result<impl IntoResponse, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let article = Article::load_version(&title, &version)
+        .await
+        .ok_or(TomeError::NotFound)?;
+    let layout = layout
+        .with_direction_of(&article.content)
+        .with_license_of(&article);
+    Ok(ArticlePage {
+        layout,
+        notices: export::Notices::new(&config, &headers, &article),
+        permalink: Some(permalink::url(&article.title, &version)),
+        pinned: false,
+        backlinks: vec![],
+        html: article.shown_html(&config).await.into(),
+        article,
+        warnings: vec![],
+        version: Some(version),
+    })
+}
+
+/// Saves an old version as the newest version of the article
+async fn restore_version(
+    layout: Layout,
+    Path((title, version)): Path<(String, String)>,
+) -> Result<Response, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let Some(old) = Article::load_version(&title, &version).await else {
+        return Err(TomeError::NotFound);
+    };
+    let current = Article::load(&title).await;
+    let needs_review = current.as_ref().is_some_and(Article::requires_review);
+    // The article keeps its current title, even if the old version had another one
+    let article = Article {
+        title: current.map_or(old.title, |current| current.title),
+        content: old.content,
+    };
+
+    if needs_review {
+        let revision = review::submit(&article, layout.user.as_deref()).await?;
+        return Ok(
+            Redirect::to(&format!("/article/{}/review/{revision}", article.path())).into_response(),
+        );
+    }
+    article
+        .change_by(
+            layout.user.as_deref(),
+            Some(&format!("Restored version {version}")),
+        )
+        .await?;
+
+    Ok(Redirect::to(&format!("/article/{}", article.path())).into_response())
+}
+
+async fn edit_article(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    Path(title): Path<String>,
+    Query(query): Query<EditQuery>,
+) -> Result<Response, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+
+    if let Some(article) = Article::load(&title).await {
+        if article.is_archived() {
+            return Ok(archive::refuse_change(layout, &article.title));
+        }
+        let base_version = Article::current_version(&article.title)
+            .await
+            .unwrap_or_default();
+        let section = query
+            .section
+            .and_then(|index| Some((index, section::get(article.body(), index)?)));
+        return Ok(match section {
+            Some((index, section)) => Editor {
+                layout,
+                is_index: false,
+                original_title: article.title.clone(),
+                title: article.title.clone(),
+                content: section.to_string(),
+                templates: vec![],
+                section: Some(index),
+                missing_alt_text: vec![],
+                errors: vec![],
+                summary: String::new(),
+                base_version: Some(base_version.clone()),
+            },
+            None => Editor {
+                layout,
+                is_index: false,
+                original_title: article.title.clone(),
+                content: article.content_without_title(),
+                title: article.title,
+                templates: vec![],
+                section: None,
+                missing_alt_text: vec![],
+                errors: vec![],
+                summary: String::new(),
+                base_version: Some(base_version.clone()),
+            },
+        }
+        .into_response());
+    }
+
+    let content = namespace::initial_content(&config, &title, query.template.as_deref(), "").await;
+    Ok(Editor {
+        layout,
+        is_index: false,
+        original_title: title.clone(),
+        title,
+        content,
+        templates: page_template::names().await,
+        section: None,
+        missing_alt_text: vec![],
+        errors: vec![],
+        summary: String::new(),
+        base_version: Some(String::new()),
+    }
+    .into_response())
+}
+
+async fn edit_article_mobile(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    Path(title): Path<String>,
+    Query(query): Query<MobileEditQuery>,
+) -> Result<Response, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+
+    let content = match Article::load(&title).await {
+        Some(article) if article.is_archived() => {
+            return Ok(archive::refuse_change(layout, &article.title));
+        }
+        Some(article) if !query.append => article.content,
+        Some(_) => String::new(),
+        None => namespace::initial_content(&config, &title, None, "").await,
+    };
+    Ok(MobileEditor {
+        base_version: Article::current_version(&title).await.unwrap_or_default(),
+        title,
+        content,
+        append: query.append,
+    }
+    .into_response())
+}
+
+async fn edit_index(layout: Layout) -> Result<impl IntoResponse, TomeError> {
+    let index = Index::load().await?;
+    Ok(Editor {
+        layout,
+        is_index: true,
+        title: "Index".to_string(),
+        original_title: String::new(),
+        content: index.content,
+        templates: vec![],
+        section: None,
+        missing_alt_text: vec![],
+        errors: vec![],
+        summary: String::new(),
+        base_version: None,
+    })
+}
+
+#[axum_macros::debug_handler(state = AppState)]
+async fn post_article(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    State(auth): State<auth::Auth>,
+    Form(form): Form<ArticleForm>,
+) -> Result<Response, TomeError> {
+    if let Err(message) = Article::validate_title(&form.title) {
+        return Ok((StatusCode::BAD_REQUEST, Invalid { layout, message }).into_response());
+    }
+
+    // Changing the title renames the article, unless the title is already taken
+    let original = Some(form.original_title.as_str())
+        .filter(|original| !original.is_empty() && slug(original) != slug(&form.title));
+    let renamed_from = match original {
+        Some(original) => Article::load(original).await.map(|_| original.to_string()),
+        None => None,
+    };
+    if renamed_from.is_some() && Article::load(&form.title).await.is_some() {
+        let message = format!(
+            "There already is an article called \"{}\", choose another title.",
+            form.title
+        );
+        return Ok((StatusCode::CONFLICT, Invalid { layout, message }).into_response());
+    }
+
+    let current = Article::load(renamed_from.as_deref().unwrap_or(&form.title)).await;
+    if let Some(current) = current.as_ref().filter(|current| current.is_archived()) {
+        return Ok(archive::refuse_change(layout, &current.title));
+    }
+    let previous = current.as_ref().map(|current| current.content.clone());
+    let needs_review = current.as_ref().is_some_and(Article::requires_review);
+    if let (true, Some(original)) = (needs_review, &renamed_from) {
+        return Ok(review::refuse_rename(layout, original));
+    }
+    quota::ensure_room(form.content.len()).await?;
+    let content = match current {
+        Some(current) if form.append => {
+            format!("{}\n\n{}", current.content.trim_end(), form.content)
+        }
+        Some(current) if form.section.is_some() => {
+            let body = current.body();
+            let frontmatter = &current.content[..current.content.len() - body.len()];
+            match section::replace(body, form.section.unwrap(), &form.content) {
+                Some(body) => format!("{frontmatter}{body}"),
+                // The section was removed since the editor was opened, saving would lose the rest
+                None => {
+                    let message = format!(
+                        "\"{}\" doesn't have this section anymore, edit the whole article instead.",
+                        current.title
+                    );
+                    return Ok((StatusCode::CONFLICT, Invalid { layout, message }).into_response());
+                }
+            }
+        }
+        _ => form.content,
+    };
+    let article = Article {
+        title: form.title,
+        content,
+    };
+    let saved_title = renamed_from
+        .clone()
+        .unwrap_or_else(|| article.title.clone());
+    if let Some(base) = form.base_version.as_deref().filter(|_| !form.append) {
+        if conflict::is_outdated(&saved_title, base).await {
+            return Ok(conflict::page(layout, &saved_title, article, form.summary).await);
+        }
+    }
+    let max_size = config.max_article_size.unwrap_or(1024 * 1024);
+    if article.content.len() > max_size {
+        let message = format!(
+            "The article is {} KiB long, but articles can be at most {} KiB.",
+            article.content.len().div_ceil(1024),
+            max_size / 1024
+        );
+        return Ok((StatusCode::PAYLOAD_TOO_LARGE, Invalid { layout, message }).into_response());
+    }
+
+    if config.require_alt_text && !form.ignore_missing_alt_text {
+        let missing_alt_text = filters::images_without_alt(article.body());
+        if !missing_alt_text.is_empty() {
+            return Ok(Editor {
+                layout,
+                is_index: false,
+                title: article.title,
+                original_title: form.original_title,
+                content: article.content,
+                templates: vec![],
+                section: None,
+                missing_alt_text,
+                errors: vec![],
+                summary: form.summary,
+                base_version: form.base_version,
+            }
+            .into_response());
+        }
+    }
+
+    let problems = lint::check(article.body()).await;
+    if config.lint_blocking && !problems.is_empty() {
+        return Ok(Editor {
+            layout,
+            is_index: false,
+            title: article.title,
+            original_title: form.original_title,
+            content: article.content,
+            templates: vec![],
+            section: None,
+            missing_alt_text: vec![],
+            errors: problems,
+            summary: form.summary,
+            base_version: form.base_version,
+        }
+        .into_response());
+    }
+
+    if let Some(original) = &renamed_from {
+        rename::rename(original, &article.title).await?;
+    }
+
+    if needs_review {
+        let revision = review::submit(&article, layout.user.as_deref()).await?;
+        return Ok(
+            Redirect::to(&format!("/article/{}/review/{revision}", article.path())).into_response(),
+        );
+    }
+    article
+        .write_to_disk_by(layout.user.as_deref(), Some(&form.summary))
+        .await?;
+    mentions::notify(&auth, &article, previous.as_deref(), layout.user.as_deref()).await?;
+
+    if problems.is_empty() {
+        Ok(Redirect::to(&format!("/article/{}", article.path())).into_response())
+    } else {
+        Ok(Redirect::to(&format!("/article/{}?check=true", article.path())).into_response())
+    }
+}
+
+#[debug_handler]
+async fn update_index(Form(index): Form<Index>) -> Result<impl IntoResponse, TomeError> {
+    quota::ensure_room(index.content.len()).await?;
+    index.write_to_disk().await?;
+
+    Ok(Redirect::to("/"))
+}
+
+#[axum_macros::debug_handler(state = AppState)]
+async fn get_index(layout: Layout) -> Result<impl IntoResponse, TomeError> {
+    let index = Index::load().await?;
+    Ok(IndexPage {
+        layout: layout.with_direction_of(&index.content),
+        index,
+    })
+}
+
+async fn get_overview(layout: Layout, Query(query): Query<OverviewQuery>) -> impl IntoResponse {
+    let needle = query.q.trim().to_lowercase();
+    let overview = Overview::load().await;
+    let prefix = format!("{}{}", query.namespace, layout::NAMESPACE_SEPARATOR);
+    let mut articles: Vec<(String, String, bool)> = overview
+        .articles
+        .into_iter()
+        .map(|(slug, title)| {
+            let archived = overview.archived.contains(&slug);
+            (slug, title, archived)
+        })
+        .filter(|(_, title, archived)| {
+            title.to_lowercase().contains(&needle)
+                && (query.namespace.is_empty() || title.starts_with(&prefix))
+                && (query.archived || !archived)
+        })
+        .collect();
+    articles.sort_by_key(|(_, title, _)| title.to_lowercase());
+    OverviewPage {
+        layout,
+        articles,
+        query: query.q,
+        namespace: query.namespace,
+        archived: query.archived,
+    }
+}
+
+/// Renders Markdown into HTML like article pages, without links to edit sections
+pub fn render(markdown: &str) -> String {
+    filters::render(markdown, |event| event)
+}
+
+/// Builds the wiki for `config`, serving its content directory
+pub async fn app(config: TomeConfig) -> color_eyre::Result<Router> {
+    let wiki = Wiki::open(&config).await?;
+    let tenants = tenant::Tenants::open(&config).await?;
+    let router = wiki
+        .run(routes(config, wiki.clone(), tenants.clone()))
+        .await?;
+    Ok(tenants.serve(router))
+}
+
+/// The routes of `wiki`, which is set for every request they answer, managing `tenants`
+async fn routes(
+    config: TomeConfig,
+    wiki: Wiki,
+    tenants: tenant::Tenants,
+) -> color_eyre::Result<Router> {
+    if config.demo_mode {
+        demo::start(&config).await?;
+    }
+    search::build().await;
+    backlinks::build().await;
+
+    let analytics = if config.analytics {
+        Analytics::start().await
+    } else {
+        Analytics::default()
+    };
+
+    let state = AppState {
+        config: config.clone(),
+        auth: auth::Auth::load(&config).await?,
+        analytics,
+        stale: Stale::start(&config),
+        render_cache: RenderCache::default(),
+        tenants,
+    };
+
+    let router = Router::new()
+        .route(
+            "/",
+            get(get_index).layer(middleware::from_fn(caching::page)),
+        )
+        .route("/", post(update_index))
+        .route("/overview", get(get_overview))
+        .route("/new", get(paste::get_new))
+        .route("/new", post(paste::post_new))
+        .route("/changes", get(changes::get_changes))
+        .route("/tags", get(tags::get_tags))
+        .route("/tags/:tag", get(tags::get_tag))
+        .route("/snapshots", get(snapshot::get_snapshots))
+        .route("/snapshot/:name", get(snapshot::get_snapshot))
+        .route("/snapshot/:name/:id", get(snapshot::get_snapshot_article))
+        .route("/changes.atom", get(feed::changes_feed))
+        .route("/article/:id/history.atom", get(feed::history_feed))
+        .route("/search", get(search::get_search))
+        .route("/login", get(auth::get_login))
+        .route("/login", post(auth::post_login))
+        .route("/logout", post(auth::post_logout))
+        .route(
+            "/article/:id",
+            get(get_article).layer(middleware::from_fn(caching::page)),
+        )
+        .route("/edit/article/:id", get(edit_article))
+        .route("/m/edit/article/:id", get(edit_article_mobile))
+        .route("/edit/index", get(edit_index))
+        .route("/article/:id", post(post_article))
+        .route("/article/:id/history/:version", get(article_version))
+        .route(
+            "/article/:id/permalink/:version",
+            get(permalink::get_permalink),
+        )
+        .route("/article/:id/raw", get(raw_article))
+        .route("/article/:id/history", get(article_history))
+        .route(
+            "/article/:id/history/:version/tag",
+            post(version_tags::post_tag),
+        )
+        .route("/article/:id/tags", get(version_tags::get_tags))
+        .route("/article/:id/tags/:tag", get(version_tags::get_tag))
+        .route(
+            "/article/:id/tags/:tag/delete",
+            post(version_tags::post_untag),
+        )
+        .route("/article/:id/history/:version/diff", get(diff::get_diff))
+        .route(
+            "/article/:id/history/:version/restore",
+            post(restore_version),
+        )
+        .route("/article/:id/compare", get(diff::get_compare))
+        .route(
+            "/article/:id/review/:revision",
+            get(review::get_review).post(review::post_review),
+        )
+        .route("/reviews", get(review::get_reviews))
+        .route("/stale", get(stale::get_stale))
+        .route("/article/:id/export.html", get(export::export_html))
+        .route(
+            "/article/:id/annotations",
+            get(annotations::get_annotations).post(annotations::post_annotation),
+        )
+        .route(
+            "/article/:id/annotations/:annotation",
+            delete(annotations::delete_annotation),
+        )
+        .route("/media", get(get_media_overview))
+        .route(
+            "/media",
+            // Leaves room for the rest of the form, files are limited by `post_media`
+            post(post_media).layer(DefaultBodyLimit::max(
+                media::max_upload_size(&config).saturating_add(64 * 1024),
+            )),
+        )
+        .route("/admin/replace", get(replace::get_replace))
+        .route("/admin/replace", post(replace::post_replace))
+        .route("/admin/retag", get(retag::get_retag))
+        .route("/admin/retag", post(retag::post_retag))
+        .route("/admin/analytics", get(analytics::get_analytics))
+        .route("/admin/analytics/referrers", get(analytics::get_referrers))
+        .route("/metrics", get(prometheus::get_metrics))
+        .route("/user/:name", get(mentions::get_user))
+        .route("/notifications", get(mentions::get_notifications))
+        .route("/article/:id/delete", post(trash::post_delete))
+        .route("/article/:id/archive", post(archive::post_archive))
+        .route("/overview/archive", post(archive::post_archive_namespace))
+        .route("/article/:id/rename", get(rename::get_rename))
+        .route("/article/:id/rename", post(rename::post_rename))
+        .route("/admin/freeze", get(freeze::get_freeze))
+        .route("/admin/freeze", post(freeze::post_freeze))
+        .route(
+            "/admin/tenants",
+            get(tenant::get_tenants).post(tenant::post_tenants),
+        )
+        .route("/admin/tenants/:name/quota", post(tenant::post_quota))
+        .route("/admin/trash", get(trash::get_trash))
+        .route("/admin/trash/:id/restore", post(trash::post_restore))
+        .route("/admin/trash/:id/purge", post(trash::post_purge))
+        .route("/api/inbox", post(inbox::post_inbox))
+        .route(
+            "/api/articles",
+            get(api::get_articles).post(paste::post_api_article),
+        )
+        .route("/api/changes", get(api::get_changes))
+        .route(
+            "/api/article/:id/history/:version/signature",
+            get(signature::verify),
+        )
+        .route("/api/signing-key", get(signature::public_key))
+        .route("/api/fragment", get(fragment::get_fragment))
+        .route("/api/preview/:title", get(preview::get_preview))
+        .route("/preview", post(preview::post_live_preview))
+        .route_service(
+            "/favicon.ico",
+            get_service(ServeFile::new(content_path("media/favicon.ico"))),
+        )
+        .nest(
+            "/media/",
+            Router::new()
+                .route("/:name/delete", post(media::post_delete_media))
+                .route("/:name/rename", post(media::post_rename_media))
+                .route("/thumb/:size/:name", get(thumbnail::get_thumbnail))
+                .route("/:name", get(image_variants::get_media))
+                .fallback_service(get_service(ServeDir::new(content_path("media"))))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    caching::media,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    hotlink::protect,
+                )),
+        )
+        .route("/static/:name", get(assets::get_asset))
+        .route("/static/pdfjs/:name", get(pdf_preview::get_pdfjs))
+        .route("/custom.css", get(assets::custom_css))
+        .route("/sw.js", get(assets::service_worker))
+        .route("/manifest.webmanifest", get(assets::manifest))
+        .fallback(|layout: Layout| async { (StatusCode::NOT_FOUND, NotFound { layout }) });
+
+    #[cfg(feature = "pandoc")]
+    let router = router.route("/article/:id/export", get(pandoc::export));
+
+    let router = router
+        .layer(CatchPanicLayer::custom(error::panicked))
+        .layer(middleware::from_fn_with_state(state.clone(), error::render))
+        .layer(middleware::from_fn_with_state(state.clone(), freeze::guard))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::authenticate,
+        ));
+
+    let router = if config.analytics {
+        router.layer(middleware::from_fn_with_state(
+            state.clone(),
+            analytics::track,
+        ))
+    } else {
+        router
+    };
+
+    let router = if config.trace_sample_rate.is_some() || !config.trace_sampling.is_empty() {
+        router.layer(middleware::from_fn_with_state(
+            state.clone(),
+            sampling::sample,
+        ))
+    } else {
+        router
+    };
+
+    let router = if config.base_path.is_some() {
+        router.layer(middleware::from_fn_with_state(
+            state.clone(),
+            base_path::prefix,
+        ))
+    } else {
+        router
+    };
+
+    prometheus::install(&config);
+    let router = if config.metrics {
+        router.layer(middleware::from_fn(prometheus::track))
+    } else {
+        router
+    };
+    Ok(router
+        .with_state(state)
+        .layer(middleware::from_fn_with_state(wiki, wiki::enter)))
+}
+
+/// Runs the command given on the command line, or serves the wiki
+pub async fn run(cli: Cli) -> color_eyre::Result<()> {
+    if let Some(Command::Config(args)) = cli.command {
+        return config::run(args, &cli.config_file, cli.config).await;
+    }
+    let config = config::load(&cli.config_file, cli.config.clone())?;
+
+    match cli.command {
+        None | Some(Command::Serve) => serve(&cli.config_file, cli.config, config).await,
+        Some(command) => {
+            let wiki = Wiki::open(&config).await?;
+            wiki.run(async {
+                match command {
+                    Command::Replace(args) => replace::run(args).await,
+                    Command::Import(args) => import::run(args).await,
+                    Command::History(args) => history::run(args).await,
+                    Command::Storage(args) => storage::run(args).await,
+                    Command::Export(args) => export::run(args, &config).await,
+                    Command::User(args) => auth::run(args, &config).await,
+                    Command::Snapshot(args) => snapshot::run(args).await,
+                    Command::Media(args) => media::run(args, &config).await,
+                    Command::Check => check::run(&config).await,
+                    Command::Bench(args) => bench::run(args, &config).await,
+                    Command::Migrate(args) => migrations::run_command(args).await,
+                    Command::Dump(args) => dump::dump(args).await,
+                    Command::Load(args) => dump::load(args).await,
+                    Command::Serve | Command::Config(_) => unreachable!(),
+                }
+            })
+            .await
+        }
+    }
+}
+
+/// Serves the wiki with `config`, loaded from `config_file` and `arguments`,
+/// after setting it up if needed
+async fn serve(
+    config_file: &std::path::Path,
+    arguments: TomeConfig,
+    config: TomeConfig,
+) -> color_eyre::Result<()> {
+    let addr = SocketAddr::from((
+        config.host.unwrap_or(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0))),
+        config.port.unwrap_or(5422),
+    ));
+    let config = if !config.demo_mode && setup::is_needed(&config).await {
+        setup::run(addr, config_file, config).await?;
+        config::load(config_file, arguments)?
+    } else {
+        config
+    };
+
+    let router = app(config).await?;
+
+    axum::Server::bind(&addr)
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown::signal())
+        .await?;
+    shutdown::finish_writes().await;
+    Ok(())
+}
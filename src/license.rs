@@ -0,0 +1,68 @@
+//! # Licenses
+//!
+//! `license` in the config is the license of the wiki's content, and the
+//! `license:` frontmatter of an article overrides it for that article. The
+//! license is shown in the footer and added to exported articles, printed
+//! pages and ZIM archives. Creative Commons licenses, written like
+//! `CC BY-SA 4.0` or as their SPDX id `CC-BY-SA-4.0`, link to their deed,
+//! and so does any license given as a URL.
+use crate::{frontmatter, Article, TomeConfig};
+
+/// The Creative Commons licenses with their path on creativecommons.org
+const CREATIVE_COMMONS: &[(&str, &str)] = &[
+    ("CC-BY", "licenses/by"),
+    ("CC-BY-SA", "licenses/by-sa"),
+    ("CC-BY-ND", "licenses/by-nd"),
+    ("CC-BY-NC", "licenses/by-nc"),
+    ("CC-BY-NC-SA", "licenses/by-nc-sa"),
+    ("CC-BY-NC-ND", "licenses/by-nc-nd"),
+    ("CC0", "publicdomain/zero"),
+];
+
+#[derive(Clone)]
+pub struct License {
+    pub name: String,
+    /// Where the license text can be read
+    pub url: Option<String>,
+}
+
+/// The deed of a Creative Commons license like `CC BY-SA 4.0`
+fn creative_commons_url(name: &str) -> Option<String> {
+    let id = name.trim().to_uppercase().replace(' ', "-");
+    let (license, version) = id.rsplit_once('-')?;
+    if !version.chars().all(|c| c.is_ascii_digit() || c == '.') || !version.contains('.') {
+        return None;
+    }
+    let (_, path) = CREATIVE_COMMONS.iter().find(|(cc, _)| *cc == license)?;
+    Some(format!("https://creativecommons.org/{path}/{version}/"))
+}
+
+impl License {
+    pub fn new(name: &str) -> Self {
+        let url = if name.starts_with("https://") || name.starts_with("http://") {
+            Some(name.to_string())
+        } else {
+            creative_commons_url(name)
+        };
+        License {
+            name: name.to_string(),
+            url,
+        }
+    }
+
+    /// The license of the wiki's content
+    pub fn of_site(config: &TomeConfig) -> Option<Self> {
+        config.license.as_deref().map(License::new)
+    }
+
+    /// The license in the frontmatter of `article`
+    pub fn of_article(article: &Article) -> Option<Self> {
+        frontmatter::license(&frontmatter::parse(&article.content).unwrap_or_default())
+            .map(|license| License::new(&license))
+    }
+
+    /// The license of `article`, which is the site's unless it has its own
+    pub fn of(config: &TomeConfig, article: &Article) -> Option<Self> {
+        License::of_article(article).or_else(|| License::of_site(config))
+    }
+}
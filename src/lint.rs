@@ -0,0 +1,121 @@
+//! # Linting
+//!
+//! Articles are checked for common mistakes when they are saved: code
+//! fences that are never closed, images pointing to media files that don't
+//! exist and raw HTML that tome doesn't render safely (like `<iframe>` or
+//! event handler attributes). Problems are shown as warnings on the saved
+//! article, or keep the article from being saved if `lint_blocking` is set.
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+use crate::config::content_path;
+
+/// HTML elements articles shouldn't contain
+pub const DISALLOWED_ELEMENTS: &[&str] = &[
+    "script", "style", "iframe", "frame", "object", "embed", "form", "link", "meta", "base",
+];
+
+/// The line `offset` is on, starting at 1
+fn line(markdown: &str, offset: usize) -> usize {
+    markdown[..offset].matches('\n').count() + 1
+}
+
+/// Finds a code fence that is opened but never closed
+fn unclosed_fence(markdown: &str) -> Option<usize> {
+    let mut open: Option<(usize, char, usize)> = None;
+    for (number, line) in markdown.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if line.len() - trimmed.len() > 3 {
+            continue;
+        }
+        let Some(marker) = trimmed.chars().next().filter(|c| *c == '`' || *c == '~') else {
+            continue;
+        };
+        let length = trimmed.chars().take_while(|c| *c == marker).count();
+        if length < 3 {
+            continue;
+        }
+        match open {
+            None => open = Some((number + 1, marker, length)),
+            Some((_, open_marker, open_length))
+                if marker == open_marker
+                    && length >= open_length
+                    && trimmed[length..].trim().is_empty() =>
+            {
+                open = None
+            }
+            Some(_) => {}
+        }
+    }
+    open.map(|(line, _, _)| line)
+}
+
+/// The name of the disallowed element or attribute in a piece of raw HTML
+fn disallowed_html(html: &str) -> Option<String> {
+    let lower = html.to_lowercase();
+    for element in DISALLOWED_ELEMENTS {
+        let tag = format!("<{element}");
+        if let Some(start) = lower.find(&tag) {
+            let next = lower[start + tag.len()..].chars().next();
+            if next.is_none_or(|c| c.is_whitespace() || c == '>' || c == '/') {
+                return Some(format!("<{element}>"));
+            }
+        }
+    }
+    let mut rest = lower.as_str();
+    while let Some(start) = rest.find(" on") {
+        let attribute: String = rest[start + 1..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .collect();
+        if attribute.len() > 2
+            && rest[start + 1 + attribute.len()..]
+                .trim_start()
+                .starts_with('=')
+        {
+            return Some(attribute);
+        }
+        rest = &rest[start + 1..];
+    }
+    None
+}
+
+/// Checks an article's Markdown, returning a message for every problem
+pub async fn check(markdown: &str) -> Vec<String> {
+    let mut problems = vec![];
+
+    if let Some(line) = unclosed_fence(markdown) {
+        problems.push(format!("Line {line}: this code block is never closed"));
+    }
+
+    // Collect everything first, checking the media directory needs to await
+    let mut images = vec![];
+    for (event, range) in Parser::new_ext(markdown, Options::all()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Image(_, dest, _)) => {
+                if let Some(name) = dest.strip_prefix("/media/") {
+                    images.push((line(markdown, range.start), name.to_string()));
+                }
+            }
+            Event::Html(html) => {
+                if let Some(html) = disallowed_html(&html) {
+                    let line = line(markdown, range.start);
+                    problems.push(format!("Line {line}: {html} isn't allowed in articles"));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (line, name) in images {
+        let name = urlencoding::decode(&name).map_or(name.clone(), |name| name.into_owned());
+        if name.contains('/')
+            || tokio::fs::metadata(content_path(&format!("media/{name}")))
+                .await
+                .is_err()
+        {
+            problems.push(format!("Line {line}: the image {name} doesn't exist"));
+        }
+    }
+
+    problems
+}
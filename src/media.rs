@@ -1,55 +1,664 @@
+//! # Media
+//!
+//! Media are uploaded at `/media` and served from the content's `media/`
+//! directory. Which files can be uploaded is decided by the
+//! `upload_policies`, each a kind of files with its endings, a size limit
+//! and who can upload them: `editor` (anyone who can edit) or `admin`
+//! (only the `admin_user`). The first policy with a file's ending applies.
+//! `allowed_uploads` is a policy for editors without a size limit, checked
+//! after the others, and files no policy allows are rejected.
+//!
+//! Uploads are also checked to be what their ending says: tome looks at
+//! the first bytes of the file, and rejects a `.png` that is really an
+//! executable. Files whose kind can't be told from their bytes, like text,
+//! are taken as they are. With `fix_upload_endings`, a file with the wrong
+//! ending is stored with the right one instead, if a policy allows it.
+//!
+//! Each file of an upload can be at most `max_upload_size` bytes, 10 MiB
+//! by default. Uploads are written to a temporary file in `.uploads/` of
+//! the content directory while they arrive, instead of being kept in
+//! memory, and only moved to `media/` once every check passed.
+//!
+//! The media page shows [thumbnails](crate::thumbnail) of images.
+//!
+//! `tome media import <dir>` adds a directory of files, like an existing
+//! image library, to the media. Files in subdirectories are imported too,
+//! under their own name, since media don't have folders. They are checked
+//! like uploads by the administrator, and files with the name of another
+//! media file are reported and skipped unless `--overwrite` is given.
+//!
+//! `tome media gc` lists the files no version of any article
+//! [uses](crate::media_usage), except those changed within the last day,
+//! which may just not be linked yet. With `--delete`, it moves them to
+//! `.media-trash/` in the content directory, and removes files that have
+//! been in there for longer than `media_trash_days` (30 by default).
+//!
+//! Media can be deleted and renamed on the media page by anyone who could
+//! upload them. A file can only be renamed to a name a policy allows, so
+//! renaming can't get around the policies. Links to a file aren't changed
+//! when it is renamed.
 use askama::Template;
 use askama_axum::IntoResponse;
 use axum::{
-    extract::{Multipart, State},
-    response::Redirect,
+    extract::{Multipart, Path, State},
+    http::StatusCode,
+    response::{Redirect, Response},
+    Form,
 };
+use clap::{Args, Subcommand};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio_stream::{wrappers::ReadDirStream, StreamExt};
 
-use crate::TomeConfig;
+use crate::config::content_path;
+use crate::error::TomeError;
+use crate::layout::Layout;
+use crate::{
+    image_variants, media_usage, paths, prometheus, quota, render_cache, shutdown, thumbnail,
+    Invalid, TomeConfig,
+};
+
+const DEFAULT_MAX_UPLOAD_SIZE: usize = 10 * 1024 * 1024;
+/// How much of an upload is kept to tell what kind of file it is
+const SNIFF_LENGTH: usize = 8 * 1024;
+const UPLOADS_PATH: &str = ".uploads";
+const MEDIA_TRASH_PATH: &str = ".media-trash";
+/// How long new media files are kept even if they aren't used
+const UNUSED_GRACE: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+fn seconds_since_epoch() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// The largest file that can be uploaded in bytes
+pub fn max_upload_size(config: &TomeConfig) -> usize {
+    config.max_upload_size.unwrap_or(DEFAULT_MAX_UPLOAD_SIZE)
+}
+
+/// Who can upload files of a kind
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Anyone who can edit the wiki
+    #[default]
+    Editor,
+    /// Only the administrator
+    Admin,
+}
+
+/// A kind of files that can be uploaded, e.g. images or documents
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UploadPolicy {
+    pub name: String,
+    /// File endings like `.png`
+    pub endings: Vec<String>,
+    /// The largest file in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<usize>,
+    #[serde(default)]
+    pub role: Role,
+}
+
+impl UploadPolicy {
+    /// Parses a policy given on the command line like
+    /// `name = "documents", endings = [".pdf"], role = "admin"`
+    pub fn parse(policy: &str) -> Result<Self, String> {
+        crate::config::parse_table(policy)
+    }
+
+    fn allows(&self, file_name: &str) -> bool {
+        self.endings
+            .iter()
+            .any(|ending| file_name.ends_with(ending.as_str()))
+    }
+}
+
+/// The policies uploads are checked against, in order
+fn policies(config: &TomeConfig) -> Vec<UploadPolicy> {
+    let mut policies = config.upload_policies.clone();
+    if !config.allowed_uploads.is_empty() {
+        policies.push(UploadPolicy {
+            name: "media".to_string(),
+            endings: config.allowed_uploads.clone(),
+            max_size: None,
+            role: Role::Editor,
+        });
+    }
+    policies
+}
+
+/// Whether the logged in `user` can upload files of `policy`
+fn can_upload(config: &TomeConfig, policy: &UploadPolicy, user: Option<&str>) -> bool {
+    policy.role == Role::Editor || (user.is_some() && user == config.admin_user.as_deref())
+}
 
 #[derive(Template)]
 #[template(path = "media.html")]
 pub struct MediaOverview {
+    layout: Layout,
     allowed_uploads: String,
+    /// Name, endings and size limit of the kinds of files the user can upload
+    policies: Vec<(String, String, Option<String>)>,
     media: Vec<String>,
+    thumbnail_size: u32,
 }
 
-pub async fn get_media_overview(State(config): State<TomeConfig>) -> impl IntoResponse {
-    let mut entries = ReadDirStream::new(tokio::fs::read_dir("content/media").await.unwrap());
+/// The ending of a file name, without the dot and in lowercase
+fn extension(file_name: &str) -> String {
+    file_name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+fn same_extension(a: &str, b: &str) -> bool {
+    let canonical = |extension| match extension {
+        "jpeg" => "jpg",
+        "tiff" => "tif",
+        extension => extension,
+    };
+    canonical(a) == canonical(b)
+}
+
+/// Checks that `data` is what the ending of `file_name` says. Returns the
+/// ending it should have instead if it is another kind of file.
+fn sniff(file_name: &str, data: &[u8]) -> Result<Option<&'static str>, String> {
+    let extension = extension(file_name);
+    match infer::get(data) {
+        // Formats like Office documents are also zip archives, so any match counts
+        Some(kind)
+            if same_extension(kind.extension(), &extension) || infer::is(data, &extension) =>
+        {
+            Ok(None)
+        }
+        Some(kind) => Ok(Some(kind.extension())),
+        None if infer::is_supported(&extension) => {
+            Err(format!("{file_name} isn't a .{extension} file"))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Guesses the MIME type of a media file from its extension
+pub fn mime_type(file_name: &str) -> &'static str {
+    match extension(file_name).as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
+
+pub async fn get_media_overview(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+) -> Result<impl IntoResponse, TomeError> {
+    let mut entries = ReadDirStream::new(tokio::fs::read_dir(content_path("media")).await?);
     let mut media = vec![];
-    let allowed_uploads = config.allowed_uploads.join(", ");
     while let Some(Ok(entry)) = entries.next().await {
         let file_name = entry.file_name().to_string_lossy().into_owned();
         media.push(file_name);
     }
 
-    MediaOverview {
+    let policies: Vec<UploadPolicy> = policies(&config)
+        .into_iter()
+        .filter(|policy| can_upload(&config, policy, layout.user.as_deref()))
+        .collect();
+    let allowed_uploads = policies
+        .iter()
+        .flat_map(|policy| policy.endings.clone())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let policies = policies
+        .into_iter()
+        .map(|policy| {
+            (
+                policy.name,
+                policy.endings.join(", "),
+                policy
+                    .max_size
+                    .map(|size| format!("{} KiB", size.div_ceil(1024))),
+            )
+        })
+        .collect();
+
+    Ok(MediaOverview {
+        layout,
         allowed_uploads,
+        policies,
         media,
+        thumbnail_size: thumbnail::smallest_size(&config),
+    })
+}
+
+/// An upload written to a temporary file, which is removed unless it was moved to the media
+struct Received {
+    path: std::path::PathBuf,
+    /// The start of the file
+    head: Vec<u8>,
+    size: usize,
+}
+
+impl Drop for Received {
+    fn drop(&mut self) {
+        if self.path.exists() {
+            let _ = std::fs::remove_file(&self.path);
+        }
     }
 }
 
+/// Writes `field` to a temporary file, or returns `None` if it is larger than `max_size`
+async fn receive(
+    field: &mut axum::extract::multipart::Field<'_>,
+    max_size: usize,
+) -> Result<Option<Received>, TomeError> {
+    let dir = content_path(UPLOADS_PATH);
+    tokio::fs::create_dir_all(&dir).await?;
+    let mut received = Received {
+        path: std::path::Path::new(&dir).join(uuid::Uuid::new_v4().hyphenated().to_string()),
+        head: vec![],
+        size: 0,
+    };
+    let mut file = tokio::fs::File::create(&received.path).await?;
+    while let Some(chunk) = field.chunk().await? {
+        received.size += chunk.len();
+        if received.size > max_size {
+            return Ok(None);
+        }
+        if received.head.len() < SNIFF_LENGTH {
+            let rest = SNIFF_LENGTH - received.head.len();
+            received
+                .head
+                .extend_from_slice(&chunk[..rest.min(chunk.len())]);
+        }
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    Ok(Some(received))
+}
+
+/// Checks a file called `file_name`, which starts with `head` and is `size` bytes long,
+/// against the policies, of which only those `allowed` can be used. Returns the name to store
+/// it as, or why it can't be stored.
+fn check_upload(
+    config: &TomeConfig,
+    file_name: &str,
+    head: &[u8],
+    size: usize,
+    allowed: impl Fn(&UploadPolicy) -> bool,
+) -> Result<String, (StatusCode, String)> {
+    let max_upload_size = max_upload_size(config);
+    if size > max_upload_size {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Uploads can be at most {max_upload_size} bytes"),
+        ));
+    }
+    let file_name = match sniff(file_name, head) {
+        Ok(None) => file_name.to_string(),
+        Ok(Some(extension)) if config.fix_upload_endings => {
+            let stem = file_name
+                .rsplit_once('.')
+                .map_or(file_name, |(stem, _)| stem);
+            format!("{stem}.{extension}")
+        }
+        Ok(Some(extension)) => {
+            return Err((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!("{file_name} is really a .{extension} file"),
+            ))
+        }
+        Err(message) => return Err((StatusCode::UNSUPPORTED_MEDIA_TYPE, message)),
+    };
+    let policies = policies(config);
+    let Some(policy) = policies.iter().find(|policy| policy.allows(&file_name)) else {
+        return Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("Files like {file_name} can't be uploaded"),
+        ));
+    };
+    if !allowed(policy) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("Only the administrator can upload {}", policy.name),
+        ));
+    }
+    if let Some(max_size) = policy.max_size.filter(|max_size| size > *max_size) {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("{} can be at most {max_size} bytes", policy.name),
+        ));
+    }
+    Ok(file_name)
+}
+
 pub async fn post_media(
+    layout: Layout,
     State(config): State<TomeConfig>,
     mut multipart: Multipart,
-) -> impl IntoResponse {
-    while let Some(field) = multipart.next_field().await.unwrap() {
-        let name = field.name().unwrap().to_string();
-        let file_name = field.file_name().unwrap().to_string();
-        if name == "image"
-            && config
-                .allowed_uploads
-                .iter()
-                .any(|ending| file_name.ends_with(ending))
-        {
-            let data = field.bytes().await.unwrap();
+) -> Result<Response, TomeError> {
+    while let Some(mut field) = multipart.next_field().await? {
+        if field.name() != Some("image") {
+            continue;
+        }
+        let Some(file_name) = field.file_name().map(str::to_string) else {
+            return Err(TomeError::BadRequest(
+                "The upload has no file name".to_string(),
+            ));
+        };
+        paths::check_file_name(&file_name).map_err(TomeError::BadRequest)?;
+        let max_upload_size = max_upload_size(&config);
+        let Some(received) = receive(&mut field, max_upload_size).await? else {
+            return Ok((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Uploads can be at most {max_upload_size} bytes"),
+            )
+                .into_response());
+        };
+        let user = layout.user.as_deref();
+        let file_name = match check_upload(
+            &config,
+            &file_name,
+            &received.head,
+            received.size,
+            |policy| can_upload(&config, policy, user),
+        ) {
+            Ok(file_name) => file_name,
+            Err((status, message)) => {
+                if status == StatusCode::FORBIDDEN {
+                    tracing::warn!("Refused the upload of {file_name} by {user:?}");
+                }
+                return Ok((status, message).into_response());
+            }
+        };
+
+        // The received file already takes up its room in the content directory
+        quota::ensure_room(0).await?;
+        let _writing = shutdown::writing().await;
+        tokio::fs::rename(&received.path, content_path(&format!("media/{file_name}"))).await?;
+        thumbnail::refresh(&config, &file_name).await;
+        image_variants::refresh(&config, &file_name).await;
+        render_cache::invalidate();
+        prometheus::media_uploaded(received.size);
+    }
+
+    Ok(Redirect::to("/media").into_response())
+}
+
+/// Whether the logged in `user` can upload a file called `name`
+fn can_upload_as(config: &TomeConfig, name: &str, user: Option<&str>) -> bool {
+    policies(config)
+        .iter()
+        .find(|policy| policy.allows(name))
+        .is_some_and(|policy| can_upload(config, policy, user))
+}
+
+/// Whether the logged in `user` can delete or rename the media file `name`
+fn can_change(config: &TomeConfig, name: &str, user: Option<&str>) -> bool {
+    let is_admin = user.is_some() && user == config.admin_user.as_deref();
+    is_admin || can_upload_as(config, name, user)
+}
+
+pub async fn post_delete_media(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    Path(name): Path<String>,
+) -> Result<Response, TomeError> {
+    paths::check_file_name(&name).map_err(TomeError::BadRequest)?;
+    if !can_change(&config, &name, layout.user.as_deref()) {
+        let message = format!("You can't delete {name}.");
+        return Ok((StatusCode::FORBIDDEN, Invalid { layout, message }).into_response());
+    }
+    tokio::fs::remove_file(content_path(&format!("media/{name}"))).await?;
+    thumbnail::remove(&name).await;
+    image_variants::remove(&name).await;
+    render_cache::invalidate();
+    tracing::info!("Deleted the media file {name}");
+    Ok(Redirect::to("/media").into_response())
+}
+
+#[derive(Deserialize)]
+pub struct RenameForm {
+    name: String,
+}
+
+pub async fn post_rename_media(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    Path(name): Path<String>,
+    Form(form): Form<RenameForm>,
+) -> Result<Response, TomeError> {
+    let new_name = form.name.trim();
+    let user = layout.user.as_deref();
+    paths::check_file_name(&name).map_err(TomeError::BadRequest)?;
+    paths::check_file_name(new_name).map_err(TomeError::BadRequest)?;
+    if !can_change(&config, &name, user) {
+        let message = format!("You can't rename {name}.");
+        return Ok((StatusCode::FORBIDDEN, Invalid { layout, message }).into_response());
+    }
+    // Otherwise files could be renamed to endings that aren't safe to serve, like `.html`
+    if !can_upload_as(&config, new_name, user) {
+        let message =
+            format!("Files like {new_name} can't be uploaded, so nothing can be renamed to it.");
+        return Ok((StatusCode::FORBIDDEN, Invalid { layout, message }).into_response());
+    }
+    let from = content_path(&format!("media/{name}"));
+    let to = content_path(&format!("media/{new_name}"));
+    tokio::fs::metadata(&from).await?;
+    if new_name != name && tokio::fs::metadata(&to).await.is_ok() {
+        let message = format!("There already is a file called {new_name}.");
+        return Ok((StatusCode::CONFLICT, Invalid { layout, message }).into_response());
+    }
+    tokio::fs::rename(from, to).await?;
+    thumbnail::remove(&name).await;
+    thumbnail::remove(new_name).await;
+    image_variants::remove(&name).await;
+    image_variants::remove(new_name).await;
+    render_cache::invalidate();
+    tracing::info!("Renamed the media file {name} to {new_name}");
+    Ok(Redirect::to("/media").into_response())
+}
+
+/// Arguments for `tome media`
+#[derive(Args)]
+pub struct MediaArgs {
+    #[command(subcommand)]
+    command: MediaCommand,
+}
+
+#[derive(Subcommand)]
+enum MediaCommand {
+    /// Add the files in a directory and its subdirectories to the media
+    Import {
+        dir: std::path::PathBuf,
+        /// Move the files instead of copying them
+        #[arg(long = "move")]
+        move_files: bool,
+        /// Replace media files with the same name instead of skipping the file
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// List the media files no article uses
+    Gc {
+        /// Move them to the media trash, and remove files that have been in it long enough
+        #[arg(long)]
+        delete: bool,
+    },
+}
+
+/// Every file in `dir` and its subdirectories, sorted by path
+fn files_in(dir: &std::path::Path) -> std::io::Result<Vec<std::path::PathBuf>> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(files_in(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// What happened to a file `tome media import` found
+enum Imported {
+    Added(String),
+    /// The same file already is in the media
+    Unchanged(String),
+    Skipped(String),
+}
+
+async fn import_file(
+    config: &TomeConfig,
+    path: &std::path::Path,
+    move_files: bool,
+    overwrite: bool,
+) -> std::io::Result<Imported> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if let Err(message) = paths::check_file_name(&name) {
+        return Ok(Imported::Skipped(message));
+    }
+    let mut head = vec![];
+    let file = tokio::fs::File::open(path).await?;
+    file.take(SNIFF_LENGTH as u64)
+        .read_to_end(&mut head)
+        .await?;
+    let size = tokio::fs::metadata(path).await?.len() as usize;
+    // Whoever runs tome can upload anything the administrator can
+    let name = match check_upload(config, &name, &head, size, |_| true) {
+        Ok(name) => name,
+        Err((_, message)) => return Ok(Imported::Skipped(message)),
+    };
+
+    let target = content_path(&format!("media/{name}"));
+    if tokio::fs::metadata(&target).await.is_ok() {
+        if tokio::fs::read(&target).await? == tokio::fs::read(path).await? {
+            return Ok(Imported::Unchanged(name));
+        }
+        if !overwrite {
+            return Ok(Imported::Skipped(format!(
+                "there already is another media file called {name}"
+            )));
+        }
+    }
+    // Moving only works within a file system
+    if !move_files || tokio::fs::rename(path, &target).await.is_err() {
+        tokio::fs::copy(path, &target).await?;
+        if move_files {
+            tokio::fs::remove_file(path).await?;
+        }
+    }
+    thumbnail::refresh(config, &name).await;
+    image_variants::refresh(config, &name).await;
+    Ok(Imported::Added(name))
+}
 
-            tokio::fs::write(format!("content/media/{}", file_name), data)
-                .await
-                .unwrap();
+/// Removes files that have been in the media trash for longer than `media_trash_days`
+async fn empty_trash(config: &TomeConfig) -> std::io::Result<()> {
+    let days = config.media_trash_days.unwrap_or(30);
+    let now = seconds_since_epoch();
+    let Ok(mut entries) = tokio::fs::read_dir(content_path(MEDIA_TRASH_PATH)).await else {
+        return Ok(());
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let trashed = entry.file_name().to_string_lossy().into_owned();
+        // Trashed files are called `<seconds since the epoch>-<name>`
+        let Some((Ok(since), name)) = trashed
+            .split_once('-')
+            .map(|(since, name)| (since.parse::<u64>(), name))
+        else {
+            continue;
+        };
+        if now.saturating_sub(since) > days * 24 * 60 * 60 {
+            tokio::fs::remove_file(entry.path()).await?;
+            println!("Removed {name} from the media trash");
         }
     }
+    Ok(())
+}
 
-    Redirect::to("/media")
+async fn collect_garbage(config: &TomeConfig, delete: bool) -> color_eyre::Result<()> {
+    let used = media_usage::index(config).await;
+    let mut unused = vec![];
+    let mut total = 0;
+    let mut entries = tokio::fs::read_dir(content_path("media")).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        if !metadata.is_file() {
+            continue;
+        }
+        total += 1;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_new = metadata
+            .modified()?
+            .elapsed()
+            .is_ok_and(|age| age < UNUSED_GRACE);
+        if !used.contains_key(&name) && !is_new {
+            unused.push(name);
+        }
+    }
+    unused.sort();
+
+    if delete {
+        tokio::fs::create_dir_all(content_path(MEDIA_TRASH_PATH)).await?;
+        let now = seconds_since_epoch();
+        for name in &unused {
+            tokio::fs::rename(
+                content_path(&format!("media/{name}")),
+                content_path(&format!("{MEDIA_TRASH_PATH}/{now}-{name}")),
+            )
+            .await?;
+            thumbnail::remove(name).await;
+            image_variants::remove(name).await;
+            println!("Moved {name} to the media trash");
+        }
+        empty_trash(config).await?;
+    } else {
+        for name in &unused {
+            println!("{name}");
+        }
+    }
+    println!("{} of {total} media files are unused.", unused.len());
+    Ok(())
+}
+
+pub async fn run(args: MediaArgs, config: &TomeConfig) -> color_eyre::Result<()> {
+    match args.command {
+        MediaCommand::Import {
+            dir,
+            move_files,
+            overwrite,
+        } => {
+            let (mut added, mut skipped) = (0, 0);
+            for path in files_in(&dir)? {
+                match import_file(config, &path, move_files, overwrite).await? {
+                    Imported::Added(name) => {
+                        println!("Imported {} as {name}", path.display());
+                        added += 1;
+                    }
+                    Imported::Unchanged(name) => {
+                        println!("{} already is in the media as {name}", path.display());
+                    }
+                    Imported::Skipped(reason) => {
+                        println!("Skipped {}: {reason}", path.display());
+                        skipped += 1;
+                    }
+                }
+            }
+            println!("Imported {added} files, skipped {skipped}.");
+        }
+        MediaCommand::Gc { delete } => collect_garbage(config, delete).await?,
+    }
+    Ok(())
 }
@@ -0,0 +1,74 @@
+//! # Media Usage
+//!
+//! Which media files the wiki refers to, for `tome media gc`. A file is in
+//! use if any version of any article, the index page or the configuration
+//! (like `custom_head_html`) links to it under `/media/`, as an image, a
+//! link, raw HTML or a thumbnail, or shows it with `{{file-info name}}`.
+//! Versions count too, so restoring an old one never brings back a broken
+//! image. Articles in the trash don't count, which is why unused files go
+//! to a trash of their own first.
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::config::content_path;
+use crate::storage::article_slugs;
+use crate::{file_info, paths, Article, TomeConfig};
+
+/// Media files that are used without being linked
+const ALWAYS_USED: &[&str] = &["favicon.ico"];
+
+/// What `/media/` is followed by up to the end of the link
+fn reference_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r#"/media/([^\s)"'<>\]|]+)"#).unwrap())
+}
+
+/// The media files `text` refers to
+pub fn references(text: &str) -> Vec<String> {
+    reference_pattern()
+        .captures_iter(text)
+        .filter_map(|captures| {
+            // Thumbnails are at `/media/thumb/<size>/<name>`
+            let path = captures[1].rsplit('/').next()?;
+            let path = path.split(['?', '#']).next()?;
+            let name = urlencoding::decode(path).ok()?;
+            paths::is_file_name(&name).then(|| name.into_owned())
+        })
+        .chain(file_info::references(text))
+        .collect()
+}
+
+/// Every media file that is used, with the titles of the articles using it
+pub async fn index(config: &TomeConfig) -> BTreeMap<String, BTreeSet<String>> {
+    let mut used: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for name in ALWAYS_USED {
+        used.entry(name.to_string()).or_default();
+    }
+    let config = serde_json::to_string(config).unwrap_or_default();
+    let index = tokio::fs::read_to_string(content_path("index.md"))
+        .await
+        .unwrap_or_default();
+    for name in references(&config).into_iter().chain(references(&index)) {
+        used.entry(name).or_default();
+    }
+
+    for slug in article_slugs().await {
+        let Some(article) = Article::load(&slug).await else {
+            continue;
+        };
+        for name in references(&article.content) {
+            used.entry(name).or_default().insert(article.title.clone());
+        }
+        for (version, _) in Article::get_versions(&article.title).await {
+            let Some(old) = Article::load_version(&article.title, &version).await else {
+                continue;
+            };
+            for name in references(&old.content) {
+                used.entry(name).or_default().insert(article.title.clone());
+            }
+        }
+    }
+    used
+}
@@ -0,0 +1,227 @@
+//! # Mentions
+//!
+//! `@name` in an article links to the user page `/user/name`, which lists
+//! the articles mentioning them. When a saved version mentions a user the
+//! previous version didn't, they get a notification, shown at
+//! `/notifications` once they log in. Notifications are kept in
+//! `notifications.json` in the content directory. Mentions inside code are
+//! ignored, and so is the `@` of e-mail addresses.
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use std::time::SystemTime;
+
+use askama::Template;
+use askama_axum::IntoResponse;
+use axum::extract::Path;
+use axum::response::{Redirect, Response};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::sync::Mutex;
+
+use crate::auth::Auth;
+use crate::config::content_path;
+use crate::error::TomeError;
+use crate::filters::code_ranges;
+use crate::layout::Layout;
+use crate::{storage, Article};
+
+const NOTIFICATIONS_PATH: &str = "notifications.json";
+
+static LOCK: Mutex<()> = Mutex::const_new(());
+
+/// A user was mentioned in an article
+#[derive(Serialize, Deserialize)]
+struct Notification {
+    title: String,
+    slug: String,
+    /// Who saved the version mentioning the user
+    by: Option<String>,
+    time: SystemTime,
+    read: bool,
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+/// The mentions in `markdown` and where they are, including the `@`
+fn find(markdown: &str) -> Vec<(Range<usize>, &str)> {
+    if !markdown.contains('@') {
+        return vec![];
+    }
+    let code = code_ranges(markdown);
+    let mut mentions = vec![];
+    for (start, _) in markdown.match_indices('@') {
+        // Like e-mail addresses, URLs of profiles or escaped mentions
+        let after_word = markdown[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| is_name_char(c) || "/@\\`".contains(c));
+        if after_word || code.iter().any(|range| range.contains(&start)) {
+            continue;
+        }
+        let rest = &markdown[start + 1..];
+        let length = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+        // A sentence may end right after the name
+        let name = rest[..length].trim_end_matches(['.', '-']);
+        if !name.is_empty() {
+            mentions.push((start..start + 1 + name.len(), name));
+        }
+    }
+    mentions
+}
+
+/// The names of all users mentioned in `markdown`
+pub fn mentioned(markdown: &str) -> BTreeSet<String> {
+    find(markdown)
+        .into_iter()
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+/// Turns mentions in `markdown` into Markdown links to the user pages
+pub fn link(markdown: &str) -> Cow<'_, str> {
+    let mentions = find(markdown);
+    if mentions.is_empty() {
+        return Cow::Borrowed(markdown);
+    }
+    let mut out = String::with_capacity(markdown.len());
+    let mut copied = 0;
+    for (range, name) in mentions {
+        out.push_str(&markdown[copied..range.start]);
+        out.push_str(&format!("[@{name}](/user/{})", urlencoding::encode(name)));
+        copied = range.end;
+    }
+    out.push_str(&markdown[copied..]);
+    Cow::Owned(out)
+}
+
+async fn read_notifications() -> BTreeMap<String, Vec<Notification>> {
+    match tokio::fs::read_to_string(content_path(NOTIFICATIONS_PATH)).await {
+        Ok(json) => serde_json::from_str(&json).unwrap_or_default(),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+async fn write_notifications(
+    notifications: &BTreeMap<String, Vec<Notification>>,
+) -> tokio::io::Result<()> {
+    tokio::fs::write(
+        content_path(NOTIFICATIONS_PATH),
+        serde_json::to_string_pretty(notifications)?,
+    )
+    .await
+}
+
+/// Notifies the users `article` mentions, unless its `previous` version did already
+pub async fn notify(
+    auth: &Auth,
+    article: &Article,
+    previous: Option<&str>,
+    by: Option<&str>,
+) -> tokio::io::Result<()> {
+    let before = previous.map(mentioned).unwrap_or_default();
+    let users: Vec<String> = mentioned(&article.content)
+        .into_iter()
+        .filter(|name| !before.contains(name) && auth.exists(name) && Some(name.as_str()) != by)
+        .collect();
+    if users.is_empty() {
+        return Ok(());
+    }
+
+    let _lock = LOCK.lock().await;
+    let mut notifications = read_notifications().await;
+    for user in users {
+        tracing::info!("Notifying {user} of a mention in {}", article.title);
+        notifications.entry(user).or_default().push(Notification {
+            title: article.title.clone(),
+            slug: article.path(),
+            by: by.map(str::to_string),
+            time: SystemTime::now(),
+            read: false,
+        });
+    }
+    write_notifications(&notifications).await
+}
+
+#[derive(Template)]
+#[template(path = "user.html")]
+pub struct UserPage {
+    layout: Layout,
+    name: String,
+    /// Slugs and titles of the articles mentioning the user
+    articles: Vec<(String, String)>,
+}
+
+pub async fn get_user(
+    layout: Layout,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, TomeError> {
+    let name = urlencoding::decode(&name)?.into_owned();
+    let mut articles = vec![];
+    for slug in storage::article_slugs().await {
+        if let Some(article) = Article::load(&slug).await {
+            if mentioned(article.body()).contains(&name) {
+                articles.push((slug, article.title));
+            }
+        }
+    }
+    articles.sort_by_key(|(_, title)| title.to_lowercase());
+    Ok(UserPage {
+        layout,
+        name,
+        articles,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "notifications.html")]
+pub struct Notifications {
+    layout: Layout,
+    /// Slug, title, author, time and whether it is new, newest first
+    notifications: Vec<(String, String, Option<String>, String, bool)>,
+}
+
+/// Shows the logged in user's notifications and marks them as read
+pub async fn get_notifications(layout: Layout) -> Result<Response, TomeError> {
+    let Some(user) = layout.user.clone() else {
+        return Ok(Redirect::to("/login?next=/notifications").into_response());
+    };
+    let _lock = LOCK.lock().await;
+    let mut all = read_notifications().await;
+    let Some(own) = all.get_mut(&user) else {
+        return Ok(Notifications {
+            layout,
+            notifications: vec![],
+        }
+        .into_response());
+    };
+
+    let notifications = own
+        .iter()
+        .rev()
+        .map(|notification| {
+            let time = OffsetDateTime::from(notification.time)
+                .format(&time::format_description::well_known::Rfc2822)
+                .unwrap();
+            (
+                notification.slug.clone(),
+                notification.title.clone(),
+                notification.by.clone(),
+                time,
+                !notification.read,
+            )
+        })
+        .collect();
+    if own.iter().any(|notification| !notification.read) {
+        own.iter_mut()
+            .for_each(|notification| notification.read = true);
+        write_notifications(&all).await?;
+    }
+    Ok(Notifications {
+        layout,
+        notifications,
+    }
+    .into_response())
+}
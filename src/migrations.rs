@@ -0,0 +1,226 @@
+//! # Migrations
+//!
+//! When a release changes how the content directory is laid out, its
+//! migration upgrades older content on startup. `.tome-version` in the
+//! content directory records the layout the content has, and every
+//! migration after it runs once, in order. Before migrating, the parts of
+//! the content migrations change (`articles/`, `objects/` and
+//! `redirects.json`) are copied to `.migrations/<layout>/`, so
+//! `tome migrate revert` can put them back for an older release, which
+//! would otherwise not understand the new layout. Media is never changed.
+//! Content from a newer release than this one isn't touched at all.
+//! `tome migrate` shows the layout and the backups.
+use std::path::Path;
+
+use clap::{Args, Subcommand};
+use color_eyre::eyre::eyre;
+use tokio::sync::Mutex;
+
+use crate::config::{content_dir, content_path};
+use crate::demo::copy_dir;
+use crate::{slug, storage};
+
+const VERSION_PATH: &str = ".tome-version";
+const BACKUPS_PATH: &str = ".migrations";
+
+/// Keeps apps built at the same time from migrating twice
+static LOCK: Mutex<()> = Mutex::const_new(());
+
+/// What migrations can change, which is backed up before they run
+const BACKED_UP: &[&str] = &["articles", "objects", "redirects.json"];
+
+/// What every migration does, the first one upgrades content to layout 1
+const MIGRATIONS: &[&str] = &[
+    "Move article directories named after percent-encoded titles to their slugs",
+    "Move versions saved as <id>.md files into the object storage",
+];
+
+/// The layout this release writes
+pub const LAYOUT: usize = MIGRATIONS.len();
+
+/// Upgrades the content from the layout before `layout` to `layout`
+async fn apply(layout: usize) -> color_eyre::Result<()> {
+    match layout {
+        1 => slug::migrate().await,
+        2 => storage::move_legacy_versions().await.map(|_| ()),
+        _ => unreachable!("There is no migration to layout {layout}"),
+    }
+}
+
+/// The layout of the content, 0 for content from before layouts were recorded
+async fn read_layout() -> color_eyre::Result<usize> {
+    match tokio::fs::read_to_string(content_path(VERSION_PATH)).await {
+        Ok(layout) => layout.trim().parse().map_err(|_| {
+            eyre!(
+                "{VERSION_PATH} should be a number, not \"{}\"",
+                layout.trim()
+            )
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn write_layout(layout: usize) -> tokio::io::Result<()> {
+    tokio::fs::write(content_path(VERSION_PATH), format!("{layout}\n")).await
+}
+
+/// Whether there are articles to migrate, which a new content directory doesn't have
+async fn has_articles() -> bool {
+    let Ok(mut entries) = tokio::fs::read_dir(storage::articles_dir()).await else {
+        return false;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if !entry.file_name().to_string_lossy().starts_with('.') {
+            return true;
+        }
+    }
+    false
+}
+
+fn backup_dir(layout: usize) -> String {
+    content_path(&format!("{BACKUPS_PATH}/{layout}"))
+}
+
+/// Copies what migrations change to the backup of `layout`, unless there is one
+async fn back_up(layout: usize) -> color_eyre::Result<()> {
+    let backup = backup_dir(layout);
+    // An earlier attempt that failed already backed up the content as it was
+    if tokio::fs::metadata(&backup).await.is_ok() {
+        return Ok(());
+    }
+    let content = content_dir();
+    tokio::task::spawn_blocking(move || {
+        let partial = format!("{backup}.partial");
+        let _ = std::fs::remove_dir_all(&partial);
+        std::fs::create_dir_all(&partial)?;
+        for name in BACKED_UP {
+            let from = Path::new(&content).join(name);
+            let to = Path::new(&partial).join(name);
+            if from.is_dir() {
+                copy_dir(&from, &to)?;
+            } else if from.exists() {
+                std::fs::copy(&from, &to)?;
+            }
+        }
+        std::fs::rename(&partial, &backup)
+    })
+    .await??;
+    tracing::info!("Backed up the content of layout {layout} in {BACKUPS_PATH}/{layout}");
+    Ok(())
+}
+
+/// Runs the migrations the content hasn't had yet
+pub async fn run() -> color_eyre::Result<()> {
+    let _lock = LOCK.lock().await;
+    let layout = read_layout().await?;
+    if layout > LAYOUT {
+        return Err(eyre!(
+            "The content has layout {layout}, which is newer than this release of tome \
+             understands (up to {LAYOUT}). Upgrade tome, or revert the content with the \
+             release that migrated it."
+        ));
+    }
+    if layout == LAYOUT {
+        return Ok(());
+    }
+    if has_articles().await {
+        back_up(layout).await?;
+    }
+    for (migration, description) in MIGRATIONS.iter().enumerate().skip(layout) {
+        let to = migration + 1;
+        tracing::info!("Migrating the content to layout {to}: {description}");
+        apply(to).await?;
+        write_layout(to).await?;
+    }
+    Ok(())
+}
+
+/// The layouts there are backups of, oldest first
+async fn backups() -> Vec<usize> {
+    let mut layouts = vec![];
+    let Ok(mut entries) = tokio::fs::read_dir(content_path(BACKUPS_PATH)).await else {
+        return layouts;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if let Ok(layout) = entry.file_name().to_string_lossy().parse() {
+            layouts.push(layout);
+        }
+    }
+    layouts.sort();
+    layouts
+}
+
+/// Puts back the newest backup, returning its layout
+async fn revert() -> color_eyre::Result<usize> {
+    let layout = read_layout().await?;
+    let Some(backup) = backups()
+        .await
+        .into_iter()
+        .filter(|backup| *backup < layout)
+        .max()
+    else {
+        return Err(eyre!("There is no backup of a layout older than {layout}"));
+    };
+    let dir = backup_dir(backup);
+    let restore = dir.clone();
+    let content = content_dir();
+    tokio::task::spawn_blocking(move || {
+        for name in BACKED_UP {
+            let current = Path::new(&content).join(name);
+            if current.is_dir() {
+                std::fs::remove_dir_all(&current)?;
+            } else if current.exists() {
+                std::fs::remove_file(&current)?;
+            }
+            let from = Path::new(&restore).join(name);
+            if from.is_dir() {
+                copy_dir(&from, &current)?;
+            } else if from.exists() {
+                std::fs::copy(&from, &current)?;
+            }
+        }
+        std::io::Result::Ok(())
+    })
+    .await??;
+    write_layout(backup).await?;
+    tokio::fs::remove_dir_all(&dir).await?;
+    Ok(backup)
+}
+
+/// Arguments for `tome migrate`
+#[derive(Args)]
+pub struct MigrateArgs {
+    #[command(subcommand)]
+    command: Option<MigrateCommand>,
+}
+
+#[derive(Subcommand)]
+enum MigrateCommand {
+    /// Show the layout of the content and the backups of older ones
+    Status,
+    /// Put back the content as it was before the last migration, for an older release
+    Revert,
+}
+
+pub async fn run_command(args: MigrateArgs) -> color_eyre::Result<()> {
+    match args.command.unwrap_or(MigrateCommand::Status) {
+        MigrateCommand::Status => {
+            println!(
+                "The content has layout {}, the newest is {LAYOUT}.",
+                read_layout().await?
+            );
+            for backup in backups().await {
+                println!("Backup of layout {backup} in {BACKUPS_PATH}/{backup}");
+            }
+        }
+        MigrateCommand::Revert => {
+            let layout = revert().await?;
+            println!(
+                "Put back the content of layout {layout}. Start the older release now, \
+                 this one migrates it again."
+            );
+        }
+    }
+    Ok(())
+}
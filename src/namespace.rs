@@ -0,0 +1,109 @@
+//! # Namespace Defaults
+//!
+//! `namespaces` in the config gives namespaces defaults, so they work like
+//! the spaces of other wikis. New articles in a namespace start from its
+//! page `template` and with its `tags`, `requires_review` makes changes to
+//! them wait for a review, and `accent_color` colors their pages. Articles
+//! only get the defaults when they are created, so they can still change
+//! them. Namespaces inside others inherit their defaults, e.g. `Team:Ops`
+//! those of `Team`, and settings of the inner namespace win.
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+use crate::layout::NAMESPACE_SEPARATOR;
+use crate::slug::slug;
+use crate::{frontmatter, page_template, TomeConfig};
+
+/// The defaults of the articles in a namespace
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct NamespaceDefaults {
+    pub name: String,
+    /// The page template new articles start from, without the `Template:` namespace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requires_review: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accent_color: Option<String>,
+}
+
+impl NamespaceDefaults {
+    /// Parses defaults given on the command line like `name = "Team", tags = ["team"]`
+    pub fn parse(defaults: &str) -> Result<Self, String> {
+        crate::config::parse_table(defaults)
+    }
+}
+
+/// The defaults of articles titled `title`, from its namespace and the ones containing it
+pub fn of(config: &TomeConfig, title: &str) -> NamespaceDefaults {
+    let segments: Vec<String> = slug(title)
+        .split(NAMESPACE_SEPARATOR)
+        .map(str::to_string)
+        .collect();
+    let mut defaults = NamespaceDefaults::default();
+    // Innermost first, so its settings win
+    for end in (1..segments.len()).rev() {
+        let namespace = segments[..end].join(&NAMESPACE_SEPARATOR.to_string());
+        for settings in config
+            .namespaces
+            .iter()
+            .filter(|settings| slug(&settings.name) == namespace)
+        {
+            defaults.template = defaults.template.or(settings.template.clone());
+            defaults.requires_review = defaults.requires_review.or(settings.requires_review);
+            defaults.accent_color = defaults.accent_color.or(settings.accent_color.clone());
+            for tag in &settings.tags {
+                if !defaults.tags.contains(tag) {
+                    defaults.tags.push(tag.clone());
+                }
+            }
+        }
+    }
+    defaults
+}
+
+/// The content of the new article `title`, which is `content` with the
+/// defaults of its namespace added to the frontmatter
+pub fn new_content(config: &TomeConfig, title: &str, content: &str) -> String {
+    let defaults = of(config, title);
+    let requires_review = defaults.requires_review.unwrap_or(false);
+    if defaults.tags.is_empty() && !requires_review {
+        return content.to_string();
+    }
+    let Ok(mut meta) = frontmatter::parse(content) else {
+        return content.to_string();
+    };
+    let mut tags = frontmatter::tags(&meta);
+    for tag in defaults.tags {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    frontmatter::set_tags(&mut meta, tags);
+    if requires_review {
+        meta.insert(Value::from("requires_review"), Value::from(true));
+    }
+    frontmatter::join(&meta, frontmatter::split(content).1)
+}
+
+/// The content the editor starts with for the new article `title`, from the
+/// page `template` that was picked or else the one of its namespace
+pub async fn initial_content(
+    config: &TomeConfig,
+    title: &str,
+    template: Option<&str>,
+    author: &str,
+) -> String {
+    let template = template
+        .map(str::to_string)
+        .or_else(|| of(config, title).template);
+    let content = match template {
+        Some(template) => page_template::instantiate(&template, title, author)
+            .await
+            .unwrap_or_default(),
+        None => String::new(),
+    };
+    new_content(config, title, &content)
+}
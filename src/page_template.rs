@@ -0,0 +1,36 @@
+//! # Page Templates
+//!
+//! Articles in the `Template:` namespace can be used to prefill the editor
+//! when creating a new article. Their placeholders are filled in at that point:
+//! `{{title}}` with the new article's title, `{{date}}` with today's date
+//! and `{{author}}` with the name of the person creating it.
+use time::OffsetDateTime;
+
+use crate::{Article, Overview};
+
+pub const NAMESPACE: &str = "Template:";
+
+/// Lists the names of all templates, without their namespace
+pub async fn names() -> Vec<String> {
+    let mut names: Vec<String> = Overview::load()
+        .await
+        .articles
+        .into_iter()
+        .filter_map(|(_, title)| title.strip_prefix(NAMESPACE).map(str::to_string))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Loads the template `name` and substitutes its placeholders
+pub async fn instantiate(name: &str, title: &str, author: &str) -> Option<String> {
+    let template = Article::load(&format!("{NAMESPACE}{name}")).await?;
+    let date = OffsetDateTime::now_utc().date().to_string();
+    Some(
+        template
+            .content
+            .replace("{{title}}", title)
+            .replace("{{date}}", &date)
+            .replace("{{author}}", author),
+    )
+}
@@ -0,0 +1,163 @@
+//! # Pandoc Export
+//!
+//! With the `pandoc` feature enabled, articles can be converted into any
+//! of a few document formats by piping the rendered HTML through a pandoc
+//! binary (`pandoc_path`, defaulting to `pandoc` on the `PATH`). The
+//! `export_header` and `export_footer` are added as the first and last
+//! paragraph, followed by the license, which is also set as the document's
+//! `rights`.
+//!
+//! Articles are written by editors, but pandoc would read any file or URL
+//! an image points to into the document. Only images from the media are
+//! kept, embedded as data URLs, and pandoc runs with `--sandbox` so it
+//! reads nothing else either.
+use std::process::Stdio;
+
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::error::TomeError;
+use crate::export::{embed, embed_images, Notices};
+use crate::{filters, Article, TomeConfig};
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    format: String,
+}
+
+/// Returns pandoc's name for `format`, the file extension and content type
+fn output_format(format: &str) -> Option<(&'static str, &'static str, &'static str)> {
+    match format {
+        "docx" => Some((
+            "docx",
+            "docx",
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        )),
+        "odt" => Some(("odt", "odt", "application/vnd.oasis.opendocument.text")),
+        "latex" => Some(("latex", "tex", "application/x-latex")),
+        _ => None,
+    }
+}
+
+/// `html` between the header and footer, followed by the license
+fn with_notices(notices: &Notices, html: &str) -> String {
+    let paragraph = |text: &Option<String>| match text {
+        Some(text) => format!(
+            "<p>{}</p>\n",
+            askama_escape::escape(text, askama_escape::Html)
+        ),
+        None => String::new(),
+    };
+    let license = notices.license.as_ref().map(|license| match &license.url {
+        Some(url) => format!("License: {} ({url})", license.name),
+        None => format!("License: {}", license.name),
+    });
+    format!(
+        "{}{html}{}{}",
+        paragraph(&notices.header),
+        paragraph(&notices.footer),
+        paragraph(&license)
+    )
+}
+
+/// `html` without the images that weren't embedded, like those of raw HTML pointing anywhere
+fn only_embedded_images(html: &str) -> String {
+    ammonia::Builder::default()
+        .add_url_schemes(["data"])
+        .attribute_filter(|element, attribute, value| match (element, attribute) {
+            ("img", "src") if !value.starts_with("data:") => None,
+            _ => Some(value.into()),
+        })
+        .clean(html)
+        .to_string()
+}
+
+pub async fn export(
+    State(config): State<TomeConfig>,
+    headers: HeaderMap,
+    Path(title): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, TomeError> {
+    let Some((format, extension, content_type)) = output_format(&query.format) else {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            format!("Unsupported export format {}", query.format),
+        )
+            .into_response());
+    };
+
+    let title = urlencoding::decode(&title)?.into_owned();
+    let article = Article::load(&title).await.ok_or(TomeError::NotFound)?;
+
+    let html = filters::render(article.body(), |event| event);
+    let notices = Notices::new(&config, &headers, &article);
+    let images = embed_images(article.body()).await;
+    let html = only_embedded_images(&embed(&with_notices(&notices, &html), &images));
+
+    let pandoc = config.pandoc_path.as_deref().unwrap_or("pandoc");
+    let child = Command::new(pandoc)
+        .args([
+            "--from",
+            "html",
+            "--to",
+            format,
+            "--standalone",
+            "--sandbox",
+            "--output",
+            "-",
+        ])
+        .arg("--metadata")
+        .arg(format!("title={}", article.title))
+        .args(
+            notices
+                .license
+                .iter()
+                .flat_map(|license| ["--metadata".to_string(), format!("rights={}", license.name)]),
+        )
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            return Err(TomeError::Internal(format!(
+                "Could not run pandoc at {pandoc}: {e}"
+            )));
+        }
+    };
+
+    // Written while the output is read, pandoc may start writing before it read everything
+    let mut stdin = child.stdin.take().unwrap();
+    tokio::spawn(async move {
+        if let Err(e) = stdin.write_all(html.as_bytes()).await {
+            tracing::error!("Could not write to pandoc: {e}");
+        }
+    });
+    // Collected before responding, so a failed conversion isn't sent as a document
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(TomeError::Internal(format!(
+            "pandoc failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.{extension}\"", article.path()),
+            ),
+        ],
+        output.stdout,
+    )
+        .into_response())
+}
@@ -0,0 +1,197 @@
+//! # Paste to Create
+//!
+//! `/new` creates an article from pasted text, and `POST /api/articles`
+//! does the same for scripts, taking the text as a form, a JSON object
+//! with `title` and `text`, or a plain text body with the title in the
+//! `title` query parameter. Without a title, the first line of the text
+//! becomes the title, and if there already is an article with that title,
+//! a number is added to it.
+use askama::Template;
+use askama_axum::IntoResponse;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{Redirect, Response};
+use axum::Form;
+use serde::Deserialize;
+
+use crate::auth::Auth;
+use crate::layout::Layout;
+use crate::{mentions, namespace, quota, slug, Article, TomeConfig};
+
+/// The longest title taken from the first line of the text
+const MAX_TITLE_LENGTH: usize = 80;
+
+#[derive(Template, Default)]
+#[template(path = "new.html")]
+pub struct NewArticle {
+    layout: Layout,
+    title: String,
+    text: String,
+    error: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct Paste {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    text: String,
+}
+
+/// A title from the first line of `text` with Markdown heading marks removed
+fn title_from(text: &str) -> String {
+    let line = text
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default();
+    let line = line.trim().trim_start_matches('#').trim();
+    let title: String = line
+        .chars()
+        .filter(|c| !matches!(c, '/' | '\\') && !c.is_control())
+        .take(MAX_TITLE_LENGTH)
+        .collect();
+    title.trim().to_string()
+}
+
+/// `title`, or `title 2`, `title 3`, … if there already is an article with it
+async fn unused_title(title: &str) -> String {
+    let mut candidate = title.to_string();
+    let mut number = 1;
+    while Article::load(&slug(&candidate)).await.is_some() {
+        number += 1;
+        candidate = format!("{title} {number}");
+    }
+    candidate
+}
+
+/// Saves the pasted text as a new article
+async fn create(
+    config: &TomeConfig,
+    auth: &Auth,
+    paste: Paste,
+    user: Option<&str>,
+) -> Result<Article, (StatusCode, String)> {
+    let text = paste.text.trim();
+    if text.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "There is no text to save.".to_string(),
+        ));
+    }
+    let title = match paste.title.trim() {
+        "" => title_from(text),
+        title => title.to_string(),
+    };
+    Article::validate_title(&title).map_err(|message| (StatusCode::BAD_REQUEST, message))?;
+    let max_size = config.max_article_size.unwrap_or(1024 * 1024);
+    if text.len() > max_size {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Articles can be at most {} KiB.", max_size / 1024),
+        ));
+    }
+
+    if let Err(e) = quota::ensure_room(text.len()).await {
+        return Err((StatusCode::INSUFFICIENT_STORAGE, e.to_string()));
+    }
+
+    let title = unused_title(&title).await;
+    let content = namespace::new_content(config, &title, &format!("{text}\n"));
+    let article = Article::new(title, content);
+    article
+        .write_to_disk_by(user, Some("Created from pasted text"))
+        .await
+        .map_err(|e| {
+            tracing::error!("Could not save {}: {e}", article.title);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "The article could not be saved.".to_string(),
+            )
+        })?;
+    if let Err(e) = mentions::notify(auth, &article, None, user).await {
+        tracing::error!(
+            "Could not notify the users mentioned in {}: {e}",
+            article.title
+        );
+    }
+    Ok(article)
+}
+
+pub async fn get_new(layout: Layout) -> impl IntoResponse {
+    NewArticle {
+        layout,
+        ..Default::default()
+    }
+}
+
+pub async fn post_new(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    State(auth): State<Auth>,
+    Form(paste): Form<Paste>,
+) -> Response {
+    let (title, text) = (paste.title.clone(), paste.text.clone());
+    match create(&config, &auth, paste, layout.user.as_deref()).await {
+        Ok(article) => Redirect::to(&format!("/article/{}", article.path())).into_response(),
+        Err((status, message)) => (
+            status,
+            NewArticle {
+                layout,
+                title,
+                text,
+                error: Some(message),
+            },
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ApiQuery {
+    #[serde(default)]
+    title: String,
+}
+
+/// Reads the paste from a form, JSON object or plain text body
+fn paste(headers: &HeaderMap, title: String, body: &str) -> Option<Paste> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .unwrap_or_default();
+    let mut paste = if content_type.starts_with("application/x-www-form-urlencoded") {
+        serde_urlencoded::from_str::<Paste>(body).ok()?
+    } else if content_type.starts_with("application/json") {
+        serde_json::from_str::<Paste>(body).ok()?
+    } else {
+        Paste {
+            text: body.to_string(),
+            ..Default::default()
+        }
+    };
+    if paste.title.is_empty() {
+        paste.title = title;
+    }
+    Some(paste)
+}
+
+pub async fn post_api_article(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    State(auth): State<Auth>,
+    Query(query): Query<ApiQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let Some(paste) = paste(&headers, query.title, &body) else {
+        return (StatusCode::BAD_REQUEST, "The body is invalid").into_response();
+    };
+    match create(&config, &auth, paste, layout.user.as_deref()).await {
+        Ok(article) => (
+            StatusCode::CREATED,
+            [(header::LOCATION, format!("/article/{}", article.path()))],
+            article.title,
+        )
+            .into_response(),
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
@@ -0,0 +1,48 @@
+//! # Safe Paths
+//!
+//! Titles, media file names and version ids come from requests and end up
+//! in paths on disk. Articles are stored under their [slug](crate::slug),
+//! which only has letters, digits and dashes, but other names are used as
+//! they are. So they are checked to name a single file first: without
+//! slashes or control characters, and not `.` or `..`, so a name like
+//! `../../tome.toml` can't escape the content directory. Names of files
+//! tome writes also can't be hidden files or contain a colon, which
+//! Windows reads as a drive or stream.
+
+/// What keeps `name` from naming something inside a directory, if anything
+pub fn problem(name: &str) -> Option<&'static str> {
+    if name.trim().is_empty() {
+        Some("it is empty")
+    } else if name == "." || name == ".." {
+        Some("it can't be \".\" or \"..\"")
+    } else if name.contains(['/', '\\']) {
+        Some("it can't contain slashes")
+    } else if name.chars().any(char::is_control) {
+        Some("it can't contain control characters")
+    } else {
+        None
+    }
+}
+
+/// Whether a file called `name` can be stored in a directory of the content
+pub fn check_file_name(name: &str) -> Result<(), String> {
+    let problem = problem(name).or_else(|| {
+        if name.starts_with('.') {
+            Some("it can't start with a dot")
+        } else if name.contains(':') {
+            Some("it can't contain colons")
+        } else {
+            None
+        }
+    });
+    match problem {
+        Some(problem) => Err(format!(
+            "\"{name}\" can't be used as a file name, {problem}."
+        )),
+        None => Ok(()),
+    }
+}
+
+pub fn is_file_name(name: &str) -> bool {
+    check_file_name(name).is_ok()
+}
@@ -0,0 +1,46 @@
+//! # PDF Previews
+//!
+//! PDFs embedded like images, `![Report](/media/report.pdf)`, are shown
+//! in the article with PDF.js, a page at a time, and the media page shows
+//! their first page as a thumbnail. `pdf_preview.js` draws them with the
+//! PDF.js files at `/static/pdfjs/`, which are served from `pdfjs_dir`: a
+//! copy of the `build` directory of the `pdfjs-dist` package. Without it,
+//! the same version is loaded from a CDN like Bulma. Readers without
+//! JavaScript get a link to the file.
+use axum::extract::{Path, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Redirect, Response};
+
+use crate::error::TomeError;
+use crate::TomeConfig;
+
+/// The version of PDF.js loaded without a `pdfjs_dir`
+const PDFJS_VERSION: &str = "4.10.38";
+
+/// The files of PDF.js that `pdf_preview.js` loads
+pub const PDFJS_FILES: &[&str] = &["pdf.min.mjs", "pdf.worker.min.mjs"];
+
+/// Whether a link to `dest` embeds a PDF from the media
+pub fn is_media_pdf(dest: &str) -> bool {
+    let path = dest.split(['?', '#']).next().unwrap_or_default();
+    path.starts_with("/media/") && path.to_lowercase().ends_with(".pdf")
+}
+
+pub async fn get_pdfjs(
+    State(config): State<TomeConfig>,
+    Path(name): Path<String>,
+) -> Result<Response, TomeError> {
+    if !PDFJS_FILES.contains(&name.as_str()) {
+        return Err(TomeError::NotFound);
+    }
+    let Some(dir) = &config.pdfjs_dir else {
+        return Ok(Redirect::temporary(&format!(
+            "https://cdn.jsdelivr.net/npm/pdfjs-dist@{PDFJS_VERSION}/build/{name}"
+        ))
+        .into_response());
+    };
+    let script = tokio::fs::read(std::path::Path::new(dir).join(&name))
+        .await
+        .map_err(|_| TomeError::NotFound)?;
+    Ok(([(header::CONTENT_TYPE, "text/javascript")], script).into_response())
+}
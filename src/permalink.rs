@@ -0,0 +1,115 @@
+//! # Permanent Links
+//!
+//! `/article/:id/permalink/:version` always shows the same version of an
+//! article, for citing it elsewhere. Every article page links to the
+//! permanent link of its current version. The first time a permanent link
+//! is opened, its version is copied to `permalinks/` in the content
+//! directory, so it keeps working after the article is renamed, deleted
+//! or purged.
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use serde::{Deserialize, Serialize};
+
+use crate::config::content_path;
+use crate::error::TomeError;
+use crate::layout::Layout;
+use crate::slug::slug;
+use crate::storage::dir_name;
+use crate::{export, rename, Article, ArticlePage, TomeConfig};
+
+const PERMALINKS_PATH: &str = "permalinks";
+
+/// A version copied when its permanent link was first opened
+#[derive(Serialize, Deserialize)]
+struct Pinned {
+    title: String,
+    content: String,
+}
+
+/// The permanent link of `version` of the article `title`
+pub fn url(title: &str, version: &str) -> String {
+    format!("/article/{}/permalink/{version}", slug(title))
+}
+
+/// Versions are ids or commit hashes, anything else can't be a file name
+fn is_version(version: &str) -> bool {
+    !version.is_empty()
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn pinned_path(title: &str, version: &str) -> String {
+    content_path(&format!(
+        "{PERMALINKS_PATH}/{}/{version}.json",
+        dir_name(&slug(title))
+    ))
+}
+
+async fn read_pinned(title: &str, version: &str) -> Option<Article> {
+    let json = tokio::fs::read_to_string(pinned_path(title, version))
+        .await
+        .ok()?;
+    let pinned: Pinned = serde_json::from_str(&json).ok()?;
+    Some(Article {
+        title: pinned.title,
+        content: pinned.content,
+    })
+}
+
+async fn pin(title: &str, version: &str, article: &Article) -> tokio::io::Result<()> {
+    let path = pinned_path(title, version);
+    if let Some((dir, _)) = path.rsplit_once('/') {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let pinned = Pinned {
+        title: article.title.clone(),
+        content: article.content.clone(),
+    };
+    tokio::fs::write(path, serde_json::to_string(&pinned)?).await
+}
+
+/// The version from the pinned copy or else the history, following renames.
+/// Versions loaded from the history are pinned.
+pub async fn load(title: &str, version: &str) -> Option<Article> {
+    if let Some(article) = read_pinned(title, version).await {
+        return Some(article);
+    }
+    let article = match Article::load_version(title, version).await {
+        Some(article) => article,
+        None => Article::load_version(&rename::resolve(title).await?, version).await?,
+    };
+    // The version can still be shown, it just isn't pinned yet
+    if let Err(e) = pin(title, version, &article).await {
+        tracing::error!("Could not pin version {version} of {title}: {e}");
+    }
+    Some(article)
+}
+
+pub async fn get_permalink(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    headers: HeaderMap,
+    Path((title, version)): Path<(String, String)>,
+) -> Result<impl IntoResponse, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    if !is_version(&version) {
+        return Err(TomeError::NotFound);
+    }
+    let article = load(&title, &version).await.ok_or(TomeError::NotFound)?;
+    let layout = layout
+        .with_direction_of(&article.content)
+        .with_license_of(&article);
+    Ok(ArticlePage {
+        layout,
+        notices: export::Notices::new(&config, &headers, &article),
+        permalink: Some(url(&title, &version)),
+        pinned: true,
+        backlinks: vec![],
+        html: article.shown_html(&config).await.into(),
+        article,
+        warnings: vec![],
+        version: Some(version),
+    })
+}
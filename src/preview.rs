@@ -0,0 +1,80 @@
+//! # Link Previews
+//!
+//! `/api/preview/:title` returns the beginning of an article, rendered, for
+//! the preview cards `previews.js` shows while hovering links to it. The
+//! excerpt is the article's first paragraph, shortened to `EXCERPT_LENGTH`
+//! characters.
+//!
+//! `POST /preview` renders the Markdown in its body for the live preview of
+//! the editor, with the same filter as articles, so the preview can't
+//! drift from the saved page. With `?title=`, it is shown as that
+//! article's body would be, e.g. with automatic links.
+use askama_axum::IntoResponse;
+use axum::extract::{Path, Query, State};
+use axum::http::header;
+use axum::Json;
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use serde::{Deserialize, Serialize};
+
+use crate::error::TomeError;
+use crate::{filters, Article, TomeConfig};
+
+const EXCERPT_LENGTH: usize = 300;
+
+#[derive(Serialize)]
+struct Preview {
+    title: String,
+    html: String,
+}
+
+/// The Markdown of the first paragraph in `markdown`, shortened to about `EXCERPT_LENGTH`
+fn excerpt(markdown: &str) -> String {
+    let Some(range) = Parser::new_ext(markdown, Options::all())
+        .into_offset_iter()
+        .find_map(|(event, range)| matches!(event, Event::Start(Tag::Paragraph)).then_some(range))
+    else {
+        return String::new();
+    };
+    let paragraph = markdown[range].trim();
+    if paragraph.chars().count() <= EXCERPT_LENGTH {
+        return paragraph.to_string();
+    }
+    // Cut at a word, so no link or emphasis is split in the middle of a word
+    let cut: String = paragraph.chars().take(EXCERPT_LENGTH).collect();
+    let cut = cut
+        .rsplit_once(char::is_whitespace)
+        .map_or(cut.as_str(), |(cut, _)| cut);
+    format!("{}…", cut.trim_end())
+}
+
+pub async fn get_preview(Path(title): Path<String>) -> Result<impl IntoResponse, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let article = Article::load(&title).await.ok_or(TomeError::NotFound)?;
+    Ok(Json(Preview {
+        html: filters::render(&excerpt(article.body()), |event| event),
+        title: article.title,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct LivePreviewQuery {
+    #[serde(default)]
+    title: String,
+}
+
+pub async fn post_live_preview(
+    State(config): State<TomeConfig>,
+    Query(query): Query<LivePreviewQuery>,
+    markdown: String,
+) -> Result<impl IntoResponse, TomeError> {
+    let article = Article {
+        title: query.title,
+        content: markdown,
+    };
+    let body = article.shown_body(&config).await;
+    let html = filters::custom_md(body).map_err(|e| TomeError::Internal(e.to_string()))?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+        html.to_string(),
+    ))
+}
@@ -0,0 +1,125 @@
+//! # Prometheus Metrics
+//!
+//! With `metrics`, `/metrics` serves the wiki's metrics in the Prometheus
+//! text format: requests by method, route and status with how long they
+//! took, how many articles were saved, how large uploaded media files
+//! are and how often the render cache had the page asked for. Routes are their patterns like `/article/:id`, so every article
+//! doesn't become a metric of its own. `/metrics` isn't behind the login,
+//! so keep it from the public in the reverse proxy if that matters.
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use axum::extract::{MatchedPath, State};
+use axum::http::{header, Request};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+use crate::error::TomeError;
+use crate::TomeConfig;
+
+const DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+const SIZE_BUCKETS: &[f64] = &[
+    1024.0,
+    16.0 * 1024.0,
+    128.0 * 1024.0,
+    1024.0 * 1024.0,
+    4.0 * 1024.0 * 1024.0,
+    16.0 * 1024.0 * 1024.0,
+    64.0 * 1024.0 * 1024.0,
+];
+
+/// The recorder every metric goes to, which is installed once per process
+/// because it is global, however many apps are built
+fn handle() -> Option<&'static PrometheusHandle> {
+    static HANDLE: OnceLock<Option<PrometheusHandle>> = OnceLock::new();
+    HANDLE
+        .get_or_init(|| {
+            let builder = PrometheusBuilder::new()
+                .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), DURATION_BUCKETS)
+                .and_then(|builder| {
+                    builder.set_buckets_for_metric(
+                        Matcher::Full("tome_media_upload_bytes".to_string()),
+                        SIZE_BUCKETS,
+                    )
+                });
+            match builder.and_then(PrometheusBuilder::install_recorder) {
+                Ok(handle) => Some(handle),
+                Err(e) => {
+                    tracing::warn!("Couldn't record metrics: {e}");
+                    None
+                }
+            }
+        })
+        .as_ref()
+}
+
+/// Starts recording metrics if `metrics` is on
+pub fn install(config: &TomeConfig) {
+    if config.metrics {
+        handle();
+    }
+}
+
+/// Counts a saved version of an article
+pub fn article_saved() {
+    ::metrics::counter!("tome_article_saves_total").increment(1);
+}
+
+/// Records the size of an uploaded media file
+pub fn media_uploaded(size: usize) {
+    ::metrics::histogram!("tome_media_upload_bytes").record(size as f64);
+}
+
+/// Counts a page the render cache had
+pub fn render_cache_hit() {
+    ::metrics::counter!("tome_render_cache_hits_total").increment(1);
+}
+
+/// Counts a page the render cache didn't have
+pub fn render_cache_miss() {
+    ::metrics::counter!("tome_render_cache_misses_total").increment(1);
+}
+
+/// Counts `request` and how long it took by its route
+pub async fn track<B>(request: Request<B>, next: Next<B>) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("fallback".to_string(), |path| path.as_str().to_string());
+    let start = Instant::now();
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16().to_string();
+    ::metrics::counter!(
+        "tome_http_requests_total",
+        "method" => method.clone(),
+        "route" => route.clone(),
+        "status" => status
+    )
+    .increment(1);
+    ::metrics::histogram!(
+        "tome_http_request_duration_seconds",
+        "method" => method,
+        "route" => route
+    )
+    .record(start.elapsed().as_secs_f64());
+    response
+}
+
+pub async fn get_metrics(State(config): State<TomeConfig>) -> Result<Response, TomeError> {
+    let handle = config
+        .metrics
+        .then(handle)
+        .flatten()
+        .ok_or(TomeError::NotFound)?;
+    handle.run_upkeep();
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        handle.render(),
+    )
+        .into_response())
+}
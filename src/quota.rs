@@ -0,0 +1,51 @@
+//! # Storage Quotas
+//!
+//! With `storage_quota`, the content directory of a wiki can only take up
+//! that many bytes, counting every version of every article, the media and
+//! everything else tome keeps there. Saves and uploads in the web interface
+//! that would go past it are refused with a page saying how much room is
+//! left, while commands like `tome import` aren't limited. Every tenant has
+//! its own quota, which the administrator changes on `/admin/tenants`.
+use std::sync::atomic::Ordering;
+
+use crate::error::TomeError;
+use crate::wiki;
+
+/// The bytes taken up by the files in `dir` and the directories in it
+pub async fn used(dir: &str) -> u64 {
+    let mut used = 0;
+    let mut dirs = vec![std::path::PathBuf::from(dir)];
+    while let Some(dir) = dirs.pop() {
+        let Ok(mut entries) = tokio::fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            match entry.metadata().await {
+                Ok(metadata) if metadata.is_dir() => dirs.push(entry.path()),
+                Ok(metadata) => used += metadata.len(),
+                Err(_) => {}
+            }
+        }
+    }
+    used
+}
+
+/// The quota of the current wiki, if it has one
+pub fn quota() -> Option<u64> {
+    Some(wiki::current().storage_quota.load(Ordering::Relaxed)).filter(|quota| *quota > 0)
+}
+
+/// Refuses adding `size` bytes to the current wiki if that takes it past its quota
+pub async fn ensure_room(size: usize) -> Result<(), TomeError> {
+    let Some(quota) = quota() else {
+        return Ok(());
+    };
+    let used = used(&wiki::current().content_dir).await;
+    if used.saturating_add(size as u64) > quota {
+        return Err(TomeError::OutOfRoom(format!(
+            "The wiki can take up {quota} bytes and only has {} bytes left",
+            quota.saturating_sub(used)
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,160 @@
+//! # Renaming
+//!
+//! Changing an article's title in the editor moves the article, its
+//! history and everything else stored with it to the new title. The old
+//! title keeps working: `content/redirects.json` maps it to the new one
+//! and visitors are redirected there. Both are stored as slugs.
+//! `/article/:id/rename` renames an article without editing it.
+use std::collections::BTreeMap;
+
+use askama::Template;
+use askama_axum::IntoResponse;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::{Redirect, Response};
+use axum::Form;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::config::content_path;
+use crate::error::TomeError;
+use crate::layout::Layout;
+use crate::slug::slug;
+use crate::storage::storage;
+use crate::{archive, backlinks, history, render_cache, review, search, Article, Invalid};
+
+const REDIRECTS_PATH: &str = "redirects.json";
+
+static LOCK: Mutex<()> = Mutex::const_new(());
+
+async fn read_redirects() -> BTreeMap<String, String> {
+    match tokio::fs::read_to_string(content_path(REDIRECTS_PATH)).await {
+        Ok(json) => serde_json::from_str(&json).unwrap_or_default(),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+/// The slug an article was renamed to, if it was
+pub async fn resolve(title: &str) -> Option<String> {
+    read_redirects().await.remove(&slug(title))
+}
+
+/// Moves the article `from` to `to` and redirects the old title
+pub async fn rename(from: &str, to: &str) -> tokio::io::Result<()> {
+    let _lock = LOCK.lock().await;
+    storage().rename(from, to).await?;
+    render_cache::invalidate();
+    search::remove(from).await;
+    backlinks::remove(from).await;
+
+    let event = format!("Renamed from {from}");
+    let (from, to) = (slug(from), slug(to));
+    let mut redirects = read_redirects().await;
+    // The new title is a real article now, and older titles point straight to it
+    redirects.remove(&to);
+    for target in redirects.values_mut() {
+        if *target == from {
+            *target = to.clone();
+        }
+    }
+    redirects.insert(from, to.clone());
+    tokio::fs::write(
+        content_path(REDIRECTS_PATH),
+        serde_json::to_string_pretty(&redirects)?,
+    )
+    .await?;
+    history::record_event(&to, &event).await
+}
+
+#[derive(Template)]
+#[template(path = "rename.html")]
+pub struct Rename {
+    layout: Layout,
+    path: String,
+    title: String,
+}
+
+#[derive(Deserialize)]
+pub struct RenameForm {
+    title: String,
+}
+
+pub async fn get_rename(
+    layout: Layout,
+    Path(title): Path<String>,
+) -> Result<impl IntoResponse, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let article = Article::load(&title).await.ok_or(TomeError::NotFound)?;
+    Ok(Rename {
+        layout,
+        path: article.path(),
+        title: article.title,
+    })
+}
+
+pub async fn post_rename(
+    layout: Layout,
+    Path(title): Path<String>,
+    Form(form): Form<RenameForm>,
+) -> Result<Response, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let article = Article::load(&title).await.ok_or(TomeError::NotFound)?;
+    if archive::is_archived(&article.content) {
+        return Ok(archive::refuse_change(layout, &article.title));
+    }
+    let new_title = form.title.trim().to_string();
+    if let Err(message) = Article::validate_title(&new_title) {
+        return Ok((StatusCode::BAD_REQUEST, Invalid { layout, message }).into_response());
+    }
+
+    // Only changing the case or punctuation keeps the slug, then there is nothing to move
+    if slug(&new_title) != article.path() {
+        if article.requires_review() {
+            return Ok(review::refuse_rename(layout, &article.title));
+        }
+        if Article::load(&new_title).await.is_some() {
+            let message = format!(
+                "There already is an article called \"{new_title}\", choose another title."
+            );
+            return Ok((StatusCode::CONFLICT, Invalid { layout, message }).into_response());
+        }
+        rename(&article.title, &new_title).await?;
+    }
+
+    let renamed = Article {
+        title: new_title,
+        content: article.content,
+    };
+    if renamed.requires_review() {
+        let revision = review::submit(&renamed, layout.user.as_deref()).await?;
+        return Ok(
+            Redirect::to(&format!("/article/{}/review/{revision}", renamed.path())).into_response(),
+        );
+    }
+    renamed
+        .write_to_disk_by(
+            layout.user.as_deref(),
+            Some(&format!("Renamed from {}", article.title)),
+        )
+        .await?;
+    Ok(Redirect::to(&format!("/article/{}", renamed.path())).into_response())
+}
+
+/// Rewrites redirects between titles into redirects between slugs
+pub async fn migrate() -> tokio::io::Result<()> {
+    let _lock = LOCK.lock().await;
+    let redirects = read_redirects().await;
+    let migrated: BTreeMap<String, String> = redirects
+        .iter()
+        .map(|(from, to)| (slug(from), slug(to)))
+        .filter(|(from, to)| from != to)
+        .collect();
+    if migrated == redirects {
+        return Ok(());
+    }
+    tokio::fs::write(
+        content_path(REDIRECTS_PATH),
+        serde_json::to_string_pretty(&migrated)?,
+    )
+    .await
+}
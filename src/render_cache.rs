@@ -0,0 +1,107 @@
+//! # Render Cache
+//!
+//! Article pages keep the HTML of the current version of articles in
+//! memory, so popular pages aren't rendered from Markdown on every visit.
+//! Entries are kept by the article and the modification time of its
+//! `current.md`, so editing the file by hand also renders it anew. As
+//! articles show the titles of other articles (with `autolink_titles`),
+//! glossary terms and media files, any saved, renamed or deleted article
+//! and any change to the media clears the whole cache. It holds at most
+//! `MAX_ENTRIES` articles, dropping the least recently shown one. Hits and
+//! misses are counted in the metrics.
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::{prometheus, storage, wiki, Article, TomeConfig};
+
+const MAX_ENTRIES: usize = 1000;
+
+struct Entry {
+    modified: SystemTime,
+    generation: u64,
+    html: Arc<str>,
+    used: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    /// Counts lookups, to know which entry was used longest ago
+    clock: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct RenderCache {
+    inner: Arc<Mutex<Inner>>,
+}
+
+/// Makes every cached page of the current wiki stale, after something
+/// they could show changed
+pub fn invalidate() {
+    wiki::current()
+        .render_generation
+        .fetch_add(1, Ordering::SeqCst);
+}
+
+impl RenderCache {
+    fn get(&self, title: &str, modified: SystemTime, generation: u64) -> Option<Arc<str>> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let clock = inner.clock;
+        let entry = inner.entries.get_mut(title)?;
+        if entry.modified != modified || entry.generation != generation {
+            return None;
+        }
+        entry.used = clock;
+        Some(entry.html.clone())
+    }
+
+    fn insert(&self, title: String, modified: SystemTime, generation: u64, html: Arc<str>) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.entries.len() >= MAX_ENTRIES && !inner.entries.contains_key(&title) {
+            let oldest = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(title, _)| title.clone());
+            if let Some(oldest) = oldest {
+                inner.entries.remove(&oldest);
+            }
+        }
+        let used = inner.clock;
+        inner.entries.insert(
+            title,
+            Entry {
+                modified,
+                generation,
+                html,
+                used,
+            },
+        );
+    }
+
+    /// The HTML of the current version of `article`, rendered if it isn't cached
+    pub async fn article_html(&self, config: &TomeConfig, article: &Article) -> Arc<str> {
+        let current = format!("{}/current.md", storage::article_dir(&article.title));
+        let modified = tokio::fs::metadata(&current)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        // Read first, so anything changed while rendering makes the entry stale
+        let generation = wiki::current().render_generation.load(Ordering::SeqCst);
+        if let Some(html) =
+            modified.and_then(|modified| self.get(&article.title, modified, generation))
+        {
+            prometheus::render_cache_hit();
+            return html;
+        }
+        prometheus::render_cache_miss();
+        let html: Arc<str> = article.shown_html(config).await.into();
+        if let Some(modified) = modified {
+            self.insert(article.title.clone(), modified, generation, html.clone());
+        }
+        html
+    }
+}
@@ -0,0 +1,202 @@
+use askama::Template;
+use askama_axum::IntoResponse;
+use axum::Form;
+use clap::Args;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::error::TomeError;
+use crate::layout::Layout;
+use crate::{archive, review, Article, Overview};
+
+/// Arguments for `tome replace`
+#[derive(Args)]
+pub struct ReplaceArgs {
+    /// Regular expression to search for
+    pattern: String,
+    /// Replacement text, may refer to capture groups like `$1`
+    replacement: String,
+    /// Only touch articles whose title matches this regular expression
+    #[arg(long)]
+    filter: Option<String>,
+    /// Write the changes instead of only previewing them
+    #[arg(long)]
+    apply: bool,
+}
+
+/// A pending replacement in a single article
+pub struct Change {
+    title: String,
+    path: String,
+    lines: Vec<(String, String)>,
+    content: String,
+}
+
+#[derive(Template, Default)]
+#[template(path = "replace.html")]
+pub struct Replace {
+    layout: Layout,
+    pattern: String,
+    replacement: String,
+    filter: String,
+    error: Option<String>,
+    applied: bool,
+    /// How many of the applied changes wait for a review
+    submitted: usize,
+    changes: Vec<Change>,
+}
+
+#[derive(Deserialize)]
+pub struct ReplaceForm {
+    pattern: String,
+    replacement: String,
+    #[serde(default)]
+    filter: String,
+    action: String,
+}
+
+async fn find_changes(pattern: &Regex, replacement: &str, filter: Option<&Regex>) -> Vec<Change> {
+    let mut changes = vec![];
+
+    for (path, title) in Overview::load().await.articles {
+        if filter.is_some_and(|filter| !filter.is_match(&title)) {
+            continue;
+        }
+        let Some(article) = Article::load(&title).await else {
+            continue;
+        };
+        // Archived articles stay as they are
+        if archive::is_archived(&article.content) {
+            continue;
+        }
+
+        // The frontmatter stays as it is, replacing in it could break its fields
+        let body = article.body();
+        let frontmatter = &article.content[..article.content.len() - body.len()];
+        let replaced = pattern.replace_all(body, replacement);
+        if replaced == body {
+            continue;
+        }
+
+        let lines = body
+            .lines()
+            .filter(|line| pattern.is_match(line))
+            .map(|line| {
+                (
+                    line.to_string(),
+                    pattern.replace_all(line, replacement).into_owned(),
+                )
+            })
+            .collect();
+
+        changes.push(Change {
+            title,
+            path,
+            lines,
+            content: format!("{frontmatter}{replaced}"),
+        });
+    }
+
+    changes.sort_by(|a, b| a.title.cmp(&b.title));
+    changes
+}
+
+/// Writes `changes` by `author` and returns how many were submitted for review instead
+async fn apply_changes(changes: &[Change], author: Option<&str>) -> Result<usize, TomeError> {
+    let mut submitted = 0;
+    for change in changes {
+        let article = Article {
+            title: change.title.clone(),
+            content: change.content.clone(),
+        };
+        if review::save_or_submit(&article, author, None)
+            .await?
+            .is_some()
+        {
+            submitted += 1;
+        }
+    }
+    Ok(submitted)
+}
+
+pub async fn get_replace(layout: Layout) -> impl IntoResponse {
+    Replace {
+        layout,
+        ..Default::default()
+    }
+}
+
+pub async fn post_replace(
+    layout: Layout,
+    Form(form): Form<ReplaceForm>,
+) -> Result<impl IntoResponse, TomeError> {
+    let author = layout.user.clone();
+    let mut page = Replace {
+        layout,
+        pattern: form.pattern,
+        replacement: form.replacement,
+        filter: form.filter,
+        ..Default::default()
+    };
+
+    let pattern = match Regex::new(&page.pattern) {
+        Ok(pattern) => pattern,
+        Err(e) => {
+            page.error = Some(e.to_string());
+            return Ok(page);
+        }
+    };
+    let filter = match page.filter.as_str() {
+        "" => None,
+        filter => match Regex::new(filter) {
+            Ok(filter) => Some(filter),
+            Err(e) => {
+                page.error = Some(e.to_string());
+                return Ok(page);
+            }
+        },
+    };
+
+    page.changes = find_changes(&pattern, &page.replacement, filter.as_ref()).await;
+
+    if form.action == "apply" {
+        page.submitted = apply_changes(&page.changes, author.as_deref()).await?;
+        page.applied = true;
+    }
+
+    Ok(page)
+}
+
+/// Runs `tome replace`, printing a preview of every change
+/// and writing them if `--apply` was given.
+pub async fn run(args: ReplaceArgs) -> color_eyre::Result<()> {
+    let pattern = Regex::new(&args.pattern)?;
+    let filter = args.filter.as_deref().map(Regex::new).transpose()?;
+
+    let changes = find_changes(&pattern, &args.replacement, filter.as_ref()).await;
+
+    for change in &changes {
+        println!("{}", change.title);
+        for (old, new) in &change.lines {
+            println!("  - {old}");
+            println!("  + {new}");
+        }
+    }
+
+    if changes.is_empty() {
+        println!("No articles match.");
+    } else if args.apply {
+        let submitted = apply_changes(&changes, None).await?;
+        println!("Updated {} articles.", changes.len() - submitted);
+        if submitted > 0 {
+            println!("Submitted {submitted} articles for review.");
+        }
+    } else {
+        println!(
+            "{} articles would change. Run again with --apply to write them.",
+            changes.len()
+        );
+    }
+
+    Ok(())
+}
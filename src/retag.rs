@@ -0,0 +1,216 @@
+use askama::Template;
+use askama_axum::IntoResponse;
+use axum::Form;
+use regex::Regex;
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+
+use crate::error::TomeError;
+use crate::layout::Layout;
+use crate::{archive, frontmatter, review, Article, Overview};
+
+/// A frontmatter change in a single article
+pub struct Change {
+    title: String,
+    path: String,
+    before: String,
+    after: String,
+    content: String,
+}
+
+#[derive(Template, Default)]
+#[template(path = "retag.html")]
+pub struct Retag {
+    layout: Layout,
+    filter: String,
+    tag_filter: String,
+    operation: String,
+    key: String,
+    value: String,
+    error: Option<String>,
+    applied: bool,
+    /// How many of the applied changes wait for a review
+    submitted: usize,
+    changes: Vec<Change>,
+}
+
+#[derive(Deserialize)]
+pub struct RetagForm {
+    #[serde(default)]
+    filter: String,
+    #[serde(default)]
+    tag_filter: String,
+    operation: String,
+    #[serde(default)]
+    key: String,
+    #[serde(default)]
+    value: String,
+    action: String,
+}
+
+enum Operation {
+    AddTag(String),
+    RemoveTag(String),
+    SetField(String, Option<Value>),
+}
+
+impl Operation {
+    fn parse(operation: &str, key: &str, value: &str) -> Result<Self, String> {
+        match operation {
+            "add_tag" | "remove_tag" if value.trim().is_empty() => {
+                Err("A tag is required".to_string())
+            }
+            "add_tag" => Ok(Operation::AddTag(value.trim().to_string())),
+            "remove_tag" => Ok(Operation::RemoveTag(value.trim().to_string())),
+            "set_field" if key.trim().is_empty() => Err("A field name is required".to_string()),
+            "set_field" if value.is_empty() => {
+                Ok(Operation::SetField(key.trim().to_string(), None))
+            }
+            "set_field" => serde_yaml::from_str(value)
+                .map(|value| Operation::SetField(key.trim().to_string(), Some(value)))
+                .map_err(|e| e.to_string()),
+            _ => Err(format!("Unknown operation {operation}")),
+        }
+    }
+
+    fn apply(&self, meta: &mut Mapping) {
+        match self {
+            Operation::AddTag(tag) => {
+                let mut tags = frontmatter::tags(meta);
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+                frontmatter::set_tags(meta, tags);
+            }
+            Operation::RemoveTag(tag) => {
+                let mut tags = frontmatter::tags(meta);
+                tags.retain(|t| t != tag);
+                frontmatter::set_tags(meta, tags);
+            }
+            Operation::SetField(key, Some(value)) => {
+                meta.insert(Value::from(key.as_str()), value.clone());
+            }
+            Operation::SetField(key, None) => {
+                meta.remove(&Value::from(key.as_str()));
+            }
+        }
+    }
+}
+
+fn describe(meta: &Mapping) -> String {
+    if meta.is_empty() {
+        return String::new();
+    }
+    let yaml = serde_yaml::to_string(meta).unwrap_or_default();
+    yaml.strip_prefix("---\n").unwrap_or(&yaml).to_string()
+}
+
+async fn find_changes(
+    operation: &Operation,
+    filter: Option<&Regex>,
+    tag_filter: Option<&str>,
+) -> Vec<Change> {
+    let mut changes = vec![];
+
+    for (path, title) in Overview::load().await.articles {
+        if filter.is_some_and(|filter| !filter.is_match(&title)) {
+            continue;
+        }
+        let Some(article) = Article::load(&title).await else {
+            continue;
+        };
+        // Archived articles stay as they are
+        if archive::is_archived(&article.content) {
+            continue;
+        }
+
+        // Frontmatter that doesn't parse would lose its fields when it is written back
+        let Ok(before) = frontmatter::parse(&article.content) else {
+            continue;
+        };
+        if tag_filter.is_some_and(|tag| !frontmatter::tags(&before).iter().any(|t| t == tag)) {
+            continue;
+        }
+
+        let mut after = before.clone();
+        operation.apply(&mut after);
+        if after == before {
+            continue;
+        }
+
+        let (_, body) = frontmatter::split(&article.content);
+        changes.push(Change {
+            title,
+            path,
+            before: describe(&before),
+            after: describe(&after),
+            content: frontmatter::join(&after, body),
+        });
+    }
+
+    changes.sort_by(|a, b| a.title.cmp(&b.title));
+    changes
+}
+
+pub async fn get_retag(layout: Layout) -> impl IntoResponse {
+    Retag {
+        layout,
+        operation: "add_tag".to_string(),
+        ..Default::default()
+    }
+}
+
+pub async fn post_retag(
+    layout: Layout,
+    Form(form): Form<RetagForm>,
+) -> Result<impl IntoResponse, TomeError> {
+    let author = layout.user.clone();
+    let mut page = Retag {
+        layout,
+        filter: form.filter,
+        tag_filter: form.tag_filter,
+        operation: form.operation,
+        key: form.key,
+        value: form.value,
+        ..Default::default()
+    };
+
+    let operation = match Operation::parse(&page.operation, &page.key, &page.value) {
+        Ok(operation) => operation,
+        Err(e) => {
+            page.error = Some(e);
+            return Ok(page);
+        }
+    };
+    let filter = match page.filter.as_str() {
+        "" => None,
+        filter => match Regex::new(filter) {
+            Ok(filter) => Some(filter),
+            Err(e) => {
+                page.error = Some(e.to_string());
+                return Ok(page);
+            }
+        },
+    };
+    let tag_filter = Some(page.tag_filter.trim()).filter(|tag| !tag.is_empty());
+
+    page.changes = find_changes(&operation, filter.as_ref(), tag_filter).await;
+
+    if form.action == "apply" {
+        for change in &page.changes {
+            let article = Article {
+                title: change.title.clone(),
+                content: change.content.clone(),
+            };
+            if review::save_or_submit(&article, author.as_deref(), None)
+                .await?
+                .is_some()
+            {
+                page.submitted += 1;
+            }
+        }
+        page.applied = true;
+    }
+
+    Ok(page)
+}
@@ -0,0 +1,251 @@
+//! # Reviews
+//!
+//! Articles with `requires_review: true` in their frontmatter can't be
+//! changed directly. Saving them stores the new content as a pending
+//! revision in the article's `pending/` directory instead, and the
+//! revision only becomes the current version once someone approves it.
+//! Everyone but its submitter can do that, and the approver becomes the
+//! author of the new version. Bulk replacements, retagging and the inbox go
+//! through reviews just like the editor. Such articles can't be renamed,
+//! since a rename takes effect right away, not once it is approved.
+use std::time::SystemTime;
+
+use askama::Template;
+use askama_axum::IntoResponse;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::{Redirect, Response};
+use axum::Form;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use time::OffsetDateTime;
+use tokio_stream::wrappers::ReadDirStream;
+use tokio_stream::StreamExt;
+
+use crate::diff::{diff, DiffLine};
+use crate::error::TomeError;
+use crate::layout::Layout;
+use crate::slug::slug;
+use crate::storage::article_dir;
+use crate::{archive, frontmatter, Article, Invalid, Overview};
+
+/// Whether changes to an article with this content have to be reviewed
+pub fn requires_review(content: &str) -> bool {
+    frontmatter::parse(content)
+        .unwrap_or_default()
+        .get(&Value::from("requires_review"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// The page turning away renaming the article `title`, whose changes have to be reviewed
+pub fn refuse_rename(layout: Layout, title: &str) -> Response {
+    let message = format!(
+        "Changes to \"{title}\" have to be reviewed, so it can't be renamed. \
+         Turn off `requires_review` first."
+    );
+    (StatusCode::FORBIDDEN, Invalid { layout, message }).into_response()
+}
+
+fn pending_dir(title: &str) -> String {
+    format!("{}/pending", article_dir(title))
+}
+
+/// Who submitted a pending revision, stored next to it
+#[derive(Serialize, Deserialize, Default)]
+struct Submission {
+    submitter: Option<String>,
+}
+
+/// Stores `article` as a pending revision by `submitter` and returns its id
+pub async fn submit(article: &Article, submitter: Option<&str>) -> Result<String, TomeError> {
+    archive::ensure_changeable(&article.title).await?;
+    let dir = pending_dir(&article.title);
+    tokio::fs::create_dir_all(&dir).await?;
+    let revision = uuid::Uuid::new_v4().hyphenated().to_string();
+    let submission = Submission {
+        submitter: submitter.map(str::to_string),
+    };
+    tokio::fs::write(
+        format!("{dir}/{revision}.json"),
+        serde_json::to_string(&submission)?,
+    )
+    .await?;
+    tokio::fs::write(format!("{dir}/{revision}.md"), article.content_with_title()).await?;
+    Ok(revision)
+}
+
+/// Saves `article` by `author`, or submits it for review if its current version requires one,
+/// returning the id of the submitted revision
+pub async fn save_or_submit(
+    article: &Article,
+    author: Option<&str>,
+    summary: Option<&str>,
+) -> Result<Option<String>, TomeError> {
+    let needs_review = Article::load(&article.title)
+        .await
+        .is_some_and(|current| requires_review(&current.content));
+    if needs_review {
+        return Ok(Some(submit(article, author).await?));
+    }
+    article.change_by(author, summary).await?;
+    Ok(None)
+}
+
+/// The pending revisions of an article, oldest first
+async fn pending(title: &str) -> Vec<(String, SystemTime)> {
+    let Ok(dir) = tokio::fs::read_dir(pending_dir(title)).await else {
+        return vec![];
+    };
+    let mut entries = ReadDirStream::new(dir);
+    let mut revisions = vec![];
+    while let Some(Ok(entry)) = entries.next().await {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(revision) = file_name.strip_suffix(".md") {
+            if let Ok(submitted) = entry.metadata().await.and_then(|meta| meta.modified()) {
+                revisions.push((revision.to_string(), submitted));
+            }
+        }
+    }
+    revisions.sort_by_key(|(_, submitted)| *submitted);
+    revisions
+}
+
+/// Revision ids are uuids, anything else could point outside the directory
+fn is_revision_id(revision: &str) -> bool {
+    uuid::Uuid::parse_str(revision).is_ok()
+}
+
+async fn load_revision(title: &str, revision: &str) -> Option<String> {
+    if !is_revision_id(revision) {
+        return None;
+    }
+    tokio::fs::read_to_string(format!("{}/{revision}.md", pending_dir(title)))
+        .await
+        .ok()
+}
+
+/// Who submitted a revision, revisions of older releases have no submitter
+async fn load_submission(title: &str, revision: &str) -> Submission {
+    match tokio::fs::read_to_string(format!("{}/{revision}.json", pending_dir(title))).await {
+        Ok(json) => serde_json::from_str(&json).unwrap_or_default(),
+        Err(_) => Submission::default(),
+    }
+}
+
+fn format_time(time: SystemTime) -> String {
+    OffsetDateTime::from(time)
+        .format(&time::format_description::well_known::Rfc2822)
+        .unwrap()
+}
+
+#[derive(Template)]
+#[template(path = "reviews.html")]
+pub struct Reviews {
+    layout: Layout,
+    /// Article path, article title, revision and submission time
+    revisions: Vec<(String, String, String, String)>,
+}
+
+/// Lists the pending revisions of all articles
+pub async fn get_reviews(layout: Layout) -> impl IntoResponse {
+    let mut revisions = vec![];
+    for (path, title) in Overview::load().await.articles {
+        for (revision, submitted) in pending(&title).await {
+            revisions.push((
+                path.clone(),
+                title.clone(),
+                revision,
+                format_time(submitted),
+            ));
+        }
+    }
+    Reviews { layout, revisions }
+}
+
+#[derive(Template)]
+#[template(path = "review.html")]
+pub struct Review {
+    layout: Layout,
+    path: String,
+    title: String,
+    revision: String,
+    submitter: Option<String>,
+    /// Every line of the diff with its CSS class
+    lines: Vec<DiffLine>,
+}
+
+pub async fn get_review(
+    layout: Layout,
+    Path((title, revision)): Path<(String, String)>,
+) -> Result<impl IntoResponse, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let content = load_revision(&title, &revision)
+        .await
+        .ok_or(TomeError::NotFound)?;
+    let current = Article::load(&title)
+        .await
+        .map(|article| article.content)
+        .unwrap_or_default();
+
+    let lines = diff(&current, &content);
+    let submitter = load_submission(&title, &revision).await.submitter;
+
+    Ok(Review {
+        layout,
+        path: slug(&title),
+        title,
+        revision,
+        submitter,
+        lines,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct ReviewForm {
+    action: String,
+}
+
+/// Approves or rejects a pending revision
+pub async fn post_review(
+    layout: Layout,
+    Path((title, revision)): Path<(String, String)>,
+    Form(form): Form<ReviewForm>,
+) -> Result<impl IntoResponse, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let content = load_revision(&title, &revision)
+        .await
+        .ok_or(TomeError::NotFound)?;
+
+    let submitter = load_submission(&title, &revision).await.submitter;
+
+    match form.action.as_str() {
+        "approve" => {
+            let approver = layout.user.as_deref();
+            if submitter.is_some() && submitter.as_deref() == approver {
+                return Err(TomeError::Forbidden(
+                    "Someone else has to approve your own revision.".to_string(),
+                ));
+            }
+            let summary = submitter.map(|submitter| format!("Submitted by {submitter}"));
+            Article {
+                title: frontmatter::title(&frontmatter::parse(&content).unwrap_or_default())
+                    .unwrap_or(title.clone()),
+                content,
+            }
+            .change_by(approver, summary.as_deref())
+            .await?;
+        }
+        "reject" => {}
+        action => {
+            return Err(TomeError::BadRequest(format!(
+                "\"{action}\" is neither approve nor reject"
+            )))
+        }
+    }
+    let dir = pending_dir(&title);
+    tokio::fs::remove_file(format!("{dir}/{revision}.md")).await?;
+    let _ = tokio::fs::remove_file(format!("{dir}/{revision}.json")).await;
+
+    Ok(Redirect::to(&format!("/article/{}", slug(&title))))
+}
@@ -0,0 +1,92 @@
+//! # Trace Sampling
+//!
+//! To find out why pages are slow in production without logging every
+//! request at `TRACE`, a share of requests can be traced in detail: a
+//! sampled request runs in a `sampled_request` span with its method, route
+//! and status, and the `DEBUG` logs and timing spans of loading, saving and
+//! rendering articles are only shown within it. `trace_sample_rate` is the
+//! share of all requests, like `0.01` for 1%, and `trace_sampling` sets
+//! other rates for some routes, like
+//! `trace_sampling = [{ route = "/article/:id", rate = 0.1 }]`. Routes are
+//! their patterns as in the metrics. Nothing is sampled by default.
+use std::time::Instant;
+
+use axum::extract::{MatchedPath, State};
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use tracing::Instrument;
+
+use crate::TomeConfig;
+
+/// The name of the span sampled requests run in
+pub const SAMPLED_SPAN: &str = "sampled_request";
+
+/// What the `tome` binary logs unless `RUST_LOG` says otherwise: `INFO`,
+/// and `DEBUG` within sampled requests
+pub const DEFAULT_LOG_FILTER: &str = "info,[sampled_request]=debug";
+
+/// How many requests of a route are sampled
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RouteSampling {
+    /// A route pattern like `/article/:id`
+    pub route: String,
+    /// The share of requests between 0 and 1
+    pub rate: f64,
+}
+
+impl RouteSampling {
+    /// Parses a rate given on the command line like `route = "/article/:id", rate = 0.1`
+    pub fn parse(sampling: &str) -> Result<Self, String> {
+        crate::config::parse_table(sampling)
+    }
+}
+
+/// The share of requests to `route` that are sampled
+fn rate(config: &TomeConfig, route: &str) -> f64 {
+    config
+        .trace_sampling
+        .iter()
+        .find(|sampling| sampling.route == route)
+        .map(|sampling| sampling.rate)
+        .or(config.trace_sample_rate)
+        .unwrap_or(0.0)
+}
+
+/// Whether a request is sampled at `rate`
+fn is_sampled(rate: f64) -> bool {
+    if rate <= 0.0 {
+        return false;
+    }
+    (OsRng.next_u64() as f64 / u64::MAX as f64) < rate
+}
+
+/// Runs a share of requests in a span that shows their `DEBUG` logs
+pub async fn sample<B>(
+    State(config): State<TomeConfig>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("fallback".to_string(), |path| path.as_str().to_string());
+    if !is_sampled(rate(&config, &route)) {
+        return next.run(request).await;
+    }
+
+    let span = tracing::info_span!(
+        SAMPLED_SPAN,
+        method = %request.method(),
+        route,
+        path = request.uri().path(),
+        status = tracing::field::Empty,
+    );
+    let start = Instant::now();
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("status", response.status().as_u16());
+    span.in_scope(|| tracing::debug!("Answered in {:?}", start.elapsed()));
+    response
+}
@@ -0,0 +1,74 @@
+//! # Sanitizing HTML
+//!
+//! Markdown passes raw HTML through, so without care any editor could add
+//! scripts to the pages everyone reads. Rendered articles are therefore
+//! cleaned with ammonia, which keeps a list of harmless elements and
+//! attributes (plus those tome renders itself, like heading ids and task
+//! list checkboxes) and removes everything else. `allowed_html_tags` and
+//! `allowed_html_attributes` add to that list. For a wiki only trusted
+//! people edit, like a personal one, `trusted_html` turns cleaning off.
+use ammonia::Builder;
+
+use crate::{wiki, TomeConfig};
+
+/// Attributes of the HTML tome renders from Markdown
+///
+/// The attributes for previews of links are only added after cleaning, see
+/// [`crate::filters`].
+const RENDERED_ATTRIBUTES: &[&str] = &["id", "class", "dir", "aria-label"];
+
+/// What is allowed besides ammonia's defaults
+#[derive(Default)]
+pub(crate) struct Allowed {
+    tags: Vec<String>,
+    attributes: Vec<String>,
+}
+
+/// What the current wiki allows, `None` if its HTML is trusted
+pub(crate) type Cleaning = Option<Allowed>;
+
+pub fn init(config: &TomeConfig) {
+    let allowed = (!config.trusted_html).then(|| Allowed {
+        tags: config.allowed_html_tags.clone(),
+        attributes: config.allowed_html_attributes.clone(),
+    });
+    let _ = wiki::current().cleaning.set(allowed);
+}
+
+/// The cleaner for HTML with what `allowed` allows
+fn cleaner(allowed: &Allowed) -> Builder<'_> {
+    let tags = allowed.tags.iter().map(String::as_str);
+    let mut builder = Builder::default();
+    builder
+        // Tags whose content ammonia removes can't also be allowed
+        .rm_clean_content_tags(tags.clone())
+        .add_tags(tags)
+        .add_tags(["input"])
+        .add_tag_attributes("input", ["type", "checked", "disabled"])
+        .add_tag_attributes("a", ["download"])
+        .add_tag_attributes("th", ["style"])
+        .add_tag_attributes("td", ["style"])
+        .add_generic_attributes(RENDERED_ATTRIBUTES)
+        .add_generic_attributes(allowed.attributes.iter().map(String::as_str))
+        // Links can't open new tabs, which is what `noopener` is for
+        .link_rel(None);
+    // Table cells only need their alignment, unless styles are allowed everywhere
+    if !allowed
+        .attributes
+        .iter()
+        .any(|attribute| attribute == "style")
+    {
+        builder.filter_style_properties(["text-align"].into());
+    }
+    builder
+}
+
+/// Removes everything from `html` that isn't allowed
+pub fn clean(html: String) -> String {
+    let wiki = wiki::current();
+    let default = Some(Allowed::default());
+    match wiki.cleaning.get().unwrap_or(&default) {
+        Some(allowed) => cleaner(allowed).clean(&html).to_string(),
+        None => html,
+    }
+}
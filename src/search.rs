@@ -0,0 +1,293 @@
+//! # Full-Text Search
+//!
+//! When the server starts, every article is split into words and added to
+//! an inverted index kept in memory, which saving an article updates.
+//! `/search?q=` looks up articles containing all words of the query and
+//! ranks them by how often the words appear, with matches in the title
+//! counting more. Results show a snippet of the text around the first
+//! match. [Archived](crate::archive) articles are only found with
+//! `&archived=true`.
+use std::collections::HashMap;
+
+use askama::Template;
+use axum::extract::Query;
+use axum::response::IntoResponse;
+use pulldown_cmark::{Event, Parser, Tag};
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+use crate::layout::Layout;
+use crate::slug::slug;
+use crate::{wiki, Article, Overview};
+
+/// How much finding a word in the title counts compared to the text
+const TITLE_WEIGHT: f64 = 5.0;
+/// Characters of context shown on either side of a match
+const SNIPPET_CONTEXT: usize = 80;
+const MAX_RESULTS: usize = 50;
+
+/// A searchable article
+struct Document {
+    title: String,
+    /// The article's text without Markdown
+    text: String,
+    archived: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct Index {
+    documents: HashMap<String, Document>,
+    /// How often each word appears in each article, by slug
+    words: HashMap<String, HashMap<String, f64>>,
+}
+
+/// Splits `text` into lowercase words
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// The text of a Markdown document, without any markup
+fn plain_text(markdown: &str) -> String {
+    let mut text = String::new();
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak
+            | Event::HardBreak
+            | Event::End(
+                Tag::Paragraph
+                | Tag::Heading(..)
+                | Tag::Item
+                | Tag::CodeBlock(_)
+                | Tag::TableCell
+                | Tag::BlockQuote,
+            ) if !text.ends_with(' ') => text.push(' '),
+            _ => {}
+        }
+    }
+    text
+}
+
+impl Index {
+    fn remove(&mut self, slug: &str) {
+        if self.documents.remove(slug).is_some() {
+            self.words.retain(|_, articles| {
+                articles.remove(slug);
+                !articles.is_empty()
+            });
+        }
+    }
+
+    fn insert(&mut self, article: &Article) {
+        let slug = article.path();
+        self.remove(&slug);
+
+        let text = plain_text(article.body());
+        let mut counts: HashMap<String, f64> = HashMap::new();
+        for word in words(&text) {
+            *counts.entry(word).or_default() += 1.0;
+        }
+        for word in words(&article.title) {
+            *counts.entry(word).or_default() += TITLE_WEIGHT;
+        }
+        for (word, count) in counts {
+            self.words
+                .entry(word)
+                .or_default()
+                .insert(slug.clone(), count);
+        }
+        self.documents.insert(
+            slug,
+            Document {
+                title: article.title.clone(),
+                text,
+                archived: article.is_archived(),
+            },
+        );
+    }
+
+    /// The slugs of articles containing every word of `query`, best matches first
+    fn search(&self, query: &[String]) -> Vec<(String, f64)> {
+        let mut scores: Option<HashMap<String, f64>> = None;
+        for word in query {
+            let Some(articles) = self.words.get(word) else {
+                return vec![];
+            };
+            // Rare words say more about an article than common ones
+            let weight = (self.documents.len() as f64 / articles.len() as f64).ln() + 1.0;
+            let matches = articles
+                .iter()
+                .map(|(slug, count)| (slug.clone(), count * weight));
+            scores = Some(match scores {
+                None => matches.collect(),
+                Some(scores) => {
+                    let matches: HashMap<String, f64> = matches.collect();
+                    scores
+                        .into_iter()
+                        .filter_map(|(slug, score)| {
+                            Some((slug.clone(), score + matches.get(&slug)?))
+                        })
+                        .collect()
+                }
+            });
+        }
+
+        let mut results: Vec<(String, f64)> = scores.unwrap_or_default().into_iter().collect();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        results
+    }
+}
+
+/// Indexes every article, searching is only possible afterwards.
+/// Building it again replaces the index.
+pub async fn build() {
+    let mut index = Index::default();
+    for (path, _) in Overview::load().await.articles {
+        if let Some(article) = Article::load(&path).await {
+            index.insert(&article);
+        }
+    }
+    tracing::info!("Indexed {} articles for search", index.documents.len());
+    let wiki = wiki::current();
+    if let Err(index) = wiki.search.set(RwLock::new(index)) {
+        *wiki.search.get().unwrap().write().await = index.into_inner();
+    }
+}
+
+/// Adds a newly saved version of an article to the index
+pub async fn update(article: &Article) {
+    if let Some(index) = wiki::current().search.get() {
+        index.write().await.insert(article);
+    }
+}
+
+/// Removes an article that no longer exists under `title` from the index
+pub async fn remove(title: &str) {
+    if let Some(index) = wiki::current().search.get() {
+        index.write().await.remove(&slug(title));
+    }
+}
+
+/// Part of a snippet
+pub struct Piece {
+    text: String,
+    /// Whether this is a word of the query
+    highlight: bool,
+}
+
+impl Piece {
+    fn new(text: impl Into<String>, highlight: bool) -> Self {
+        Piece {
+            text: text.into(),
+            highlight,
+        }
+    }
+}
+
+/// The part of `text` around its first match, split into highlighted and plain pieces
+fn snippet(text: &str, query: &[String]) -> Vec<Piece> {
+    let lower = text.to_lowercase();
+    // Lowercasing can change lengths, only use it to find matches if it doesn't
+    let first = if lower.len() == text.len() {
+        query
+            .iter()
+            .filter_map(|word| lower.find(word.as_str()))
+            .min()
+            .unwrap_or(0)
+    } else {
+        0
+    };
+
+    let mut start = first.saturating_sub(SNIPPET_CONTEXT);
+    while !text.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (first + SNIPPET_CONTEXT * 2).min(text.len());
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+
+    let mut pieces = vec![];
+    if start > 0 {
+        pieces.push(Piece::new("…", false));
+    }
+    let excerpt = &text[start..end];
+    let mut plain = String::new();
+    let mut rest = excerpt;
+    while let Some(c) = rest.chars().next() {
+        let word: String = rest.chars().take_while(|c| c.is_alphanumeric()).collect();
+        if !word.is_empty() && query.contains(&word.to_lowercase()) {
+            pieces.push(Piece::new(std::mem::take(&mut plain), false));
+            pieces.push(Piece::new(word.clone(), true));
+            rest = &rest[word.len()..];
+        } else if word.is_empty() {
+            plain.push(c);
+            rest = &rest[c.len_utf8()..];
+        } else {
+            plain.push_str(&word);
+            rest = &rest[word.len()..];
+        }
+    }
+    pieces.push(Piece::new(plain, false));
+    if end < text.len() {
+        pieces.push(Piece::new("…", false));
+    }
+    pieces
+}
+
+pub struct SearchResult {
+    path: String,
+    title: String,
+    archived: bool,
+    snippet: Vec<Piece>,
+}
+
+#[derive(Template)]
+#[template(path = "search.html")]
+pub struct Search {
+    layout: Layout,
+    query: String,
+    results: Vec<SearchResult>,
+    /// Matching articles, including those not shown
+    total: usize,
+    archived: bool,
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    q: String,
+    /// Also find archived articles
+    #[serde(default)]
+    archived: bool,
+}
+
+pub async fn get_search(layout: Layout, Query(query): Query<SearchQuery>) -> impl IntoResponse {
+    let words: Vec<String> = words(&query.q).collect();
+    let mut results = vec![];
+    let mut total = 0;
+    if let Some(index) = wiki::current().search.get() {
+        let index = index.read().await;
+        let mut matches = index.search(&words);
+        matches.retain(|(path, _)| query.archived || !index.documents[path].archived);
+        total = matches.len();
+        for (path, _) in matches.into_iter().take(MAX_RESULTS) {
+            let document = &index.documents[&path];
+            results.push(SearchResult {
+                title: document.title.clone(),
+                archived: document.archived,
+                snippet: snippet(&document.text, &words),
+                path,
+            });
+        }
+    }
+    Search {
+        layout,
+        query: query.q,
+        results,
+        total,
+        archived: query.archived,
+    }
+}
@@ -0,0 +1,55 @@
+//! # Sections
+//!
+//! A section starts at a heading and extends up to the next heading of the
+//! same or a higher level. Sections are numbered by the position of their
+//! heading in the article, which is the same numbering the renderer uses
+//! for its "edit" links.
+use std::ops::Range;
+
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag};
+
+/// The byte range of every section in `markdown`
+pub fn sections(markdown: &str) -> Vec<Range<usize>> {
+    let headings: Vec<(HeadingLevel, usize)> = Parser::new_ext(markdown, Options::all())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::Heading(level, _, _)) => Some((level, range.start)),
+            _ => None,
+        })
+        .collect();
+
+    headings
+        .iter()
+        .enumerate()
+        .map(|(i, (level, start))| {
+            let end = headings[i + 1..]
+                .iter()
+                .find(|(next, _)| next <= level)
+                .map_or(markdown.len(), |(_, next)| *next);
+            *start..end
+        })
+        .collect()
+}
+
+/// The content of section `index`
+pub fn get(markdown: &str, index: usize) -> Option<&str> {
+    sections(markdown)
+        .get(index)
+        .map(|range| &markdown[range.clone()])
+}
+
+/// Replaces section `index` with `section`
+pub fn replace(markdown: &str, index: usize, section: &str) -> Option<String> {
+    let range = sections(markdown).get(index)?.clone();
+    let mut section = section.trim_end().to_string();
+    if range.end < markdown.len() {
+        section.push_str("\n\n");
+    } else {
+        section.push('\n');
+    }
+    Some(format!(
+        "{}{section}{}",
+        &markdown[..range.start],
+        &markdown[range.end..]
+    ))
+}
@@ -0,0 +1,180 @@
+//! # First Start
+//!
+//! Started in a directory without `content/`, tome serves a setup page at
+//! `/setup` instead of the wiki, and every other page redirects there. It
+//! asks for the wiki's name, the administrator's credentials and the file
+//! endings that can be uploaded, saves them in `tome.toml` and creates the
+//! content directory. Then the setup server stops and the wiki starts.
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use askama::Template;
+use askama_axum::IntoResponse;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{Redirect, Response};
+use axum::routing::get;
+use axum::{Form, Router};
+use axum_macros::FromRef;
+use serde::Deserialize;
+use tokio::sync::Notify;
+
+use crate::error::TomeError;
+use crate::layout::Layout;
+use crate::{assets, auth, config, TomeConfig};
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Whether tome runs for the first time in this directory
+pub async fn is_needed(config: &TomeConfig) -> bool {
+    tokio::fs::metadata(config::configured_content_dir(config))
+        .await
+        .is_err()
+}
+
+#[derive(Clone, FromRef)]
+struct SetupState {
+    config: TomeConfig,
+    /// Where the configuration is written
+    config_file: PathBuf,
+    /// Notified once the setup is done
+    finished: Arc<Notify>,
+}
+
+#[derive(Template, Default)]
+#[template(path = "setup.html")]
+struct Setup {
+    layout: Layout,
+    site_name: String,
+    admin_user: String,
+    allowed_uploads: String,
+    errors: Vec<String>,
+    finished: bool,
+}
+
+#[derive(Deserialize)]
+struct SetupForm {
+    site_name: String,
+    admin_user: String,
+    password: String,
+    password_confirmation: String,
+    #[serde(default)]
+    allowed_uploads: String,
+}
+
+/// Turns a list like `png, .JPG pdf` into file endings like `.png`
+fn parse_endings(endings: &str) -> Result<Vec<String>, String> {
+    endings
+        .split([',', ' '])
+        .map(str::trim)
+        .filter(|ending| !ending.is_empty())
+        .map(|ending| {
+            let ending = ending.trim_start_matches('.').to_lowercase();
+            if ending.is_empty() || ending.contains(['/', '\\', '.']) {
+                Err(format!("\"{ending}\" isn't a file ending like png"))
+            } else {
+                Ok(format!(".{ending}"))
+            }
+        })
+        .collect()
+}
+
+async fn get_setup(layout: Layout, State(config): State<TomeConfig>) -> impl IntoResponse {
+    Setup {
+        layout,
+        site_name: config.site_name.unwrap_or_else(|| "Tome".to_string()),
+        admin_user: config.admin_user.unwrap_or_else(|| "admin".to_string()),
+        allowed_uploads: if config.allowed_uploads.is_empty() {
+            "png, jpg, gif, svg".to_string()
+        } else {
+            config.allowed_uploads.join(", ")
+        },
+        ..Default::default()
+    }
+}
+
+async fn post_setup(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    State(config_file): State<PathBuf>,
+    State(finished): State<Arc<Notify>>,
+    Form(form): Form<SetupForm>,
+) -> Result<Response, TomeError> {
+    // Another request may have finished the setup already
+    if !is_needed(&config).await {
+        return Ok(Redirect::to("/").into_response());
+    }
+
+    let mut page = Setup {
+        layout,
+        site_name: form.site_name.trim().to_string(),
+        admin_user: form.admin_user.trim().to_string(),
+        allowed_uploads: form.allowed_uploads,
+        ..Default::default()
+    };
+
+    if page.site_name.is_empty() {
+        page.errors.push("The wiki needs a name".to_string());
+    }
+    if page.admin_user.is_empty() {
+        page.errors
+            .push("The administrator needs a user name".to_string());
+    }
+    if form.password.chars().count() < MIN_PASSWORD_LENGTH {
+        page.errors.push(format!(
+            "The password needs at least {MIN_PASSWORD_LENGTH} characters"
+        ));
+    } else if form.password != form.password_confirmation {
+        page.errors.push("The passwords don't match".to_string());
+    }
+    let allowed_uploads = match parse_endings(&page.allowed_uploads) {
+        Ok(endings) => endings,
+        Err(e) => {
+            page.errors.push(e);
+            vec![]
+        }
+    };
+    if !page.errors.is_empty() {
+        return Ok((StatusCode::BAD_REQUEST, page).into_response());
+    }
+
+    let hash = auth::hash_password(&form.password);
+    let mut options = toml::value::Table::new();
+    options.insert("site_name".to_string(), page.site_name.clone().into());
+    options.insert("admin_user".to_string(), page.admin_user.clone().into());
+    options.insert("admin_password_hash".to_string(), hash.into());
+    options.insert("allowed_uploads".to_string(), allowed_uploads.into());
+    config::update(&config_file, options).await?;
+    config::create_directories(config::configured_content_dir(&config)).await?;
+
+    tracing::info!("Setup finished, starting the wiki");
+    finished.notify_one();
+    page.finished = true;
+    Ok(page.into_response())
+}
+
+/// Serves the setup on `addr` until it is done
+pub async fn run(
+    addr: SocketAddr,
+    config_file: &Path,
+    config: TomeConfig,
+) -> color_eyre::Result<()> {
+    let finished = Arc::new(Notify::new());
+    let router = Router::new()
+        .route("/setup", get(get_setup).post(post_setup))
+        .route("/static/:name", get(assets::get_asset))
+        .fallback(|| async { Redirect::temporary("/setup") })
+        .with_state(SetupState {
+            config,
+            config_file: config_file.to_path_buf(),
+            finished: finished.clone(),
+        });
+
+    tracing::info!("Nothing is set up yet, continue at http://{addr}/setup");
+    axum::Server::bind(&addr)
+        .serve(router.into_make_service())
+        .with_graceful_shutdown(finished.notified())
+        .await?;
+    Ok(())
+}
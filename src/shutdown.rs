@@ -0,0 +1,72 @@
+//! # Graceful Shutdown
+//!
+//! On `SIGTERM` or `Ctrl+C`, tome stops accepting connections, lets the
+//! requests it is answering finish and then waits for article saves and
+//! media uploads still being written, including those continued in the
+//! background, before it exits. Writes hold a [`writing`] guard while they
+//! run, which shutting down waits for, at most `SHUTDOWN_TIMEOUT`.
+//! `current.md` is also written next to the article and then moved in
+//! place, so even a crash never leaves half of it behind.
+use std::path::Path;
+use std::time::Duration;
+
+use tokio::sync::{RwLock, RwLockReadGuard};
+
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+static WRITES: RwLock<()> = RwLock::const_new(());
+
+/// Held while writing to the content directory, so shutting down waits for it
+pub async fn writing() -> RwLockReadGuard<'static, ()> {
+    WRITES.read().await
+}
+
+/// Writes `contents` to a file next to `path` and moves it in place
+pub async fn write_atomically(path: impl AsRef<Path>, contents: &[u8]) -> tokio::io::Result<()> {
+    let path = path.as_ref();
+    let partial = path.with_file_name(format!(".{}", uuid::Uuid::new_v4().hyphenated()));
+    if let Err(e) = tokio::fs::write(&partial, contents).await {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(e);
+    }
+    tokio::fs::rename(&partial, path).await
+}
+
+/// Waits until the process is asked to stop
+pub async fn signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!("Couldn't listen for Ctrl+C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("Couldn't listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+    tracing::info!("Shutting down after the requests being answered");
+}
+
+/// Waits for the writes still running, and keeps new ones from starting
+pub async fn finish_writes() {
+    match tokio::time::timeout(SHUTDOWN_TIMEOUT, WRITES.write()).await {
+        Ok(_done) => tracing::info!("Every write is finished"),
+        Err(_) => tracing::warn!("Gave up waiting for writes after {SHUTDOWN_TIMEOUT:?}"),
+    }
+}
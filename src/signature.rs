@@ -0,0 +1,205 @@
+//! # Version Signatures
+//!
+//! With `sign_versions` enabled, every saved version gets a `.sig` file
+//! next to it holding the SHA-256 hash of its content and an Ed25519
+//! signature by the server's key. The key is created as
+//! `content/signing.key` on first start. The signed message also contains
+//! the article title and version id, so a signature can't be moved to
+//! another version. `GET /api/article/:id/history/:version/signature`
+//! checks a version against its signature.
+use axum::extract::Path;
+use axum::response::IntoResponse;
+use axum::Json;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::content_path;
+use crate::error::TomeError;
+use crate::storage::article_dir;
+use crate::{paths, wiki, Article, TomeConfig};
+
+const KEY_PATH: &str = "signing.key";
+
+/// Loads or creates the server's signing key if `sign_versions` is enabled
+pub fn init(config: &TomeConfig) -> color_eyre::Result<()> {
+    if !config.sign_versions {
+        return Ok(());
+    }
+    let key = match std::fs::read_to_string(content_path(KEY_PATH)) {
+        Ok(key) => {
+            let bytes: [u8; 32] = STANDARD
+                .decode(key.trim())?
+                .try_into()
+                .map_err(|_| color_eyre::eyre::eyre!("{KEY_PATH} is not an Ed25519 key"))?;
+            SigningKey::from_bytes(&bytes)
+        }
+        Err(_) => {
+            let key = SigningKey::generate(&mut rand_core::OsRng);
+            std::fs::write(content_path(KEY_PATH), STANDARD.encode(key.to_bytes()))?;
+            tracing::info!("Created a new signing key in {KEY_PATH}");
+            key
+        }
+    };
+    let _ = wiki::current().signing_key.set(key);
+    Ok(())
+}
+
+/// The contents of a `.sig` file
+#[derive(Serialize, Deserialize)]
+struct VersionSignature {
+    sha256: String,
+    public_key: String,
+    signature: String,
+    /// The title the version was signed under, articles keep their signatures when renamed
+    #[serde(default)]
+    title: Option<String>,
+}
+
+fn sha256(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn message(title: &str, version: &str, sha256: &str) -> String {
+    format!("{title}\n{version}\n{sha256}")
+}
+
+fn signature_path(title: &str, version: &str) -> String {
+    format!("{}/{version}.sig", article_dir(title))
+}
+
+/// Signs a newly written version, if signing is enabled
+pub async fn sign(title: &str, version: &str, content: &str) -> tokio::io::Result<()> {
+    let wiki = wiki::current();
+    let Some(key) = wiki.signing_key.get() else {
+        return Ok(());
+    };
+    let sha256 = sha256(content);
+    let signature = key.sign(message(title, version, &sha256).as_bytes());
+    let signature = VersionSignature {
+        sha256,
+        public_key: STANDARD.encode(key.verifying_key().to_bytes()),
+        signature: STANDARD.encode(signature.to_bytes()),
+        title: Some(title.to_string()),
+    };
+    tokio::fs::write(
+        signature_path(title, version),
+        serde_json::to_string_pretty(&signature)?,
+    )
+    .await
+}
+
+/// Records the title the signatures in `dir` were made under, for articles
+/// moved to their slug
+pub async fn migrate(dir: &str, title: &str) -> tokio::io::Result<()> {
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry
+            .path()
+            .extension()
+            .is_none_or(|extension| extension != "sig")
+        {
+            continue;
+        }
+        let json = tokio::fs::read_to_string(entry.path()).await?;
+        let Ok(mut signature) = serde_json::from_str::<VersionSignature>(&json) else {
+            continue;
+        };
+        if signature.title.is_none() {
+            signature.title = Some(title.to_string());
+            tokio::fs::write(entry.path(), serde_json::to_string_pretty(&signature)?).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The result of checking a version against its signature
+#[derive(Serialize)]
+pub struct Verification {
+    version: String,
+    sha256: String,
+    signed: bool,
+    /// The signature matches the content
+    valid: bool,
+    /// The signature was made with this server's current key
+    trusted: bool,
+    public_key: Option<String>,
+}
+
+fn verify_signature(signature: &VersionSignature, message: &str) -> Option<()> {
+    let public_key: [u8; 32] = STANDARD
+        .decode(&signature.public_key)
+        .ok()?
+        .try_into()
+        .ok()?;
+    let bytes: [u8; 64] = STANDARD
+        .decode(&signature.signature)
+        .ok()?
+        .try_into()
+        .ok()?;
+    VerifyingKey::from_bytes(&public_key)
+        .ok()?
+        .verify(message.as_bytes(), &Signature::from_bytes(&bytes))
+        .ok()
+}
+
+pub async fn verify(
+    Path((title, version)): Path<(String, String)>,
+) -> Result<impl IntoResponse, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    // Ids are uuids or, with git storage, commit hashes, the storage checks which it knows
+    if !paths::is_file_name(&version) {
+        return Err(TomeError::NotFound);
+    }
+    let article = Article::load_version(&title, &version)
+        .await
+        .ok_or(TomeError::NotFound)?;
+
+    let sha256 = sha256(&article.content);
+    let signature: Option<VersionSignature> =
+        match tokio::fs::read_to_string(signature_path(&title, &version)).await {
+            Ok(json) => serde_json::from_str(&json).ok(),
+            Err(_) => None,
+        };
+
+    let verification = match signature {
+        Some(signature) => {
+            let signed_title = signature.title.as_deref().unwrap_or(&title);
+            let valid = signature.sha256 == sha256
+                && verify_signature(&signature, &message(signed_title, &version, &sha256))
+                    .is_some();
+            let trusted = wiki::current().signing_key.get().is_some_and(|key| {
+                STANDARD.encode(key.verifying_key().to_bytes()) == signature.public_key
+            });
+            Verification {
+                version,
+                sha256,
+                signed: true,
+                valid,
+                trusted: valid && trusted,
+                public_key: Some(signature.public_key),
+            }
+        }
+        None => Verification {
+            version,
+            sha256,
+            signed: false,
+            valid: false,
+            trusted: false,
+            public_key: None,
+        },
+    };
+    Ok(Json(verification))
+}
+
+/// The public key versions are currently signed with
+pub async fn public_key() -> Result<impl IntoResponse, TomeError> {
+    let wiki = wiki::current();
+    let key = wiki.signing_key.get().ok_or(TomeError::NotFound)?;
+    Ok(STANDARD.encode(key.verifying_key().to_bytes()))
+}
@@ -0,0 +1,96 @@
+//! # Slugs
+//!
+//! Articles are stored and linked under a slug: their title transliterated
+//! to lowercase ASCII letters and digits separated by dashes, so
+//! `Größe: Übersicht` becomes `grosse:ubersicht`. Namespace separators are
+//! kept. The title itself is kept in the article's frontmatter whenever it
+//! differs from the slug.
+use sha2::{Digest, Sha256};
+use tokio_stream::wrappers::ReadDirStream;
+use tokio_stream::StreamExt;
+
+use crate::layout::NAMESPACE_SEPARATOR;
+use crate::{frontmatter, storage};
+
+/// Longest slug in bytes, to keep paths below Windows' limit of 260 characters
+const MAX_LENGTH: usize = 100;
+
+/// The slug of `title`, slugs are their own slug
+pub fn slug(title: &str) -> String {
+    let parts: Vec<String> = title
+        .split(NAMESPACE_SEPARATOR)
+        .map(|part| {
+            let mut slug = String::new();
+            for c in deunicode::deunicode(part).chars() {
+                if c.is_ascii_alphanumeric() {
+                    slug.push(c.to_ascii_lowercase());
+                } else if !slug.is_empty() && !slug.ends_with('-') {
+                    slug.push('-');
+                }
+            }
+            slug.trim_end_matches('-').to_string()
+        })
+        .filter(|part| !part.is_empty())
+        .collect();
+    let slug = parts.join(&NAMESPACE_SEPARATOR.to_string());
+    if slug.len() <= MAX_LENGTH {
+        return slug;
+    }
+    // Long titles are cut short, with a hash to tell apart titles that start the same
+    let hash: String = Sha256::digest(slug.as_bytes())[..4]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    let start = slug[..MAX_LENGTH - hash.len() - 1].trim_end_matches(['-', NAMESPACE_SEPARATOR]);
+    format!("{start}-{hash}")
+}
+
+/// Moves articles stored under their percent-encoded title, or any other
+/// directory name older releases used, to the directory of their slug
+pub async fn migrate() -> color_eyre::Result<()> {
+    let mut entries = ReadDirStream::new(tokio::fs::read_dir(storage::articles_dir()).await?);
+    while let Some(entry) = entries.next().await {
+        let entry = entry?;
+        let Ok(name) = entry.file_name().into_string() else {
+            tracing::warn!("Ignoring {}, its name isn't UTF-8", entry.path().display());
+            continue;
+        };
+        // Like the `.git` directory of the git storage
+        if name.starts_with('.') {
+            continue;
+        }
+        if storage::slug_of_dir(&name).is_some() || !entry.file_type().await?.is_dir() {
+            continue;
+        }
+        let title = urlencoding::decode(&name)
+            .map(|title| title.into_owned())
+            .unwrap_or_else(|_| name.clone());
+        let slug = slug(&title);
+        if slug.is_empty() {
+            continue;
+        }
+        let dir = storage::article_dir(&slug);
+        if tokio::fs::metadata(&dir).await.is_ok() {
+            tracing::warn!("Can't move {name} to {slug}, an article is already stored there");
+            continue;
+        }
+        tokio::fs::rename(entry.path(), &dir).await?;
+
+        let current = format!("{dir}/current.md");
+        if let Ok(content) = tokio::fs::read_to_string(&current).await {
+            let Ok(mut meta) = frontmatter::parse(&content) else {
+                tracing::warn!("Can't add the title {title} to the invalid frontmatter of {slug}");
+                continue;
+            };
+            if frontmatter::title(&meta).is_none() && title != slug {
+                frontmatter::set_title(&mut meta, Some(&title));
+                let (_, body) = frontmatter::split(&content);
+                tokio::fs::write(&current, frontmatter::join(&meta, body)).await?;
+            }
+        }
+        crate::signature::migrate(&dir, &title).await?;
+        tracing::info!("Moved {title} to {slug}");
+    }
+    crate::rename::migrate().await?;
+    Ok(())
+}
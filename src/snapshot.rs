@@ -0,0 +1,247 @@
+//! # Snapshots
+//!
+//! `tome snapshot create <name>` records the current version of every
+//! article as a named snapshot, e.g. of the documentation at a release.
+//! Snapshots are read-only: `/snapshot/:name` lists the articles in it and
+//! `/snapshot/:name/:id` shows an article as it was, with links to other
+//! articles of the snapshot staying inside it. The versions are pinned like
+//! [permanent links](crate::permalink), so snapshots survive renaming and
+//! deleting articles. Each snapshot is stored in `snapshots/<name>.json` in
+//! the content directory.
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+use askama::Template;
+use axum::extract::Path;
+use axum::response::IntoResponse;
+use clap::{Args, Subcommand};
+use pulldown_cmark::{Event, Tag};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::config::content_path;
+use crate::error::TomeError;
+use crate::layout::Layout;
+use crate::slug::slug;
+use crate::{filters, permalink, storage, Article};
+
+const SNAPSHOTS_PATH: &str = "snapshots";
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    created: SystemTime,
+    /// Titles and versions of the articles by slug
+    articles: BTreeMap<String, (String, String)>,
+}
+
+/// Snapshot names become file names, so they are restricted like version tags
+fn is_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '.' || c == '-' || c == '_')
+}
+
+fn snapshot_path(name: &str) -> String {
+    content_path(&format!("{SNAPSHOTS_PATH}/{name}.json"))
+}
+
+async fn read(name: &str) -> Option<Snapshot> {
+    if !is_name(name) {
+        return None;
+    }
+    let json = tokio::fs::read_to_string(snapshot_path(name)).await.ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// The names of all snapshots, sorted
+async fn names() -> Vec<String> {
+    let Ok(mut dir) = tokio::fs::read_dir(content_path(SNAPSHOTS_PATH)).await else {
+        return vec![];
+    };
+    let mut names = vec![];
+    while let Ok(Some(entry)) = dir.next_entry().await {
+        if let Some(name) = entry.file_name().to_string_lossy().strip_suffix(".json") {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    names
+}
+
+fn format_time(time: SystemTime) -> String {
+    OffsetDateTime::from(time)
+        .format(&time::format_description::well_known::Rfc2822)
+        .unwrap()
+}
+
+/// Records the current version of every article as the snapshot `name`
+async fn create(name: &str) -> color_eyre::Result<usize> {
+    if !is_name(name) {
+        return Err(color_eyre::eyre::eyre!(
+            "Snapshot names can only contain letters, digits, dots, dashes and underscores"
+        ));
+    }
+    if tokio::fs::metadata(snapshot_path(name)).await.is_ok() {
+        return Err(color_eyre::eyre::eyre!(
+            "There already is a snapshot {name}"
+        ));
+    }
+
+    let mut articles = BTreeMap::new();
+    for article_slug in storage::article_slugs().await {
+        let Some(article) = Article::load(&article_slug).await else {
+            continue;
+        };
+        let Some(version) = Article::current_version(&article.title).await else {
+            tracing::warn!("{} has no saved versions, it isn't included", article.title);
+            continue;
+        };
+        permalink::load(&article.title, &version).await;
+        articles.insert(article_slug, (article.title, version));
+    }
+
+    let snapshot = Snapshot {
+        created: SystemTime::now(),
+        articles,
+    };
+    tokio::fs::create_dir_all(content_path(SNAPSHOTS_PATH)).await?;
+    tokio::fs::write(
+        snapshot_path(name),
+        serde_json::to_string_pretty(&snapshot)?,
+    )
+    .await?;
+    Ok(snapshot.articles.len())
+}
+
+/// Arguments for `tome snapshot`
+#[derive(Args)]
+pub struct SnapshotArgs {
+    #[command(subcommand)]
+    command: SnapshotCommand,
+}
+
+#[derive(Subcommand)]
+enum SnapshotCommand {
+    /// Record the current version of every article under a name
+    Create { name: String },
+    /// List all snapshots
+    List,
+}
+
+pub async fn run(args: SnapshotArgs) -> color_eyre::Result<()> {
+    match args.command {
+        SnapshotCommand::Create { name } => {
+            let count = create(&name).await?;
+            println!("Saved {count} articles as the snapshot {name}, see /snapshot/{name}.");
+        }
+        SnapshotCommand::List => {
+            for name in names().await {
+                if let Some(snapshot) = read(&name).await {
+                    println!(
+                        "{name}\t{}\t{} articles",
+                        format_time(snapshot.created),
+                        snapshot.articles.len()
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "snapshots.html")]
+struct Snapshots {
+    layout: Layout,
+    names: Vec<String>,
+}
+
+pub async fn get_snapshots(layout: Layout) -> impl IntoResponse {
+    Snapshots {
+        layout,
+        names: names().await,
+    }
+}
+
+#[derive(Template)]
+#[template(path = "snapshot.html")]
+struct SnapshotPage {
+    layout: Layout,
+    name: String,
+    created: String,
+    /// Slugs and titles, sorted by title
+    articles: Vec<(String, String)>,
+}
+
+pub async fn get_snapshot(
+    layout: Layout,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, TomeError> {
+    let snapshot = read(&name).await.ok_or(TomeError::NotFound)?;
+    let mut articles: Vec<(String, String)> = snapshot
+        .articles
+        .into_iter()
+        .map(|(slug, (title, _))| (slug, title))
+        .collect();
+    articles.sort_by_key(|(_, title)| title.to_lowercase());
+    Ok(SnapshotPage {
+        layout,
+        name,
+        created: format_time(snapshot.created),
+        articles,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "snapshot_article.html")]
+struct SnapshotArticle {
+    layout: Layout,
+    name: String,
+    title: String,
+    path: String,
+    html: String,
+}
+
+pub async fn get_snapshot_article(
+    layout: Layout,
+    Path((name, title)): Path<(String, String)>,
+) -> Result<impl IntoResponse, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let snapshot = read(&name).await.ok_or(TomeError::NotFound)?;
+    let path = slug(&title);
+    let (title, version) = snapshot.articles.get(&path).ok_or(TomeError::NotFound)?;
+    let article = permalink::load(title, version)
+        .await
+        .ok_or(TomeError::NotFound)?;
+
+    let html = filters::render(article.body(), |event| match event {
+        Event::Start(Tag::Link(link_type, dest, link_title)) => {
+            let dest = match dest.strip_prefix("/article/") {
+                Some(linked) => {
+                    let (linked, fragment) = match linked.split_once('#') {
+                        Some((linked, fragment)) => (linked, format!("#{fragment}")),
+                        None => (linked, String::new()),
+                    };
+                    let linked = slug(&urlencoding::decode(linked).unwrap_or(linked.into()));
+                    if snapshot.articles.contains_key(&linked) {
+                        format!("/snapshot/{name}/{linked}{fragment}").into()
+                    } else {
+                        dest
+                    }
+                }
+                None => dest,
+            };
+            Event::Start(Tag::Link(link_type, dest, link_title))
+        }
+        _ => event,
+    });
+    Ok(SnapshotArticle {
+        layout: layout.with_direction_of(&article.content),
+        title: article.title.clone(),
+        path,
+        name,
+        html,
+    })
+}
@@ -0,0 +1,161 @@
+//! # Stale Articles
+//!
+//! Articles can name a date by which they should be reviewed again with
+//! `review_by: YYYY-MM-DD` in their frontmatter. Overdue articles show a
+//! banner, and a daily check collects them for `/stale`. If `stale_command`
+//! is configured, it is run once per overdue article on every check with
+//! the title and date as arguments and the article's `watchers:` in
+//! `TOME_WATCHERS`, e.g. to send an email.
+use std::sync::Arc;
+use std::time::Duration;
+
+use askama::Template;
+use askama_axum::IntoResponse;
+use axum::extract::State;
+use serde_yaml::Value;
+use time::macros::format_description;
+use time::{Date, OffsetDateTime};
+use tokio::sync::RwLock;
+
+use crate::layout::Layout;
+use crate::{frontmatter, wiki, Article, Overview, TomeConfig};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The `review_by:` date of an article
+pub fn review_by(content: &str) -> Option<Date> {
+    let meta = frontmatter::parse(content).unwrap_or_default();
+    let date = meta.get(&Value::from("review_by"))?.as_str()?;
+    Date::parse(date, format_description!("[year]-[month]-[day]")).ok()
+}
+
+pub fn is_overdue(content: &str) -> bool {
+    review_by(content).is_some_and(|date| date < OffsetDateTime::now_utc().date())
+}
+
+fn watchers(content: &str) -> Vec<String> {
+    match frontmatter::parse(content)
+        .unwrap_or_default()
+        .get(&Value::from("watchers"))
+    {
+        Some(Value::Sequence(watchers)) => watchers
+            .iter()
+            .filter_map(|watcher| watcher.as_str().map(str::to_string))
+            .collect(),
+        Some(Value::String(watcher)) => vec![watcher.clone()],
+        _ => vec![],
+    }
+}
+
+#[derive(Clone)]
+struct StaleArticle {
+    path: String,
+    title: String,
+    review_by: Date,
+}
+
+#[derive(Default)]
+struct Inner {
+    articles: Vec<StaleArticle>,
+    checked: Option<OffsetDateTime>,
+}
+
+/// The overdue articles found by the last check
+#[derive(Clone, Default)]
+pub struct Stale {
+    inner: Arc<RwLock<Inner>>,
+}
+
+impl Stale {
+    /// Starts the daily check for overdue articles
+    pub fn start(config: &TomeConfig) -> Self {
+        let stale = Stale::default();
+        let background = stale.clone();
+        let command = config.stale_command.clone();
+        wiki::spawn(async move {
+            let mut interval = tokio::time::interval(CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                background.check(command.as_deref()).await;
+            }
+        });
+        stale
+    }
+
+    async fn check(&self, command: Option<&str>) {
+        let mut articles = vec![];
+        for (path, title) in Overview::load().await.articles {
+            let Some(article) = Article::load(&title).await else {
+                continue;
+            };
+            if !is_overdue(&article.content) {
+                continue;
+            }
+            let review_by = review_by(&article.content).unwrap();
+            if let Some(command) = command {
+                notify(command, &title, review_by, &watchers(&article.content)).await;
+            }
+            articles.push(StaleArticle {
+                path,
+                title,
+                review_by,
+            });
+        }
+        articles.sort_by_key(|article| article.review_by);
+
+        let mut inner = self.inner.write().await;
+        inner.articles = articles;
+        inner.checked = Some(OffsetDateTime::now_utc());
+    }
+}
+
+async fn notify(command: &str, title: &str, review_by: Date, watchers: &[String]) {
+    let status = tokio::process::Command::new(command)
+        .arg(title)
+        .arg(review_by.to_string())
+        .env("TOME_WATCHERS", watchers.join(","))
+        .status()
+        .await;
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => tracing::warn!("stale_command failed for {title}: {status}"),
+        Err(e) => tracing::warn!("Could not run stale_command for {title}: {e}"),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "stale.html")]
+pub struct StaleReport {
+    layout: Layout,
+    checked: String,
+    /// Article path, title, review date and days overdue
+    articles: Vec<(String, String, String, i64)>,
+}
+
+pub async fn get_stale(layout: Layout, State(stale): State<Stale>) -> impl IntoResponse {
+    let inner = stale.inner.read().await;
+    let today = OffsetDateTime::now_utc().date();
+    StaleReport {
+        layout,
+        checked: inner
+            .checked
+            .map(|checked| {
+                checked
+                    .format(&time::format_description::well_known::Rfc2822)
+                    .unwrap()
+            })
+            .unwrap_or_default(),
+        articles: inner
+            .articles
+            .iter()
+            .map(|article| {
+                (
+                    article.path.clone(),
+                    article.title.clone(),
+                    article.review_by.to_string(),
+                    (today - article.review_by).whole_days(),
+                )
+            })
+            .collect(),
+    }
+}
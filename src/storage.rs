@@ -0,0 +1,606 @@
+//! # Version Storage
+//!
+//! Article versions are stored by the SHA-256 hash of their content in
+//! `objects/` of the content directory, so saving the same content again (in any article)
+//! doesn't take up more space. Every object has a `.refs` file counting
+//! the versions using it. Each article lists its versions in a
+//! `versions.json` file, while `current.md` stays a plain copy of the
+//! latest version. Objects are compressed with zstd. A new version of an
+//! article is usually stored as a line diff against the previous version
+//! (a `.delta` object), with a full copy every `SNAPSHOT_INTERVAL` versions
+//! so reading a version never has to apply too many diffs. Versions saved as
+//! `<id>.md` files by older releases are still read, and
+//! `tome storage compact` moves them (and any uncompressed objects)
+//! into compressed objects.
+//!
+//! Articles are stored in directories named after their slug, with
+//! namespace separators replaced by dots and Windows device names like
+//! `con` followed by an underscore, so every slug is a valid file name on
+//! all platforms.
+//!
+//! With `git_storage`, versions are commits in a git repository instead,
+//! see [`crate::git`].
+//!
+//! Deleted articles are moved to `trash/` until they are restored
+//! or purged. Purging removes the references of the article's versions,
+//! and objects nothing refers to anymore are deleted.
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use clap::{Args, Subcommand};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+use tokio::sync::Mutex;
+use tokio_stream::wrappers::ReadDirStream;
+use tokio_stream::StreamExt;
+
+use crate::config::content_path;
+use crate::git::Git;
+use crate::layout::NAMESPACE_SEPARATOR;
+use crate::slug::slug;
+use crate::{paths, wiki, TomeConfig};
+
+const ARTICLES_PATH: &str = "articles";
+const OBJECTS_PATH: &str = "objects";
+const TRASH_PATH: &str = "trash";
+/// File names Windows reserves for devices, even with an extension
+const RESERVED_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+const COMPRESSION_LEVEL: i32 = 3;
+/// The longest chain of deltas before a full copy is stored again
+const SNAPSHOT_INTERVAL: usize = 16;
+
+/// Where the versions of articles are kept
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Stores a new version of an article and returns its id
+    async fn save(&self, title: &str, content: &str) -> tokio::io::Result<String>;
+    /// The content of a version
+    async fn load(&self, title: &str, version: &str) -> Option<String>;
+    /// The ids of all versions of an article and when they were saved, oldest first
+    async fn versions(&self, title: &str) -> Vec<(String, SystemTime)>;
+    /// Moves an article and all of its versions to a new title
+    async fn rename(&self, from: &str, to: &str) -> tokio::io::Result<()>;
+    /// Moves an article into the trash as `id`
+    async fn trash(&self, title: &str, id: &str) -> tokio::io::Result<()>;
+    /// Moves the trashed article `id` back to `title`
+    async fn restore(&self, id: &str, title: &str) -> tokio::io::Result<()>;
+    /// Deletes the trashed article `id` and all of its versions for good
+    async fn purge(&self, id: &str) -> tokio::io::Result<()>;
+}
+
+/// Chooses the storage for `config`, before anything is stored
+pub async fn init(config: &TomeConfig) -> tokio::io::Result<()> {
+    let storage: Arc<dyn Storage> = if config.git_storage {
+        Arc::new(Git::open(config.git_remote.clone()).await?)
+    } else {
+        Arc::new(ContentAddressed)
+    };
+    let _ = wiki::current().storage.set(storage);
+    Ok(())
+}
+
+/// The storage used for all articles of the current wiki
+pub fn storage() -> Arc<dyn Storage> {
+    match wiki::current().storage.get() {
+        Some(storage) => storage.clone(),
+        None => Arc::new(ContentAddressed),
+    }
+}
+
+/// An entry of an article's `versions.json`
+#[derive(Serialize, Deserialize, Clone)]
+struct Version {
+    id: String,
+    hash: String,
+    saved: SystemTime,
+}
+
+/// Guards `.refs` files and version indices against concurrent updates
+static LOCK: Mutex<()> = Mutex::const_new(());
+
+pub struct ContentAddressed;
+
+fn hash(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn object_path(hash: &str) -> String {
+    format!("{}/{}/{}", objects_dir(), &hash[..2], &hash[2..])
+}
+
+/// The directory all articles are stored in
+pub fn articles_dir() -> String {
+    content_path(ARTICLES_PATH)
+}
+
+fn objects_dir() -> String {
+    content_path(OBJECTS_PATH)
+}
+
+fn trash_dir() -> String {
+    content_path(TRASH_PATH)
+}
+
+/// The name of the directory the article with `slug` is stored in
+pub fn dir_name(slug: &str) -> String {
+    slug.split(NAMESPACE_SEPARATOR)
+        .map(|part| {
+            if RESERVED_NAMES.contains(&part) {
+                format!("{part}_")
+            } else {
+                part.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// The slug of the article stored in the directory `name`, if it is a slug's directory
+pub fn slug_of_dir(name: &str) -> Option<String> {
+    let slug = name
+        .split('.')
+        .map(|part| part.strip_suffix('_').unwrap_or(part))
+        .collect::<Vec<_>>()
+        .join(&NAMESPACE_SEPARATOR.to_string());
+    (slug == crate::slug::slug(&slug) && dir_name(&slug) == name).then_some(slug)
+}
+
+/// The directory the article `title` is stored in
+pub fn article_dir(title: &str) -> String {
+    format!("{}/{}", articles_dir(), dir_name(&slug(title)))
+}
+
+/// The slugs of all stored articles
+pub async fn article_slugs() -> Vec<String> {
+    let Ok(dir) = tokio::fs::read_dir(articles_dir()).await else {
+        return vec![];
+    };
+    let mut entries = ReadDirStream::new(dir);
+    let mut slugs = vec![];
+    while let Some(Ok(entry)) = entries.next().await {
+        // Like the `.git` directory of the git storage
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if !entry
+            .file_type()
+            .await
+            .is_ok_and(|file_type| file_type.is_dir())
+        {
+            continue;
+        }
+        match entry.file_name().to_str().and_then(slug_of_dir) {
+            Some(slug) => slugs.push(slug),
+            None => tracing::warn!("Ignoring {}, it isn't an article", entry.path().display()),
+        }
+    }
+    slugs
+}
+
+fn index_path(title: &str) -> String {
+    format!("{}/versions.json", article_dir(title))
+}
+
+/// Reads the version index at `path`, which is empty if there is none yet
+///
+/// An index that doesn't parse is an error, writing an empty one back would lose every version.
+async fn read_index_at(path: &str) -> tokio::io::Result<Vec<Version>> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(json) => serde_json::from_str(&json).map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{path}: {e}"))
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e),
+    }
+}
+
+async fn read_index(title: &str) -> tokio::io::Result<Vec<Version>> {
+    read_index_at(&index_path(title)).await
+}
+
+/// A version stored as the changes to another object
+#[derive(Serialize, Deserialize)]
+struct Delta {
+    base: String,
+    /// The number of deltas that have to be applied to get this version
+    depth: usize,
+    ops: Vec<DeltaOp>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum DeltaOp {
+    /// Lines from the base
+    Copy(usize, usize),
+    Insert(String),
+}
+
+impl Delta {
+    fn new(base: &str, base_content: &str, base_depth: usize, content: &str) -> Self {
+        let old: Vec<&str> = base_content.split_inclusive('\n').collect();
+        let new: Vec<&str> = content.split_inclusive('\n').collect();
+        let ops = capture_diff_slices(Algorithm::Myers, &old, &new)
+            .into_iter()
+            .filter_map(|op| match op {
+                DiffOp::Equal { old_index, len, .. } => Some(DeltaOp::Copy(old_index, len)),
+                DiffOp::Delete { .. } => None,
+                DiffOp::Insert {
+                    new_index, new_len, ..
+                }
+                | DiffOp::Replace {
+                    new_index, new_len, ..
+                } => Some(DeltaOp::Insert(
+                    new[new_index..new_index + new_len].concat(),
+                )),
+            })
+            .collect();
+        Delta {
+            base: base.to_string(),
+            depth: base_depth + 1,
+            ops,
+        }
+    }
+
+    fn apply(&self, base_content: &str) -> Option<String> {
+        let lines: Vec<&str> = base_content.split_inclusive('\n').collect();
+        let mut content = String::new();
+        for op in &self.ops {
+            match op {
+                DeltaOp::Copy(start, len) => {
+                    content.push_str(&lines.get(*start..start + len)?.concat())
+                }
+                DeltaOp::Insert(text) => content.push_str(text),
+            }
+        }
+        Some(content)
+    }
+}
+
+enum Object {
+    Full(String),
+    Delta(Delta),
+}
+
+async fn read_stored_object(hash: &str) -> Option<Object> {
+    let path = object_path(hash);
+    if let Ok(compressed) = tokio::fs::read(format!("{path}.delta")).await {
+        let json = zstd::decode_all(compressed.as_slice()).ok()?;
+        return serde_json::from_slice(&json).ok().map(Object::Delta);
+    }
+    let content = match tokio::fs::read(format!("{path}.zst")).await {
+        Ok(compressed) => zstd::decode_all(compressed.as_slice()).ok()?,
+        Err(_) => tokio::fs::read(&path).await.ok()?,
+    };
+    String::from_utf8(content).ok().map(Object::Full)
+}
+
+async fn read_object(hash: &str) -> Option<String> {
+    let mut deltas = vec![];
+    let mut hash = hash.to_string();
+    let mut content = loop {
+        match read_stored_object(&hash).await? {
+            Object::Full(content) => break content,
+            Object::Delta(delta) => {
+                hash = delta.base.clone();
+                deltas.push(delta);
+            }
+        }
+    };
+    for delta in deltas.iter().rev() {
+        content = delta.apply(&content)?;
+    }
+    Some(content)
+}
+
+/// How many deltas have to be applied to read an object
+async fn depth(hash: &str) -> Option<usize> {
+    match read_stored_object(hash).await? {
+        Object::Full(_) => Some(0),
+        Object::Delta(delta) => Some(delta.depth),
+    }
+}
+
+async fn object_exists(hash: &str) -> bool {
+    let path = object_path(hash);
+    for path in [format!("{path}.zst"), format!("{path}.delta"), path] {
+        if tokio::fs::metadata(path).await.is_ok() {
+            return true;
+        }
+    }
+    false
+}
+
+async fn write_object(hash: &str, content: &str) -> tokio::io::Result<()> {
+    tokio::fs::create_dir_all(format!("{}/{}", objects_dir(), &hash[..2])).await?;
+    let compressed = zstd::encode_all(content.as_bytes(), COMPRESSION_LEVEL)?;
+    tokio::fs::write(format!("{}.zst", object_path(hash)), compressed).await
+}
+
+/// Stores `content` as a delta against `base` if that is smaller than a full copy
+async fn write_delta(hash: &str, content: &str, base: &str) -> tokio::io::Result<bool> {
+    let Some(base_depth) = depth(base).await else {
+        return Ok(false);
+    };
+    if base_depth + 1 >= SNAPSHOT_INTERVAL {
+        return Ok(false);
+    }
+    let Some(base_content) = read_object(base).await else {
+        return Ok(false);
+    };
+    let delta = Delta::new(base, &base_content, base_depth, content);
+    if delta.apply(&base_content).as_deref() != Some(content) {
+        return Ok(false);
+    }
+
+    let compressed = zstd::encode_all(serde_json::to_vec(&delta)?.as_slice(), COMPRESSION_LEVEL)?;
+    let full = zstd::encode_all(content.as_bytes(), COMPRESSION_LEVEL)?;
+    if compressed.len() >= full.len() {
+        return Ok(false);
+    }
+    tokio::fs::create_dir_all(format!("{}/{}", objects_dir(), &hash[..2])).await?;
+    tokio::fs::write(format!("{}.delta", object_path(hash)), compressed).await?;
+    // The base must be kept as long as this delta exists
+    add_reference(base).await?;
+    Ok(true)
+}
+
+async fn add_reference(hash: &str) -> tokio::io::Result<()> {
+    let refs_path = format!("{}.refs", object_path(hash));
+    let refs: u64 = match tokio::fs::read_to_string(&refs_path).await {
+        Ok(refs) => refs.trim().parse().unwrap_or(0),
+        Err(_) => 0,
+    };
+    tokio::fs::write(&refs_path, (refs + 1).to_string()).await
+}
+
+/// Removes a reference to an object, deleting it once nothing refers to it anymore
+async fn remove_reference(hash: &str) -> tokio::io::Result<()> {
+    let mut hash = hash.to_string();
+    loop {
+        let path = object_path(&hash);
+        let refs_path = format!("{path}.refs");
+        let refs: u64 = match tokio::fs::read_to_string(&refs_path).await {
+            Ok(refs) => refs.trim().parse().unwrap_or(0),
+            Err(_) => 0,
+        };
+        if refs > 1 {
+            return tokio::fs::write(&refs_path, (refs - 1).to_string()).await;
+        }
+
+        // A delta refers to its base, which may not be needed anymore either
+        let base = match read_stored_object(&hash).await {
+            Some(Object::Delta(delta)) => Some(delta.base),
+            _ => None,
+        };
+        for path in [
+            format!("{path}.zst"),
+            format!("{path}.delta"),
+            path,
+            refs_path,
+        ] {
+            if tokio::fs::metadata(&path).await.is_ok() {
+                tokio::fs::remove_file(path).await?;
+            }
+        }
+        match base {
+            Some(base) => hash = base,
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Stores `content` if it isn't stored already and counts another reference to it.
+/// New content is stored as a delta against `parent` where possible.
+async fn add_object(content: &str, parent: Option<&str>) -> tokio::io::Result<String> {
+    let hash = hash(content);
+    if !object_exists(&hash).await {
+        let stored_as_delta = match parent {
+            Some(parent) => write_delta(&hash, content, parent).await?,
+            None => false,
+        };
+        if !stored_as_delta {
+            write_object(&hash, content).await?;
+        }
+    }
+    add_reference(&hash).await?;
+    Ok(hash)
+}
+
+/// Versions saved as separate files before versions were content-addressed
+async fn legacy_versions(title: &str) -> Vec<(String, SystemTime)> {
+    let Ok(dir) = tokio::fs::read_dir(article_dir(title)).await else {
+        return vec![];
+    };
+    let mut entries = ReadDirStream::new(dir);
+    let mut versions = vec![];
+    while let Some(Ok(entry)) = entries.next().await {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let Some(version) = file_name.strip_suffix(".md") else {
+            continue;
+        };
+        if version == "current" {
+            continue;
+        }
+        // Not every filesystem records modification times
+        let edited = entry
+            .metadata()
+            .await
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        versions.push((version.to_string(), edited));
+    }
+    versions
+}
+
+#[async_trait]
+impl Storage for ContentAddressed {
+    async fn save(&self, title: &str, content: &str) -> tokio::io::Result<String> {
+        let _lock = LOCK.lock().await;
+        let mut index = read_index(title).await?;
+        let parent = index.last().map(|version| version.hash.clone());
+        let version = Version {
+            id: uuid::Uuid::new_v4().hyphenated().to_string(),
+            hash: add_object(content, parent.as_deref()).await?,
+            saved: SystemTime::now(),
+        };
+        index.push(version.clone());
+        tokio::fs::write(index_path(title), serde_json::to_string_pretty(&index)?).await?;
+        Ok(version.id)
+    }
+
+    async fn load(&self, title: &str, version: &str) -> Option<String> {
+        let index = match read_index(title).await {
+            Ok(index) => index,
+            Err(e) => {
+                tracing::error!("Can't read the versions of {title}: {e}");
+                return None;
+            }
+        };
+        match index.iter().find(|v| v.id == version) {
+            Some(version) => read_object(&version.hash).await,
+            // Versions of older releases are files named after their id
+            None if paths::is_file_name(version) => {
+                tokio::fs::read_to_string(format!("{}/{version}.md", article_dir(title)))
+                    .await
+                    .ok()
+            }
+            None => None,
+        }
+    }
+
+    async fn versions(&self, title: &str) -> Vec<(String, SystemTime)> {
+        let mut versions = legacy_versions(title).await;
+        match read_index(title).await {
+            Ok(index) => {
+                versions.extend(index.into_iter().map(|version| (version.id, version.saved)))
+            }
+            Err(e) => tracing::error!("Can't read the versions of {title}: {e}"),
+        }
+        versions
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> tokio::io::Result<()> {
+        let _lock = LOCK.lock().await;
+        tokio::fs::rename(article_dir(from), article_dir(to)).await
+    }
+
+    async fn trash(&self, title: &str, id: &str) -> tokio::io::Result<()> {
+        let _lock = LOCK.lock().await;
+        tokio::fs::create_dir_all(trash_dir()).await?;
+        tokio::fs::rename(article_dir(title), format!("{}/{id}", trash_dir())).await
+    }
+
+    async fn restore(&self, id: &str, title: &str) -> tokio::io::Result<()> {
+        let _lock = LOCK.lock().await;
+        tokio::fs::rename(format!("{}/{id}", trash_dir()), article_dir(title)).await
+    }
+
+    async fn purge(&self, id: &str) -> tokio::io::Result<()> {
+        let _lock = LOCK.lock().await;
+        let dir = format!("{}/{id}", trash_dir());
+        for version in read_index_at(&format!("{dir}/versions.json")).await? {
+            remove_reference(&version.hash).await?;
+        }
+        tokio::fs::remove_dir_all(dir).await
+    }
+}
+
+/// Arguments for `tome storage`
+#[derive(Args)]
+pub struct StorageArgs {
+    #[command(subcommand)]
+    command: StorageCommand,
+}
+
+#[derive(Subcommand)]
+enum StorageCommand {
+    /// Compress uncompressed objects and move old version files into objects
+    Compact,
+}
+
+/// Compresses every object that is still stored as plain text
+async fn compress_objects() -> color_eyre::Result<usize> {
+    let mut count = 0;
+    let Ok(dirs) = tokio::fs::read_dir(objects_dir()).await else {
+        return Ok(count);
+    };
+    let mut dirs = ReadDirStream::new(dirs);
+    while let Some(dir) = dirs.next().await {
+        let dir = dir?;
+        if !dir.file_type().await?.is_dir() {
+            continue;
+        }
+        let prefix = dir.file_name().to_string_lossy().into_owned();
+        let mut objects = ReadDirStream::new(tokio::fs::read_dir(dir.path()).await?);
+        while let Some(object) = objects.next().await {
+            let object = object?;
+            let name = object.file_name().to_string_lossy().into_owned();
+            if name.contains('.') {
+                continue;
+            }
+            let content = tokio::fs::read_to_string(object.path()).await?;
+            write_object(&format!("{prefix}{name}"), &content).await?;
+            tokio::fs::remove_file(object.path()).await?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Moves the `<id>.md` version files of an article into objects
+async fn migrate_legacy_versions(title: &str) -> color_eyre::Result<usize> {
+    let mut legacy = legacy_versions(title).await;
+    if legacy.is_empty() {
+        return Ok(0);
+    }
+    let dir = article_dir(title);
+    let _lock = LOCK.lock().await;
+    legacy.sort_by_key(|(_, saved)| *saved);
+    let mut index = read_index(title).await?;
+    let mut parent = None;
+    for (id, saved) in &legacy {
+        let content = tokio::fs::read_to_string(format!("{dir}/{id}.md")).await?;
+        let hash = add_object(&content, parent.as_deref()).await?;
+        index.push(Version {
+            id: id.clone(),
+            hash: hash.clone(),
+            saved: *saved,
+        });
+        parent = Some(hash);
+    }
+    index.sort_by_key(|version| version.saved);
+    tokio::fs::write(index_path(title), serde_json::to_string_pretty(&index)?).await?;
+    for (id, _) in &legacy {
+        tokio::fs::remove_file(format!("{dir}/{id}.md")).await?;
+    }
+    Ok(legacy.len())
+}
+
+/// Moves the `<id>.md` version files of every article into objects
+pub async fn move_legacy_versions() -> color_eyre::Result<usize> {
+    let mut migrated = 0;
+    for (_, title) in crate::Overview::load().await.articles {
+        migrated += migrate_legacy_versions(&title).await?;
+    }
+    Ok(migrated)
+}
+
+pub async fn run(args: StorageArgs) -> color_eyre::Result<()> {
+    match args.command {
+        StorageCommand::Compact => {
+            let migrated = move_legacy_versions().await?;
+            let compressed = compress_objects().await?;
+            println!(
+                "Moved {migrated} version files into storage and compressed {compressed} objects."
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,74 @@
+//! # Tags
+//!
+//! Articles are tagged with a `tags:` list in their frontmatter. The tags
+//! are shown on the article, `/tags` lists every tag with the number of
+//! articles that have it and `/tags/:tag` those articles. Tags are matched
+//! regardless of case. The retagging page at `/admin/retag` changes
+//! the tags of many articles at once.
+use std::collections::BTreeMap;
+
+use askama::Template;
+use axum::extract::Path;
+use axum::response::IntoResponse;
+
+use crate::error::TomeError;
+use crate::layout::Layout;
+use crate::{storage, Article};
+
+#[derive(Template)]
+#[template(path = "tags.html")]
+struct Tags {
+    layout: Layout,
+    /// Every tag and how many articles have it, sorted by tag
+    tags: Vec<(String, usize)>,
+}
+
+pub async fn get_tags(layout: Layout) -> impl IntoResponse {
+    // The spelling of a tag that was seen first is shown
+    let mut tags: BTreeMap<String, (String, usize)> = BTreeMap::new();
+    for slug in storage::article_slugs().await {
+        if let Some(article) = Article::load(&slug).await {
+            for tag in article.tags() {
+                tags.entry(tag.to_lowercase()).or_insert((tag, 0)).1 += 1;
+            }
+        }
+    }
+    Tags {
+        layout,
+        tags: tags.into_values().collect(),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "tag.html")]
+struct Tag {
+    layout: Layout,
+    tag: String,
+    /// Slugs and titles of the tagged articles, sorted by title
+    articles: Vec<(String, String)>,
+}
+
+pub async fn get_tag(
+    layout: Layout,
+    Path(tag): Path<String>,
+) -> Result<impl IntoResponse, TomeError> {
+    let tag = urlencoding::decode(&tag)?.into_owned();
+    let mut articles = vec![];
+    for slug in storage::article_slugs().await {
+        if let Some(article) = Article::load(&slug).await {
+            if article
+                .tags()
+                .iter()
+                .any(|t| t.to_lowercase() == tag.to_lowercase())
+            {
+                articles.push((slug, article.title));
+            }
+        }
+    }
+    articles.sort_by_key(|(_, title)| title.to_lowercase());
+    Ok(Tag {
+        layout,
+        tag,
+        articles,
+    })
+}
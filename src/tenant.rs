@@ -0,0 +1,386 @@
+//! # Tenants
+//!
+//! One tome can host wikis for friends or small communities. With
+//! `tenants_dir` and `tenant_domain`, every directory in `tenants_dir` is a
+//! tenant: the directory `friends` is served to requests for
+//! `friends.<tenant_domain>`, every other host gets the main wiki. A tenant
+//! keeps its own `tome.toml`, `users.toml` and `content` directory in its
+//! directory, so it shares neither articles, media, users nor configuration
+//! with the others. Its `tome.toml` can set any option except where its
+//! content and users are stored, and its `storage_quota` limits the room it
+//! takes up.
+//!
+//! The administrator of the main wiki (`admin_user`) manages the tenants on
+//! `/admin/tenants`, which lists their users and the room they take up,
+//! creates tenants with their own administrator and changes their quotas
+//! while tome runs. Further users of a tenant are added with
+//! `tome --users-file <tenants_dir>/<name>/users.toml user add`.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use askama::Template;
+use axum::body::Body;
+use axum::extract::{Path as UrlPath, State};
+use axum::http::{header, Request, StatusCode};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::{Form, Router};
+use hyper::service::Service;
+use serde::Deserialize;
+use tokio::sync::{mpsc, oneshot, RwLock};
+
+use crate::error::TomeError;
+use crate::file_info::format_size;
+use crate::layout::Layout;
+use crate::setup::MIN_PASSWORD_LENGTH;
+use crate::{auth, config, quota, Invalid, TomeConfig, Wiki};
+
+/// The longest name of a tenant, which is a label of its domain
+const MAX_NAME_LENGTH: usize = 63;
+const MIB: u64 = 1024 * 1024;
+
+/// A wiki hosted for a tenant
+struct Tenant {
+    wiki: Wiki,
+    /// Locked since routers can't be shared between threads, only sent
+    router: Mutex<Router>,
+    config: TomeConfig,
+}
+
+/// Asks for the tenant in a directory to be opened
+type Opening = (PathBuf, oneshot::Sender<color_eyre::Result<Tenant>>);
+
+/// Where tenants are stored and their domain
+struct Hosting {
+    dir: PathBuf,
+    domain: String,
+    /// Opens new tenants, in a task of its own since their routes include
+    /// the handler creating them
+    opener: mpsc::Sender<Opening>,
+}
+
+/// The tenants of the main wiki, empty without `tenants_dir`
+#[derive(Clone, Default)]
+pub struct Tenants {
+    hosting: Option<Arc<Hosting>>,
+    hosted: Arc<RwLock<BTreeMap<String, Tenant>>>,
+}
+
+/// Whether `name` can name a tenant, as a label of its domain
+fn is_name(name: &str) -> bool {
+    (1..=MAX_NAME_LENGTH).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+/// The configuration of the tenant in `dir`, which stores everything in it
+fn tenant_config(dir: &Path) -> color_eyre::Result<TomeConfig> {
+    let mut config = config::load(&dir.join(config::CONFIG_PATH), TomeConfig::default())?;
+    config.content_dir = Some(dir.join("content").to_string_lossy().into_owned());
+    config.users_file = Some(dir.join("users.toml").to_string_lossy().into_owned());
+    config.tenants_dir = None;
+    config.tenant_domain = None;
+    Ok(config)
+}
+
+/// Opens the wiki of the tenant in `dir`
+async fn host(dir: &Path) -> color_eyre::Result<Tenant> {
+    let config = tenant_config(dir)?;
+    let wiki = Wiki::open(&config).await?;
+    let router = wiki
+        .run(crate::routes(
+            config.clone(),
+            wiki.clone(),
+            Tenants::default(),
+        ))
+        .await?;
+    Ok(Tenant {
+        wiki,
+        router: Mutex::new(router),
+        config,
+    })
+}
+
+impl Tenants {
+    /// Opens every tenant in the `tenants_dir` of `config`
+    pub async fn open(config: &TomeConfig) -> color_eyre::Result<Self> {
+        let (Some(dir), Some(domain)) = (&config.tenants_dir, &config.tenant_domain) else {
+            return Ok(Tenants::default());
+        };
+        let dir = PathBuf::from(dir);
+        tokio::fs::create_dir_all(&dir).await?;
+        let mut hosted = BTreeMap::new();
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !entry.file_type().await?.is_dir() {
+                continue;
+            }
+            if !is_name(&name) {
+                tracing::warn!(
+                    "{} can't be a tenant, as {name} can't be part of a domain",
+                    entry.path().display()
+                );
+                continue;
+            }
+            let tenant = host(&entry.path())
+                .await
+                .map_err(|e| color_eyre::eyre::eyre!("The tenant {name} can't start: {e}"))?;
+            hosted.insert(name, tenant);
+        }
+        tracing::info!("Hosting {} tenants at *.{domain}", hosted.len());
+        let (opener, mut openings) = mpsc::channel::<Opening>(1);
+        tokio::spawn(async move {
+            while let Some((dir, opened)) = openings.recv().await {
+                let _ = opened.send(host(&dir).await);
+            }
+        });
+        Ok(Tenants {
+            hosting: Some(Arc::new(Hosting {
+                dir,
+                domain: domain.to_ascii_lowercase(),
+                opener,
+            })),
+            hosted: Arc::new(RwLock::new(hosted)),
+        })
+    }
+
+    /// `router` of the main wiki, passing the requests for tenants on to them
+    pub fn serve(&self, router: Router) -> Router {
+        match self.hosting {
+            Some(_) => Router::new().fallback(dispatch).with_state(Dispatch {
+                tenants: self.clone(),
+                main: Arc::new(Mutex::new(router)),
+            }),
+            None => router,
+        }
+    }
+
+    /// The tenant a request for `host` is for, if it is for one
+    fn name_for(&self, host: &str) -> Option<String> {
+        let hosting = self.hosting.as_ref()?;
+        let host = host.split(':').next()?.to_ascii_lowercase();
+        let name = host.strip_suffix(&hosting.domain)?.strip_suffix('.')?;
+        is_name(name).then(|| name.to_string())
+    }
+}
+
+/// The routers requests are passed on to
+#[derive(Clone)]
+struct Dispatch {
+    tenants: Tenants,
+    main: Arc<Mutex<Router>>,
+}
+
+/// Answers every request with the wiki of the tenant it is for, or the main wiki
+///
+/// This is the fallback of a router without routes, so the routes of the
+/// main wiki don't match a request before it is passed on to a tenant.
+async fn dispatch(
+    State(Dispatch { tenants, main }): State<Dispatch>,
+    request: Request<Body>,
+) -> Response {
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| request.uri().host());
+    let mut router = match host.and_then(|host| tenants.name_for(host)) {
+        Some(name) => match tenants.hosted.read().await.get(&name) {
+            Some(tenant) => tenant.router.lock().unwrap().clone(),
+            None => return StatusCode::NOT_FOUND.into_response(),
+        },
+        None => main.lock().unwrap().clone(),
+    };
+    match router.call(request).await {
+        Ok(response) => response,
+        Err(infallible) => match infallible {},
+    }
+}
+
+/// Turns away everyone but the administrator of the main wiki
+fn ensure_admin(config: &TomeConfig, layout: &Layout) -> Result<(), TomeError> {
+    if layout.user.is_some() && layout.user == config.admin_user {
+        Ok(())
+    } else {
+        Err(TomeError::Forbidden(
+            "Only the administrator can manage tenants.".to_string(),
+        ))
+    }
+}
+
+/// A tenant as listed on `/admin/tenants`
+struct Listed {
+    name: String,
+    host: String,
+    users: usize,
+    used: String,
+    /// The quota in MiB, if there is one
+    quota: Option<u64>,
+}
+
+#[derive(Template)]
+#[template(path = "tenants.html")]
+struct TenantsPage {
+    layout: Layout,
+    domain: String,
+    tenants: Vec<Listed>,
+}
+
+pub async fn get_tenants(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    State(tenants): State<Tenants>,
+) -> Result<impl IntoResponse, TomeError> {
+    ensure_admin(&config, &layout)?;
+    let Some(hosting) = &tenants.hosting else {
+        return Err(TomeError::NotFound);
+    };
+    let mut listed = vec![];
+    for (name, tenant) in tenants.hosted.read().await.iter() {
+        let users = auth::read_users(auth::users_path(&tenant.config)).await?;
+        let admin = tenant
+            .config
+            .admin_user
+            .as_ref()
+            .filter(|admin| !users.contains_key(*admin));
+        listed.push(Listed {
+            name: name.clone(),
+            host: format!("{name}.{}", hosting.domain),
+            users: users.len() + usize::from(admin.is_some()),
+            used: format_size(quota::used(tenant.wiki.content_dir()).await),
+            quota: tenant.config.storage_quota.map(|quota| quota / MIB),
+        });
+    }
+    Ok(TenantsPage {
+        layout,
+        domain: hosting.domain.clone(),
+        tenants: listed,
+    })
+}
+
+/// A quota in MiB as entered in a form, which is none if it is empty
+fn parse_quota(quota: &str) -> Result<Option<u64>, String> {
+    match quota.trim() {
+        "" => Ok(None),
+        quota => match quota.parse::<u64>() {
+            Ok(mib) if mib > 0 => Ok(Some(mib.saturating_mul(MIB))),
+            _ => Err(format!("\"{quota}\" isn't a number of MiB")),
+        },
+    }
+}
+
+/// Changes the `storage_quota` in the configuration file of the tenant in `dir`
+async fn write_quota(dir: &Path, quota: Option<u64>) -> color_eyre::Result<()> {
+    let path = dir.join(config::CONFIG_PATH);
+    let mut table: toml::value::Table = match tokio::fs::read_to_string(&path).await {
+        Ok(existing) => toml::from_str(&existing)
+            .map_err(|e| color_eyre::eyre::eyre!("{} is invalid: {e}", path.display()))?,
+        Err(_) => toml::value::Table::new(),
+    };
+    match quota {
+        Some(quota) => table.insert("storage_quota".to_string(), (quota as i64).into()),
+        None => table.remove("storage_quota"),
+    };
+    tokio::fs::write(path, toml::to_string(&table)?).await?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct NewTenant {
+    name: String,
+    admin_user: String,
+    password: String,
+    #[serde(default)]
+    quota: String,
+}
+
+pub async fn post_tenants(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    State(tenants): State<Tenants>,
+    Form(form): Form<NewTenant>,
+) -> Result<Response, TomeError> {
+    ensure_admin(&config, &layout)?;
+    let Some(hosting) = &tenants.hosting else {
+        return Err(TomeError::NotFound);
+    };
+    let name = form.name.trim().to_ascii_lowercase();
+    let admin_user = form.admin_user.trim();
+    let message = if !is_name(&name) {
+        Some(format!(
+            "\"{name}\" can't name a tenant, use up to {MAX_NAME_LENGTH} letters, digits and dashes."
+        ))
+    } else if admin_user.is_empty() {
+        Some("The tenant's administrator needs a user name.".to_string())
+    } else if form.password.chars().count() < MIN_PASSWORD_LENGTH {
+        Some(format!(
+            "The password needs at least {MIN_PASSWORD_LENGTH} characters."
+        ))
+    } else {
+        None
+    };
+    if let Some(message) = message {
+        return Ok((StatusCode::BAD_REQUEST, Invalid { layout, message }).into_response());
+    }
+    let quota = parse_quota(&form.quota).map_err(TomeError::BadRequest)?;
+
+    let mut hosted = tenants.hosted.write().await;
+    let dir = hosting.dir.join(&name);
+    if hosted.contains_key(&name) || tokio::fs::metadata(&dir).await.is_ok() {
+        let message = format!("There already is a tenant called {name}.");
+        return Ok((StatusCode::CONFLICT, Invalid { layout, message }).into_response());
+    }
+    tokio::fs::create_dir_all(&dir).await?;
+    let mut options = toml::value::Table::new();
+    options.insert("site_name".to_string(), name.clone().into());
+    options.insert("admin_user".to_string(), admin_user.into());
+    options.insert(
+        "admin_password_hash".to_string(),
+        auth::hash_password(&form.password).into(),
+    );
+    config::update(&dir.join(config::CONFIG_PATH), options).await?;
+    write_quota(&dir, quota).await?;
+    let (opened, tenant) = oneshot::channel();
+    hosting
+        .opener
+        .send((dir, opened))
+        .await
+        .map_err(|_| TomeError::Internal("New tenants can't be opened anymore".to_string()))?;
+    let tenant = tenant
+        .await
+        .map_err(|_| TomeError::Internal(format!("The tenant {name} wasn't opened")))??;
+    hosted.insert(name.clone(), tenant);
+    tracing::info!("Created the tenant {name}");
+    Ok(Redirect::to("/admin/tenants").into_response())
+}
+
+#[derive(Deserialize)]
+pub struct QuotaForm {
+    #[serde(default)]
+    quota: String,
+}
+
+pub async fn post_quota(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    State(tenants): State<Tenants>,
+    UrlPath(name): UrlPath<String>,
+    Form(form): Form<QuotaForm>,
+) -> Result<Response, TomeError> {
+    ensure_admin(&config, &layout)?;
+    let Some(hosting) = &tenants.hosting else {
+        return Err(TomeError::NotFound);
+    };
+    let quota = parse_quota(&form.quota).map_err(TomeError::BadRequest)?;
+    let mut hosted = tenants.hosted.write().await;
+    let tenant = hosted.get_mut(&name).ok_or(TomeError::NotFound)?;
+    write_quota(&hosting.dir.join(&name), quota).await?;
+    tenant.config.storage_quota = quota;
+    tenant.wiki.set_storage_quota(quota);
+    tracing::info!("Changed the quota of the tenant {name} to {quota:?} bytes");
+    Ok(Redirect::to("/admin/tenants").into_response())
+}
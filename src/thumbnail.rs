@@ -0,0 +1,129 @@
+//! # Thumbnails
+//!
+//! The media page shows images as small thumbnails instead of their full
+//! size, which are served at `/media/thumb/<size>/<name>`. The size is the
+//! longest side in pixels and one of `thumbnail_sizes`, 200 by default.
+//! Thumbnails are made when an image is uploaded or first asked for and
+//! cached in `.thumbnails/` of the content directory until the image
+//! changes. JPEGs stay JPEGs, other images become PNGs. Files tome can't
+//! read as images, like SVGs, redirect to the file itself.
+use askama_axum::IntoResponse;
+use axum::extract::{Path, State};
+use axum::http::header;
+use axum::response::{Redirect, Response};
+use image::ImageFormat;
+
+use crate::config::content_path;
+use crate::error::TomeError;
+use crate::{paths, TomeConfig};
+
+const THUMBNAILS_PATH: &str = ".thumbnails";
+const DEFAULT_SIZES: &[u32] = &[200];
+
+/// The sizes thumbnails are made in
+pub fn sizes(config: &TomeConfig) -> Vec<u32> {
+    if config.thumbnail_sizes.is_empty() {
+        DEFAULT_SIZES.to_vec()
+    } else {
+        config.thumbnail_sizes.clone()
+    }
+}
+
+/// The size of the thumbnails on the media page
+pub fn smallest_size(config: &TomeConfig) -> u32 {
+    sizes(config).into_iter().min().unwrap_or(DEFAULT_SIZES[0])
+}
+
+fn format(name: &str) -> ImageFormat {
+    match ImageFormat::from_path(name) {
+        Ok(ImageFormat::Jpeg) => ImageFormat::Jpeg,
+        _ => ImageFormat::Png,
+    }
+}
+
+fn thumbnail_path(size: u32, name: &str) -> String {
+    content_path(&format!("{THUMBNAILS_PATH}/{size}/{name}"))
+}
+
+/// Makes the thumbnail of the media file `name`, unless it is up to date.
+/// Returns whether there is one, which there isn't if `name` isn't an image.
+async fn make(size: u32, name: &str) -> std::io::Result<bool> {
+    let original = content_path(&format!("media/{name}"));
+    let modified = tokio::fs::metadata(&original).await?.modified()?;
+    let target = thumbnail_path(size, name);
+    if let Ok(thumbnail) = tokio::fs::metadata(&target).await {
+        if thumbnail.modified()? >= modified {
+            return Ok(true);
+        }
+    }
+
+    let format = format(name);
+    tokio::task::spawn_blocking(move || {
+        let Ok(image) = image::open(&original) else {
+            return Ok(false);
+        };
+        let thumbnail = if image.width() > size || image.height() > size {
+            image.thumbnail(size, size)
+        } else {
+            image
+        };
+        // JPEGs can't be transparent
+        let thumbnail = match format {
+            ImageFormat::Jpeg => thumbnail.to_rgb8().into(),
+            _ => thumbnail,
+        };
+        let target = std::path::Path::new(&target);
+        let dir = target.parent().unwrap();
+        std::fs::create_dir_all(dir)?;
+        // Written next to it first, so nobody is served half a thumbnail
+        let partial = dir.join(uuid::Uuid::new_v4().hyphenated().to_string());
+        if let Err(e) = thumbnail.save_with_format(&partial, format) {
+            let _ = std::fs::remove_file(&partial);
+            return Err(std::io::Error::other(e));
+        }
+        std::fs::rename(&partial, target)?;
+        Ok(true)
+    })
+    .await?
+}
+
+/// Removes the thumbnails of the media file `name`, after it was deleted or renamed
+pub async fn remove(name: &str) {
+    let Ok(mut sizes) = tokio::fs::read_dir(content_path(THUMBNAILS_PATH)).await else {
+        return;
+    };
+    while let Ok(Some(size)) = sizes.next_entry().await {
+        let thumbnail = size.path().join(name);
+        if tokio::fs::metadata(&thumbnail).await.is_ok() {
+            let _ = tokio::fs::remove_file(thumbnail).await;
+        }
+    }
+}
+
+/// Makes the thumbnails of the media file `name` again, after it was uploaded
+pub async fn refresh(config: &TomeConfig, name: &str) {
+    remove(name).await;
+    for size in sizes(config) {
+        if let Err(e) = make(size, name).await {
+            tracing::warn!("Couldn't make a thumbnail of {name}: {e}");
+        }
+    }
+}
+
+pub async fn get_thumbnail(
+    State(config): State<TomeConfig>,
+    Path((size, name)): Path<(u32, String)>,
+) -> Result<Response, TomeError> {
+    if !sizes(&config).contains(&size) || !paths::is_file_name(&name) {
+        return Err(TomeError::NotFound);
+    }
+    if !make(size, &name).await? {
+        return Ok(Redirect::to(&format!("/media/{}", urlencoding::encode(&name))).into_response());
+    }
+    let thumbnail = tokio::fs::read(thumbnail_path(size, &name)).await?;
+    Ok((
+        [(header::CONTENT_TYPE, format(&name).to_mime_type())],
+        thumbnail,
+    )
+        .into_response())
+}
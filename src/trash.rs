@@ -0,0 +1,174 @@
+//! # Trash
+//!
+//! Deleting an article moves it with its whole history into the trash
+//! instead of removing it. `/admin/trash` lists deleted articles, which can
+//! be restored under their old title or purged for good. With
+//! `append_only_history`, articles can't be deleted or purged, since the
+//! versions of the log have to stay where they are. Articles deleted before
+//! can still be restored.
+use std::time::SystemTime;
+
+use askama::Template;
+use askama_axum::IntoResponse;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{Redirect, Response};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::sync::Mutex;
+
+use crate::config::content_path;
+use crate::error::TomeError;
+use crate::layout::Layout;
+use crate::storage::storage;
+use crate::{archive, backlinks, history, render_cache, search, Article, Invalid, TomeConfig};
+
+const TRASH_INDEX_PATH: &str = "trash.json";
+
+static LOCK: Mutex<()> = Mutex::const_new(());
+
+/// An article in the trash
+#[derive(Serialize, Deserialize)]
+struct Trashed {
+    id: String,
+    slug: String,
+    title: String,
+    deleted: SystemTime,
+}
+
+/// The articles in the trash, refusing an index that doesn't parse instead of
+/// overwriting it, which would lose track of everything in the trash
+async fn read_trash() -> Result<Vec<Trashed>, TomeError> {
+    match tokio::fs::read_to_string(content_path(TRASH_INDEX_PATH)).await {
+        Ok(json) => serde_json::from_str(&json)
+            .map_err(|e| TomeError::Internal(format!("{TRASH_INDEX_PATH} is invalid: {e}"))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn write_trash(trash: &[Trashed]) -> tokio::io::Result<()> {
+    tokio::fs::write(
+        content_path(TRASH_INDEX_PATH),
+        serde_json::to_string_pretty(trash)?,
+    )
+    .await
+}
+
+/// Moves `article` into the trash, unless it is archived
+pub async fn delete(article: &Article) -> Result<(), TomeError> {
+    archive::ensure_changeable(&article.title).await?;
+    let _lock = LOCK.lock().await;
+    // Read first, the article mustn't end up in the trash without being listed there
+    let mut trash = read_trash().await?;
+    let id = uuid::Uuid::new_v4().hyphenated().to_string();
+    storage().trash(&article.title, &id).await?;
+    render_cache::invalidate();
+    search::remove(&article.title).await;
+    backlinks::remove(&article.title).await;
+
+    trash.push(Trashed {
+        id,
+        slug: article.path(),
+        title: article.title.clone(),
+        deleted: SystemTime::now(),
+    });
+    Ok(write_trash(&trash).await?)
+}
+
+pub async fn post_delete(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    Path(title): Path<String>,
+) -> Result<Response, TomeError> {
+    if config.append_only_history {
+        let message = "The history is append-only, so articles can't be deleted.".to_string();
+        return Ok((StatusCode::FORBIDDEN, Invalid { layout, message }).into_response());
+    }
+
+    let title = urlencoding::decode(&title)?.into_owned();
+    let article = Article::load(&title).await.ok_or(TomeError::NotFound)?;
+    delete(&article).await?;
+    Ok(Redirect::to("/overview").into_response())
+}
+
+#[derive(Template)]
+#[template(path = "trash.html")]
+pub struct Trash {
+    layout: Layout,
+    /// Trash id, article title and deletion time, most recently deleted first
+    articles: Vec<(String, String, String)>,
+    append_only: bool,
+}
+
+pub async fn get_trash(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+) -> Result<impl IntoResponse, TomeError> {
+    let mut trash = read_trash().await?;
+    trash.sort_by_key(|trashed| std::cmp::Reverse(trashed.deleted));
+    let articles = trash
+        .into_iter()
+        .map(|trashed| {
+            let deleted = OffsetDateTime::from(trashed.deleted)
+                .format(&time::format_description::well_known::Rfc2822)
+                .unwrap();
+            (trashed.id, trashed.title, deleted)
+        })
+        .collect();
+    Ok(Trash {
+        layout,
+        articles,
+        append_only: config.append_only_history,
+    })
+}
+
+pub async fn post_restore(layout: Layout, Path(id): Path<String>) -> Result<Response, TomeError> {
+    let _lock = LOCK.lock().await;
+    let mut trash = read_trash().await?;
+    let position = trash
+        .iter()
+        .position(|trashed| trashed.id == id)
+        .ok_or(TomeError::NotFound)?;
+    if Article::load(&trash[position].slug).await.is_some() {
+        let message = format!(
+            "There already is an article called \"{}\", rename it before restoring this one.",
+            trash[position].title
+        );
+        return Ok((StatusCode::CONFLICT, Invalid { layout, message }).into_response());
+    }
+
+    let trashed = trash.remove(position);
+    storage().restore(&trashed.id, &trashed.slug).await?;
+    render_cache::invalidate();
+    write_trash(&trash).await?;
+    history::record_event(&trashed.slug, "Restored from the trash").await?;
+    if let Some(article) = Article::load(&trashed.slug).await {
+        search::update(&article).await;
+        backlinks::update(&article).await;
+    }
+    Ok(Redirect::to(&format!("/article/{}", trashed.slug)).into_response())
+}
+
+pub async fn post_purge(
+    layout: Layout,
+    State(config): State<TomeConfig>,
+    Path(id): Path<String>,
+) -> Result<Response, TomeError> {
+    if config.append_only_history {
+        let message =
+            "The history is append-only, so deleted articles can't be purged.".to_string();
+        return Ok((StatusCode::FORBIDDEN, Invalid { layout, message }).into_response());
+    }
+
+    let _lock = LOCK.lock().await;
+    let mut trash = read_trash().await?;
+    let position = trash
+        .iter()
+        .position(|trashed| trashed.id == id)
+        .ok_or(TomeError::NotFound)?;
+    let trashed = trash.remove(position);
+    storage().purge(&trashed.id).await?;
+    write_trash(&trash).await?;
+    Ok(Redirect::to("/admin/trash").into_response())
+}
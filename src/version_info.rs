@@ -0,0 +1,57 @@
+//! # Edit Summaries
+//!
+//! Every saved version gets a sidecar `versions/<version>.json` in its
+//! article's directory, recording when it was saved, who saved it and the
+//! edit summary entered in the editor. The history page shows the summary
+//! and author next to each version. Versions saved before there were
+//! sidecars only show their date. Sidecars are moved along with the article
+//! when it is renamed or deleted.
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage::article_dir;
+
+/// The longest edit summary that is kept, in characters
+pub const MAX_SUMMARY_LENGTH: usize = 250;
+
+#[derive(Serialize, Deserialize)]
+pub struct VersionInfo {
+    pub saved: SystemTime,
+    pub author: Option<String>,
+    pub summary: Option<String>,
+}
+
+fn path(title: &str, version: &str) -> String {
+    format!("{}/versions/{version}.json", article_dir(title))
+}
+
+/// Describes a new `version` of the article `title`
+pub async fn write(
+    title: &str,
+    version: &str,
+    author: Option<&str>,
+    summary: Option<&str>,
+) -> tokio::io::Result<()> {
+    let path = path(title, version);
+    // Saving unchanged content may return the version saved before
+    if tokio::fs::metadata(&path).await.is_ok() {
+        return Ok(());
+    }
+    let summary = summary
+        .map(|summary| summary.trim().chars().take(MAX_SUMMARY_LENGTH).collect())
+        .filter(|summary: &String| !summary.is_empty());
+    let info = VersionInfo {
+        saved: SystemTime::now(),
+        author: author.map(str::to_string),
+        summary,
+    };
+    tokio::fs::create_dir_all(format!("{}/versions", article_dir(title))).await?;
+    tokio::fs::write(path, serde_json::to_string_pretty(&info)?).await
+}
+
+/// What is known about `version` of the article `title`
+pub async fn read(title: &str, version: &str) -> Option<VersionInfo> {
+    let json = tokio::fs::read_to_string(path(title, version)).await.ok()?;
+    serde_json::from_str(&json).ok()
+}
@@ -0,0 +1,150 @@
+//! # Version Tags
+//!
+//! Versions of an article can be tagged with a name like `v1.0` or
+//! `approved-2024-06` on its history page. `/article/:id/tags` lists the
+//! tags and `/article/:id/tags/:name` leads to the permanent link of the
+//! tagged version, which is pinned when it is tagged so it is kept even if
+//! the article's history is purged. Tags are stored in
+//! `version_tags.json` in the article's directory.
+use std::collections::BTreeMap;
+
+use askama::Template;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::Form;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::error::TomeError;
+use crate::layout::Layout;
+use crate::storage::article_dir;
+use crate::{permalink, Article, Invalid};
+
+const MAX_TAG_LENGTH: usize = 64;
+
+static LOCK: Mutex<()> = Mutex::const_new(());
+
+fn tags_path(title: &str) -> String {
+    format!("{}/version_tags.json", article_dir(title))
+}
+
+/// The versions of the article `title` by tag
+pub async fn read(title: &str) -> BTreeMap<String, String> {
+    match tokio::fs::read_to_string(tags_path(title)).await {
+        Ok(json) => serde_json::from_str(&json).unwrap_or_default(),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+async fn write(title: &str, tags: &BTreeMap<String, String>) -> tokio::io::Result<()> {
+    tokio::fs::write(tags_path(title), serde_json::to_string_pretty(tags)?).await
+}
+
+/// The tags of each version of the article `title`
+pub async fn by_version(title: &str) -> BTreeMap<String, Vec<String>> {
+    let mut versions: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (tag, version) in read(title).await {
+        versions.entry(version).or_default().push(tag);
+    }
+    versions
+}
+
+fn validate(tag: &str) -> Result<(), String> {
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_LENGTH {
+        return Err(format!(
+            "Tags have to be between 1 and {MAX_TAG_LENGTH} characters long."
+        ));
+    }
+    if !tag
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '.' || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "\"{tag}\" can only contain letters, digits, dots, dashes and underscores."
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct TagForm {
+    name: String,
+}
+
+pub async fn post_tag(
+    layout: Layout,
+    Path((title, version)): Path<(String, String)>,
+    Form(form): Form<TagForm>,
+) -> Result<Response, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let Some(article) = Article::load(&title).await else {
+        return Err(TomeError::NotFound);
+    };
+    let tag = form.name.trim().to_string();
+    if let Err(message) = validate(&tag) {
+        return Ok((StatusCode::BAD_REQUEST, Invalid { layout, message }).into_response());
+    }
+    if permalink::load(&article.title, &version).await.is_none() {
+        return Err(TomeError::NotFound);
+    }
+
+    let _lock = LOCK.lock().await;
+    let mut tags = read(&article.title).await;
+    if tags.get(&tag).is_some_and(|tagged| *tagged != version) {
+        let message = format!("Another version is already tagged \"{tag}\".");
+        return Ok((StatusCode::CONFLICT, Invalid { layout, message }).into_response());
+    }
+    tags.insert(tag, version);
+    write(&article.title, &tags).await?;
+    Ok(Redirect::to(&format!("/article/{}/history", article.path())).into_response())
+}
+
+pub async fn post_untag(Path((title, tag)): Path<(String, String)>) -> Result<Response, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let tag = urlencoding::decode(&tag)?.into_owned();
+    let Some(article) = Article::load(&title).await else {
+        return Err(TomeError::NotFound);
+    };
+    let _lock = LOCK.lock().await;
+    let mut tags = read(&article.title).await;
+    if tags.remove(&tag).is_none() {
+        return Err(TomeError::NotFound);
+    }
+    write(&article.title, &tags).await?;
+    Ok(Redirect::to(&format!("/article/{}/tags", article.path())).into_response())
+}
+
+#[derive(Template)]
+#[template(path = "version_tags.html")]
+struct Tags {
+    layout: Layout,
+    title: String,
+    path: String,
+    /// Names and versions, ordered by name
+    tags: Vec<(String, String)>,
+}
+
+pub async fn get_tags(layout: Layout, Path(title): Path<String>) -> Result<Response, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let Some(article) = Article::load(&title).await else {
+        return Err(TomeError::NotFound);
+    };
+    Ok(Tags {
+        layout,
+        tags: read(&article.title).await.into_iter().collect(),
+        path: article.path(),
+        title: article.title,
+    }
+    .into_response())
+}
+
+/// Leads to the permanent link of the tagged version
+pub async fn get_tag(Path((title, tag)): Path<(String, String)>) -> Result<Response, TomeError> {
+    let title = urlencoding::decode(&title)?.into_owned();
+    let tag = urlencoding::decode(&tag)?.into_owned();
+    match read(&title).await.get(&tag) {
+        Some(version) => Ok(Redirect::to(&permalink::url(&title, version)).into_response()),
+        None => Err(TomeError::NotFound),
+    }
+}
@@ -0,0 +1,132 @@
+//! # Wikis
+//!
+//! A wiki is a content directory together with what tome keeps in memory
+//! about it: where its versions are stored, the search index, the link
+//! graph, the end of the history log, the signing key and how its HTML is
+//! cleaned. Everything that reads or writes content uses the wiki it runs
+//! for. An app sets its wiki for every request it answers and commands run
+//! inside the wiki of their configuration, so several apps with their own
+//! `content_dir` can run in one process. Programs embedding tome open a
+//! [`Wiki`] and use articles inside [`Wiki::run`]. Work continued in the
+//! background keeps the wiki it was started for.
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use ed25519_dalek::SigningKey;
+use tokio::sync::{Mutex, RwLock};
+use tokio::task::JoinHandle;
+
+use crate::storage::Storage;
+use crate::TomeConfig;
+use crate::{backlinks, config, history, migrations, sanitize, search, signature, storage};
+
+tokio::task_local! {
+    static CURRENT: Arc<WikiState>;
+}
+
+/// What tome keeps about the content of a wiki
+pub(crate) struct WikiState {
+    pub(crate) content_dir: String,
+    pub(crate) storage: OnceLock<Arc<dyn Storage>>,
+    pub(crate) search: OnceLock<RwLock<search::Index>>,
+    pub(crate) links: OnceLock<RwLock<backlinks::Graph>>,
+    pub(crate) last_entry: OnceLock<Mutex<Option<history::LastEntry>>>,
+    pub(crate) signing_key: OnceLock<SigningKey>,
+    pub(crate) cleaning: OnceLock<sanitize::Cleaning>,
+    /// Changed to make every cached page stale
+    pub(crate) render_generation: AtomicU64,
+    /// The `storage_quota` in bytes, 0 if there is none
+    pub(crate) storage_quota: AtomicU64,
+}
+
+/// A content directory opened with a configuration
+#[derive(Clone)]
+pub struct Wiki {
+    state: Arc<WikiState>,
+}
+
+impl Wiki {
+    /// Opens the `content_dir` of `config`, creating and migrating it if needed
+    pub async fn open(config: &TomeConfig) -> color_eyre::Result<Self> {
+        let wiki = Wiki {
+            state: Arc::new(WikiState {
+                content_dir: config::configured_content_dir(config).to_string(),
+                storage: OnceLock::new(),
+                search: OnceLock::new(),
+                links: OnceLock::new(),
+                last_entry: OnceLock::new(),
+                signing_key: OnceLock::new(),
+                cleaning: OnceLock::new(),
+                render_generation: AtomicU64::new(0),
+                storage_quota: AtomicU64::new(config.storage_quota.unwrap_or(0)),
+            }),
+        };
+        config::create_directories(&wiki.state.content_dir).await?;
+        wiki.run(async {
+            storage::init(config).await?;
+            signature::init(config)?;
+            history::init(config);
+            sanitize::init(config);
+            migrations::run().await
+        })
+        .await?;
+        Ok(wiki)
+    }
+
+    /// Runs `task` with the content of this wiki
+    pub async fn run<F: Future>(&self, task: F) -> F::Output {
+        CURRENT.scope(self.state.clone(), task).await
+    }
+
+    /// The directory the content of this wiki is stored in
+    pub fn content_dir(&self) -> &str {
+        &self.state.content_dir
+    }
+
+    /// Changes the `storage_quota` of this wiki while it runs
+    pub(crate) fn set_storage_quota(&self, quota: Option<u64>) {
+        self.state
+            .storage_quota
+            .store(quota.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Runs `f` with the content of this wiki, for code that isn't async
+    pub fn run_sync<R>(&self, f: impl FnOnce() -> R) -> R {
+        CURRENT.sync_scope(self.state.clone(), f)
+    }
+}
+
+/// The wiki the running task is for
+///
+/// # Panics
+///
+/// Outside of [`Wiki::run`], as there is no content to use.
+pub(crate) fn current() -> Arc<WikiState> {
+    CURRENT
+        .try_with(Arc::clone)
+        .expect("tome's content was used outside of Wiki::run")
+}
+
+/// Answers requests with the content of `wiki`
+pub(crate) async fn enter(
+    State(wiki): State<Wiki>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    wiki.run(next.run(request)).await
+}
+
+/// Continues `task` in the background, for the wiki it is spawned from
+pub(crate) fn spawn<F>(task: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(CURRENT.scope(current(), task))
+}
@@ -0,0 +1,308 @@
+//! # ZIM Export
+//!
+//! Writes the rendered wiki as a ZIM archive (format version 5) that can be
+//! read offline with Kiwix. Articles go into the `A` namespace under their
+//! slug and link to each other relatively, media files go into `I` and the wiki's details
+//! into `M`. Blobs are stored in uncompressed clusters.
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use md5::{Digest, Md5};
+use pulldown_cmark::{Event, Tag};
+use time::OffsetDateTime;
+use tokio_stream::wrappers::ReadDirStream;
+use tokio_stream::StreamExt;
+
+use crate::config::content_path;
+use crate::license::License;
+use crate::media::mime_type;
+use crate::slug::slug;
+use crate::{filters, Article, Index, Overview, TomeConfig};
+
+const MAGIC_NUMBER: u32 = 72173914;
+const NO_PAGE: u32 = 0xffff_ffff;
+const CLUSTER_SIZE: usize = 1024 * 1024;
+/// URL of the wiki's index page, chosen so it can't collide with an article
+const MAIN_PAGE: &str = "~index";
+
+/// A single file in the archive
+struct Entry {
+    namespace: u8,
+    url: String,
+    title: String,
+    mime_type: String,
+    blob: Vec<u8>,
+}
+
+struct Cluster {
+    blobs: Vec<Vec<u8>>,
+}
+
+impl Cluster {
+    fn to_bytes(&self) -> Vec<u8> {
+        // No compression
+        let mut bytes = vec![1];
+        let mut offset = 4 * (self.blobs.len() as u32 + 1);
+        bytes.extend(offset.to_le_bytes());
+        for blob in &self.blobs {
+            offset += blob.len() as u32;
+            bytes.extend(offset.to_le_bytes());
+        }
+        for blob in &self.blobs {
+            bytes.extend(blob);
+        }
+        bytes
+    }
+}
+
+/// Assembles entries into the bytes of a ZIM archive
+fn write_archive(mut entries: Vec<Entry>) -> Vec<u8> {
+    entries.sort_by(|a, b| (a.namespace, &a.url).cmp(&(b.namespace, &b.url)));
+
+    let mime_types: Vec<String> = entries
+        .iter()
+        .map(|entry| entry.mime_type.clone())
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let mut mime_list = vec![];
+    for mime_type in &mime_types {
+        mime_list.extend(mime_type.as_bytes());
+        mime_list.push(0);
+    }
+    mime_list.push(0);
+
+    // Distribute blobs into clusters and build the directory entries
+    let mut clusters = vec![Cluster { blobs: vec![] }];
+    let mut cluster_size = 0;
+    let mut dirents = vec![];
+    for entry in &mut entries {
+        if cluster_size > CLUSTER_SIZE {
+            clusters.push(Cluster { blobs: vec![] });
+            cluster_size = 0;
+        }
+        let cluster = clusters.len() - 1;
+        let blob = clusters[cluster].blobs.len();
+        cluster_size += entry.blob.len();
+        clusters[cluster]
+            .blobs
+            .push(std::mem::take(&mut entry.blob));
+
+        let mime = mime_types
+            .iter()
+            .position(|mime_type| *mime_type == entry.mime_type)
+            .unwrap() as u16;
+        let mut dirent = vec![];
+        dirent.extend(mime.to_le_bytes());
+        dirent.push(0);
+        dirent.push(entry.namespace);
+        dirent.extend(0u32.to_le_bytes());
+        dirent.extend((cluster as u32).to_le_bytes());
+        dirent.extend((blob as u32).to_le_bytes());
+        dirent.extend(entry.url.as_bytes());
+        dirent.push(0);
+        dirent.extend(entry.title.as_bytes());
+        dirent.push(0);
+        dirents.push(dirent);
+    }
+
+    let mut titles: Vec<usize> = (0..entries.len()).collect();
+    titles.sort_by(|&a, &b| {
+        let title = |entry: &Entry| {
+            if entry.title.is_empty() {
+                entry.url.clone()
+            } else {
+                entry.title.clone()
+            }
+        };
+        (entries[a].namespace, title(&entries[a])).cmp(&(entries[b].namespace, title(&entries[b])))
+    });
+
+    let main_page = entries
+        .iter()
+        .position(|entry| entry.namespace == b'A' && entry.url == MAIN_PAGE)
+        .map_or(NO_PAGE, |index| index as u32);
+
+    let count = entries.len() as u64;
+    let mime_list_pos = 80u64;
+    let url_ptr_pos = mime_list_pos + mime_list.len() as u64;
+    let title_ptr_pos = url_ptr_pos + 8 * count;
+    let dirents_pos = title_ptr_pos + 4 * count;
+    let cluster_ptr_pos = dirents_pos + dirents.iter().map(|d| d.len() as u64).sum::<u64>();
+    let clusters: Vec<Vec<u8>> = clusters.iter().map(Cluster::to_bytes).collect();
+    let clusters_pos = cluster_ptr_pos + 8 * clusters.len() as u64;
+    let checksum_pos = clusters_pos + clusters.iter().map(|c| c.len() as u64).sum::<u64>();
+
+    let mut zim = vec![];
+    zim.extend(MAGIC_NUMBER.to_le_bytes());
+    zim.extend(5u16.to_le_bytes());
+    zim.extend(0u16.to_le_bytes());
+    zim.extend(uuid::Uuid::new_v4().as_bytes());
+    zim.extend((count as u32).to_le_bytes());
+    zim.extend((clusters.len() as u32).to_le_bytes());
+    zim.extend(url_ptr_pos.to_le_bytes());
+    zim.extend(title_ptr_pos.to_le_bytes());
+    zim.extend(cluster_ptr_pos.to_le_bytes());
+    zim.extend(mime_list_pos.to_le_bytes());
+    zim.extend(main_page.to_le_bytes());
+    zim.extend(NO_PAGE.to_le_bytes());
+    zim.extend(checksum_pos.to_le_bytes());
+
+    zim.extend(&mime_list);
+    let mut offset = dirents_pos;
+    for dirent in &dirents {
+        zim.extend(offset.to_le_bytes());
+        offset += dirent.len() as u64;
+    }
+    for index in titles {
+        zim.extend((index as u32).to_le_bytes());
+    }
+    for dirent in &dirents {
+        zim.extend(dirent);
+    }
+    let mut offset = clusters_pos;
+    for cluster in &clusters {
+        zim.extend(offset.to_le_bytes());
+        offset += cluster.len() as u64;
+    }
+    for cluster in &clusters {
+        zim.extend(cluster);
+    }
+
+    let checksum = Md5::digest(&zim);
+    zim.extend(checksum);
+    zim
+}
+
+/// Renders Markdown with links pointing to the other entries of the archive
+fn render(markdown: &str) -> String {
+    filters::render(markdown, |event| match event {
+        Event::Start(Tag::Link(link_type, dest, title)) => {
+            // Relative links must be encoded, otherwise slugs like `wiki:syntax` look like URLs
+            let dest = match dest.strip_prefix("/article/") {
+                Some(article) => {
+                    let (article, fragment) = match article.split_once('#') {
+                        Some((article, fragment)) => (article, format!("#{fragment}")),
+                        None => (article, String::new()),
+                    };
+                    let title = urlencoding::decode(article).unwrap_or(article.into());
+                    format!("{}{fragment}", urlencoding::encode(&slug(&title))).into()
+                }
+                None => dest,
+            };
+            Event::Start(Tag::Link(link_type, dest, title))
+        }
+        Event::Start(Tag::Image(link_type, dest, title)) => {
+            let dest = match dest.strip_prefix("/media/") {
+                Some(name) => format!("../I/{name}").into(),
+                None => dest,
+            };
+            Event::Start(Tag::Image(link_type, dest, title))
+        }
+        _ => event,
+    })
+}
+
+fn page(title: &str, html: &str, license: Option<License>) -> Vec<u8> {
+    let title = askama_escape::escape(title, askama_escape::Html);
+    let license = match license {
+        Some(license) => format!(
+            "<footer>License: {}</footer>\n",
+            askama_escape::escape(&license.name, askama_escape::Html)
+        ),
+        None => String::new(),
+    };
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         </head>\n<body>\n<h1>{title}</h1>\n{html}\n{license}</body>\n</html>\n"
+    )
+    .into_bytes()
+}
+
+impl Entry {
+    fn new(namespace: u8, url: &str, mime_type: &str, blob: Vec<u8>) -> Self {
+        Entry {
+            namespace,
+            url: url.to_string(),
+            title: String::new(),
+            mime_type: mime_type.to_string(),
+            blob,
+        }
+    }
+}
+
+/// Writes every article, the index page and all media into a ZIM archive at `output`
+pub async fn export(output: &Path, config: &TomeConfig) -> color_eyre::Result<()> {
+    let mut entries = vec![];
+
+    let index = Index::load().await?;
+    entries.push(Entry {
+        title: "Index".to_string(),
+        ..Entry::new(
+            b'A',
+            MAIN_PAGE,
+            "text/html",
+            page("Index", &render(&index.content), License::of_site(config)),
+        )
+    });
+
+    let mut articles = BTreeMap::new();
+    for (path, _) in Overview::load().await.articles {
+        if let Some(article) = Article::load(&path).await {
+            articles.insert(path, article);
+        }
+    }
+    for (path, article) in &articles {
+        entries.push(Entry {
+            title: article.title.clone(),
+            ..Entry::new(
+                b'A',
+                path,
+                "text/html",
+                page(
+                    &article.title,
+                    &render(article.body()),
+                    License::of(config, article),
+                ),
+            )
+        });
+    }
+
+    let mut media = ReadDirStream::new(tokio::fs::read_dir(content_path("media")).await?);
+    while let Some(entry) = media.next().await {
+        let entry = entry?;
+        if !entry.file_type().await?.is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let data = tokio::fs::read(entry.path()).await?;
+        entries.push(Entry::new(b'I', &name, mime_type(&name), data));
+    }
+
+    let date = OffsetDateTime::now_utc().date().to_string();
+    let license = config.license.clone().unwrap_or_default();
+    for (name, value) in [
+        ("Title", "Tome"),
+        ("Description", "An offline copy of a Tome wiki"),
+        ("Creator", "Tome"),
+        ("Publisher", "Tome"),
+        ("Language", "eng"),
+        ("Date", &date),
+        ("License", &license),
+    ] {
+        if value.is_empty() {
+            continue;
+        }
+        entries.push(Entry::new(
+            b'M',
+            name,
+            "text/plain",
+            value.as_bytes().to_vec(),
+        ));
+    }
+
+    let count = articles.len();
+    tokio::fs::write(output, write_archive(entries)).await?;
+    println!("Exported {count} articles to {}", output.display());
+    Ok(())
+}
@@ -0,0 +1,3315 @@
+//! Runs wikis in-process. Every test has a [`TestWiki`] of its own, a
+//! temporary directory with the content directory and configuration file,
+//! so tests don't see each other's articles, media or settings.
+use std::future::Future;
+use std::path::PathBuf;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::Router;
+use clap::Parser;
+use figment::providers::{Format, Serialized, Toml};
+use figment::Figment;
+use tempfile::TempDir;
+use tome::TomeConfig;
+use tower::ServiceExt;
+
+/// A wiki with `content/` and `tome.toml` in a temporary directory
+struct TestWiki {
+    dir: TempDir,
+}
+
+impl TestWiki {
+    fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        for path in ["content/articles", "content/media"] {
+            std::fs::create_dir_all(dir.path().join(path)).unwrap();
+        }
+        std::fs::write(dir.path().join("content/index.md"), "# Welcome").unwrap();
+        TestWiki { dir }
+    }
+
+    /// `path` in the temporary directory, like `content/index.md`
+    fn path(&self, path: &str) -> PathBuf {
+        self.dir.path().join(path)
+    }
+
+    /// The defaults and `toml`, for the content of this wiki
+    fn config(&self, toml: &str) -> TomeConfig {
+        Figment::from(Serialized::defaults(TomeConfig::default()))
+            .merge(Toml::string(toml))
+            .merge(Serialized::default("content_dir", self.path("content")))
+            .extract()
+            .unwrap()
+    }
+
+    /// The command line `tome <args>`, with the configuration file and content of this wiki
+    fn cli(&self, args: &[&str]) -> tome::Cli {
+        let config_file = self.path("tome.toml");
+        let content_dir = self.path("content");
+        let options = [
+            "--config-file",
+            config_file.to_str().unwrap(),
+            "--content-dir",
+            content_dir.to_str().unwrap(),
+        ];
+        tome::Cli::parse_from([&["tome"], &options[..], args].concat())
+    }
+
+    async fn app(&self) -> Router {
+        tome::app(self.config(r#"allowed_uploads = ["png"]"#))
+            .await
+            .unwrap()
+    }
+
+    /// Runs `task` with the content of this wiki, to use articles directly
+    async fn run<F: Future>(&self, task: F) -> F::Output {
+        tome::Wiki::open(&self.config(""))
+            .await
+            .unwrap()
+            .run(task)
+            .await
+    }
+
+    async fn send(&self, request: Request<Body>) -> Response {
+        send_to(self.app().await, request).await
+    }
+
+    async fn get(&self, uri: &str) -> Response {
+        self.send(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+    }
+
+    async fn post(&self, uri: &str) -> Response {
+        self.send(Request::post(uri).body(Body::empty()).unwrap())
+            .await
+    }
+
+    async fn save(&self, title: &str, content: &str) -> Response {
+        self.send(edit_request(&[
+            ("title", title),
+            ("original_title", title),
+            ("content", content),
+        ]))
+        .await
+    }
+
+    async fn save_from(&self, title: &str, content: &str, base_version: &str) -> Response {
+        self.send(edit_request(&[
+            ("title", title),
+            ("original_title", title),
+            ("content", content),
+            ("base_version", base_version),
+        ]))
+        .await
+    }
+
+    async fn upload(&self, file_name: &str, data: &[u8]) -> Response {
+        self.send(upload_request(file_name, data)).await
+    }
+
+    /// The trash ids listed on `/admin/trash`
+    async fn trashed(&self) -> Vec<String> {
+        self.get("/admin/trash")
+            .await
+            .body
+            .split(r#"action="/admin/trash/"#)
+            .filter(|rest| {
+                rest.get(36..)
+                    .is_some_and(|rest| rest.starts_with("/restore"))
+            })
+            .map(|rest| rest[..36].to_string())
+            .collect()
+    }
+}
+
+fn edit_request(form: &[(&str, &str)]) -> Request<Body> {
+    Request::post("/article/edit")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(serde_urlencoded::to_string(form).unwrap()))
+        .unwrap()
+}
+
+struct Response {
+    status: StatusCode,
+    location: Option<String>,
+    body: String,
+}
+
+/// Sends `request` to `app`, for wikis with their own configuration
+async fn send_to(app: Router, request: Request<Body>) -> Response {
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let location = response
+        .headers()
+        .get(header::LOCATION)
+        .map(|location| location.to_str().unwrap().to_string());
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    Response {
+        status,
+        location,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    }
+}
+
+#[tokio::test]
+async fn creates_and_edits_articles() {
+    let wiki = TestWiki::new();
+    let response = wiki.get("/article/crud").await;
+    assert_eq!(response.status, StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(response.location.as_deref(), Some("/edit/article/crud"));
+
+    let response = wiki.save("CRUD", "First *draft*").await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+    assert_eq!(response.location.as_deref(), Some("/article/crud"));
+
+    let response = wiki.get("/article/crud").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.body.contains("<em>draft</em>"));
+
+    wiki.save("CRUD", "Second draft").await;
+    let response = wiki.get("/edit/article/crud").await;
+    assert!(response.body.contains("Second draft"));
+    assert!(response.body.contains(r#"name="title" value="CRUD""#));
+
+    let response = wiki.get("/overview").await;
+    assert!(response
+        .body
+        .contains(r#"<a href="/article/crud">CRUD</a>"#));
+}
+
+#[tokio::test]
+async fn rejects_invalid_titles() {
+    let wiki = TestWiki::new();
+    for title in ["", "..", "a/b"] {
+        let response = wiki.save(title, "content").await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST, "{title:?}");
+    }
+}
+
+#[tokio::test]
+async fn renames_articles_and_redirects() {
+    let wiki = TestWiki::new();
+    wiki.save("Old name", "Renamed content").await;
+    let form = serde_urlencoded::to_string([
+        ("title", "New name"),
+        ("original_title", "Old name"),
+        ("content", "Renamed content"),
+    ])
+    .unwrap();
+    wiki.send(
+        Request::post("/article/old-name")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(form))
+            .unwrap(),
+    )
+    .await;
+
+    let response = wiki.get("/article/old-name").await;
+    assert_eq!(response.status, StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(response.location.as_deref(), Some("/article/new-name"));
+    let response = wiki.get("/article/new-name/history").await;
+    assert_eq!(response.body.matches(r#"name="from""#).count(), 2);
+}
+
+#[tokio::test]
+async fn renames_articles_without_editing_them() {
+    let wiki = TestWiki::new();
+    wiki.save("Before", "Moved content").await;
+    wiki.save("Taken", "Already here").await;
+    let rename = |title: &str| {
+        Request::post("/article/before/rename")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(format!("title={title}")))
+            .unwrap()
+    };
+
+    let response = wiki.send(rename("Taken")).await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+
+    let response = wiki.send(rename("After")).await;
+    assert_eq!(response.location.as_deref(), Some("/article/after"));
+    let response = wiki.get("/article/before").await;
+    assert_eq!(response.location.as_deref(), Some("/article/after"));
+    let response = wiki.get("/article/after").await;
+    assert!(response.body.contains("<h1>After</h1>"));
+    assert!(response.body.contains("Moved content"));
+    let response = wiki.get("/article/after/history").await;
+    assert_eq!(response.body.matches(r#"name="from""#).count(), 2);
+}
+
+#[tokio::test]
+async fn renames_articles_on_the_rename_page() {
+    let wiki = TestWiki::new();
+    wiki.save("Guides:Setup", "How to set up").await;
+    wiki.save("Linking", "See [[Guides:Setup]]").await;
+    let rename = |path: &str, title: &str| {
+        Request::post(format!("/article/{path}/rename"))
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(
+                serde_urlencoded::to_string([("title", title)]).unwrap(),
+            ))
+            .unwrap()
+    };
+
+    let response = wiki.get("/article/guides:setup").await;
+    assert!(response
+        .body
+        .contains(r#"href="/article/guides:setup/rename""#));
+    let response = wiki.get("/article/guides:setup/rename").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response
+        .body
+        .contains(r#"action="/article/guides:setup/rename""#));
+    assert!(response.body.contains(r#"value="Guides:Setup""#));
+    assert_eq!(
+        wiki.get("/article/missing/rename").await.status,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        wiki.send(rename("missing", "Found")).await.status,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        wiki.send(rename("guides:setup", " ")).await.status,
+        StatusCode::BAD_REQUEST
+    );
+
+    // Changing only the case keeps the slug and the history where they are
+    let response = wiki.send(rename("guides:setup", "Guides:SETUP")).await;
+    assert_eq!(response.location.as_deref(), Some("/article/guides:setup"));
+    let response = wiki.get("/article/guides:setup").await;
+    assert!(response.body.contains("SETUP"));
+
+    let response = wiki
+        .send(rename("guides:setup", "Guides:Installation"))
+        .await;
+    assert_eq!(
+        response.location.as_deref(),
+        Some("/article/guides:installation")
+    );
+    let response = wiki.get("/article/guides:installation/history").await;
+    assert_eq!(response.body.matches(r#"name="from""#).count(), 3);
+    assert!(response.body.contains("Renamed from Guides:SETUP"));
+    // Links to the old title lead to the new one
+    let response = wiki.get("/article/linking").await;
+    assert!(response.body.contains(r#"href="/article/Guides%3ASetup""#));
+    let response = wiki.get("/article/Guides%3ASetup").await;
+    assert_eq!(
+        response.location.as_deref(),
+        Some("/article/guides:installation")
+    );
+}
+
+#[tokio::test]
+async fn keeps_every_version() {
+    let wiki = TestWiki::new();
+    wiki.save("Versions", "one").await;
+    wiki.save("Versions", "two").await;
+    wiki.save("Versions", "three").await;
+
+    let response = wiki.get("/article/versions/history").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let versions: Vec<&str> = response
+        .body
+        .split(r#"name="from" value=""#)
+        .skip(1)
+        .map(|rest| &rest[..36])
+        .collect();
+    assert_eq!(versions.len(), 3);
+
+    let mut contents = vec![];
+    for version in versions {
+        let response = wiki
+            .get(&format!("/article/versions/history/{version}"))
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        let content = ["one", "two", "three"]
+            .into_iter()
+            .find(|content| {
+                response
+                    .body
+                    .contains(&format!("<p dir=\"auto\">{content}</p>"))
+            })
+            .unwrap();
+        contents.push(content);
+    }
+    contents.sort();
+    assert_eq!(contents, ["one", "three", "two"]);
+
+    let response = wiki
+        .get("/article/versions/history/00000000-0000-0000-0000-000000000000")
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn shows_the_history_of_titles_linked_with_spaces_and_namespaces() {
+    let wiki = TestWiki::new();
+    wiki.save("Guides:First steps", "one").await;
+    wiki.save("Guides:First steps", "two").await;
+
+    // Wiki links use the title, percent-encoded
+    let response = wiki.get("/article/Guides%3AFirst%20steps/history").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.body.contains("History of Guides:First steps"));
+    assert!(response
+        .body
+        .contains(r#"href="/article/guides:first-steps/tags""#));
+    let version = response
+        .body
+        .split(r#"name="from" value=""#)
+        .nth(1)
+        .map(|rest| &rest[..36])
+        .unwrap();
+    let response = wiki
+        .get(&format!(
+            "/article/Guides%3AFirst%20steps/history/{version}"
+        ))
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = wiki
+        .get("/article/Guides%3AFirst%20steps/history/00000000-0000-0000-0000-000000000000")
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+fn upload_request(file_name: &str, data: &[u8]) -> Request<Body> {
+    let boundary = "tome-test-boundary";
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"image\"; filename=\"{file_name}\"\r\n\
+         Content-Type: application/octet-stream\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend(data);
+    body.extend(format!("\r\n--{boundary}--\r\n").as_bytes());
+    Request::post("/media")
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={boundary}"),
+        )
+        .body(Body::from(body))
+        .unwrap()
+}
+
+/// `data` as far as uploads are checked, following the bytes PNG images start with
+fn png(data: &[u8]) -> Vec<u8> {
+    [b"\x89PNG\r\n\x1a\n", data].concat()
+}
+
+#[tokio::test]
+async fn uploads_and_serves_media() {
+    let wiki = TestWiki::new();
+    let response = wiki.upload("upload-test.png", &png(b"a png")).await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+
+    let response = wiki.get("/media/upload-test.png").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body, String::from_utf8_lossy(&png(b"a png")));
+
+    let response = wiki.get("/media").await;
+    assert!(response.body.contains("upload-test.png"));
+
+    wiki.upload("script.sh", b"echo hi").await;
+    let response = wiki.get("/media/script.sh").await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn searches_article_text() {
+    let wiki = TestWiki::new();
+    wiki.save("Searchable", "The zebra crossing is painted.")
+        .await;
+
+    let response = wiki.get("/search?q=zebra").await;
+    assert!(response
+        .body
+        .contains(r#"<a href="/article/searchable">Searchable</a>"#));
+    assert!(response.body.contains("<mark>zebra</mark>"));
+
+    let response = wiki.get("/search?q=zebra+giraffe").await;
+    assert!(response.body.contains("No articles contain"));
+}
+
+#[tokio::test]
+async fn unknown_pages_are_not_found() {
+    let wiki = TestWiki::new();
+    let response = wiki.get("/no/such/page").await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn compares_versions() {
+    let wiki = TestWiki::new();
+    wiki.save("Diffed", "unchanged\nold words here\n").await;
+    wiki.save("Diffed", "unchanged\nnew words here\nadded\n")
+        .await;
+
+    let response = wiki.get("/article/diffed/history").await;
+    let oldest = response
+        .body
+        .rsplit(r#"name="from" value=""#)
+        .next()
+        .map(|rest| &rest[..36])
+        .unwrap();
+
+    let response = wiki
+        .get(&format!("/article/diffed/history/{oldest}/diff"))
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.body.contains("lines added: 2"));
+    assert!(response.body.contains("lines removed: 1"));
+    assert!(response
+        .body
+        .contains(r#"<span class="diff-word">new</span>"#));
+
+    let response = wiki
+        .get(&format!(
+            "/article/diffed/compare?from={oldest}&to={oldest}"
+        ))
+        .await;
+    assert!(response.body.contains("Both versions are the same."));
+}
+
+#[tokio::test]
+async fn saves_articles_through_the_library() {
+    let wiki = TestWiki::new();
+    wiki.run(async {
+        tome::Article::new("Embedded Article", "Written *directly*")
+            .write_to_disk()
+            .await
+            .unwrap();
+
+        let article = tome::Article::load("embedded-article").await.unwrap();
+        assert_eq!(article.title(), "Embedded Article");
+        assert_eq!(
+            tome::render(article.body()),
+            "<p dir=\"auto\">Written <em>directly</em></p>\n"
+        );
+        assert_eq!(
+            tome::Article::get_versions("Embedded Article").await.len(),
+            1
+        );
+    })
+    .await;
+
+    let response = wiki.get("/article/embedded-article").await;
+    assert!(response.body.contains("<em>directly</em>"));
+}
+
+#[tokio::test]
+async fn restores_old_versions() {
+    let wiki = TestWiki::new();
+    wiki.save("Restored", "first draft").await;
+    wiki.save("Restored", "second draft").await;
+
+    let response = wiki.get("/article/restored/history").await;
+    let oldest = response
+        .body
+        .rsplit(r#"name="from" value=""#)
+        .next()
+        .map(|rest| &rest[..36])
+        .unwrap();
+
+    let response = wiki
+        .send(
+            Request::post(format!("/article/restored/history/{oldest}/restore"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.location.as_deref(), Some("/article/restored"));
+
+    let response = wiki.get("/article/restored").await;
+    assert!(response.body.contains("first draft"));
+    let response = wiki.get("/article/restored/history").await;
+    assert_eq!(response.body.matches(r#"name="from""#).count(), 3);
+}
+
+#[tokio::test]
+async fn writes_a_valid_default_config() {
+    let wiki = TestWiki::new();
+    let _ = wiki.app().await;
+    tome::run(wiki.cli(&["config", "init"])).await.unwrap();
+    assert!(tome::run(wiki.cli(&["config", "init"])).await.is_err());
+
+    let config = std::fs::read_to_string(wiki.path("tome.toml")).unwrap();
+    assert!(config.contains("# port = 5422"));
+    // Every option is commented out, so uncommenting them all has to work too
+    let uncommented: String = config
+        .lines()
+        .filter(|line| line.contains(" = "))
+        .map(|line| format!("{}\n", line.trim_start_matches("# ")))
+        .collect();
+    std::fs::write(wiki.path("tome.toml"), uncommented).unwrap();
+    tome::run(wiki.cli(&["config", "check"])).await.unwrap();
+}
+
+#[tokio::test]
+async fn prefers_the_command_line_over_the_config_file() {
+    let wiki = TestWiki::new();
+    let check = |args: &[&str]| tome::run(wiki.cli(&[args, &["config", "check"]].concat()));
+    std::fs::write(wiki.path("tome.toml"), "trace_sample_rate = 1.5").unwrap();
+    assert!(check(&[]).await.is_err());
+    check(&["--trace-sample-rate", "0.5"]).await.unwrap();
+
+    std::fs::write(wiki.path("tome.toml"), "trace_sample_rate = 0.5").unwrap();
+    check(&[]).await.unwrap();
+    assert!(check(&["--trace-sample-rate", "1.5"]).await.is_err());
+}
+
+#[tokio::test]
+async fn explains_mistakes_in_the_config() {
+    let wiki = TestWiki::new();
+    std::fs::write(wiki.path("tome.toml"), "prot = 8080\nhistory_page_size = 0").unwrap();
+    let check = tome::run(wiki.cli(&["config", "check"])).await.unwrap_err();
+    assert_eq!(check.to_string(), "Found 2 mistakes in the configuration");
+
+    // Other commands refuse to start and say what is wrong
+    let output = wiki.path("wiki.zim");
+    let export = tome::run(wiki.cli(&["export", "--format", "zim", output.to_str().unwrap()]))
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(
+        export.contains("unknown option `prot`, did you mean `port`?"),
+        "{export}"
+    );
+    assert!(
+        export.contains("history_page_size must be at least 1"),
+        "{export}"
+    );
+    assert!(!output.exists());
+
+    std::fs::write(
+        wiki.path("tome.toml"),
+        "port = 8080\nhistory_page_size = 10",
+    )
+    .unwrap();
+    tome::run(wiki.cli(&["config", "check"])).await.unwrap();
+    std::fs::write(wiki.path("tome.toml"), "port = \"eighty\"").unwrap();
+    let check = tome::run(wiki.cli(&["config", "check"])).await.unwrap_err();
+    assert!(
+        check.to_string().contains("tome.toml is invalid"),
+        "{check}"
+    );
+}
+
+#[tokio::test]
+async fn deletes_restores_and_purges_articles() {
+    let wiki = TestWiki::new();
+    wiki.save("Deleted", "a version only this article has")
+        .await;
+    let response = wiki.post("/article/deleted/delete").await;
+    assert_eq!(response.location.as_deref(), Some("/overview"));
+    // Missing articles open the editor to create them
+    let response = wiki.get("/article/deleted").await;
+    assert_eq!(response.location.as_deref(), Some("/edit/article/deleted"));
+
+    let id = wiki.trashed().await.first().unwrap().clone();
+    let response = wiki.post(&format!("/admin/trash/{id}/restore")).await;
+    assert_eq!(response.location.as_deref(), Some("/article/deleted"));
+    assert!(wiki
+        .get("/article/deleted")
+        .await
+        .body
+        .contains("a version only this article has"));
+
+    wiki.post("/article/deleted/delete").await;
+    let id = wiki.trashed().await.first().unwrap().clone();
+    let response = wiki.post(&format!("/admin/trash/{id}/purge")).await;
+    assert_eq!(response.location.as_deref(), Some("/admin/trash"));
+    assert!(!wiki.trashed().await.contains(&id));
+    let response = wiki.post(&format!("/admin/trash/{id}/restore")).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn refuses_to_overwrite_unreadable_indexes() {
+    let wiki = TestWiki::new();
+    wiki.save("Indexed", "The first version").await;
+    let versions = wiki.path("content/articles/indexed/versions.json");
+    std::fs::write(&versions, "[{\"id\": ").unwrap();
+    let response = wiki.save("Indexed", "The second version").await;
+    assert_eq!(response.status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(std::fs::read_to_string(&versions).unwrap(), "[{\"id\": ");
+
+    wiki.save("Trashed", "In the trash").await;
+    let trash = wiki.path("content/trash.json");
+    std::fs::write(&trash, "not json").unwrap();
+    let response = wiki.post("/article/trashed/delete").await;
+    assert_eq!(response.status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(std::fs::read_to_string(&trash).unwrap(), "not json");
+    assert_eq!(wiki.get("/article/trashed").await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn keeps_the_append_only_history_verifiable() {
+    let wiki = TestWiki::new();
+    // Deleted before the history became append-only
+    wiki.save("Earlier", "Deleted early").await;
+    wiki.post("/article/earlier/delete").await;
+    let id = wiki.trashed().await.first().unwrap().clone();
+
+    let router = tome::app(wiki.config("append_only_history = true"))
+        .await
+        .unwrap();
+    let send = |request: Request<Body>| {
+        let router = router.clone();
+        async move { router.oneshot(request).await.unwrap().status() }
+    };
+    let form = |uri: &str, form: &[(&str, &str)]| {
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(serde_urlencoded::to_string(form).unwrap()))
+            .unwrap()
+    };
+    let edit = [
+        ("title", "Logged"),
+        ("original_title", "Logged"),
+        ("content", "Recorded"),
+    ];
+    assert_eq!(
+        send(form("/article/edit", &edit)).await,
+        StatusCode::SEE_OTHER
+    );
+    assert_eq!(
+        send(form("/article/logged/delete", &[])).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        send(form("/article/logged/rename", &[("title", "Moved")])).await,
+        StatusCode::SEE_OTHER
+    );
+    assert_eq!(
+        send(form(&format!("/admin/trash/{id}/restore"), &[])).await,
+        StatusCode::SEE_OTHER
+    );
+
+    let log = std::fs::read_to_string(wiki.path("content/history.log")).unwrap();
+    assert!(log.contains("Renamed from Logged"));
+    assert!(log.contains("Restored from the trash"));
+    tome::run(wiki.cli(&["history", "verify"])).await.unwrap();
+}
+
+#[tokio::test]
+async fn only_users_can_edit() {
+    let wiki = TestWiki::new();
+    let _ = wiki.app().await;
+    // The password is "correct horse"
+    let config: TomeConfig = wiki.config(
+            r#"
+            admin_user = "editor"
+            admin_password_hash = "$argon2id$v=19$m=19456,t=2,p=1$RUT9xXtVCiS0hbxNuuSjLg$txZZL9n9cmfX6Ohf7ElB32tBCeq/Ky0EVHV9L+uWlfE"
+            "#,
+        );
+    let router = tome::app(config).await.unwrap();
+    let form = |body: &str, cookie: &str| {
+        Request::post("/article/edit")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(header::COOKIE, cookie)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let article = "title=Protected&original_title=Protected&content=Only+for+users";
+
+    let response = router.clone().oneshot(form(article, "")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = router
+        .clone()
+        .oneshot(Request::get("/edit/index").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()[header::LOCATION],
+        "/login?next=%2Fedit%2Findex"
+    );
+
+    let login = |password: &str| {
+        Request::post("/login")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(format!(
+                "user=editor&password={password}&next=%2Fedit%2Findex"
+            )))
+            .unwrap()
+    };
+    let response = router.clone().oneshot(login("wrong")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = router
+        .clone()
+        .oneshot(login("correct%20horse"))
+        .await
+        .unwrap();
+    assert_eq!(response.headers()[header::LOCATION], "/edit/index");
+    let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+    let cookie = cookie.split(';').next().unwrap().to_string();
+
+    let response = router
+        .clone()
+        .oneshot(form(article, &cookie))
+        .await
+        .unwrap();
+    assert!(response.status().is_redirection());
+    let response = wiki.get("/article/protected").await;
+    assert!(response.body.contains("Only for users"));
+}
+
+#[tokio::test]
+async fn serves_custom_styles() {
+    let wiki = TestWiki::new();
+    let _ = wiki.app().await;
+    std::fs::write(wiki.path("content/custom.css"), "h1 { color: teal; }").unwrap();
+    let config: TomeConfig = wiki.config(r##"accent_color = "#8c4799""##);
+    let response = tome::app(config)
+        .await
+        .unwrap()
+        .oneshot(Request::get("/custom.css").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "text/css");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let css = String::from_utf8_lossy(&body);
+    assert!(css.contains("--tome-accent: #8c4799;"));
+    assert!(css.ends_with("h1 { color: teal; }"));
+}
+
+#[tokio::test]
+async fn embeds_media_in_html_exports() {
+    let wiki = TestWiki::new();
+    wiki.upload("embedded.png", &png(b"embedded")).await;
+    wiki.save("Embedding", "![Media](/media/embedded.png)")
+        .await;
+    let html = wiki.get("/article/embedding/export.html").await.body;
+    assert!(html.contains(r#"<img src="data:image/png;base64,"#));
+}
+
+#[tokio::test]
+async fn adds_notices_to_exports() {
+    let wiki = TestWiki::new();
+    wiki.save("Exported", "Some text").await;
+    let config: TomeConfig = wiki.config(
+        r#"
+            public_url = "https://wiki.example.com/"
+            export_footer = "{title} from {url} is confidential"
+            "#,
+    );
+    let router = tome::app(config).await.unwrap();
+    let notice = "Exported from https://wiki.example.com/article/exported is confidential";
+    for uri in ["/article/exported/export.html", "/article/exported"] {
+        let response = router
+            .clone()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains(notice), "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn shows_licenses() {
+    let wiki = TestWiki::new();
+    wiki.save("Licensed", "Free text").await;
+    wiki.save("Relicensed", "---\nlicense: MIT\n---\nOther text")
+        .await;
+    let config: TomeConfig = wiki.config(r#"license = "CC BY-SA 4.0""#);
+    let router = tome::app(config).await.unwrap();
+    let page = |uri: &'static str| {
+        let router = router.clone();
+        async move {
+            let response = router
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            String::from_utf8_lossy(&body).into_owned()
+        }
+    };
+
+    let deed = r#"<a rel="license" href="https://creativecommons.org/licenses/by-sa/4.0/">CC BY-SA 4.0</a>"#;
+    assert!(page("/article/licensed").await.contains(deed));
+    assert!(page("/article/licensed/export.html").await.contains(deed));
+    let relicensed = page("/article/relicensed").await;
+    assert!(relicensed.contains("MIT"));
+    assert!(!relicensed.contains("CC BY-SA"));
+}
+
+#[tokio::test]
+async fn renders_wikilinks() {
+    let wiki = TestWiki::new();
+    wiki.save(
+        "Linking",
+        "See [[Team:Ops Runbook]], [[Other page|the other one]] and `[[not a link]]`.",
+    )
+    .await;
+    let response = wiki.get("/article/linking").await;
+    assert!(response
+        .body
+        .contains(r#"<a href="/article/Team%3AOps%20Runbook" data-preview="/api/preview/Team%3AOps%20Runbook">Team:Ops Runbook</a>"#));
+    assert!(response.body.contains(
+        r#"href="/article/Other%20page" data-preview="/api/preview/Other%20page">the other one</a>"#
+    ));
+    assert!(response.body.contains("<code>[[not a link]]</code>"));
+}
+
+#[tokio::test]
+async fn links_mentions_to_user_pages() {
+    let wiki = TestWiki::new();
+    wiki.save(
+        "Mentioning",
+        "Ask @grace.hopper. Not mail@example.com or `@code`.",
+    )
+    .await;
+    let response = wiki.get("/article/mentioning").await;
+    assert!(response
+        .body
+        .contains(r#"<a href="/user/grace.hopper">@grace.hopper</a>."#));
+    assert!(response.body.contains("mail@example.com"));
+    assert!(!response.body.contains("/user/example.com"));
+    assert!(response.body.contains("<code>@code</code>"));
+
+    let response = wiki.get("/user/grace.hopper").await;
+    assert!(response
+        .body
+        .contains(r#"<a href="/article/mentioning">Mentioning</a>"#));
+}
+
+#[tokio::test]
+async fn edits_single_sections() {
+    let wiki = TestWiki::new();
+    wiki.save(
+        "Sectioned",
+        "# Sectioned\n\n## One\n\nFirst\n\n## Two\n\nSecond",
+    )
+    .await;
+    let edit_section = |section: &'static str, content: &'static str| {
+        edit_request(&[
+            ("title", "Sectioned"),
+            ("original_title", "Sectioned"),
+            ("content", content),
+            ("section", section),
+        ])
+    };
+
+    let response = wiki.send(edit_section("2", "## Two\n\nChanged")).await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+    let page = wiki.get("/article/sectioned").await.body;
+    assert!(page.contains("First"));
+    assert!(page.contains("Changed"));
+
+    // A section that no longer exists must not replace the whole article
+    let response = wiki.send(edit_section("7", "## Lost\n\nOnly this")).await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+    let page = wiki.get("/article/sectioned").await.body;
+    assert!(page.contains("First"));
+    assert!(!page.contains("Only this"));
+}
+
+#[tokio::test]
+async fn links_to_sections_and_blocks() {
+    let wiki = TestWiki::new();
+    wiki.save(
+        "Referenced",
+        "Intro\n\n## Getting started\n\nFirst steps\n\n## Later\n\nAn important point ^point\n",
+    )
+    .await;
+    wiki.save(
+        "Referencing",
+        "[[Referenced#Getting started]] and [[Referenced#^point|that point]]",
+    )
+    .await;
+
+    let response = wiki.get("/article/referencing").await;
+    assert!(response
+        .body
+        .contains(r#"<a href="/article/Referenced#getting-started" data-preview="/api/fragment?article=Referenced&amp;id=getting-started">"#));
+    assert!(response
+        .body
+        .contains(r#"href="/article/Referenced#^point" data-preview="/api/fragment?article=Referenced&amp;id=%5Epoint">that point</a>"#));
+    let response = wiki.get("/article/referenced").await;
+    assert!(response
+        .body
+        .contains(r#"<p id="^point" dir="auto">An important point</p>"#));
+
+    let response = wiki
+        .get("/api/fragment?article=Referenced&id=getting-started")
+        .await;
+    assert!(response.body.contains("First steps"));
+    assert!(!response.body.contains("important"));
+    let response = wiki
+        .get("/api/fragment?article=Referenced&id=%5Epoint")
+        .await;
+    assert!(response.body.contains("An important point"));
+    assert!(!response.body.contains("^point"));
+    let response = wiki
+        .get("/api/fragment?article=Referenced&id=missing")
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn lists_recent_changes() {
+    let wiki = TestWiki::new();
+    wiki.save("Changed first", "One").await;
+    wiki.save("Changed second", "Two").await;
+    let response = wiki.get("/changes").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let first = response.body.find(">Changed first</a>").unwrap();
+    let second = response.body.find(">Changed second</a>").unwrap();
+    assert!(second < first);
+}
+
+#[tokio::test]
+async fn has_feeds_of_changes() {
+    let wiki = TestWiki::new();
+    wiki.save("In the feed", "One").await;
+    let response = wiki.get("/changes.atom").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.body.contains("<title>In the feed</title>"));
+    assert!(response.body.contains("/article/in-the-feed/history/"));
+
+    let response = wiki.get("/article/in-the-feed/history.atom").await;
+    assert!(response
+        .body
+        .contains("<title>History of In the feed</title>"));
+    assert_eq!(response.body.matches("<entry>").count(), 1);
+    assert_eq!(
+        wiki.get("/article/not-in-the-feed/history.atom")
+            .await
+            .status,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn previews_linked_articles() {
+    let wiki = TestWiki::new();
+    wiki.save(
+        "Previewed",
+        &format!("{}\n\n## More\n\nNot in the preview", "word ".repeat(100)),
+    )
+    .await;
+    wiki.save("Previewing", "See [[Previewed]] or [Previewed]")
+        .await;
+
+    let response = wiki.get("/article/previewing").await;
+    assert!(response
+        .body
+        .contains(r#"href="/article/Previewed" data-preview="/api/preview/Previewed">"#));
+    let response = wiki.get("/api/preview/Previewed").await;
+    assert!(response.body.contains("word word"));
+    assert!(response.body.contains("…"));
+    assert!(!response.body.contains("Not in the preview"));
+    let response = wiki.get("/api/preview/Not%20there").await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn renders_tables_of_contents() {
+    let wiki = TestWiki::new();
+    wiki.save(
+        "Contents",
+        "Intro\n\n[TOC]\n\n## First `part`\n\n### Detail\n\n## Second\n\n```\n[TOC]\n```",
+    )
+    .await;
+    let body = wiki.get("/article/contents").await.body;
+    assert!(body.contains(
+        r##"<nav class="toc" aria-label="Contents"><ul><li><a href="#first-part">First part</a><ul><li><a href="#detail">Detail</a></li></ul></li><li><a href="#second">Second</a></li></ul></nav>"##
+    ));
+    assert!(body.contains(r#"<h2 id="first-part""#));
+    assert!(body.contains("<code>[TOC]"));
+
+    wiki.save("Contents by flag", "---\ntoc: true\n---\nIntro\n\n## Only")
+        .await;
+    let body = wiki.get("/article/contents-by-flag").await.body;
+    assert!(body.contains(r##"<nav class="toc" aria-label="Contents"><ul><li><a href="#only">"##));
+    assert!(body.find("<nav class=\"toc\"").unwrap() < body.find("Intro").unwrap());
+}
+
+#[tokio::test]
+async fn renders_live_previews() {
+    let wiki = TestWiki::new();
+    let response = wiki.send(
+        Request::post("/preview?title=Drafted")
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(Body::from(
+                "---\ntags:\n  - draft\n---\n[[Other Page]] and **bold**<script>alert(1)</script>",
+            ))
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.body.contains(r#"href="/article/Other%20Page""#));
+    assert!(response.body.contains("<strong>bold</strong>"));
+    assert!(!response.body.contains("tags:"));
+    assert!(!response.body.contains("<script>"));
+}
+
+#[tokio::test]
+async fn links_to_versions_permanently() {
+    let wiki = TestWiki::new();
+    wiki.save("Cited", "What was cited").await;
+    let body = wiki.get("/article/cited").await.body;
+    let start = body.find("/article/cited/permalink/").unwrap();
+    let permalink = &body[start..start + body[start..].find('"').unwrap()];
+    assert!(wiki.get(permalink).await.body.contains("What was cited"));
+
+    wiki.save("Cited", "What it says now").await;
+    let response = wiki.get(permalink).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.body.contains("What was cited"));
+    assert!(response.body.contains("permanent link"));
+    let response = wiki.get("/article/cited/permalink/..%2F..%2Fusers").await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn shows_edit_summaries_in_history() {
+    let wiki = TestWiki::new();
+    let form = serde_urlencoded::to_string([
+        ("title", "Summarized"),
+        ("content", "Text"),
+        ("summary", "  Fix <typos>  "),
+    ])
+    .unwrap();
+    wiki.send(
+        Request::post("/article/edit")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(form))
+            .unwrap(),
+    )
+    .await;
+    wiki.save("Summarized", "More text").await;
+
+    let response = wiki.get("/article/summarized/history").await;
+    assert!(response.body.contains("<p>Fix &lt;typos&gt;</p>"));
+    assert_eq!(response.body.matches("<p>Fix").count(), 1);
+}
+
+#[tokio::test]
+async fn rejects_conflicting_edits() {
+    let wiki = TestWiki::new();
+    wiki.save("Contested", "First").await;
+    let body = wiki.get("/edit/article/contested").await.body;
+    let start = body.find(r#"name="base_version" value=""#).unwrap() + 27;
+    let base = body[start..start + body[start..].find('"').unwrap()].to_string();
+
+    assert!(wiki
+        .save_from("Contested", "Mine", &base)
+        .await
+        .location
+        .is_some());
+    let response = wiki.save_from("Contested", "Theirs", &base).await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+    assert!(response.body.contains("Theirs</textarea>"));
+    assert!(wiki.get("/article/contested").await.body.contains("Mine"));
+
+    // Creating an article someone else created in the meantime conflicts, too
+    assert_eq!(
+        wiki.save_from("Contested", "New", "").await.status,
+        StatusCode::CONFLICT
+    );
+}
+
+#[tokio::test]
+async fn tags_versions() {
+    let wiki = TestWiki::new();
+    wiki.save("Policy", "Approved text").await;
+    let body = wiki.get("/article/policy/history").await.body;
+    let start = body.find(r#"name="from" value=""#).unwrap() + 19;
+    let version = &body[start..start + 36];
+    let wiki = &wiki;
+    let tag = |version: String| async move {
+        wiki.send(
+            Request::post(format!("/article/policy/history/{version}/tag"))
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from("name=approved-2024-06"))
+                .unwrap(),
+        )
+        .await
+    };
+    assert_eq!(
+        tag(version.to_string()).await.location.as_deref(),
+        Some("/article/policy/history")
+    );
+    wiki.save("Policy", "Draft text").await;
+
+    let response = wiki.get("/article/policy/tags/approved-2024-06").await;
+    let permalink = response.location.unwrap();
+    assert_eq!(permalink, format!("/article/policy/permalink/{version}"));
+    assert!(wiki.get(&permalink).await.body.contains("Approved text"));
+    assert!(wiki
+        .get("/article/policy/tags")
+        .await
+        .body
+        .contains(">approved-2024-06</a>"));
+
+    let body = wiki.get("/article/policy/history").await.body;
+    let start = body.find(r#"name="from" value=""#).unwrap() + 19;
+    let newest = body[start..start + 36].to_string();
+    assert_eq!(tag(newest).await.status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn snapshots_the_wiki() {
+    let wiki = TestWiki::new();
+    wiki.save("Released", "Version one, see [[Release notes]]")
+        .await;
+    wiki.save("Release notes", "Notes").await;
+    tome::run(wiki.cli(&["snapshot", "create", "release-1.0"]))
+        .await
+        .unwrap();
+    assert!(tome::run(wiki.cli(&["snapshot", "create", "release-1.0"]))
+        .await
+        .is_err());
+    wiki.save("Released", "Version two").await;
+
+    assert!(wiki
+        .get("/snapshot/release-1.0")
+        .await
+        .body
+        .contains(">Released</a>"));
+    let response = wiki.get("/snapshot/release-1.0/released").await;
+    assert!(response.body.contains("Version one"));
+    assert!(response
+        .body
+        .contains(r#"href="/snapshot/release-1.0/release-notes""#));
+    assert_eq!(
+        wiki.get("/snapshot/..%2Fusers").await.status,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn turns_away_changes_while_frozen() {
+    let wiki = TestWiki::new();
+    let _ = wiki.app().await;
+    let config = wiki.config(r#"freeze_windows = ["2000-01-01T00:00:00Z/2999-01-01T00:00:00Z"]"#);
+    let frozen = tome::app(config).await.unwrap();
+    let form = "title=Frozen&original_title=Frozen&content=Text";
+    let response = frozen
+        .clone()
+        .oneshot(
+            Request::post("/article/edit")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(form))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let response = frozen
+        .oneshot(Request::get("/article/crud").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_ne!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        wiki.get("/article/frozen").await.location.as_deref(),
+        Some("/edit/article/frozen")
+    );
+}
+
+#[tokio::test]
+async fn serves_raw_markdown() {
+    let wiki = TestWiki::new();
+    wiki.save("Raw", "# Heading\n\n*Not rendered*").await;
+    let response = wiki
+        .app()
+        .await
+        .oneshot(
+            Request::get("/article/raw/raw")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/markdown; charset=utf-8"
+    );
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    // The display title is kept in the frontmatter
+    assert!(String::from_utf8_lossy(&body).ends_with("# Heading\n\n*Not rendered*"));
+    assert_eq!(
+        wiki.get("/article/not-raw/raw").await.status,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn lists_backlinks() {
+    let wiki = TestWiki::new();
+    wiki.save("Linked to", "Nothing").await;
+    wiki.save("Linking wikilink", "See [[Linked to#Section]]")
+        .await;
+    wiki.save("Linking markdown", "See [here](/article/linked-to)")
+        .await;
+    wiki.save("Not linking", "Linked to").await;
+
+    let body = wiki.get("/article/linked-to").await.body;
+    let backlinks = &body[body.find("What links here").unwrap()..];
+    assert!(backlinks.contains(r#"<a href="/article/linking-markdown">Linking markdown</a>"#));
+    assert!(backlinks.contains(r#"<a href="/article/linking-wikilink">Linking wikilink</a>"#));
+    assert!(!backlinks.contains("Not linking"));
+
+    wiki.save("Linking markdown", "No more links").await;
+    assert!(!wiki
+        .get("/article/linked-to")
+        .await
+        .body
+        .contains("Linking markdown"));
+}
+
+#[tokio::test]
+async fn trusts_users_logged_in_by_a_proxy() {
+    let wiki = TestWiki::new();
+    let _ = wiki.app().await;
+    let config = wiki.config(
+        r#"
+            proxy_user_header = "Remote-User"
+            proxy_groups_header = "Remote-Groups"
+            proxy_editor_groups = ["editors"]
+            trusted_proxies = ["127.0.0.1"]
+            "#,
+    );
+    let proxied = tome::app(config).await.unwrap();
+    let open_editor = |from: [u8; 4], groups: &str| {
+        let request = Request::get("/edit/article/proxied")
+            .extension(axum::extract::ConnectInfo(std::net::SocketAddr::from((
+                from, 40000,
+            ))))
+            .header("Remote-User", "alice")
+            .header("Remote-Groups", groups)
+            .body(Body::empty())
+            .unwrap();
+        let proxied = proxied.clone();
+        async move { proxied.oneshot(request).await.unwrap().status() }
+    };
+    assert_eq!(
+        open_editor([127, 0, 0, 1], "users, editors").await,
+        StatusCode::OK
+    );
+    // Other groups can only read
+    assert_eq!(
+        open_editor([127, 0, 0, 1], "users").await,
+        StatusCode::SEE_OTHER
+    );
+    // Anyone else could send the header, too
+    assert_eq!(
+        open_editor([10, 0, 0, 1], "editors").await,
+        StatusCode::SEE_OTHER
+    );
+}
+
+#[tokio::test]
+async fn lists_articles_by_tag() {
+    let wiki = TestWiki::new();
+    wiki.save("Tagged soup", "---\ntags: [Recipe, vegan]\n---\nSoup")
+        .await;
+    wiki.save("Tagged bread", "---\ntags: [recipe]\n---\nBread")
+        .await;
+
+    let body = wiki.get("/article/tagged-soup").await.body;
+    assert!(body.contains(r#"<a class="tag" href="/tags/vegan">vegan</a>"#));
+    assert!(!body.contains("tags: ["));
+    let body = wiki.get("/tags/recipe").await.body;
+    assert!(body.contains(">Tagged bread</a>") && body.contains(">Tagged soup</a>"));
+    assert!(wiki
+        .get("/tags")
+        .await
+        .body
+        .contains(r#"href="/tags/vegan""#));
+}
+
+#[tokio::test]
+async fn applies_upload_policies() {
+    let wiki = TestWiki::new();
+    let _ = wiki.app().await;
+    // The administrator's password is "correct horse", everyone else logs in through a proxy
+    let config = wiki.config(
+            r#"
+            admin_user = "editor"
+            admin_password_hash = "$argon2id$v=19$m=19456,t=2,p=1$RUT9xXtVCiS0hbxNuuSjLg$txZZL9n9cmfX6Ohf7ElB32tBCeq/Ky0EVHV9L+uWlfE"
+            proxy_user_header = "Remote-User"
+            trusted_proxies = ["127.0.0.1"]
+            upload_policies = [
+                { name = "images", endings = [".png"], max_size = 16 },
+                { name = "documents", endings = [".pdf"], role = "admin" },
+            ]
+            "#,
+        );
+    let policed = tome::app(config).await.unwrap();
+    let login = Request::post("/login")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("user=editor&password=correct%20horse"))
+        .unwrap();
+    let response = policed.clone().oneshot(login).await.unwrap();
+    let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+    let admin = cookie.split(';').next().unwrap().to_string();
+
+    let upload_as = |file_name: &str, data: &[u8], cookie: Option<&str>| {
+        let mut request = upload_request(file_name, data);
+        match cookie {
+            Some(cookie) => {
+                request
+                    .headers_mut()
+                    .insert(header::COOKIE, cookie.parse().unwrap());
+            }
+            None => {
+                request
+                    .headers_mut()
+                    .insert("Remote-User", "alice".parse().unwrap());
+                request.extensions_mut().insert(axum::extract::ConnectInfo(
+                    std::net::SocketAddr::from(([127, 0, 0, 1], 40000)),
+                ));
+            }
+        }
+        let policed = policed.clone();
+        async move { policed.oneshot(request).await.unwrap().status() }
+    };
+    assert_eq!(
+        upload_as("policy-small.png", &png(b"tiny"), None).await,
+        StatusCode::SEE_OTHER
+    );
+    assert_eq!(
+        upload_as(
+            "policy-large.png",
+            &png(b"far more than sixteen bytes"),
+            None
+        )
+        .await,
+        StatusCode::PAYLOAD_TOO_LARGE
+    );
+    assert_eq!(
+        upload_as("policy.pdf", b"%PDF", None).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        upload_as("policy.pdf", b"%PDF", Some(&admin)).await,
+        StatusCode::SEE_OTHER
+    );
+    assert_eq!(
+        upload_as("policy.sh", b"echo hi", Some(&admin)).await,
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    );
+}
+
+#[tokio::test]
+async fn reads_metadata_from_frontmatter() {
+    let wiki = TestWiki::new();
+    wiki.save(
+        "Metadata",
+        "+++\ncreated = 2024-01-31\ndraft = true\nauthor = \"Ada\"\ntags = [\"toml\"]\n+++\nThe body",
+    )
+    .await;
+
+    let body = wiki.get("/article/metadata").await.body;
+    assert!(body.contains("This article is a draft"));
+    assert!(body.contains("Written on 2024-01-31"));
+    assert!(body.contains("<dt>author</dt>\n    <dd>Ada</dd>"));
+    assert!(body.contains(r#"href="/tags/toml""#));
+    assert!(!body.contains("+++"));
+}
+
+#[tokio::test]
+async fn creates_articles_from_pasted_text() {
+    let wiki = TestWiki::new();
+    let response = wiki
+        .send(
+            Request::post("/new")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from("title=&text=%23+Pasted+notes%0A%0ASome+text"))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.location.as_deref(), Some("/article/pasted-notes"));
+    assert!(wiki
+        .get("/article/pasted-notes")
+        .await
+        .body
+        .contains("Some text"));
+
+    // The title is taken, so the second paste gets a number
+    let response = wiki
+        .send(
+            Request::post("/api/articles")
+                .header(header::CONTENT_TYPE, "text/plain")
+                .body(Body::from("# Pasted notes\n\nMore text"))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::CREATED);
+    assert_eq!(
+        response.location.as_deref(),
+        Some("/article/pasted-notes-2")
+    );
+    assert!(wiki
+        .get("/article/pasted-notes-2")
+        .await
+        .body
+        .contains("More text"));
+}
+
+#[tokio::test]
+async fn renders_errors_for_browsers() {
+    let wiki = TestWiki::new();
+    let response = wiki
+        .send(
+            Request::get("/article/%25FF")
+                .header(header::ACCEPT, "text/html,application/xhtml+xml")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert!(response.body.contains("Page Not Found"));
+
+    let response = wiki.get("/article/%25FF").await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert_eq!(response.body, "Not Found");
+}
+
+#[tokio::test]
+async fn links_titles_of_articles_automatically() {
+    let wiki = TestWiki::new();
+    wiki.save("Quokka", "A marsupial").await;
+    wiki.save("Quokka Habitat", "Rottnest Island").await;
+    wiki.save(
+        "Marsupials",
+        "The quokka habitat is small. Quokka are cute, quokka!\n\n```\nQuokka\n```",
+    )
+    .await;
+    wiki.save("Quokka Facts", "---\nautolink: false\n---\nQuokka Habitat")
+        .await;
+    let config: TomeConfig = wiki.config("autolink_titles = true");
+    let router = tome::app(config).await.unwrap();
+    let page = |uri: &'static str| {
+        let router = router.clone();
+        async move {
+            let response = router
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            String::from_utf8_lossy(&body).into_owned()
+        }
+    };
+
+    let body = page("/article/marsupials").await;
+    assert!(body.contains(r#"<a href="/article/quokka-habitat""#));
+    assert!(body.contains(">quokka habitat</a>"));
+    assert_eq!(body.matches(r#"<a href="/article/quokka""#).count(), 1);
+    assert!(body.contains(">Quokka</a> are cute, quokka!"));
+    assert!(body.contains("<code>Quokka\n</code>"));
+
+    let body = page("/article/quokka-facts").await;
+    assert!(!body.contains(r#"<a href="/article/quokka-habitat""#));
+}
+
+#[tokio::test]
+async fn removes_unsafe_html_from_articles() {
+    let wiki = TestWiki::new();
+    wiki.save(
+        "Unsafe HTML",
+        "<img src=\"/media/cat.png\" alt=\"Cat\" onerror=\"alert(1)\">\n\n\
+         <details><summary>More</summary><a href=\"javascript:alert(1)\">Click</a></details>\n\n\
+         <a href=\"/elsewhere\" data-preview=\"https://evil.example/x.json\">Hover</a> \
+         <a href=\"/media/x.pdf\" data-pdf=\"https://evil.example/x.pdf\">PDF</a>\n\n\
+         - [x] Done",
+    )
+    .await;
+    let response = wiki.get("/article/unsafe-html").await;
+    assert!(response
+        .body
+        .contains(r#"<img src="/media/cat.png" alt="Cat">"#));
+    assert!(!response.body.contains("onerror"));
+    assert!(response.body.contains("<details><summary>More</summary>"));
+    assert!(!response.body.contains("javascript:"));
+    assert!(!response.body.contains("evil.example"));
+    assert!(response
+        .body
+        .contains(r#"<input disabled="" type="checkbox" checked="""#));
+}
+
+#[tokio::test]
+async fn explains_glossary_terms() {
+    let wiki = TestWiki::new();
+    wiki.save(
+        "Jargon",
+        "# Jargon\n\n- QPU: Quantum Processing Unit\n- **SLA**: Service \"level\" agreement",
+    )
+    .await;
+    wiki.save(
+        "Cloud Contract",
+        "The SLA covers every QPU, not a qpu. `SLA`",
+    )
+    .await;
+    let config: TomeConfig = wiki.config(r#"glossary_article = "Jargon""#);
+    let response = tome::app(config)
+        .await
+        .unwrap()
+        .oneshot(
+            Request::get("/article/cloud-contract")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body = String::from_utf8_lossy(&body);
+    assert!(body.contains(r#"<abbr title="Service &quot;level&quot; agreement">SLA</abbr>"#));
+    assert!(body.contains(r#"every <abbr title="Quantum Processing Unit">QPU</abbr>, not a qpu."#));
+    assert!(body.contains("<code>SLA</code>"));
+}
+
+#[tokio::test]
+async fn keeps_names_inside_the_content_directory() {
+    let wiki = TestWiki::new();
+    let response = wiki.upload("../escaped.png", b"outside").await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert!(!wiki.path("content/escaped.png").exists());
+    let response = wiki.upload(".hidden.png", b"hidden").await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    wiki.save("Traversal", "Inside").await;
+    let response = wiki.get("/article/traversal/history/..%2F..%2Findex").await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert!(!response.body.contains("Welcome"));
+}
+
+#[tokio::test]
+async fn deletes_and_renames_media() {
+    let wiki = TestWiki::new();
+    wiki.upload("mistake.png", &png(b"oops")).await;
+    let rename = |from: &str, to: &str| {
+        Request::post(format!("/media/{from}/rename"))
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(format!("name={to}")))
+            .unwrap()
+    };
+    let response = wiki.send(rename("mistake.png", "fixed.png")).await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+    assert_eq!(
+        wiki.get("/media/mistake.png").await.status,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        wiki.get("/media/fixed.png").await.body,
+        String::from_utf8_lossy(&png(b"oops"))
+    );
+
+    let response = wiki.send(rename("fixed.png", "fixed.html")).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let response = wiki.send(rename("fixed.png", "..%2Ffixed.png")).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    let delete = |name: &str| {
+        Request::post(format!("/media/{name}/delete"))
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = wiki.send(delete("fixed.png")).await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+    assert_eq!(
+        wiki.get("/media/fixed.png").await.status,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        wiki.send(delete("fixed.png")).await.status,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn applies_namespace_defaults() {
+    let wiki = TestWiki::new();
+    wiki.save("Template:Meeting", "# {{title}}\n\n## Attendees")
+        .await;
+    let config: TomeConfig = wiki.config(
+        r##"
+            namespaces = [
+                { name = "Crew", template = "Meeting", tags = ["crew"], accent_color = "#2a7ab0" },
+                { name = "Crew:Ops", tags = ["ops"], requires_review = true },
+            ]
+            "##,
+    );
+    let router = tome::app(config).await.unwrap();
+    let page = |uri: &'static str| {
+        let router = router.clone();
+        async move {
+            let response = router
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            String::from_utf8_lossy(&body).into_owned()
+        }
+    };
+
+    let body = page("/edit/article/Crew:Ops:Standup").await;
+    assert!(body.contains("# Crew:Ops:Standup"));
+    assert!(body.contains("requires_review: true"));
+    assert!(body.contains("  - ops\n  - crew"));
+    assert!(body.contains("--tome-accent: #2a7ab0"));
+
+    let body = page("/edit/article/Elsewhere").await;
+    assert!(!body.contains("Attendees"));
+    assert!(!body.contains("--tome-accent"));
+}
+
+#[tokio::test]
+async fn checks_the_contents_of_uploads() {
+    let wiki = TestWiki::new();
+    let response = wiki
+        .upload("disguised.png", b"MZ\x90\x00not an image")
+        .await;
+    assert_eq!(response.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let response = wiki.upload("pretend.png", &b"%PDF-1.7"[..]).await;
+    assert_eq!(response.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert!(response.body.contains(".pdf"));
+
+    let config: TomeConfig = wiki.config(
+        r#"
+            allowed_uploads = [".png", ".jpg"]
+            fix_upload_endings = true
+            "#,
+    );
+    let router = tome::app(config).await.unwrap();
+    let response = router
+        .clone()
+        .oneshot(upload_request("photo.jpg", b"\xff\xd8\xff\xe0 a jpeg"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let response = router
+        .oneshot(upload_request("renamed.jpg", &png(b"really a png")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(
+        wiki.get("/media/renamed.jpg").await.status,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(wiki.get("/media/renamed.png").await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn archives_articles() {
+    let wiki = TestWiki::new();
+    wiki.save("Vault:Old Plan", "Plans for the archivable quarter")
+        .await;
+    wiki.save("Vault:Older Plan", "More archivable plans").await;
+    let archive = |uri: &str, body: &'static str| {
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body))
+            .unwrap()
+    };
+    let response = wiki
+        .send(archive("/article/vault:old-plan/archive", "action=archive"))
+        .await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+
+    let page = wiki.get("/article/vault:old-plan").await.body;
+    assert!(page.contains("This article is archived"));
+    assert!(!page.contains("/edit/article/vault:old-plan"));
+    let response = wiki.save("Vault:Old Plan", "Changed anyway").await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        wiki.get("/edit/article/vault:old-plan").await.status,
+        StatusCode::FORBIDDEN
+    );
+
+    assert!(!wiki.get("/overview").await.body.contains("vault:old-plan"));
+    assert!(wiki
+        .get("/overview?archived=true")
+        .await
+        .body
+        .contains("vault:old-plan"));
+    let search = wiki.get("/search?q=archivable").await.body;
+    assert!(!search.contains("vault:old-plan"));
+    assert!(search.contains("vault:older-plan"));
+    assert!(wiki
+        .get("/search?q=archivable&archived=true")
+        .await
+        .body
+        .contains("vault:old-plan"));
+
+    let response = wiki
+        .send(archive(
+            "/overview/archive",
+            "namespace=Vault&action=unarchive",
+        ))
+        .await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+    assert_eq!(
+        wiki.save("Vault:Old Plan", "Changed again").await.status,
+        StatusCode::SEE_OTHER
+    );
+    wiki.send(archive(
+        "/overview/archive",
+        "namespace=Vault&action=archive",
+    ))
+    .await;
+    let overview = wiki.get("/overview?namespace=Vault").await.body;
+    assert!(!overview.contains("vault:older-plan"));
+}
+
+#[tokio::test]
+async fn reviews_changes_by_someone_else() {
+    let wiki = TestWiki::new();
+    wiki.save(
+        "Checked",
+        "---\nrequires_review: true\n---\nThe approved text",
+    )
+    .await;
+    let config = wiki.config(
+        r#"
+            proxy_user_header = "Remote-User"
+            trusted_proxies = ["127.0.0.1"]
+            inbox_token = "inbox secret"
+            inbox_article = "Checked"
+            "#,
+    );
+    let router = tome::app(config).await.unwrap();
+    let send =
+        |user: &str, uri: &str, form: &[(&str, &str)]| {
+            let mut request = Request::post(uri)
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .header("Remote-User", user)
+                .header(header::AUTHORIZATION, "Bearer inbox secret")
+                .body(Body::from(serde_urlencoded::to_string(form).unwrap()))
+                .unwrap();
+            request.extensions_mut().insert(axum::extract::ConnectInfo(
+                std::net::SocketAddr::from(([127, 0, 0, 1], 40000)),
+            ));
+            let router = router.clone();
+            async move {
+                let response = router.oneshot(request).await.unwrap();
+                let location = response
+                    .headers()
+                    .get(header::LOCATION)
+                    .map(|location| location.to_str().unwrap().to_string());
+                (response.status(), location)
+            }
+        };
+
+    let edit = [
+        ("title", "Checked"),
+        ("original_title", "Checked"),
+        (
+            "content",
+            "---\nrequires_review: true\n---\nThe proposed text",
+        ),
+    ];
+    let (_, location) = send("alice", "/article/edit", &edit).await;
+    let review = location.unwrap();
+    assert!(review.starts_with("/article/checked/review/"));
+    let (status, _) = send("alice", &review, &[("action", "approve")]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send("bob", &review, &[("action", "approve")]).await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    let history = wiki.get("/article/checked/history").await.body;
+    assert!(history.contains("/user/bob"));
+    assert!(history.contains("Submitted by alice"));
+
+    let (status, _) = send("alice", "/api/inbox", &[("text", "An inboxed note")]).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let replace = [
+        ("pattern", "proposed"),
+        ("replacement", "replaced"),
+        ("action", "apply"),
+    ];
+    send("alice", "/admin/replace", &replace).await;
+    let retag = [
+        ("operation", "add_tag"),
+        ("value", "retagged"),
+        ("action", "apply"),
+    ];
+    send("alice", "/admin/retag", &retag).await;
+    let rename = [
+        ("title", "Moved"),
+        ("original_title", "Checked"),
+        (
+            "content",
+            "---\nrequires_review: true\n---\nThe proposed text",
+        ),
+    ];
+    let (status, _) = send("alice", "/article/edit", &rename).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send("alice", "/article/checked/rename", &[("title", "Moved")]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let page = wiki.get("/article/checked").await.body;
+    assert!(page.contains("The proposed text"));
+    assert!(!page.contains("An inboxed note"));
+    assert!(!page.contains("retagged"));
+    assert_eq!(
+        wiki.get("/reviews").await.body.matches("/review/").count(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn keeps_frontmatter_that_does_not_parse() {
+    let wiki = TestWiki::new();
+    let content = "---\nsources: [unclosed\nowner: Ada\n---\nThe body";
+    wiki.save("Broken Meta", content).await;
+    let form = Request::post("/admin/retag")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("operation=add_tag&value=retagged&action=apply"))
+        .unwrap();
+    wiki.send(form).await;
+
+    let current = std::fs::read_to_string(wiki.path("content/articles/broken-meta/current.md"));
+    assert_eq!(current.unwrap(), content);
+}
+
+#[tokio::test]
+async fn keeps_archived_articles_unchanged() {
+    let wiki = TestWiki::new();
+    wiki.save("Shelved", "The first shelved draft").await;
+    let first = wiki.run(tome::Article::current_version("Shelved")).await;
+    wiki.save("Shelved", "---\narchived: true\n---\nThe shelved draft")
+        .await;
+    let form = |uri: &str, body: &'static str| {
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    let restore = format!("/article/shelved/history/{}/restore", first.unwrap());
+    assert_eq!(wiki.post(&restore).await.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        wiki.post("/article/shelved/delete").await.status,
+        StatusCode::FORBIDDEN
+    );
+    wiki.send(form(
+        "/admin/replace",
+        "pattern=shelved&replacement=rewritten&action=apply",
+    ))
+    .await;
+    wiki.send(form(
+        "/admin/retag",
+        "operation=add_tag&value=rewritten&action=apply",
+    ))
+    .await;
+
+    let page = wiki.get("/article/shelved").await.body;
+    assert!(page.contains("The shelved draft"));
+    assert!(!page.contains("rewritten"));
+}
+
+#[tokio::test]
+async fn lists_articles_as_json() {
+    let wiki = TestWiki::new();
+    let mut since = None;
+    for title in ["Listed C", "Listed A", "Listed B"] {
+        wiki.save(title, "---\ntags: [listed]\n---\nIn the list")
+            .await;
+        since.get_or_insert_with(|| {
+            time::OffsetDateTime::now_utc()
+                .format(&time::format_description::well_known::Rfc3339)
+                .unwrap()
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let wiki = &wiki;
+    let list = |uri: String| async move {
+        let response = wiki
+            .app()
+            .await
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+        (status, headers, json)
+    };
+
+    let (status, headers, json) = list("/api/articles?tag=listed&per_page=2".to_string()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["X-Total-Count"], "3");
+    assert_eq!(json[0]["title"], "Listed A");
+    assert_eq!(json[1]["title"], "Listed B");
+    let link = headers[header::LINK].to_str().unwrap();
+    assert!(link.contains("</api/articles?tag=listed&per_page=2&page=2>; rel=\"next\""));
+
+    let (_, headers, json) = list("/api/articles?tag=listed&per_page=2&page=2".to_string()).await;
+    assert_eq!(json.as_array().unwrap().len(), 1);
+    assert!(!headers[header::LINK]
+        .to_str()
+        .unwrap()
+        .contains("rel=\"next\""));
+
+    let (_, _, json) = list("/api/articles?tag=listed&sort=-modified".to_string()).await;
+    assert_eq!(json[0]["title"], "Listed B");
+
+    let since = urlencoding::encode(since.as_deref().unwrap()).into_owned();
+    let (_, headers, json) = list(format!("/api/changes?tag=listed&modified_since={since}")).await;
+    assert_eq!(headers["X-Total-Count"], "2");
+    assert_eq!(json[0]["title"], "Listed B");
+
+    let (status, _, _) = list("/api/articles?sort=size".to_string()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn limits_the_size_of_uploads() {
+    let wiki = TestWiki::new();
+    let config: TomeConfig = wiki.config(
+        r#"
+            allowed_uploads = [".png"]
+            max_upload_size = 64
+            "#,
+    );
+    let router = tome::app(config).await.unwrap();
+    let upload_to = |file_name: &str, data: Vec<u8>| {
+        let router = router.clone();
+        let request = upload_request(file_name, &data);
+        async move { router.oneshot(request).await.unwrap().status() }
+    };
+
+    assert_eq!(
+        upload_to("limited-small.png", png(b"small")).await,
+        StatusCode::SEE_OTHER
+    );
+    assert_eq!(
+        upload_to("limited-large.png", png(&[b'x'; 100])).await,
+        StatusCode::PAYLOAD_TOO_LARGE
+    );
+    assert_eq!(
+        upload_to("limited-huge.png", png(&[b'x'; 256 * 1024])).await,
+        StatusCode::PAYLOAD_TOO_LARGE
+    );
+    assert_eq!(
+        wiki.get("/media/limited-small.png").await.status,
+        StatusCode::OK
+    );
+    assert_eq!(
+        wiki.get("/media/limited-large.png").await.status,
+        StatusCode::NOT_FOUND
+    );
+    assert!(wiki.path("content/media/limited-small.png").exists());
+}
+
+#[tokio::test]
+async fn imports_media_from_a_directory() {
+    let wiki = TestWiki::new();
+    // Sets up the content directory
+    let _ = wiki.app().await;
+    let library = tempfile::tempdir().unwrap();
+    std::fs::create_dir(library.path().join("trip")).unwrap();
+    std::fs::write(library.path().join("library-cover.png"), png(b"cover")).unwrap();
+    std::fs::write(library.path().join("trip/library-beach.png"), png(b"beach")).unwrap();
+    std::fs::write(library.path().join("library-fake.png"), b"not an image").unwrap();
+    std::fs::write(library.path().join("library-notes.txt"), b"notes").unwrap();
+
+    let dir = library.path().to_str().unwrap();
+    let policy = r#"name = "images", endings = [".png"]"#;
+    tome::run(wiki.cli(&["--upload-policies", policy, "media", "import", dir]))
+        .await
+        .unwrap();
+    assert_eq!(
+        wiki.get("/media/library-cover.png").await.status,
+        StatusCode::OK
+    );
+    assert_eq!(
+        wiki.get("/media/library-beach.png").await.status,
+        StatusCode::OK
+    );
+    assert_eq!(
+        wiki.get("/media/library-fake.png").await.status,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        wiki.get("/media/library-notes.txt").await.status,
+        StatusCode::NOT_FOUND
+    );
+
+    // A different file with the same name isn't imported over the first
+    std::fs::write(library.path().join("library-cover.png"), png(b"other")).unwrap();
+    tome::run(wiki.cli(&["--upload-policies", policy, "media", "import", dir]))
+        .await
+        .unwrap();
+    assert_eq!(
+        wiki.get("/media/library-cover.png").await.body,
+        String::from_utf8_lossy(&png(b"cover"))
+    );
+    tome::run(wiki.cli(&[
+        "--upload-policies",
+        policy,
+        "media",
+        "import",
+        "--overwrite",
+        "--move",
+        dir,
+    ]))
+    .await
+    .unwrap();
+    assert_eq!(
+        wiki.get("/media/library-cover.png").await.body,
+        String::from_utf8_lossy(&png(b"other"))
+    );
+    assert!(!library.path().join("library-cover.png").exists());
+}
+
+#[tokio::test]
+async fn imports_obsidian_vaults() {
+    let wiki = TestWiki::new();
+    let _ = wiki.app().await; // Sets up the content directory
+    let vault = tempfile::tempdir().unwrap();
+    let write = |path: &str, content: &[u8]| {
+        let path = vault.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+    write(
+        "Vault Home.md",
+        b"---\ntags:\n  - vault\n---\nSee [[Vault Plan#Goals|the plan]], [[vault-notes/Vault Todo]] \
+          and [the log](Vault%20Log.md).\n\n![[vault-diagram.png|300]]\n\n![Photo](attachments/vault%20photo.jpg)",
+    );
+    write(
+        "vault-notes/Vault Plan.md",
+        b"## Goals\n\nBack to [[Vault Home]]",
+    );
+    write("vault-notes/Vault Todo.md", b"- [ ] Import");
+    write("vault-notes/Vault Log.md", b"Day one");
+    write("Vault Log.md", b"The other log");
+    write("attachments/vault-diagram.png", &png(b"diagram"));
+    write("attachments/vault photo.jpg", b"photo");
+    write(".obsidian/app.json", b"{}");
+
+    let path = vault.path().to_str().unwrap();
+    tome::run(wiki.cli(&["import", "--from", "obsidian", path]))
+        .await
+        .unwrap();
+
+    let home = wiki.run(tome::Article::load("vault-home")).await.unwrap();
+    assert_eq!(
+        home.content(),
+        "---\ntags:\n  - vault\ntitle: Vault Home\n---\nSee [[Vault Plan#Goals|the plan]], [[Vault Todo|vault-notes/Vault Todo]] \
+         and [[Vault Log|the log]].\n\n![](/media/vault-diagram.png)\n\n![Photo](/media/vault-photo.jpg)"
+    );
+    assert!(!wiki.get("/article/vault-home").await.body.contains("[["));
+    assert!(wiki
+        .run(tome::Article::load("vault-notes:vault-log"))
+        .await
+        .is_some());
+    assert_eq!(
+        wiki.run(tome::Article::load("vault-plan"))
+            .await
+            .unwrap()
+            .content(),
+        "---\ntitle: Vault Plan\n---\n## Goals\n\nBack to [[Vault Home]]"
+    );
+    assert_eq!(wiki.get("/media/vault-photo.jpg").await.body, "photo");
+    assert_eq!(
+        wiki.get("/media/app.json").await.status,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn serves_thumbnails_of_images() {
+    let wiki = TestWiki::new();
+    let mut image = Vec::new();
+    image::DynamicImage::new_rgb8(400, 100)
+        .write_to(
+            &mut std::io::Cursor::new(&mut image),
+            image::ImageFormat::Png,
+        )
+        .unwrap();
+    let response = wiki.upload("thumbnailed.png", &image).await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+    assert!(wiki
+        .get("/media")
+        .await
+        .body
+        .contains("/media/thumb/200/thumbnailed.png"));
+
+    let response = wiki
+        .app()
+        .await
+        .oneshot(
+            Request::get("/media/thumb/200/thumbnailed.png")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let thumbnail = image::load_from_memory(&body).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (200, 50));
+
+    assert_eq!(
+        wiki.get("/media/thumb/123/thumbnailed.png").await.status,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        wiki.get("/media/thumb/200/missing.png").await.status,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn shows_information_about_media_files() {
+    let wiki = TestWiki::new();
+    wiki.upload("file-info.png", &png(&[0; 2040])).await;
+    wiki.save(
+        "Shared files",
+        "Get {{file-info file-info.png}} or {{ file-info gone.pdf }}\n\n`{{file-info file-info.png}}`",
+    )
+    .await;
+    let body = wiki.get("/article/shared-files").await.body;
+    let today = time::OffsetDateTime::now_utc().date();
+    assert!(body.contains(&format!(
+        r#"<span class="file-info"><a href="/media/file-info.png">file-info.png</a> (2.0 KiB, uploaded {today}) <a class="button is-small" href="/media/file-info.png" download="">Download</a></span>"#
+    )));
+    assert!(body.contains(r#"<span class="file-info has-text-danger">gone.pdf (missing)</span>"#));
+    assert!(body.contains("<code>{{file-info file-info.png}}</code>"));
+}
+
+#[tokio::test]
+async fn serves_images_in_the_formats_browsers_accept() {
+    let wiki = TestWiki::new();
+    let _ = wiki.app().await; // Sets up the content directory
+    let config: TomeConfig = wiki.config(
+        r#"
+            allowed_uploads = ["png"]
+            image_variants = ["webp"]
+            "#,
+    );
+    let router = tome::app(config).await.unwrap();
+    let mut image = Vec::new();
+    image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(256, 256, |x, y| {
+        image::Rgb([x as u8, y as u8, 128])
+    }))
+    .write_to(
+        &mut std::io::Cursor::new(&mut image),
+        image::ImageFormat::Png,
+    )
+    .unwrap();
+    let response = router
+        .clone()
+        .oneshot(upload_request("converted.png", &image))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+    let fetch = |accept: &str| {
+        Request::get("/media/converted.png")
+            .header(header::ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = router
+        .clone()
+        .oneshot(fetch("image/avif,image/webp,*/*"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/webp");
+    assert_eq!(response.headers()[header::VARY], "Accept");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert!(body.len() < image.len());
+    let converted = image::load_from_memory_with_format(&body, image::ImageFormat::WebP).unwrap();
+    assert_eq!((converted.width(), converted.height()), (256, 256));
+
+    let response = router.oneshot(fetch("image/png,*/*")).await.unwrap();
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(&body[..], &image[..]);
+}
+
+#[tokio::test]
+async fn protects_media_from_hotlinking() {
+    let wiki = TestWiki::new();
+    wiki.upload("hotlinked.png", &png(b"popular")).await;
+    let config: TomeConfig = wiki.config(
+        r#"
+            hotlink_protection = true
+            hotlink_allowed_hosts = ["*.friends.example"]
+            hotlink_token = "shared"
+            "#,
+    );
+    let router = tome::app(config).await.unwrap();
+    let status = |uri: &str, referer: Option<&str>| {
+        let mut request = Request::get(uri).header(header::HOST, "wiki.example:5422");
+        if let Some(referer) = referer {
+            request = request.header(header::REFERER, referer);
+        }
+        let request = request.body(Body::empty()).unwrap();
+        let router = router.clone();
+        async move { router.oneshot(request).await.unwrap().status() }
+    };
+
+    let media = "/media/hotlinked.png";
+    assert_eq!(status(media, None).await, StatusCode::OK);
+    assert_eq!(
+        status(media, Some("http://wiki.example:5422/article/a")).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(media, Some("https://blog.friends.example/post")).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(media, Some("https://elsewhere.example/")).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        status(
+            "/media/thumb/200/hotlinked.png",
+            Some("https://friends.example.evil/")
+        )
+        .await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        status(
+            "/media/hotlinked.png?token=shared",
+            Some("https://elsewhere.example/")
+        )
+        .await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn counts_referrers_of_articles() {
+    let wiki = TestWiki::new();
+    wiki.save("Referred", "Linked from elsewhere").await;
+    let config: TomeConfig = wiki.config("analytics = true");
+    let router = tome::app(config).await.unwrap();
+    for referer in [
+        "https://news.example/item?id=1",
+        "https://news.example/front",
+        "http://wiki.example/article/other",
+    ] {
+        let request = Request::get("/article/referred")
+            .header(header::HOST, "wiki.example")
+            .header(header::REFERER, referer)
+            .body(Body::empty())
+            .unwrap();
+        router.clone().oneshot(request).await.unwrap();
+    }
+
+    let response = router
+        .clone()
+        .oneshot(
+            Request::get("/admin/analytics/referrers?path=/article/referred")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let referrers: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        referrers,
+        serde_json::json!([{ "path": "/article/referred", "referrer": "news.example", "views": 2 }])
+    );
+    let response = router
+        .oneshot(
+            Request::get("/admin/analytics")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert!(String::from_utf8_lossy(&body).contains("<td>news.example</td>"));
+}
+
+#[tokio::test]
+async fn adds_up_views_of_too_many_pages() {
+    let wiki = TestWiki::new();
+    let router = tome::app(wiki.config("analytics = true")).await.unwrap();
+    // The editor opens for any title, so made-up paths are views, too
+    for i in 0..1004 {
+        let request = Request::get(format!("/edit/article/made-up-{i}"))
+            .header(header::REFERER, format!("https://site-{i}.example/"))
+            .body(Body::empty())
+            .unwrap();
+        router.clone().oneshot(request).await.unwrap();
+    }
+
+    let response = send_to(
+        router.clone(),
+        Request::get("/admin/analytics/referrers?path=%28other%20pages%29")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    let referrers: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+    assert_eq!(
+        referrers,
+        serde_json::json!([{ "path": "(other pages)", "referrer": "(other sites)", "views": 4 }])
+    );
+    let response = send_to(
+        router,
+        Request::get("/admin/analytics")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert!(response.body.contains("<td>(other pages)</td>"));
+    assert!(response.body.contains("<td>1004</td>"));
+}
+
+#[tokio::test]
+async fn previews_pdfs() {
+    let wiki = TestWiki::new();
+    wiki.save(
+        "Reports",
+        "![Annual report](/media/report.pdf) and ![Chart](/media/chart.png)",
+    )
+    .await;
+    let body = wiki.get("/article/reports").await.body;
+    assert!(body.contains(
+        r#"<a class="pdf-preview" href="/media/report.pdf" data-pdf="/media/report.pdf">Annual report</a>"#
+    ));
+    assert!(body.contains(r#"<img src="/media/chart.png" alt="Chart""#));
+
+    let response = wiki.get("/static/pdfjs/pdf.min.mjs").await;
+    assert_eq!(response.status, StatusCode::TEMPORARY_REDIRECT);
+    assert!(response.location.unwrap().ends_with("/build/pdf.min.mjs"));
+    assert_eq!(
+        wiki.get("/static/pdfjs/other.js").await.status,
+        StatusCode::NOT_FOUND
+    );
+
+    let pdfjs = tempfile::tempdir().unwrap();
+    std::fs::write(
+        pdfjs.path().join("pdf.min.mjs"),
+        "export const version = 1;",
+    )
+    .unwrap();
+    let config: TomeConfig = wiki.config(&format!("pdfjs_dir = \"{}\"", pdfjs.path().display()));
+    let response = tome::app(config)
+        .await
+        .unwrap()
+        .oneshot(
+            Request::get("/static/pdfjs/pdf.min.mjs")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(&body[..], b"export const version = 1;");
+}
+
+#[tokio::test]
+async fn checks_the_content() {
+    let wiki = TestWiki::new();
+    // Otherwise the title is added to the frontmatter, which replaces it
+    wiki.save(
+        "checked",
+        "---\ntags: [unclosed\n---\n![Missing](/media/check-missing.png)",
+    )
+    .await;
+    let problems = tome::run(wiki.cli(&["check"]))
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(problems.contains("checked has frontmatter that can't be read"));
+    assert!(
+        problems.contains("The media file check-missing.png doesn't exist, but checked uses it")
+    );
+}
+
+#[tokio::test]
+async fn collects_unused_media() {
+    let wiki = TestWiki::new();
+    for name in [
+        "gc-used.png",
+        "gc-listed.png",
+        "gc-unused.png",
+        "gc-new.png",
+    ] {
+        wiki.upload(name, &png(b"media")).await;
+    }
+    wiki.save(
+        "Uses media",
+        "![Used](/media/gc-used.png)\n\n{{file-info gc-listed.png}}",
+    )
+    .await;
+    let two_days_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(2 * 86400);
+    for name in ["gc-used.png", "gc-listed.png", "gc-unused.png"] {
+        std::fs::File::options()
+            .write(true)
+            .open(wiki.path(&format!("content/media/{name}")))
+            .unwrap()
+            .set_modified(two_days_ago)
+            .unwrap();
+    }
+
+    tome::run(wiki.cli(&["media", "gc"])).await.unwrap();
+    assert_eq!(
+        wiki.get("/media/gc-unused.png").await.status,
+        StatusCode::OK
+    );
+
+    tome::run(wiki.cli(&["media", "gc", "--delete"]))
+        .await
+        .unwrap();
+    assert_eq!(wiki.get("/media/gc-used.png").await.status, StatusCode::OK);
+    assert_eq!(
+        wiki.get("/media/gc-listed.png").await.status,
+        StatusCode::OK
+    );
+    assert_eq!(wiki.get("/media/gc-new.png").await.status, StatusCode::OK);
+    assert_eq!(
+        wiki.get("/media/gc-unused.png").await.status,
+        StatusCode::NOT_FOUND
+    );
+    let trashed: Vec<String> = std::fs::read_dir(wiki.path("content/.media-trash"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with("-gc-unused.png"))
+        .collect();
+    assert_eq!(trashed.len(), 1);
+
+    // Files in the trash for longer than `media_trash_days` are removed
+    std::fs::rename(
+        wiki.path(&format!("content/.media-trash/{}", trashed[0])),
+        wiki.path("content/.media-trash/1-gc-unused.png"),
+    )
+    .unwrap();
+    tome::run(wiki.cli(&["media", "gc", "--delete"]))
+        .await
+        .unwrap();
+    assert!(!wiki.path("content/.media-trash/1-gc-unused.png").exists());
+}
+
+#[tokio::test]
+async fn exposes_prometheus_metrics() {
+    let wiki = TestWiki::new();
+    wiki.save("Measured", "Counted").await;
+    let config: TomeConfig = wiki.config("metrics = true");
+    let router = tome::app(config).await.unwrap();
+    let request = Request::get("/article/measured")
+        .body(Body::empty())
+        .unwrap();
+    router.clone().oneshot(request).await.unwrap();
+
+    let response = router
+        .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body = String::from_utf8_lossy(&body);
+    assert!(body
+        .contains(r#"tome_http_requests_total{method="GET",route="/article/:id",status="200"}"#));
+    assert!(body.contains("tome_http_request_duration_seconds_bucket"));
+
+    // Without `metrics` there are none
+    let response = wiki
+        .app()
+        .await
+        .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn samples_requests_for_tracing() {
+    let wiki = TestWiki::new();
+    wiki.save("Sampled", "Traced in detail").await;
+    let config: TomeConfig = wiki.config(
+        r#"trace_sample_rate = 0.0
+            trace_sampling = [{ route = "/article/:id", rate = 1.0 }]"#,
+    );
+    let response = tome::app(config)
+        .await
+        .unwrap()
+        .oneshot(
+            Request::get("/article/sampled")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert!(
+        tome::run(wiki.cli(&["--trace-sample-rate", "1.5", "config", "check"]))
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn benchmarks_the_content() {
+    let wiki = TestWiki::new();
+    wiki.save("Benchmarked", "# Timed\n\nWith a [[Benchmarked|link]]")
+        .await;
+    tome::run(wiki.cli(&["bench", "--runs", "1", "--budget", "60000"]))
+        .await
+        .unwrap();
+    // Nothing renders in no time
+    let slow = tome::run(wiki.cli(&["bench", "--runs", "1", "--budget", "0"]))
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(slow.contains("Benchmarked ("));
+}
+
+#[tokio::test]
+async fn writes_articles_in_one_piece() {
+    let wiki = TestWiki::new();
+    wiki.save("Whole", "First").await;
+    wiki.save("Whole", "Second").await;
+    let current = std::fs::read_to_string(wiki.path("content/articles/whole/current.md")).unwrap();
+    assert!(current.ends_with("Second"));
+    // What is written first is moved in place, not left behind
+    let leftovers: Vec<_> = std::fs::read_dir(wiki.path("content/articles/whole"))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with('.'))
+        .collect();
+    assert!(leftovers.is_empty());
+}
+
+#[tokio::test]
+async fn records_the_content_layout() {
+    let wiki = TestWiki::new();
+    let _ = wiki.app().await; // Sets up the content directory
+    let layout = std::fs::read_to_string(wiki.path("content/.tome-version")).unwrap();
+    assert_eq!(layout.trim(), "2");
+    // A new content directory has nothing to back up or revert
+    tome::run(wiki.cli(&["migrate"])).await.unwrap();
+    assert!(tome::run(wiki.cli(&["migrate", "revert"])).await.is_err());
+}
+
+#[tokio::test]
+async fn dumps_and_loads_the_wiki() {
+    let wiki = TestWiki::new();
+    wiki.save("dumped", "First").await;
+    wiki.save("dumped", "Second").await;
+    let dump = wiki.path("dump.ndjson");
+    tome::run(wiki.cli(&["dump", "--media", dump.to_str().unwrap()]))
+        .await
+        .unwrap();
+    let dump = std::fs::read_to_string(dump).unwrap();
+    let records: Vec<serde_json::Value> = dump
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records[0]["kind"], "header");
+    let versions: Vec<&str> = records
+        .iter()
+        .filter(|record| record["kind"] == "version" && record["title"] == "dumped")
+        .map(|record| record["content"].as_str().unwrap())
+        .collect();
+    assert_eq!(versions, ["First", "Second"]);
+
+    let lines = [
+        serde_json::json!({ "kind": "header", "format": "tome-dump", "version": 1, "layout": 2, "created": "2024-01-01T00:00:00Z" }),
+        serde_json::json!({ "kind": "article", "slug": "loaded", "title": "loaded", "tags": [], "archived": false }),
+        serde_json::json!({ "kind": "version", "title": "loaded", "id": "a", "saved": "2024-01-01T00:00:00Z", "author": "ada", "summary": "Started", "content": "Draft" }),
+        serde_json::json!({ "kind": "version", "title": "loaded", "id": "b", "saved": "2024-01-02T00:00:00Z", "content": "Done" }),
+        serde_json::json!({ "kind": "media", "name": "loaded.txt", "size": 2, "modified": "2024-01-01T00:00:00Z", "sha256": "", "data": "aGk=" }),
+    ];
+    let dump: String = lines.iter().map(|line| format!("{line}\n")).collect();
+    let load = wiki.path("load.ndjson");
+    std::fs::write(&load, dump).unwrap();
+    tome::run(wiki.cli(&["load", load.to_str().unwrap()]))
+        .await
+        .unwrap();
+    let article = wiki.run(tome::Article::load("loaded")).await.unwrap();
+    assert_eq!(article.content(), "Done");
+    assert_eq!(
+        wiki.run(tome::Article::get_versions("loaded")).await.len(),
+        2
+    );
+    assert_eq!(
+        std::fs::read(wiki.path("content/media/loaded.txt")).unwrap(),
+        b"hi"
+    );
+    let response = wiki.get("/article/loaded/history").await;
+    assert!(response.body.contains("Started"));
+}
+
+#[tokio::test]
+async fn answers_conditional_requests() {
+    let wiki = TestWiki::new();
+    wiki.save("cached", "Unchanged").await;
+    wiki.upload("cached.png", &png(b"cached")).await;
+    for uri in ["/article/cached", "/media/cached.png"] {
+        let response = wiki
+            .app()
+            .await
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(header::CACHE_CONTROL));
+        let etag = response.headers()[header::ETAG].clone();
+
+        let response = wiki
+            .app()
+            .await
+            .oneshot(
+                Request::get(uri)
+                    .header(header::IF_NONE_MATCH, etag.clone())
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED, "{uri}");
+        assert_eq!(response.headers()[header::ETAG], etag);
+    }
+
+    // A change makes another tag
+    let etag = wiki
+        .app()
+        .await
+        .oneshot(Request::get("/article/cached").body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+        .headers()[header::ETAG]
+        .clone();
+    wiki.save("cached", "Changed").await;
+    let response = wiki
+        .app()
+        .await
+        .oneshot(
+            Request::get("/article/cached")
+                .header(header::IF_NONE_MATCH, etag)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn caches_rendered_articles() {
+    let wiki = TestWiki::new();
+    wiki.save("rendered-once", "Shows {{file-info render-cache.png}}")
+        .await;
+    let first = wiki.get("/article/rendered-once").await.body;
+    assert!(first.contains("(missing)"));
+    assert_eq!(wiki.get("/article/rendered-once").await.body, first);
+
+    // Media shown in the article renders it again
+    wiki.upload("render-cache.png", &png(b"cached")).await;
+    assert!(!wiki
+        .get("/article/rendered-once")
+        .await
+        .body
+        .contains("(missing)"));
+
+    // So does editing `current.md` by hand
+    let current = wiki.path("content/articles/rendered-once/current.md");
+    std::fs::write(&current, "Edited by hand").unwrap();
+    let file = std::fs::File::options().write(true).open(&current).unwrap();
+    file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))
+        .unwrap();
+    assert!(wiki
+        .get("/article/rendered-once")
+        .await
+        .body
+        .contains("Edited by hand"));
+}
+
+#[tokio::test]
+async fn serves_several_content_directories() {
+    let (first, second) = (TestWiki::new(), TestWiki::new());
+    // Both apps are running while the first one saves
+    let apps = [first.app().await, second.app().await];
+    first.save("separate", "Only here").await;
+
+    assert!(first.path("content/articles/separate/current.md").exists());
+    assert!(!second.path("content/articles/separate").exists());
+    for (app, status) in apps
+        .into_iter()
+        .zip([StatusCode::OK, StatusCode::TEMPORARY_REDIRECT])
+    {
+        let request = Request::get("/article/separate")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(app.oneshot(request).await.unwrap().status(), status);
+    }
+}
+
+#[cfg(feature = "pandoc")]
+#[tokio::test]
+async fn exports_only_media_images_through_pandoc() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let wiki = TestWiki::new();
+    wiki.upload("exported.png", &png(b"exported")).await;
+    wiki.save(
+        "Exported",
+        "![Local](/etc/passwd) ![Media](/media/exported.png)\n\n\
+         <img src=\"http://169.254.169.254/latest\">",
+    )
+    .await;
+    // Stands in for pandoc, echoing the HTML it gets if it runs sandboxed
+    let pandoc = wiki.path("pandoc");
+    std::fs::write(
+        &pandoc,
+        "#!/bin/sh\ncase \"$*\" in *--sandbox*) cat ;; *) exit 1 ;; esac\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&pandoc, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let export = |pandoc: &str| {
+        let config = wiki.config(&format!("pandoc_path = {pandoc:?}"));
+        async move {
+            let response = tome::app(config)
+                .await
+                .unwrap()
+                .oneshot(
+                    Request::get("/article/exported/export?format=docx")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            (status, String::from_utf8_lossy(&body).into_owned())
+        }
+    };
+
+    let (status, html) = export(pandoc.to_str().unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(html.contains("src=\"data:image/png;base64,"), "{html}");
+    assert!(!html.contains("/etc/passwd"));
+    assert!(!html.contains("169.254.169.254"));
+
+    let (status, _) = export("false").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn verifies_signed_versions_on_every_storage() {
+    for storage in ["", "git_storage = true"] {
+        let wiki = TestWiki::new();
+        let config = wiki.config(&format!("sign_versions = true\n{storage}"));
+        let router = tome::app(config).await.unwrap();
+        let send = |request: Request<Body>| {
+            let router = router.clone();
+            async move {
+                let response = router.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+                (status, String::from_utf8_lossy(&body).into_owned())
+            }
+        };
+        send(edit_request(&[
+            ("title", "Signed"),
+            ("original_title", "Signed"),
+            ("content", "Signed content"),
+        ]))
+        .await;
+        let history = Request::get("/article/signed/history")
+            .body(Body::empty())
+            .unwrap();
+        let (_, history) = send(history).await;
+        let version = history
+            .split("/article/signed/history/")
+            .nth(1)
+            .and_then(|rest| rest.split(['"', '/']).next())
+            .unwrap()
+            .to_string();
+
+        let uri = format!("/api/article/signed/history/{version}/signature");
+        let (status, body) = send(Request::get(uri).body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::OK, "{storage}");
+        let verification: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(verification["valid"], true, "{storage}");
+        assert_eq!(verification["trusted"], true, "{storage}");
+    }
+}
+
+#[tokio::test]
+async fn refuses_changes_past_the_storage_quota() {
+    let wiki = TestWiki::new();
+    let router = tome::app(wiki.config(
+        r#"
+        allowed_uploads = ["png"]
+        storage_quota = 1
+        "#,
+    ))
+    .await
+    .unwrap();
+    let save = edit_request(&[
+        ("title", "Too much"),
+        ("original_title", "Too much"),
+        ("content", "No room for this"),
+    ]);
+    let response = router.clone().oneshot(save).await.unwrap();
+    assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+    let upload = upload_request("full.png", &png(b"image"));
+    let response = router.oneshot(upload).await.unwrap();
+    assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+    assert!(!wiki.path("content/articles/too-much").exists());
+    assert!(!wiki.path("content/media/full.png").exists());
+}
+
+#[tokio::test]
+async fn hosts_tenants_with_their_own_users_and_content() {
+    let wiki = TestWiki::new();
+    wiki.save("Main only", "Only in the main wiki").await;
+    // The password is "correct horse"
+    let config = wiki.config(&format!(
+        r#"
+        admin_user = "editor"
+        admin_password_hash = "$argon2id$v=19$m=19456,t=2,p=1$RUT9xXtVCiS0hbxNuuSjLg$txZZL9n9cmfX6Ohf7ElB32tBCeq/Ky0EVHV9L+uWlfE"
+        tenants_dir = "{}"
+        tenant_domain = "wiki.test"
+        "#,
+        wiki.path("tenants").display()
+    ));
+    let router = tome::app(config).await.unwrap();
+    let send = |request: Request<Body>| {
+        let router = router.clone();
+        async move {
+            let response = router.oneshot(request).await.unwrap();
+            let status = response.status();
+            let cookie = response.headers().get(header::SET_COOKIE).map(|cookie| {
+                cookie
+                    .to_str()
+                    .unwrap()
+                    .split(';')
+                    .next()
+                    .unwrap()
+                    .to_string()
+            });
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            (status, cookie, String::from_utf8_lossy(&body).into_owned())
+        }
+    };
+    let form = |host: &str, uri: &str, cookie: &str, body: &str| {
+        Request::post(uri)
+            .header(header::HOST, host)
+            .header(header::COOKIE, cookie)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let get = |host: &str, uri: &str, cookie: &str| {
+        Request::get(uri)
+            .header(header::HOST, host)
+            .header(header::COOKIE, cookie)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let login = "user=editor&password=correct%20horse";
+    let (_, admin, _) = send(form("wiki.test", "/login", "", login)).await;
+    let admin = admin.unwrap();
+
+    let tenant = "name=friends&admin_user=amy&password=friendly%20horse&quota=";
+    let (status, _, _) = send(form("wiki.test", "/admin/tenants", &admin, tenant)).await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    let (status, _, _) = send(form("wiki.test", "/admin/tenants", &admin, tenant)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (_, _, page) = send(get("wiki.test", "/admin/tenants", &admin)).await;
+    assert!(page.contains("friends.wiki.test"), "{page}");
+
+    let (status, _, index) = send(get("friends.wiki.test", "/", "")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(index.contains("Welcome to Tome"));
+    let (status, _, _) = send(get("friends.wiki.test", "/article/main-only", "")).await;
+    assert_eq!(status, StatusCode::TEMPORARY_REDIRECT);
+    let (status, _, _) = send(get("friends.wiki.test:5422", "/admin/tenants", &admin)).await;
+    assert_ne!(status, StatusCode::OK);
+
+    // Neither the sessions nor the users of the main wiki count for the tenant
+    let save = "title=Friends&original_title=Friends&content=Only+for+friends";
+    let (status, _, _) = send(form("friends.wiki.test", "/article/edit", &admin, save)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = send(form("friends.wiki.test", "/login", "", login)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let amy = "user=amy&password=friendly%20horse";
+    let (_, amy, _) = send(form("friends.wiki.test", "/login", "", amy)).await;
+    let (status, _, _) = send(form(
+        "friends.wiki.test",
+        "/article/edit",
+        &amy.unwrap(),
+        save,
+    ))
+    .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert!(wiki
+        .path("tenants/friends/content/articles/friends")
+        .exists());
+    assert!(!wiki.path("content/articles/friends").exists());
+
+    let (status, _, _) = send(form(
+        "wiki.test",
+        "/admin/tenants/friends/quota",
+        &admin,
+        "quota=2",
+    ))
+    .await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    let config = std::fs::read_to_string(wiki.path("tenants/friends/tome.toml")).unwrap();
+    assert!(config.contains("storage_quota = 2097152"), "{config}");
+    let (_, _, page) = send(get("wiki.test", "/admin/tenants", &admin)).await;
+    assert!(page.contains(r#"value="2""#), "{page}");
+}
+
+#[tokio::test]
+async fn links_to_its_base_path_when_nested() {
+    let wiki = TestWiki::new();
+    wiki.save("Nested", "See [[Other]]").await;
+    let nested = tome::app(wiki.config(r#"base_path = "/wiki""#))
+        .await
+        .unwrap();
+    let app = Router::new().nest("/wiki", nested);
+    let get = |uri: &str| {
+        let app = app.clone();
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let location = response
+                .headers()
+                .get(header::LOCATION)
+                .map(|location| location.to_str().unwrap().to_string());
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            (
+                status,
+                location,
+                String::from_utf8_lossy(&body).into_owned(),
+            )
+        }
+    };
+
+    let (status, _, page) = get("/wiki/article/nested").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains(r#"data-base-path="/wiki""#));
+    assert!(page.contains(r#"href="/wiki/article/Other""#));
+    assert!(page.contains(r#"href="/wiki/edit/article/nested""#));
+    assert!(page.contains(r#"src="/wiki/static/previews.js""#));
+    assert!(page.contains(r#"data-preview="/wiki/api/preview/"#));
+    assert!(!page.contains(r#"href="/article/"#));
+
+    let (status, location, _) = get("/wiki/article/missing").await;
+    assert_eq!(status, StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(location.as_deref(), Some("/wiki/edit/article/missing"));
+    let (_, _, manifest) = get("/wiki/manifest.webmanifest").await;
+    assert!(manifest.contains(r#""start_url": "/wiki/""#), "{manifest}");
+}
+
+/// The files in the content's `objects/` ending in `ending`, like `.delta`
+fn objects(wiki: &TestWiki, ending: &str) -> usize {
+    let Ok(dirs) = std::fs::read_dir(wiki.path("content/objects")) else {
+        return 0;
+    };
+    dirs.flat_map(|dir| std::fs::read_dir(dir.unwrap().path()).unwrap())
+        .filter(|object| {
+            let name = object.as_ref().unwrap().file_name();
+            let name = name.to_string_lossy();
+            match ending {
+                "" => !name.contains('.'),
+                ending => name.ends_with(ending),
+            }
+        })
+        .count()
+}
+
+/// A long article whose `version` changes one of its lines
+fn chained(version: usize) -> String {
+    let mut lines: Vec<String> = (0..200)
+        .map(|line| format!("Line {line} of the chain\n"))
+        .collect();
+    lines[version % 200] = format!("Version {version}\n");
+    lines.concat()
+}
+
+#[tokio::test]
+async fn reads_every_version_of_long_delta_chains() {
+    let wiki = TestWiki::new();
+    let versions = wiki
+        .run(async {
+            for version in 0..40 {
+                tome::Article::new("Chain", chained(version))
+                    .write_to_disk()
+                    .await
+                    .unwrap();
+            }
+            let mut loaded = vec![];
+            for (id, _) in tome::storage().versions("Chain").await {
+                loaded.push(tome::storage().load("Chain", &id).await.unwrap());
+            }
+            loaded
+        })
+        .await;
+    assert_eq!(versions.len(), 40);
+    for (version, content) in versions.iter().enumerate() {
+        // Saving adds the title to the frontmatter
+        assert!(content.ends_with(&chained(version)), "version {version}");
+    }
+    // Every 16th version is stored in full again
+    assert!(objects(&wiki, ".delta") >= 30);
+    assert!(objects(&wiki, ".zst") >= 3);
+}
+
+#[tokio::test]
+async fn reads_uncompressed_objects_of_older_releases() {
+    use sha2::Digest;
+
+    let wiki = TestWiki::new();
+    let content = "---\ntitle: Old\n---\nStored before objects were compressed\n";
+    let hash: String = sha2::Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    std::fs::create_dir_all(wiki.path(&format!("content/objects/{}", &hash[..2]))).unwrap();
+    std::fs::write(
+        wiki.path(&format!("content/objects/{}/{}", &hash[..2], &hash[2..])),
+        content,
+    )
+    .unwrap();
+    std::fs::write(
+        wiki.path(&format!(
+            "content/objects/{}/{}.refs",
+            &hash[..2],
+            &hash[2..]
+        )),
+        "1",
+    )
+    .unwrap();
+    std::fs::create_dir_all(wiki.path("content/articles/old")).unwrap();
+    std::fs::write(wiki.path("content/articles/old/current.md"), content).unwrap();
+    let index = serde_json::json!([{
+        "id": "4a6f1ac7-68db-4bd3-9a63-2c9e4b6d6a10",
+        "hash": hash,
+        "saved": { "secs_since_epoch": 1_700_000_000, "nanos_since_epoch": 0 },
+    }]);
+    std::fs::write(
+        wiki.path("content/articles/old/versions.json"),
+        index.to_string(),
+    )
+    .unwrap();
+
+    let load = || async {
+        wiki.run(async {
+            tome::storage()
+                .load("Old", "4a6f1ac7-68db-4bd3-9a63-2c9e4b6d6a10")
+                .await
+        })
+        .await
+    };
+    assert_eq!(load().await.as_deref(), Some(content));
+    let response = wiki
+        .get("/article/old/history/4a6f1ac7-68db-4bd3-9a63-2c9e4b6d6a10")
+        .await;
+    assert!(response
+        .body
+        .contains("Stored before objects were compressed"));
+
+    tome::run(wiki.cli(&["storage", "compact"])).await.unwrap();
+    assert_eq!(objects(&wiki, ""), 0);
+    assert_eq!(load().await.as_deref(), Some(content));
+}
+
+#[tokio::test]
+async fn keeps_delta_bases_that_other_articles_need_when_purging() {
+    let wiki = TestWiki::new();
+    wiki.run(async {
+        tome::Article::new("Purged", chained(0))
+            .write_to_disk()
+            .await
+            .unwrap();
+        tome::Article::new("Purged", chained(1))
+            .write_to_disk()
+            .await
+            .unwrap();
+    })
+    .await;
+    // The second version of "Purged" is a delta, which "Kept" shares
+    let shared = wiki
+        .run(async {
+            let versions = tome::Article::get_versions("Purged").await;
+            tome::storage().load("Purged", &versions[1].0).await
+        })
+        .await
+        .unwrap();
+    assert_eq!(objects(&wiki, ".delta"), 1);
+    std::fs::create_dir_all(wiki.path("content/articles/kept")).unwrap();
+    let kept = wiki
+        .run(async { tome::storage().save("Kept", &shared).await })
+        .await
+        .unwrap();
+
+    wiki.post("/article/purged/delete").await;
+    let trashed = wiki.trashed().await;
+    assert_eq!(trashed.len(), 1);
+    let response = wiki
+        .post(&format!("/admin/trash/{}/purge", trashed[0]))
+        .await;
+    assert!(response.status.is_redirection());
+
+    let loaded = wiki
+        .run(async { tome::storage().load("Kept", &kept).await })
+        .await;
+    assert_eq!(loaded, Some(shared));
+    assert_eq!(objects(&wiki, ".delta"), 1);
+    assert_eq!(objects(&wiki, ".zst"), 1);
+}
+
+#[tokio::test]
+async fn exports_zim_archives_that_can_be_read_back() {
+    use md5::Digest;
+
+    let wiki = TestWiki::new();
+    wiki.save("Offline", "Read [[Other page]] offline").await;
+    wiki.upload("offline.png", &png(b"pixels")).await;
+    let output = wiki.path("wiki.zim");
+    tome::run(wiki.cli(&["export", "--format", "zim", output.to_str().unwrap()]))
+        .await
+        .unwrap();
+    let zim = std::fs::read(&output).unwrap();
+
+    let u16_at = |at: usize| u16::from_le_bytes(zim[at..at + 2].try_into().unwrap());
+    let u32_at = |at: usize| u32::from_le_bytes(zim[at..at + 4].try_into().unwrap());
+    let u64_at = |at: usize| u64::from_le_bytes(zim[at..at + 8].try_into().unwrap()) as usize;
+    let string_at = |at: usize| {
+        let end = at + zim[at..].iter().position(|byte| *byte == 0).unwrap();
+        (String::from_utf8(zim[at..end].to_vec()).unwrap(), end + 1)
+    };
+    assert_eq!(u32_at(0), 72173914);
+    assert_eq!(u16_at(4), 5);
+    let (count, url_ptrs, cluster_ptrs) = (u32_at(24) as usize, u64_at(32), u64_at(48));
+    let checksum = u64_at(72);
+    assert_eq!(
+        &zim[checksum..],
+        md5::Md5::digest(&zim[..checksum]).as_slice()
+    );
+
+    let mut mime_types = vec![];
+    let mut at = u64_at(56);
+    loop {
+        let (mime_type, next) = string_at(at);
+        if mime_type.is_empty() {
+            break;
+        }
+        mime_types.push(mime_type);
+        at = next;
+    }
+    assert!(mime_types.contains(&"text/html".to_string()));
+    assert!(mime_types.contains(&"image/png".to_string()));
+
+    // Each entry's namespace, URL, MIME type and blob
+    let entries: Vec<(u8, String, String, Vec<u8>)> = (0..count)
+        .map(|entry| {
+            let dirent = u64_at(url_ptrs + 8 * entry);
+            let mime_type = mime_types[u16_at(dirent) as usize].clone();
+            let (cluster, blob) = (u32_at(dirent + 8) as usize, u32_at(dirent + 12) as usize);
+            let (url, _) = string_at(dirent + 16);
+            let cluster = u64_at(cluster_ptrs + 8 * cluster);
+            assert_eq!(zim[cluster], 1, "clusters are uncompressed");
+            let start = cluster + 1 + u32_at(cluster + 1 + 4 * blob) as usize;
+            let end = cluster + 1 + u32_at(cluster + 1 + 4 * (blob + 1)) as usize;
+            (zim[dirent + 3], url, mime_type, zim[start..end].to_vec())
+        })
+        .collect();
+    let entry = |namespace: u8, url: &str| {
+        entries
+            .iter()
+            .find(|entry| entry.0 == namespace && entry.1 == url)
+            .unwrap_or_else(|| panic!("no entry {url}"))
+    };
+
+    let article = entry(b'A', "offline");
+    assert_eq!(article.2, "text/html");
+    let html = String::from_utf8_lossy(&article.3);
+    assert!(html.contains("<h1>Offline</h1>"));
+    assert!(html.contains(r#"href="other-page""#), "{html}");
+    assert_eq!(entry(b'I', "offline.png").3, png(b"pixels"));
+    assert_eq!(entry(b'M', "Title").3, b"Tome");
+    let main_page = u32_at(64) as usize;
+    assert_eq!(entries[main_page].1, "~index");
+}
+
+#[tokio::test]
+async fn resets_the_demo_to_its_snapshot() {
+    let wiki = TestWiki::new();
+    let config = wiki.config("demo_mode = true\ndemo_reset_minutes = 5");
+    let demo = tome::app(config.clone()).await.unwrap();
+
+    let response = send_to(demo.clone(), Request::get("/").body(Body::empty()).unwrap()).await;
+    assert!(response.body.contains("Welcome to the Tome demo"));
+    assert!(response
+        .body
+        .contains("Changes are undone regularly, every 5 min."));
+    assert!(wiki.path("demo/index.md").exists());
+
+    // Anyone can edit, until the demo is reset
+    let edit = edit_request(&[
+        ("title", "Writing articles"),
+        ("original_title", "Writing articles"),
+        ("content", "Vandalized"),
+    ]);
+    let response = send_to(demo.clone(), edit).await;
+    assert_eq!(
+        response.location.as_deref(),
+        Some("/article/writing-articles")
+    );
+    let article = || {
+        Request::get("/article/writing-articles")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = send_to(demo, article()).await;
+    assert!(response.body.contains("Vandalized"));
+
+    std::fs::write(wiki.path("demo/index.md"), "# Our demo").unwrap();
+    let demo = tome::app(config).await.unwrap();
+    let response = send_to(demo.clone(), article()).await;
+    assert!(!response.body.contains("Vandalized"));
+    assert!(response.body.contains("Markdown"));
+    let response = send_to(demo, Request::get("/").body(Body::empty()).unwrap()).await;
+    assert!(response.body.contains("Our demo"));
+}
+
+#[tokio::test]
+async fn replaces_text_in_the_body_of_articles() {
+    let wiki = TestWiki::new();
+    wiki.save("Project", "---\ntags: [acme]\n---\nAcme is great. acme\n")
+        .await;
+    wiki.save("Elsewhere", "Nothing to see").await;
+    let replace = |action: &str| {
+        let form = [
+            ("pattern", "(?i)acme"),
+            ("replacement", "Tome"),
+            ("action", action),
+        ];
+        Request::post("/admin/replace")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(serde_urlencoded::to_string(form).unwrap()))
+            .unwrap()
+    };
+
+    let preview = wiki.send(replace("preview")).await.body;
+    assert!(preview.contains("Acme is great. acme"));
+    assert!(preview.contains("Tome is great. Tome"));
+    assert!(!preview.contains("tags: [Tome]"));
+    assert!(!preview.contains("Elsewhere"));
+
+    wiki.send(replace("apply")).await;
+    let current =
+        std::fs::read_to_string(wiki.path("content/articles/project/current.md")).unwrap();
+    assert!(current.contains("- acme\n"), "{current}");
+    assert!(current.ends_with("---\nTome is great. Tome\n"), "{current}");
+    let response = wiki.get("/article/project/history").await;
+    assert_eq!(response.body.matches(r#"name="from""#).count(), 2);
+}